@@ -7,6 +7,8 @@ use graph_graphql::prelude::*;
 pub struct GraphQlRunner<S> {
     logger: Logger,
     store: Arc<S>,
+    max_selection_fields: Option<usize>,
+    max_query_depth: usize,
 }
 
 impl<S> GraphQlRunner<S>
@@ -14,10 +16,21 @@ where
     S: Store,
 {
     /// Creates a new query runner.
-    pub fn new(logger: &Logger, store: Arc<S>) -> Self {
+    ///
+    /// Queries and subscriptions with selection sets that expand to more than
+    /// `max_selection_fields` fields are rejected, as are ones nested deeper than
+    /// `max_query_depth`.
+    pub fn new(
+        logger: &Logger,
+        store: Arc<S>,
+        max_selection_fields: Option<usize>,
+        max_query_depth: usize,
+    ) -> Self {
         GraphQlRunner {
             logger: logger.new(o!("component" => "GraphQlRunner")),
             store: store,
+            max_selection_fields,
+            max_query_depth,
         }
     }
 }
@@ -32,6 +45,8 @@ where
             QueryExecutionOptions {
                 logger: self.logger.clone(),
                 resolver: StoreResolver::new(&self.logger, self.store.clone()),
+                max_selection_fields: self.max_selection_fields,
+                max_depth: self.max_query_depth,
             },
         );
         Box::new(future::ok(result))
@@ -43,6 +58,8 @@ where
             SubscriptionExecutionOptions {
                 logger: self.logger.clone(),
                 resolver: StoreResolver::new(&self.logger, self.store.clone()),
+                max_selection_fields: self.max_selection_fields,
+                max_depth: self.max_query_depth,
             },
         );
 
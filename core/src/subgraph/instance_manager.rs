@@ -1,5 +1,4 @@
 use futures::sync::mpsc::{channel, Receiver, Sender};
-use graph::data::subgraph::schema::SubgraphDeploymentEntity;
 use graph::prelude::{SubgraphInstance as SubgraphInstanceTrait, *};
 use std::collections::HashMap;
 use std::sync::RwLock;
@@ -273,12 +272,8 @@ impl SubgraphInstanceManager {
                             "id" => id_for_err.to_string()
                         );
 
-                        // Set subgraph status to Failed
-                        let status_ops =
-                            SubgraphDeploymentEntity::update_failed_operations(&id_for_err, true);
-                        if let Err(e) =
-                            store_for_errors.apply_entity_operations(status_ops, EventSource::None)
-                        {
+                        // Set subgraph status to Failed and record the error
+                        if let Err(e) = store_for_errors.fail_subgraph(&id_for_err, e.to_string()) {
                             error!(
                                 error_logger,
                                 "Failed to set subgraph status to Failed: {}", e;
@@ -60,7 +60,12 @@ where
 
         Box::new(
             SubgraphManifest::resolve(Link { link }, self.resolver.clone())
-                .map_err(SubgraphAssignmentProviderError::ResolveError)
+                .map_err(|e| match e {
+                    SubgraphManifestResolveError::SchemaError(e) => {
+                        SubgraphAssignmentProviderError::SchemaValidationError(e)
+                    }
+                    e => SubgraphAssignmentProviderError::ResolveError(e),
+                })
                 .and_then(move |subgraph| -> Box<Future<Item = _, Error = _> + Send> {
                     // If subgraph ID already in set
                     if !self_clone
@@ -6,6 +6,7 @@ use graph::prelude::{
     CreateSubgraphResult, SubgraphAssignmentProvider as SubgraphAssignmentProviderTrait,
     SubgraphRegistrar as SubgraphRegistrarTrait, *,
 };
+use graph_graphql::prelude::api_schema;
 
 pub struct SubgraphRegistrar<L, P, S, CS> {
     logger: Logger,
@@ -106,6 +107,26 @@ where
     }
 
     pub fn assignment_events(&self) -> impl Stream<Item = AssignmentEvent, Error = Error> + Send {
+        self.assignment_events_matching(|_| true)
+    }
+
+    /// Like `assignment_events`, but only for assignment changes of the deployment `id`.
+    pub fn assignment_events_for(
+        &self,
+        id: SubgraphDeploymentId,
+    ) -> impl Stream<Item = AssignmentEvent, Error = Error> + Send {
+        self.assignment_events_matching(move |entity_change| {
+            entity_change.entity_id == id.to_string()
+        })
+    }
+
+    fn assignment_events_matching<F>(
+        &self,
+        matches: F,
+    ) -> impl Stream<Item = AssignmentEvent, Error = Error> + Send
+    where
+        F: Fn(&EntityChange) -> bool + Send + 'static,
+    {
         let store = self.store.clone();
         let node_id = self.node_id.clone();
 
@@ -114,6 +135,7 @@ where
                 SubgraphDeploymentAssignmentEntity::subgraph_entity_pair(),
             ])
             .map_err(|()| format_err!("Entity change stream failed"))
+            .filter(move |entity_change| matches(entity_change))
             .and_then(
                 move |entity_change| -> Result<Box<Stream<Item = _, Error = _> + Send>, _> {
                     let subgraph_hash = SubgraphDeploymentId::new(entity_change.entity_id.clone())
@@ -194,6 +216,46 @@ where
                 stream::iter_ok(subgraph_ids).for_each(move |id| provider.start(id).from_err())
             })
     }
+
+    /// Resolves the manifest of the deployment `hash` and deploys it as a new version
+    /// of the subgraph `name`, or keeps the current version if `reuse_current_version`
+    /// is set and it already points at the deployment.
+    fn deploy_subgraph_version(
+        &self,
+        name: SubgraphName,
+        hash: SubgraphDeploymentId,
+        node_id: NodeId,
+        start_block: Option<EthereumBlockPointer>,
+        reuse_current_version: bool,
+    ) -> Box<Future<Item = (), Error = SubgraphRegistrarError> + Send + 'static> {
+        let logger = self.logger.clone();
+        let store = self.store.clone();
+        let chain_store = self.chain_store.clone();
+
+        Box::new(
+            SubgraphManifest::resolve(hash.to_ipfs_link(), self.resolver.clone())
+                .map_err(|e| match e {
+                    // Report schema errors, including their position, like the
+                    // errors `validate_api_schema` finds
+                    SubgraphManifestResolveError::SchemaError(e) => {
+                        SubgraphRegistrarError::SchemaValidationError(e)
+                    }
+                    e => SubgraphRegistrarError::ResolveError(e),
+                })
+                .and_then(move |manifest| {
+                    create_subgraph_version(
+                        &logger,
+                        store,
+                        chain_store,
+                        name,
+                        manifest,
+                        node_id,
+                        start_block,
+                        reuse_current_version,
+                    )
+                }),
+        )
+    }
 }
 
 impl<L, P, S, CS> SubgraphRegistrarTrait for SubgraphRegistrar<L, P, S, CS>
@@ -220,18 +282,19 @@ where
         name: SubgraphName,
         hash: SubgraphDeploymentId,
         node_id: NodeId,
+        start_block: Option<EthereumBlockPointer>,
     ) -> Box<Future<Item = (), Error = SubgraphRegistrarError> + Send + 'static> {
-        let logger = self.logger.clone();
-        let store = self.store.clone();
-        let chain_store = self.chain_store.clone();
+        self.deploy_subgraph_version(name, hash, node_id, start_block, false)
+    }
 
-        Box::new(
-            SubgraphManifest::resolve(hash.to_ipfs_link(), self.resolver.clone())
-                .map_err(SubgraphRegistrarError::ResolveError)
-                .and_then(move |manifest| {
-                    create_subgraph_version(&logger, store, chain_store, name, manifest, node_id)
-                }),
-        )
+    fn upsert_subgraph_version(
+        &self,
+        name: SubgraphName,
+        hash: SubgraphDeploymentId,
+        node_id: NodeId,
+        start_block: Option<EthereumBlockPointer>,
+    ) -> Box<Future<Item = (), Error = SubgraphRegistrarError> + Send + 'static> {
+        self.deploy_subgraph_version(name, hash, node_id, start_block, true)
     }
 
     fn remove_subgraph(
@@ -245,6 +308,17 @@ where
         )))
     }
 
+    fn remove_subgraph_by_hash(
+        &self,
+        hash: SubgraphDeploymentId,
+    ) -> Box<Future<Item = (), Error = SubgraphRegistrarError> + Send + 'static> {
+        Box::new(future::result(remove_subgraph_deployment(
+            &self.logger,
+            self.store.clone(),
+            hash,
+        )))
+    }
+
     fn list_subgraphs(
         &self,
     ) -> Box<Future<Item = Vec<SubgraphName>, Error = SubgraphRegistrarError> + Send + 'static>
@@ -379,7 +453,12 @@ fn create_subgraph_version(
     name: SubgraphName,
     manifest: SubgraphManifest,
     node_id: NodeId,
+    start_block: Option<EthereumBlockPointer>,
+    reuse_current_version: bool,
 ) -> Result<(), SubgraphRegistrarError> {
+    // Fail now rather than on the first query if the schema can't serve a GraphQL API
+    validate_api_schema(&manifest.schema)?;
+
     let mut ops = vec![];
 
     // Look up subgraph entity by name
@@ -417,16 +496,29 @@ fn create_subgraph_version(
         entity_ids: vec![subgraph_entity_id.clone()],
     });
 
-    // Create the subgraph version entity
+    // Check whether the current version already points at this deployment
+    let keep_current_version = match current_version_id_opt {
+        Some(ref current_version_id) if reuse_current_version => store
+            .get(SubgraphVersionEntity::key(current_version_id.to_owned()))?
+            .and_then(|version| version.get("deployment").cloned())
+            .map_or(false, |deployment| {
+                deployment == Value::String(manifest.id.to_string())
+            }),
+        _ => false,
+    };
+
+    // Create the subgraph version entity, unless the current version is kept
     let version_entity_id = generate_entity_id();
-    let created_at = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-    ops.extend(
-        SubgraphVersionEntity::new(subgraph_entity_id.clone(), manifest.id.clone(), created_at)
-            .write_operations(&version_entity_id),
-    );
+    if !keep_current_version {
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        ops.extend(
+            SubgraphVersionEntity::new(subgraph_entity_id.clone(), manifest.id.clone(), created_at)
+                .write_operations(&version_entity_id),
+        );
+    }
 
     // Check if subgraph deployment already exists for this hash
     let deployment_entity_opt = store.get(SubgraphDeploymentEntity::key(manifest.id.clone()))?;
@@ -450,13 +542,17 @@ fn create_subgraph_version(
             Some(chain_head_ptr) => chain_head_ptr.number,
             None => 0,
         };
-        let genesis_block_ptr = chain_store.genesis_block_ptr()?;
+        let start_block_ptr = deployment_start_block(
+            chain_store.genesis_block_ptr()?,
+            chain_head_ptr_opt,
+            start_block,
+        )?;
         ops.extend(
             SubgraphDeploymentEntity::new(
                 &manifest,
                 false,
                 false,
-                genesis_block_ptr,
+                start_block_ptr,
                 chain_head_block_number,
             )
             .create_operations(&manifest.id),
@@ -464,7 +560,7 @@ fn create_subgraph_version(
     }
 
     // If currentVersion is actually being changed, an old assignment may need to be removed.
-    if current_version_id_opt != Some(manifest.id.to_string()) {
+    if !keep_current_version && current_version_id_opt != Some(manifest.id.to_string()) {
         // If there is a previous version that will no longer be "current"
         if let Some(current_version_id) = current_version_id_opt {
             // Look up previous current version's hash
@@ -544,17 +640,24 @@ fn create_subgraph_version(
     }
 
     // TODO support delayed update of currentVersion
-    ops.extend(SubgraphEntity::update_current_version_operations(
-        &subgraph_entity_id,
-        &version_entity_id,
-    ));
+    if !keep_current_version {
+        ops.extend(SubgraphEntity::update_current_version_operations(
+            &subgraph_entity_id,
+            &version_entity_id,
+        ));
+    }
 
     // Commit entity ops
     store.apply_entity_operations(ops, EventSource::None)?;
 
     debug!(
         logger,
-        "Wrote new subgraph version to store";
+        "{}",
+        if keep_current_version {
+            "Subgraph version is already current"
+        } else {
+            "Wrote new subgraph version to store"
+        };
         "subgraph_name" => name.to_string(),
         "subgraph_hash" => manifest.id.to_string()
     );
@@ -623,6 +726,55 @@ fn remove_subgraph(
     Ok(())
 }
 
+/// Removes a deployment that is no longer referenced by any subgraph version,
+/// together with its assignment.
+fn remove_subgraph_deployment(
+    logger: &Logger,
+    store: Arc<impl Store>,
+    hash: SubgraphDeploymentId,
+) -> Result<(), SubgraphRegistrarError> {
+    let mut ops = vec![];
+
+    // The deployment may only be removed if no subgraph versions reference it
+    let referencing_versions_query = SubgraphVersionEntity::query().filter(EntityFilter::Equal(
+        "deployment".to_owned(),
+        hash.to_string().into(),
+    ));
+    let referencing_versions = store.find(referencing_versions_query.clone())?;
+    if !referencing_versions.is_empty() {
+        return Err(SubgraphRegistrarError::DeploymentInUse(
+            hash.to_string(),
+            referencing_versions.len(),
+        ));
+    }
+    ops.push(EntityOperation::AbortUnless {
+        description: "No subgraph versions may reference this subgraph hash".to_owned(),
+        query: referencing_versions_query,
+        entity_ids: vec![],
+    });
+
+    if store
+        .get(SubgraphDeploymentEntity::key(hash.clone()))?
+        .is_none()
+    {
+        return Err(SubgraphRegistrarError::DeploymentNotFound(hash.to_string()));
+    }
+
+    // Remove the deployment and its assignment
+    ops.push(EntityOperation::Remove {
+        key: SubgraphDeploymentEntity::key(hash.clone()),
+    });
+    ops.push(EntityOperation::Remove {
+        key: SubgraphDeploymentAssignmentEntity::key(hash.clone()),
+    });
+
+    store.apply_entity_operations(ops, EventSource::None)?;
+
+    debug!(logger, "Removed subgraph deployment"; "subgraph_hash" => hash.to_string());
+
+    Ok(())
+}
+
 /// Remove a set of subgraph versions atomically.
 ///
 /// It may seem like it would be easier to generate the EntityOperations for subgraph versions
@@ -792,3 +944,79 @@ fn remove_subgraph_versions(
 
     Ok(ops)
 }
+
+/// Checks that a GraphQL API can be derived from a subgraph schema. Clashing type
+/// names are reported with the position of the clashing type in the schema.
+fn validate_api_schema(schema: &Schema) -> Result<(), SubgraphRegistrarError> {
+    api_schema(&schema.document)
+        .map(|_| ())
+        .map_err(|e| SubgraphRegistrarError::SchemaValidationError(e.into()))
+}
+
+/// Determines the block a new deployment starts indexing at: the requested
+/// start block, which must not be beyond the chain head, or the genesis block.
+fn deployment_start_block(
+    genesis_block_ptr: EthereumBlockPointer,
+    chain_head_ptr_opt: Option<EthereumBlockPointer>,
+    start_block: Option<EthereumBlockPointer>,
+) -> Result<EthereumBlockPointer, SubgraphRegistrarError> {
+    let start_block = match start_block {
+        Some(start_block) => start_block,
+        None => return Ok(genesis_block_ptr),
+    };
+
+    let chain_head_block_number = chain_head_ptr_opt.map(|ptr| ptr.number).unwrap_or(0);
+    if start_block.number > chain_head_block_number {
+        return Err(SubgraphRegistrarError::StartBlockBeyondChainHead(
+            start_block.number,
+            chain_head_block_number,
+        ));
+    }
+
+    Ok(start_block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::web3::types::H256;
+
+    fn block_ptr(number: u64) -> EthereumBlockPointer {
+        EthereumBlockPointer {
+            hash: H256::zero(),
+            number,
+        }
+    }
+
+    #[test]
+    fn deployments_start_at_genesis_by_default() {
+        let start = deployment_start_block(block_ptr(0), Some(block_ptr(100)), None).unwrap();
+        assert_eq!(start, block_ptr(0));
+
+        let start = deployment_start_block(block_ptr(0), None, None).unwrap();
+        assert_eq!(start, block_ptr(0));
+    }
+
+    #[test]
+    fn deployments_can_start_at_an_explicit_block() {
+        let start = deployment_start_block(block_ptr(0), Some(block_ptr(100)), Some(block_ptr(42)))
+            .unwrap();
+        assert_eq!(start, block_ptr(42));
+
+        let start =
+            deployment_start_block(block_ptr(0), Some(block_ptr(100)), Some(block_ptr(100)))
+                .unwrap();
+        assert_eq!(start, block_ptr(100));
+    }
+
+    #[test]
+    fn start_block_must_not_be_beyond_chain_head() {
+        match deployment_start_block(block_ptr(0), Some(block_ptr(100)), Some(block_ptr(101))) {
+            Err(SubgraphRegistrarError::StartBlockBeyondChainHead(101, 100)) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        // Without a known chain head, only the genesis block can be validated
+        assert!(deployment_start_block(block_ptr(0), None, Some(block_ptr(1))).is_err());
+    }
+}
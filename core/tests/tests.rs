@@ -264,6 +264,7 @@ fn subgraph_provider_events() {
                             subgraph_name_clone1.clone(),
                             subgraph1_id_clone1.clone(),
                             node_id_clone1.clone(),
+                            None,
                         )
                         .then(move |result| {
                             assert!(result.is_err());
@@ -277,6 +278,7 @@ fn subgraph_provider_events() {
                                 subgraph_name_clone2.clone(),
                                 subgraph1_id_clone1.clone(),
                                 node_id_clone1.clone(),
+                                None,
                             )
                         })
                         .and_then(move |()| {
@@ -290,6 +292,7 @@ fn subgraph_provider_events() {
                                 subgraph_name_clone3,
                                 subgraph2_id_clone1,
                                 node_id_clone2,
+                                None,
                             )
                         })
                         .and_then(move |()| {
@@ -430,3 +433,220 @@ fn subgraph_list() {
         }))
         .unwrap();
 }
+
+#[test]
+fn upserting_the_current_version_creates_no_new_version() {
+    use graph::data::subgraph::schema::{SubgraphVersionEntity, TypedEntity};
+
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime
+        .block_on(future::lazy(|| {
+            let logger = Logger::root(slog::Discard, o!());
+            let store = Arc::new(MockStore::new(vec![]));
+            let resolver = Arc::new(IpfsClient::default());
+            let provider = graph_core::SubgraphAssignmentProvider::new(
+                logger.clone(),
+                resolver.clone(),
+                store.clone(),
+            );
+            let node_id = NodeId::new("testnode").unwrap();
+
+            let registrar = Arc::new(graph_core::SubgraphRegistrar::new(
+                logger.clone(),
+                resolver.clone(),
+                Arc::new(provider),
+                store.clone(),
+                store.clone(),
+                node_id.clone(),
+            ));
+            let subgraph_name = SubgraphName::new("subgraph").unwrap();
+
+            let registrar_clone1 = registrar.clone();
+            let registrar_clone2 = registrar.clone();
+            let subgraph_name_clone1 = subgraph_name.clone();
+            let subgraph_name_clone2 = subgraph_name.clone();
+            let node_id_clone = node_id.clone();
+
+            add_subgraph_to_ipfs(resolver, "dummy")
+                .and_then(move |subgraph_link| {
+                    let subgraph_id =
+                        SubgraphDeploymentId::new(subgraph_link.trim_left_matches("/ipfs/"))
+                            .unwrap();
+                    let subgraph_id_clone = subgraph_id.clone();
+
+                    registrar
+                        .create_subgraph(subgraph_name)
+                        .and_then(move |_| {
+                            registrar_clone1.upsert_subgraph_version(
+                                subgraph_name_clone1,
+                                subgraph_id,
+                                node_id,
+                                None,
+                            )
+                        })
+                        .and_then(move |()| {
+                            // Deploying the same hash again is a no-op
+                            registrar_clone2.upsert_subgraph_version(
+                                subgraph_name_clone2,
+                                subgraph_id_clone,
+                                node_id_clone,
+                                None,
+                            )
+                        })
+                        .from_err()
+                })
+                .map(move |()| {
+                    let versions = store.find(SubgraphVersionEntity::query()).unwrap();
+                    assert_eq!(versions.len(), 1);
+                })
+                .then(|result: Result<(), Error>| -> Result<(), ()> { Ok(result.unwrap()) })
+        }))
+        .unwrap();
+}
+
+#[test]
+fn create_subgraph_version_reports_schema_errors() {
+    /// Deploys the subgraph in `tests/subgraphs/<subgraph>` and returns the error.
+    fn deploy(subgraph: &'static str) -> SubgraphRegistrarError {
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime
+            .block_on(future::lazy(move || {
+                let logger = Logger::root(slog::Discard, o!());
+                let store = Arc::new(MockStore::new(vec![]));
+                let resolver = Arc::new(IpfsClient::default());
+                let provider = graph_core::SubgraphAssignmentProvider::new(
+                    logger.clone(),
+                    resolver.clone(),
+                    store.clone(),
+                );
+                let node_id = NodeId::new("testnode").unwrap();
+
+                let registrar = Arc::new(graph_core::SubgraphRegistrar::new(
+                    logger.clone(),
+                    resolver.clone(),
+                    Arc::new(provider),
+                    store.clone(),
+                    store,
+                    node_id.clone(),
+                ));
+                let subgraph_name = SubgraphName::new("subgraph").unwrap();
+
+                let registrar_clone = registrar.clone();
+                let subgraph_name_clone = subgraph_name.clone();
+
+                add_subgraph_to_ipfs(resolver, subgraph)
+                    .map_err(|e| -> SubgraphRegistrarError {
+                        panic!("failed to add subgraph to IPFS: {}", e)
+                    })
+                    .and_then(move |subgraph_link| {
+                        let subgraph_id =
+                            SubgraphDeploymentId::new(subgraph_link.trim_left_matches("/ipfs/"))
+                                .unwrap();
+
+                        registrar.create_subgraph(subgraph_name).and_then(move |_| {
+                            registrar_clone.create_subgraph_version(
+                                subgraph_name_clone,
+                                subgraph_id,
+                                node_id,
+                                None,
+                            )
+                        })
+                    })
+            }))
+            .expect_err("deploying a subgraph with an invalid schema succeeded")
+    }
+
+    // Syntax errors are reported with their position
+    match deploy("invalid-schema") {
+        SubgraphRegistrarError::SchemaValidationError(e) => assert!(
+            e.to_string().contains("Parse error at 2:"),
+            "unexpected error: {}",
+            e
+        ),
+        e => panic!("unexpected error: {:?}", e),
+    }
+
+    // So are types that clash with the types of the API schema
+    match deploy("clashing-schema") {
+        e @ SubgraphRegistrarError::SchemaValidationError(_) => assert_eq!(
+            e.to_string(),
+            "GraphQL schema error: type Query already exists in the input schema at 5:1"
+        ),
+        e => panic!("unexpected error: {:?}", e),
+    }
+}
+
+#[test]
+fn remove_subgraph_by_hash_only_removes_orphaned_deployments() {
+    use graph::data::subgraph::schema::{
+        SubgraphDeploymentAssignmentEntity, SubgraphDeploymentEntity, SubgraphVersionEntity,
+        TypedEntity,
+    };
+
+    let logger = Logger::root(slog::Discard, o!());
+    let store = Arc::new(MockStore::new(vec![]));
+    let resolver = Arc::new(IpfsClient::default());
+    let provider = graph_core::SubgraphAssignmentProvider::new(
+        logger.clone(),
+        resolver.clone(),
+        store.clone(),
+    );
+    let registrar = graph_core::SubgraphRegistrar::new(
+        logger,
+        resolver,
+        Arc::new(provider),
+        store.clone(),
+        store.clone(),
+        NodeId::new("testnode").unwrap(),
+    );
+
+    let referenced = SubgraphDeploymentId::new("referenced").unwrap();
+    let orphaned = SubgraphDeploymentId::new("orphaned").unwrap();
+    let mut ops = vec![];
+    for hash in vec![&referenced, &orphaned] {
+        ops.push(EntityOperation::Set {
+            key: SubgraphDeploymentEntity::key(hash.clone()),
+            data: Entity::new(),
+        });
+        ops.push(EntityOperation::Set {
+            key: SubgraphDeploymentAssignmentEntity::key(hash.clone()),
+            data: Entity::from(vec![("nodeId", Value::from("testnode"))]),
+        });
+    }
+    ops.push(EntityOperation::Set {
+        key: SubgraphVersionEntity::key("version".to_owned()),
+        data: Entity::from(vec![("deployment", Value::from(referenced.to_string()))]),
+    });
+    store
+        .apply_entity_operations(ops, EventSource::None)
+        .unwrap();
+
+    // A deployment that a version refers to is kept
+    match registrar.remove_subgraph_by_hash(referenced.clone()).wait() {
+        Err(SubgraphRegistrarError::DeploymentInUse(hash, 1)) => {
+            assert_eq!(hash, referenced.to_string())
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+    assert!(store
+        .get(SubgraphDeploymentEntity::key(referenced.clone()))
+        .unwrap()
+        .is_some());
+
+    // An orphaned deployment is removed along with its assignment
+    registrar
+        .remove_subgraph_by_hash(orphaned.clone())
+        .wait()
+        .unwrap();
+    assert!(store
+        .get(SubgraphDeploymentEntity::key(orphaned.clone()))
+        .unwrap()
+        .is_none());
+    assert!(store
+        .get(SubgraphDeploymentAssignmentEntity::key(orphaned.clone()))
+        .unwrap()
+        .is_none());
+
+    // Removing it again fails
+    assert!(registrar.remove_subgraph_by_hash(orphaned).wait().is_err());
+}
@@ -283,14 +283,16 @@ where
         &'a self,
         blocks: B,
     ) -> impl Future<Item = Vec<H256>, Error = BlockIngestorError> + Send + 'a {
-        self.chain_store.upsert_blocks(blocks).and_then(move |()| {
-            self.chain_store
-                .attempt_chain_head_update(self.ancestor_count)
-                .map_err(|e| {
-                    error!(self.logger, "failed to update chain head");
-                    BlockIngestorError::Unknown(e)
-                })
-        })
+        self.chain_store
+            .insert_new_blocks(blocks)
+            .and_then(move |_| {
+                self.chain_store
+                    .attempt_chain_head_update(self.ancestor_count)
+                    .map_err(|e| {
+                        error!(self.logger, "failed to update chain head");
+                        BlockIngestorError::Unknown(e)
+                    })
+            })
     }
 
     /// Requests the specified blocks via web3, returning them in a stream (potentially out of
@@ -3,6 +3,7 @@ use futures::prelude::*;
 use graph::ethabi::Token;
 use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Duration;
 
 use graph::components::ethereum::{EthereumAdapter as EthereumAdapterTrait, *};
 use graph::prelude::*;
@@ -220,21 +221,22 @@ where
         contract_address: Address,
         call_data: Bytes,
         block_number_opt: Option<BlockNumber>,
-    ) -> impl Future<Item = Bytes, Error = Error> + Send {
+    ) -> impl Future<Item = Bytes, Error = EthereumContractCallError> + Send {
         let web3 = self.web3.clone();
         let logger = logger.clone();
 
-        // Outer retry used only for 0-byte responses,
+        // Retry used only for 0-byte responses,
         // where we can't guarantee the problem is temporary.
         // If we keep getting back 0-byte responses,
         // eventually we assume it's right and return it.
-        retry("eth_call RPC call (outer)", &logger)
+        retry("eth_call RPC call", &logger)
             .when(|result: &Result<Bytes, _>| {
                 match result {
                     // Retry only if zero-length response received
                     Ok(bytes) => bytes.0.is_empty(),
 
-                    // Errors are retried in the inner retry
+                    // Errors are not retried here; the caller of `contract_call`
+                    // decides which errors are worth retrying
                     Err(_) => false,
                 }
             })
@@ -242,30 +244,22 @@ where
             .no_logging()
             .no_timeout()
             .run(move || {
-                let web3 = web3.clone();
-                let call_data = call_data.clone();
-
-                retry("eth_call RPC call", &logger)
-                    .no_limit()
-                    .timeout_secs(60)
-                    .run(move || {
-                        let req = CallRequest {
-                            from: None,
-                            to: contract_address,
-                            gas: None,
-                            gas_price: None,
-                            value: None,
-                            data: Some(call_data.clone()),
-                        };
-                        web3.eth()
-                            .call(req, block_number_opt)
-                            .map_err(SyncFailure::new)
-                            .from_err()
-                    })
-                    .map_err(|e| {
-                        e.into_inner().unwrap_or_else(|| {
-                            format_err!("Ethereum node took too long to perform function call")
-                        })
+                let req = CallRequest {
+                    from: None,
+                    to: contract_address,
+                    gas: None,
+                    gas_price: None,
+                    value: None,
+                    data: Some(call_data.clone()),
+                };
+                web3.eth()
+                    .call(req, block_number_opt)
+                    .timeout(Duration::from_secs(60))
+                    .map_err(|e| match e.into_inner() {
+                        Some(e) => contract_call_error(e),
+                        None => EthereumContractCallError::Error(format_err!(
+                            "Ethereum node took too long to perform function call"
+                        )),
                     })
             })
     }
@@ -553,7 +547,6 @@ where
                 Bytes(call_data),
                 Some(call.block_ptr.number.into()),
             )
-            .map_err(EthereumContractCallError::from)
             .and_then(move |output| {
                 // Decode the return values according to the ABI
                 call.function
@@ -563,3 +556,125 @@ where
         )
     }
 }
+
+/// Turns the error of an `eth_call` into a contract call error.
+///
+/// Calls that the node executed but that failed, e.g. because the contract
+/// reverted, fail the same way every time and become `Revert` errors. These
+/// are the formats the supported nodes report such failures in:
+///
+/// - Parity: code -32015 with message `VM execution error.` and data like
+///   `Reverted 0x...`
+/// - Geth: code -32000 (or 3 since Geth 1.9.15) with message
+///   `execution reverted`, optionally followed by the revert reason
+/// - Ganache: code -32000 with message `VM Exception while processing
+///   transaction: revert` (or `: invalid opcode`, etc.)
+///
+/// Geth and Ganache also use code -32000 for errors that may go away when the
+/// call is retried, e.g. `header not found`, so those are told apart by their
+/// message. All other errors are left to the caller to retry.
+fn contract_call_error(e: web3::Error) -> EthereumContractCallError {
+    const PARITY_VM_EXECUTION_ERROR: i64 = -32015;
+    const GETH_EXECUTION_REVERTED: &str = "execution reverted";
+    const GANACHE_VM_EXCEPTION: &str = "vm exception while processing transaction";
+
+    let revert_message = match *e.kind() {
+        web3::ErrorKind::Rpc(ref rpc_error) => {
+            let message = rpc_error.message.to_lowercase();
+            if rpc_error.code.code() == PARITY_VM_EXECUTION_ERROR {
+                Some(match rpc_error.data {
+                    Some(ref data) => format!("{} {}", rpc_error.message, data),
+                    None => rpc_error.message.clone(),
+                })
+            } else if message.starts_with(GETH_EXECUTION_REVERTED)
+                || message.starts_with(GANACHE_VM_EXCEPTION)
+            {
+                Some(rpc_error.message.clone())
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+
+    match revert_message {
+        Some(message) => EthereumContractCallError::Revert(message),
+        None => EthereumContractCallError::from(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonrpc_core::{Error as RpcError, ErrorCode, Value};
+
+    fn rpc_error(code: i64, message: &str, data: Option<Value>) -> web3::Error {
+        web3::ErrorKind::Rpc(RpcError {
+            code: ErrorCode::from(code),
+            message: message.to_owned(),
+            data,
+        })
+        .into()
+    }
+
+    fn is_revert(e: web3::Error) -> bool {
+        match contract_call_error(e) {
+            EthereumContractCallError::Revert(_) => true,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn parity_reverts_are_detected() {
+        let e = rpc_error(
+            -32015,
+            "VM execution error.",
+            Some(Value::String("Reverted 0x".to_owned())),
+        );
+        match contract_call_error(e) {
+            EthereumContractCallError::Revert(message) => {
+                assert_eq!(message, "VM execution error. \"Reverted 0x\"")
+            }
+            e => panic!("expected a revert, got: {:?}", e),
+        }
+        assert!(is_revert(rpc_error(-32015, "VM execution error.", None)));
+    }
+
+    #[test]
+    fn geth_reverts_are_detected() {
+        assert!(is_revert(rpc_error(-32000, "execution reverted", None)));
+        assert!(is_revert(rpc_error(
+            3,
+            "execution reverted: Ownable: caller is not the owner",
+            Some(Value::String("0x08c379a0".to_owned())),
+        )));
+    }
+
+    #[test]
+    fn ganache_reverts_are_detected() {
+        assert!(is_revert(rpc_error(
+            -32000,
+            "VM Exception while processing transaction: revert",
+            None,
+        )));
+        assert!(is_revert(rpc_error(
+            -32000,
+            "VM Exception while processing transaction: invalid opcode",
+            None,
+        )));
+    }
+
+    #[test]
+    fn retryable_errors_are_not_reverts() {
+        assert!(!is_revert(rpc_error(-32000, "header not found", None)));
+        assert!(!is_revert(rpc_error(-32005, "limit exceeded", None)));
+        assert!(!is_revert(rpc_error(
+            -32603,
+            "Internal error: request to revert-proxy timed out",
+            None,
+        )));
+        assert!(!is_revert(
+            web3::ErrorKind::Transport("connection refused".to_owned()).into()
+        ));
+    }
+}
@@ -50,6 +50,9 @@ pub enum EthereumContractCallError {
     /// `Token` is not of expected `ParamType`
     #[fail(display = "type mismatch, token {:?} is not of kind {:?}", _0, _1)]
     TypeError(Token, ParamType),
+    /// The node executed the call, but it failed, e.g. because the contract reverted
+    #[fail(display = "call reverted: {}", _0)]
+    Revert(String),
     #[fail(display = "call error: {}", _0)]
     Error(Error),
 }
@@ -200,6 +203,8 @@ pub trait EthereumAdapter: Send + Sync + 'static {
     ) -> Box<Future<Item = Vec<EthereumBlockPointer>, Error = Error> + Send>;
 
     /// Call the function of a smart contract.
+    ///
+    /// Failed calls are not retried; callers decide which errors are worth retrying.
     fn contract_call(
         &self,
         logger: &Logger,
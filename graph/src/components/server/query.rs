@@ -12,6 +12,8 @@ pub enum GraphQLServerError {
     ClientError(String),
     QueryError(QueryError),
     InternalError(String),
+    TooManyRequests(String),
+    RateLimited(String),
 }
 
 impl From<Canceled> for GraphQLServerError {
@@ -45,6 +47,8 @@ impl fmt::Display for GraphQLServerError {
             GraphQLServerError::ClientError(ref s) => write!(f, "{}", s),
             GraphQLServerError::QueryError(ref e) => write!(f, "{}", e),
             GraphQLServerError::InternalError(ref s) => write!(f, "{}", s),
+            GraphQLServerError::TooManyRequests(ref s) => write!(f, "{}", s),
+            GraphQLServerError::RateLimited(ref s) => write!(f, "{}", s),
         }
     }
 }
@@ -60,6 +64,8 @@ impl Error for GraphQLServerError {
             GraphQLServerError::ClientError(_) => None,
             GraphQLServerError::QueryError(ref e) => Some(e),
             GraphQLServerError::InternalError(_) => None,
+            GraphQLServerError::TooManyRequests(_) => None,
+            GraphQLServerError::RateLimited(_) => None,
         }
     }
 }
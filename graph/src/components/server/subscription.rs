@@ -1,12 +1,18 @@
 use futures::prelude::*;
+use futures::sync::oneshot;
 
 /// Common trait for GraphQL subscription servers.
 pub trait SubscriptionServer {
     type ServeError;
 
     /// Returns a Future that, when spawned, brings up the GraphQL subscription server.
+    ///
+    /// Sending on the `shutdown` channel stops the server from accepting new connections
+    /// and completes all active subscriptions before their connections are closed.
+    /// Dropping the sender without sending leaves the server running.
     fn serve(
         &mut self,
         port: u16,
+        shutdown: oneshot::Receiver<()>,
     ) -> Result<Box<Future<Item = (), Error = ()> + Send>, Self::ServeError>;
 }
@@ -1,6 +1,7 @@
 use failure::Error;
 use futures::Future;
 use futures::Stream;
+use std::collections::HashMap;
 use std::fmt;
 use web3::types::H256;
 
@@ -39,6 +40,10 @@ pub enum EntityFilter {
     NotStartsWith(Attribute, Value),
     EndsWith(Attribute, Value),
     NotEndsWith(Attribute, Value),
+    /// Matches string attributes against a fulltext search query.
+    Fulltext(Attribute, Value),
+    /// Matches list attributes that have the value as one of their elements.
+    ArrayContains(Attribute, Value),
 }
 
 /// The order in which entities should be restored from a store.
@@ -48,11 +53,20 @@ pub enum EntityOrder {
     Descending,
 }
 
+/// Where entities that don't have the attribute they are ordered by, or have
+/// it set to null, are placed in the order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EntityNullsOrder {
+    NullsFirst,
+    NullsLast,
+}
+
 /// How many entities to return, how many to skip etc.
 #[derive(Clone, Debug, PartialEq)]
 pub struct EntityRange {
-    /// How many entities to return.
-    pub first: usize,
+    /// How many entities to return. `None` returns all entities after `skip`, up to the
+    /// limit the store places on unbounded queries; larger values are capped at that limit.
+    pub first: Option<usize>,
 
     /// How many entities to skip.
     pub skip: usize,
@@ -76,8 +90,18 @@ pub struct EntityQuery {
     /// The direction to order entities in.
     pub order_direction: Option<EntityOrder>,
 
+    /// Where to place entities with a null value for the order attribute;
+    /// defaults to after all other entities.
+    pub order_nulls: Option<EntityNullsOrder>,
+
     /// An optional range to limit the size of the result.
     pub range: Option<EntityRange>,
+
+    /// An optional block number; entities last written in a later block are left out.
+    ///
+    /// Only the current version of each entity is stored, so this can't bring back the
+    /// state of entities that were overwritten or removed after the block.
+    pub as_of_block: Option<u64>,
 }
 
 impl EntityQuery {
@@ -88,7 +112,9 @@ impl EntityQuery {
             filter: None,
             order_by: None,
             order_direction: None,
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         }
     }
 
@@ -103,10 +129,20 @@ impl EntityQuery {
         self
     }
 
+    pub fn order_nulls(mut self, nulls: EntityNullsOrder) -> Self {
+        self.order_nulls = Some(nulls);
+        self
+    }
+
     pub fn range(mut self, range: EntityRange) -> Self {
         self.range = Some(range);
         self
     }
+
+    pub fn as_of_block(mut self, block_number: u64) -> Self {
+        self.as_of_block = Some(block_number);
+        self
+    }
 }
 
 /// Operation types that lead to entity changes.
@@ -121,8 +157,16 @@ pub enum EntityChangeOperation {
     Removed,
 }
 
+/// Entity type to subscribe to in order to receive changes to all entity types of a subgraph.
+pub const ANY_ENTITY_TYPE: &str = "*";
+
+/// Attribute that filters and orders entity queries by the number of the block at
+/// which entities were last updated. Names starting with `__` are reserved in GraphQL,
+/// so this can't clash with an attribute of an entity.
+pub const UPDATED_AT_BLOCK_ATTRIBUTE: &str = "__updatedAtBlock";
+
 /// Entity change events emitted by [Store](trait.Store.html) implementations.
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize)]
 pub struct EntityChange {
     /// ID of the subgraph the changed entity belongs to.
     pub subgraph_id: SubgraphDeploymentId,
@@ -132,6 +176,25 @@ pub struct EntityChange {
     pub entity_id: String,
     /// Operation that caused the change.
     pub operation: EntityChangeOperation,
+    /// Position of the change among all changes a store has delivered to its
+    /// subscriptions; later changes have higher numbers. Zero for changes that
+    /// haven't been delivered by a store yet.
+    ///
+    /// Sequence numbers are assigned by the process that delivers the change and
+    /// start over when it restarts; they are only meaningful for resuming a
+    /// subscription with the same process, on a best-effort basis. They are not
+    /// part of what makes two changes equal.
+    #[serde(default)]
+    pub sequence: u64,
+}
+
+impl PartialEq for EntityChange {
+    fn eq(&self, other: &EntityChange) -> bool {
+        self.subgraph_id == other.subgraph_id
+            && self.entity_type == other.entity_type
+            && self.entity_id == other.entity_id
+            && self.operation == other.operation
+    }
 }
 
 impl EntityChange {
@@ -141,12 +204,25 @@ impl EntityChange {
             entity_type: key.entity_type,
             entity_id: key.entity_id,
             operation,
+            sequence: 0,
         }
     }
 
     pub fn subgraph_entity_pair(&self) -> SubgraphEntityPair {
         (self.subgraph_id.clone(), self.entity_type.clone())
     }
+
+    /// Returns true if this change is for one of the `entities`. An entity type of
+    /// `ANY_ENTITY_TYPE` matches changes to entities of any type in its subgraph.
+    pub fn matches<'a, I>(&self, entities: I) -> bool
+    where
+        I: IntoIterator<Item = &'a SubgraphEntityPair>,
+    {
+        entities.into_iter().any(|(subgraph_id, entity_type)| {
+            *subgraph_id == self.subgraph_id
+                && (entity_type == ANY_ENTITY_TYPE || *entity_type == self.entity_type)
+        })
+    }
 }
 
 /// A stream of entity change events.
@@ -281,6 +357,25 @@ impl fmt::Display for EventSource {
 pub enum StoreError {
     #[fail(display = "store transaction failed, need to retry: {}", _0)]
     Aborted(TransactionAbortError),
+    #[fail(
+        display = "Ethereum node provided {} {}, but we expected {}. \
+                   Did you change networks without changing the network name?",
+        identifier, provided, expected
+    )]
+    NetworkMismatch {
+        identifier: String,
+        provided: String,
+        expected: String,
+    },
+    #[fail(
+        display = "block {} has {} entity operations, but at most {} are allowed",
+        block_number, count, max
+    )]
+    TooManyOperations {
+        block_number: u64,
+        count: usize,
+        max: usize,
+    },
     #[fail(display = "store error: {}", _0)]
     Unknown(Error),
 }
@@ -318,20 +413,84 @@ pub enum TransactionAbortError {
     Other(String),
 }
 
+/// The indexing status of a subgraph deployment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeploymentStatus {
+    /// The most recently processed block of the deployment.
+    pub latest_block: EthereumBlockPointer,
+    /// The head block of the chain, if the chain store knows it yet.
+    pub chain_head_block: Option<EthereumBlockPointer>,
+    pub synced: bool,
+    pub failed: bool,
+}
+
+impl DeploymentStatus {
+    /// Returns how many blocks the deployment is behind the chain head, if the
+    /// chain head is known.
+    pub fn blocks_behind(&self) -> Option<u64> {
+        self.chain_head_block
+            .map(|head| head.number.saturating_sub(self.latest_block.number))
+    }
+}
+
 /// Common trait for store implementations.
 pub trait Store: Send + Sync + 'static {
     /// Get a pointer to the most recently processed block in the subgraph.
     fn block_ptr(&self, subgraph_id: SubgraphDeploymentId) -> Result<EthereumBlockPointer, Error>;
 
+    /// Get the indexing status of a subgraph deployment.
+    fn deployment_status(&self, id: &SubgraphDeploymentId) -> Result<DeploymentStatus, Error>;
+
+    /// Checks that the store can be reached, e.g. for liveness and readiness probes.
+    /// Fails quickly instead of waiting for the store to become available.
+    fn check_health(&self) -> Result<(), Error>;
+
     /// Looks up an entity using the given store key.
     fn get(&self, key: EntityKey) -> Result<Option<Entity>, QueryExecutionError>;
 
     /// Queries the store for entities that match the store query.
     fn find(&self, query: EntityQuery) -> Result<Vec<Entity>, QueryExecutionError>;
 
+    /// Like `find`, but also returns whether more entities match the query beyond
+    /// its range. Without a range or `first`, all entities are returned and the flag
+    /// is `false`.
+    fn find_page(&self, query: EntityQuery) -> Result<(Vec<Entity>, bool), QueryExecutionError> {
+        let first = match query.range {
+            Some(EntityRange {
+                first: Some(first), ..
+            }) => first,
+            _ => return self.find(query).map(|entities| (entities, false)),
+        };
+
+        // Fetch one extra entity to tell whether there is a next page
+        let mut query = query;
+        query.range.as_mut().unwrap().first = Some(first + 1);
+        let mut entities = self.find(query)?;
+        let has_next_page = entities.len() > first;
+        entities.truncate(first);
+        Ok((entities, has_next_page))
+    }
+
     /// Queries the store for a single entity matching the store query.
+    ///
+    /// Fails with `QueryExecutionError::TooManyResults` if more than one entity matches.
     fn find_one(&self, query: EntityQuery) -> Result<Option<Entity>, QueryExecutionError>;
 
+    /// Counts the entities that match the filter of the store query. The range and order of
+    /// the query are ignored, so the result is the total number of entities a paginated `find`
+    /// with the same filter can return.
+    fn count_query(&self, query: EntityQuery) -> Result<u64, QueryExecutionError>;
+
+    /// Looks up the block at which an entity was last changed, based on the event source
+    /// that was recorded when the entity was written.
+    ///
+    /// Returns `None` if the entity does not exist, if it was not last changed by a block
+    /// (e.g. because the change was reverted) or if the block is not known to the store.
+    fn change_block(
+        &self,
+        key: EntityKey,
+    ) -> Result<Option<EthereumBlockPointer>, QueryExecutionError>;
+
     /// Updates the block pointer.  Careful: this is only safe to use if it is known that no store
     /// changes are needed to go from `block_ptr_from` to `block_ptr_to`.
     ///
@@ -363,11 +522,36 @@ pub trait Store: Send + Sync + 'static {
         event_source: EventSource,
     ) -> Result<(), StoreError>;
 
+    /// Check whether the specified entity operations could be applied, without
+    /// making any changes to the store.
+    ///
+    /// The operations are executed as if by `apply_entity_operations`, so that
+    /// `AbortUnless` checks run against the state the earlier operations leave
+    /// behind, but all writes are discarded afterwards.
+    fn validate_entity_operations(
+        &self,
+        operations: Vec<EntityOperation>,
+        event_source: EventSource,
+    ) -> Result<(), StoreError>;
+
+    /// Marks a subgraph deployment as failed and records the error that caused the
+    /// failure. Both fields are written in a single transaction.
+    fn fail_subgraph(&self, id: &SubgraphDeploymentId, error: String) -> Result<(), StoreError>;
+
+    /// Removes all entities of a subgraph deployment and their history and moves its block
+    /// pointer back to the genesis block, so that the subgraph is indexed again from scratch.
+    /// All of this happens in a single transaction.
+    ///
+    /// Fails for the subgraph of subgraphs.
+    fn reset_subgraph(&self, id: &SubgraphDeploymentId) -> Result<(), StoreError>;
+
     /// Revert the entity changes from a single block atomically in the store, and update the
     /// subgraph block pointer from `block_ptr_from` to `block_ptr_to`.
     ///
     /// `block_ptr_from` must match the current value of the subgraph block pointer.
     /// `block_ptr_to` must point to the parent block of `block_ptr_from`.
+    ///
+    /// Subscribers receive entity changes for all entities that are reverted.
     fn revert_block_operations(
         &self,
         subgraph_id: SubgraphDeploymentId,
@@ -377,11 +561,60 @@ pub trait Store: Send + Sync + 'static {
 
     /// Subscribe to entity changes for specific subgraphs and entities.
     ///
-    /// Returns a stream of entity changes that match the input arguments.
+    /// Returns a stream of entity changes that match the input arguments. All changes
+    /// made after this returns are delivered, so that reads made afterwards can be
+    /// kept up to date with the stream.
     fn subscribe(&self, entities: Vec<SubgraphEntityPair>) -> EntityChangeStream;
 
+    /// Subscribe to entity changes for several groups of subgraphs and entities at once.
+    ///
+    /// Returns one stream of entity changes per group, in the order of the groups.
+    fn subscribe_many(&self, groups: Vec<Vec<SubgraphEntityPair>>) -> Vec<EntityChangeStream> {
+        groups
+            .into_iter()
+            .map(|entities| self.subscribe(entities))
+            .collect()
+    }
+
+    /// Subscribe to entity changes like `subscribe`, but only receive changes caused by
+    /// one of `operations`. `None` includes changes caused by any operation.
+    fn subscribe_to_operations(
+        &self,
+        entities: Vec<SubgraphEntityPair>,
+        operations: Option<Vec<EntityChangeOperation>>,
+    ) -> EntityChangeStream {
+        let changes = self.subscribe(entities);
+        match operations {
+            Some(operations) => {
+                Box::new(changes.filter(move |change| operations.contains(&change.operation)))
+            }
+            None => changes,
+        }
+    }
+
+    /// Subscribe to entity changes, first replaying recent changes with a sequence number
+    /// greater than `since` that the subscriber missed.
+    ///
+    /// Stores only keep a bounded number of recent changes around, so older changes may
+    /// not be replayed. The default implementation doesn't replay anything.
+    fn subscribe_since(
+        &self,
+        entities: Vec<SubgraphEntityPair>,
+        _since: u64,
+    ) -> EntityChangeStream {
+        self.subscribe(entities)
+    }
+
+    /// Subscribe to entity changes for all entity types of a subgraph.
+    fn subscribe_all(&self, subgraph_id: SubgraphDeploymentId) -> EntityChangeStream {
+        self.subscribe(vec![(subgraph_id, ANY_ENTITY_TYPE.to_owned())])
+    }
+
     /// Counts the total number of entities in a subgraph.
     fn count_entities(&self, subgraph: SubgraphDeploymentId) -> Result<u64, Error>;
+
+    /// Lists the names of all entity types that have at least one entity in a subgraph.
+    fn entity_types(&self, subgraph: SubgraphDeploymentId) -> Result<Vec<String>, Error>;
 }
 
 pub trait SubgraphDeploymentStore: Send + Sync + 'static {
@@ -395,7 +628,31 @@ pub trait SubgraphDeploymentStore: Send + Sync + 'static {
     /// node, as the store will still accept queries.
     fn is_deployed(&self, id: &SubgraphDeploymentId) -> Result<bool, Error>;
 
+    /// Lists the IDs of all subgraph deployments in the store. The subgraph of
+    /// subgraphs is always deployed and therefore not listed.
+    fn deployed_subgraphs(&self) -> Result<Vec<SubgraphDeploymentId>, Error>;
+
     fn subgraph_schema(&self, subgraph_id: SubgraphDeploymentId) -> Result<Schema, Error>;
+
+    /// Returns the schema of the deployment that a subgraph name currently resolves to,
+    /// or `None` if the name does not resolve to a deployment.
+    fn subgraph_schema_by_name(&self, name: SubgraphName) -> Result<Option<Schema>, Error> {
+        match self.resolve_subgraph_name_to_id(name)? {
+            Some(subgraph_id) => self.subgraph_schema(subgraph_id).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the schemas of several subgraph deployments at once. Fails if the schema of
+    /// any of the deployments cannot be found.
+    fn subgraph_schemas(
+        &self,
+        ids: &[SubgraphDeploymentId],
+    ) -> Result<HashMap<SubgraphDeploymentId, Schema>, Error>;
+
+    /// Removes the cached schema of a subgraph deployment, if there is one, so that the next
+    /// call to `subgraph_schema` loads the schema from the deployment's manifest again.
+    fn invalidate_schema_cache(&self, subgraph_id: &SubgraphDeploymentId);
 }
 
 /// Common trait for blockchain store implementations.
@@ -411,6 +668,17 @@ pub trait ChainStore: Send + Sync + 'static {
         B: Stream<Item = EthereumBlock, Error = E> + Send + 'a,
         E: From<Error> + Send + 'a;
 
+    /// Insert blocks into the store, skipping blocks that are already present.
+    ///
+    /// Returns the number of blocks that were inserted.
+    fn insert_new_blocks<'a, B, E>(
+        &self,
+        blocks: B,
+    ) -> Box<Future<Item = usize, Error = E> + Send + 'a>
+    where
+        B: Stream<Item = EthereumBlock, Error = E> + Send + 'a,
+        E: From<Error> + Send + 'a;
+
     /// Try to update the head block pointer to the block with the highest block number.
     ///
     /// Only updates pointer if there is a block with a higher block number than the current head
@@ -427,6 +695,9 @@ pub trait ChainStore: Send + Sync + 'static {
     ///
     /// If the candidate new head block had one or more missing ancestors, returns
     /// `Ok(missing_blocks)`, where `missing_blocks` is a nonexhaustive list of missing blocks.
+    ///
+    /// An `ancestor_count` of zero is an error. Counts larger than the number of blocks that
+    /// can precede the candidate new head block are reduced to that number.
     fn attempt_chain_head_update(&self, ancestor_count: u64) -> Result<Vec<H256>, Error>;
 
     /// Subscribe to chain head updates.
@@ -442,6 +713,11 @@ pub trait ChainStore: Send + Sync + 'static {
     /// Get Some(block) if it is present in the chain store, or None.
     fn block(&self, block_hash: H256) -> Result<Option<EthereumBlock>, Error>;
 
+    /// Get the blocks with the given hashes in a single query. The blocks are returned
+    /// in the order of `block_hashes`; hashes of blocks that are not in the chain store
+    /// are skipped.
+    fn blocks(&self, block_hashes: &[H256]) -> Result<Vec<EthereumBlock>, Error>;
+
     /// Get the `offset`th ancestor of `block_hash`, where offset=0 means the block matching
     /// `block_hash` and offset=1 means its parent. Returns None if unable to complete due to
     /// missing blocks in the chain store.
@@ -452,4 +728,28 @@ pub trait ChainStore: Send + Sync + 'static {
         block_ptr: EthereumBlockPointer,
         offset: u64,
     ) -> Result<Option<EthereumBlock>, Error>;
+
+    /// Checks whether a block is on the main chain, i.e. whether it is the ancestor of the
+    /// current chain head at its block number. Blocks after the chain head are not on the
+    /// main chain (yet).
+    ///
+    /// Returns an error if the chain head is not known or if blocks between the chain head
+    /// and the block are missing from the chain store.
+    fn is_on_main_chain(&self, block_ptr: EthereumBlockPointer) -> Result<bool, Error> {
+        let head_ptr = self
+            .chain_head_ptr()?
+            .ok_or_else(|| format_err!("chain head is not known"))?;
+        if block_ptr.number > head_ptr.number {
+            return Ok(false);
+        }
+
+        self.ancestor_block(head_ptr, head_ptr.number - block_ptr.number)?
+            .map(|block| block.block.hash == Some(block_ptr.hash))
+            .ok_or_else(|| {
+                format_err!(
+                    "blocks between the chain head and block #{} are missing",
+                    block_ptr.number
+                )
+            })
+    }
 }
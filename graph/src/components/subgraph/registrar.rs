@@ -7,11 +7,28 @@ pub trait SubgraphRegistrar: Send + Sync + 'static {
         name: SubgraphName,
     ) -> Box<Future<Item = CreateSubgraphResult, Error = SubgraphRegistrarError> + Send + 'static>;
 
+    /// Deploys a new version of a subgraph.
+    ///
+    /// If the deployment does not exist yet, it starts indexing at `start_block` or, if no
+    /// start block is given, at the genesis block.
     fn create_subgraph_version(
         &self,
         name: SubgraphName,
         hash: SubgraphDeploymentId,
         assignment_node_id: NodeId,
+        start_block: Option<EthereumBlockPointer>,
+    ) -> Box<Future<Item = (), Error = SubgraphRegistrarError> + Send + 'static>;
+
+    /// Like `create_subgraph_version`, except that no new version is created if the
+    /// current version of the subgraph already points at the deployment `hash`.
+    ///
+    /// The deployment and its assignment are still created if they are missing.
+    fn upsert_subgraph_version(
+        &self,
+        name: SubgraphName,
+        hash: SubgraphDeploymentId,
+        assignment_node_id: NodeId,
+        start_block: Option<EthereumBlockPointer>,
     ) -> Box<Future<Item = (), Error = SubgraphRegistrarError> + Send + 'static>;
 
     fn remove_subgraph(
@@ -19,6 +36,13 @@ pub trait SubgraphRegistrar: Send + Sync + 'static {
         name: SubgraphName,
     ) -> Box<Future<Item = (), Error = SubgraphRegistrarError> + Send + 'static>;
 
+    /// Removes the deployment `hash` and its assignment. Fails if any subgraph
+    /// version still references the deployment.
+    fn remove_subgraph_by_hash(
+        &self,
+        hash: SubgraphDeploymentId,
+    ) -> Box<Future<Item = (), Error = SubgraphRegistrarError> + Send + 'static>;
+
     fn list_subgraphs(
         &self,
     ) -> Box<Future<Item = Vec<SubgraphName>, Error = SubgraphRegistrarError> + Send + 'static>;
@@ -44,6 +44,12 @@ pub enum QueryExecutionError {
     AttributeTypeError(String, String),
     EntityParseError(String),
     StoreError(failure::Error),
+    TooManyFields(Pos, usize),
+    QueryTooDeep(Pos, usize),
+    StoreBusy,
+    NotASubscription(Option<String>),
+    UndefinedFragment(Pos, String),
+    TooManyResults(String),
 }
 
 impl Error for QueryExecutionError {
@@ -151,6 +157,25 @@ impl fmt::Display for QueryExecutionError {
             StoreError(e) => {
                 write!(f, "Store error: {}", e)
             }
+            TooManyFields(_, max) => {
+                write!(f, "Selection set contains more than the maximum of {} fields", max)
+            }
+            QueryTooDeep(_, max) => {
+                write!(f, "Query has a depth that exceeds the maximum of {}", max)
+            }
+            StoreBusy => {
+                write!(f, "Store is busy, please try again later")
+            }
+            NotASubscription(Some(name)) => {
+                write!(f, "Operation \"{}\" is not a subscription", name)
+            }
+            NotASubscription(None) => write!(f, "Operation is not a subscription"),
+            UndefinedFragment(_, name) => {
+                write!(f, "Fragment is not defined: {}", name)
+            }
+            TooManyResults(entity_type) => {
+                write!(f, "Expected at most one `{}` entity, but found more", entity_type)
+            }
         }
     }
 }
@@ -223,6 +248,27 @@ impl fmt::Display for QueryError {
     }
 }
 
+/// Splits the message of a `graphql_parser` parse error into the position it reports
+/// (`Parse error at <line>:<column>`) and the remainder of the message.
+///
+/// Returns `None` if the message doesn't have that shape, which is likely to happen
+/// as the graphql_parser team makes improvements to their error reporting.
+fn split_parse_error(msg: &str) -> Option<(Pos, String)> {
+    // Split the inner message into (first line, rest)
+    let inner_msg = msg.replace("query parse error:", "");
+    let mut parts = inner_msg.trim().splitn(2, '\n');
+    let first_line = parts.next()?;
+    let rest = parts.next()?;
+
+    // Find the line and column numbers after the last space of the first line
+    let position = first_line.rsplit(' ').next()?;
+    let mut numbers = position.splitn(2, ':');
+    let line = numbers.next()?.parse().ok()?;
+    let column = numbers.next()?.parse().ok()?;
+
+    Some((Pos { line, column }, rest.trim().to_string()))
+}
+
 impl Serialize for QueryError {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -237,36 +283,19 @@ impl Serialize for QueryError {
             // for users to find where the errors are; this is likely to change as the
             // graphql_parser team makes improvements to their error reporting
             QueryError::ParseError(_) => {
-                // Split the inner message into (first line, rest)
-                let mut msg = format!("{}", self);
-                let inner_msg = msg.replace("query parse error:", "");
-                let inner_msg = inner_msg.trim();
-                let parts: Vec<&str> = inner_msg.splitn(2, '\n').collect();
-
-                // Find the colon in the first line and split there
-                let colon_pos = parts[0].rfind(':').unwrap();
-                let (a, b) = parts[0].split_at(colon_pos);
-
-                // Find the line and column numbers and convert them to u32
-                let line: u32 = a
-                    .matches(char::is_numeric)
-                    .collect::<String>()
-                    .parse()
-                    .unwrap();
-                let column: u32 = b
-                    .matches(char::is_numeric)
-                    .collect::<String>()
-                    .parse()
-                    .unwrap();
-
-                // Generate the list of locations
-                let mut location = HashMap::new();
-                location.insert("line", line);
-                location.insert("column", column);
-                map.serialize_entry("locations", &vec![location])?;
+                let msg = format!("{}", self);
+                match split_parse_error(&msg) {
+                    Some((pos, inner_msg)) => {
+                        let mut location = HashMap::new();
+                        location.insert("line", pos.line);
+                        location.insert("column", pos.column);
+                        map.serialize_entry("locations", &vec![location])?;
 
-                // Only use the remainder after the location as the error message
-                parts[1].to_string()
+                        // Only use the remainder after the location as the error message
+                        inner_msg
+                    }
+                    None => msg,
+                }
             }
 
             // Serialize entity resolution errors using their position
@@ -276,7 +305,10 @@ impl Serialize for QueryError {
             | QueryError::ExecutionError(MissingArgumentError(pos, _))
             | QueryError::ExecutionError(InvalidVariableTypeError(pos, _))
             | QueryError::ExecutionError(InvalidVariableError(pos, _, _))
-            | QueryError::ExecutionError(MissingVariableError(pos, _)) => {
+            | QueryError::ExecutionError(MissingVariableError(pos, _))
+            | QueryError::ExecutionError(TooManyFields(pos, _))
+            | QueryError::ExecutionError(QueryTooDeep(pos, _))
+            | QueryError::ExecutionError(UndefinedFragment(pos, _)) => {
                 let mut location = HashMap::new();
                 location.insert("line", pos.line);
                 location.insert("column", pos.column);
@@ -1,4 +1,5 @@
 use data::graphql::validation::{get_object_type_definitions, validate_schema};
+use data::store::{Entity, Value};
 use data::subgraph::SubgraphDeploymentId;
 use failure::Error;
 use graphql_parser;
@@ -16,6 +17,9 @@ pub struct Schema {
     pub document: schema::Document,
     // Maps an interface name to the list of entities that implement it.
     types_for_interface: BTreeMap<query::Name, Vec<ObjectType>>,
+    // Maps an entity type to the `@default` values of its fields, for the types
+    // that have any.
+    defaults_for_type: BTreeMap<String, Vec<(String, Value)>>,
 }
 
 impl Schema {
@@ -33,10 +37,19 @@ impl Schema {
             }
         }
 
+        let mut defaults_for_type = BTreeMap::new();
+        for object_type in get_object_type_definitions(&document) {
+            let defaults = Self::field_defaults(object_type)?;
+            if !defaults.is_empty() {
+                defaults_for_type.insert(object_type.name.clone(), defaults);
+            }
+        }
+
         let mut schema = Schema {
             id: id.clone(),
             document,
             types_for_interface,
+            defaults_for_type,
         };
         schema.add_subgraph_id_directives(id);
 
@@ -47,6 +60,56 @@ impl Schema {
         self.types_for_interface.get(interface_name)
     }
 
+    /// Returns whether any field of `entity_type` has a `@default(value: ...)` directive.
+    pub fn has_defaults(&self, entity_type: &str) -> bool {
+        self.defaults_for_type.contains_key(entity_type)
+    }
+
+    /// Sets the attributes that `entity` lacks to the values given by `@default(value: ...)`
+    /// directives on the fields of its type `entity_type`.
+    pub fn fill_in_defaults(&self, entity_type: &str, entity: &mut Entity) {
+        let defaults = match self.defaults_for_type.get(entity_type) {
+            Some(defaults) => defaults,
+            None => return,
+        };
+
+        for (field, value) in defaults {
+            if !entity.contains_key(field) {
+                entity.insert(field.clone(), value.clone());
+            }
+        }
+    }
+
+    // Collects the values of the `@default(value: ...)` directives on the fields of `object_type`.
+    fn field_defaults(object_type: &ObjectType) -> Result<Vec<(String, Value)>, Error> {
+        let mut defaults = vec![];
+        for field in object_type.fields.iter() {
+            let default_value = field
+                .directives
+                .iter()
+                .find(|directive| directive.name == "default")
+                .and_then(|directive| {
+                    directive
+                        .arguments
+                        .iter()
+                        .find(|(name, _)| name == "value")
+                        .map(|(_, value)| value)
+                });
+            if let Some(value) = default_value {
+                let value = Value::from_query_value(value, &field.field_type).map_err(|e| {
+                    format_err!(
+                        "Invalid default value for field `{}` of type `{}`: {}",
+                        field.name,
+                        object_type.name,
+                        e
+                    )
+                })?;
+                defaults.push((field.name.clone(), value));
+            }
+        }
+        Ok(defaults)
+    }
+
     // Adds a @subgraphId(id: ...) directive to object/interface/enum types in the schema.
     fn add_subgraph_id_directives(&mut self, id: SubgraphDeploymentId) {
         for definition in self.document.definitions.iter_mut() {
@@ -80,3 +143,48 @@ impl Schema {
         }
     }
 }
+
+#[test]
+fn fill_in_defaults_sets_missing_fields() {
+    let schema = Schema::parse(
+        "type User @entity {
+            id: ID!
+            name: String!
+            status: String! @default(value: \"active\")
+            score: Int! @default(value: 0)
+        }",
+        SubgraphDeploymentId::new("defaults").unwrap(),
+    )
+    .unwrap();
+
+    let mut entity = Entity::from(vec![
+        ("id", Value::from("1")),
+        ("name", Value::from("Alice")),
+        ("score", Value::Int(12)),
+    ]);
+    assert!(schema.has_defaults("User"));
+    schema.fill_in_defaults("User", &mut entity);
+
+    // Missing fields are defaulted, present fields are left alone
+    assert_eq!(
+        entity,
+        Entity::from(vec![
+            ("id", Value::from("1")),
+            ("name", Value::from("Alice")),
+            ("status", Value::from("active")),
+            ("score", Value::Int(12)),
+        ])
+    );
+}
+
+#[test]
+fn invalid_default_values_are_rejected() {
+    let result = Schema::parse(
+        "type User @entity {
+            id: ID!
+            balance: BigInt! @default(value: \"lots\")
+        }",
+        SubgraphDeploymentId::new("defaults").unwrap(),
+    );
+    assert!(result.is_err());
+}
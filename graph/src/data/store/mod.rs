@@ -352,8 +352,12 @@ where
 }
 
 /// An entity is represented as a map of attribute names to values.
+///
+/// Attributes are kept sorted by name, so that iterating over an entity, e.g.
+/// when serializing it or returning it from a GraphQL query, always visits
+/// them in the same order.
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
-pub struct Entity(HashMap<Attribute, Value>);
+pub struct Entity(BTreeMap<Attribute, Value>);
 impl Entity {
     /// Creates a new entity with no attributes set.
     pub fn new() -> Self {
@@ -379,6 +383,10 @@ impl Entity {
     /// If a key exists in both entities, the value from `update` is chosen.
     /// If a key only exists on one entity, the value from that entity is chosen.
     /// If a key is set to `Value::Null` in `update`, the key/value pair is removed.
+    ///
+    /// Attribute values are scalars or lists and never nested objects, so merging
+    /// only happens at the level of attributes; a list in `update` replaces the
+    /// existing list as a whole.
     pub fn merge(&mut self, update: Entity) {
         for (key, value) in update.0.into_iter() {
             match value {
@@ -390,7 +398,7 @@ impl Entity {
 }
 
 impl Deref for Entity {
-    type Target = HashMap<Attribute, Value>;
+    type Target = BTreeMap<Attribute, Value>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -398,7 +406,7 @@ impl Deref for Entity {
 }
 
 impl DerefMut for Entity {
-    fn deref_mut(&mut self) -> &mut HashMap<Attribute, Value> {
+    fn deref_mut(&mut self) -> &mut BTreeMap<Attribute, Value> {
         &mut self.0
     }
 }
@@ -413,15 +421,21 @@ impl Into<query::Value> for Entity {
     }
 }
 
+impl From<BTreeMap<Attribute, Value>> for Entity {
+    fn from(m: BTreeMap<Attribute, Value>) -> Entity {
+        Entity(m)
+    }
+}
+
 impl From<HashMap<Attribute, Value>> for Entity {
     fn from(m: HashMap<Attribute, Value>) -> Entity {
-        Entity(m)
+        Entity(m.into_iter().collect())
     }
 }
 
 impl<'a> From<Vec<(&'a str, Value)>> for Entity {
     fn from(entries: Vec<(&'a str, Value)>) -> Entity {
-        Entity::from(HashMap::from_iter(
+        Entity::from(BTreeMap::from_iter(
             entries.into_iter().map(|(k, v)| (String::from(k), v)),
         ))
     }
@@ -453,3 +467,51 @@ fn value_bigint() {
     );
     assert_eq!(query::Value::from(from_query), graphql_value);
 }
+
+#[test]
+fn entity_serialization_is_deterministic() {
+    use serde_json;
+
+    let attributes: Vec<_> = (0..20)
+        .map(|i| (format!("attr{}", i), Value::Int(i)))
+        .collect();
+    let forward = Entity::from(HashMap::from_iter(attributes.clone()));
+    let backward = Entity::from(HashMap::from_iter(attributes.into_iter().rev()));
+
+    let forward_json = serde_json::to_string(&forward).unwrap();
+    let backward_json = serde_json::to_string(&backward).unwrap();
+    assert_eq!(forward_json, backward_json);
+    assert_eq!(
+        forward_json,
+        serde_json::to_string(&forward.clone()).unwrap()
+    );
+    assert!(forward_json.starts_with(r#"{"attr0":{"type":"Int","data":0},"attr1":"#));
+}
+
+#[test]
+fn entity_merge_replaces_attributes_and_lists() {
+    let mut entity = Entity::from(vec![
+        ("id", Value::from("1")),
+        ("name", Value::from("Alice")),
+        ("age", Value::Int(30)),
+        (
+            "tags",
+            Value::List(vec![Value::from("a"), Value::from("b")]),
+        ),
+    ]);
+
+    entity.merge(Entity::from(vec![
+        ("name", Value::from("Bob")),
+        ("age", Value::Null),
+        ("tags", Value::List(vec![Value::from("c")])),
+    ]));
+
+    assert_eq!(
+        entity,
+        Entity::from(vec![
+            ("id", Value::from("1")),
+            ("name", Value::from("Bob")),
+            ("tags", Value::List(vec![Value::from("c")])),
+        ])
+    );
+}
@@ -1,3 +1,4 @@
+use bigdecimal;
 use hex;
 use num_bigint;
 use serde::{self, Deserialize, Serialize};
@@ -20,6 +21,13 @@ impl BigInt {
         ))
     }
 
+    pub fn from_unsigned_bytes_be(bytes: &[u8]) -> Self {
+        BigInt(num_bigint::BigInt::from_bytes_be(
+            num_bigint::Sign::Plus,
+            bytes,
+        ))
+    }
+
     pub fn from_signed_bytes_le(bytes: &[u8]) -> Self {
         BigInt(num_bigint::BigInt::from_signed_bytes_le(bytes))
     }
@@ -193,6 +201,119 @@ impl Rem for BigInt {
     }
 }
 
+/// An arbitrary precision decimal number, represented as `digits * 10^exp`.
+///
+/// Addition, subtraction and multiplication are exact. Division is carried out
+/// to 100 significant digits; use `round` to bring a result to a fixed scale.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BigDecimal(bigdecimal::BigDecimal);
+
+impl BigDecimal {
+    /// Creates the decimal number `digits * 10^exp`.
+    pub fn new(digits: BigInt, exp: i64) -> Self {
+        BigDecimal(bigdecimal::BigDecimal::new(digits.0, -exp))
+    }
+
+    /// Returns the digits and exponent such that the value is `digits * 10^exp`.
+    pub fn as_bigint_and_exponent(&self) -> (BigInt, i64) {
+        let (digits, scale) = self.0.as_bigint_and_exponent();
+        (BigInt(digits), -scale)
+    }
+
+    /// Rounds the number to `scale` digits after the decimal point, rounding
+    /// halfway cases away from zero.
+    pub fn round(&self, scale: i64) -> BigDecimal {
+        let (digits, current_scale) = self.0.as_bigint_and_exponent();
+        if current_scale <= scale {
+            return BigDecimal(self.0.with_scale(scale));
+        }
+
+        let mut divisor = num_bigint::BigInt::from(1);
+        for _ in 0..(current_scale - scale) {
+            divisor = divisor * 10u32;
+        }
+
+        let mut quotient = &digits / &divisor;
+        let remainder = &digits - &quotient * &divisor;
+        let remainder = if remainder < 0.into() {
+            -remainder
+        } else {
+            remainder
+        };
+        if remainder * 2u32 >= divisor {
+            if digits < 0.into() {
+                quotient = quotient - 1u32;
+            } else {
+                quotient = quotient + 1u32;
+            }
+        }
+
+        BigDecimal(bigdecimal::BigDecimal::new(quotient, scale))
+    }
+
+    /// Divides `self` by `other`, returning `None` if `other` is zero.
+    pub fn checked_div(self, other: BigDecimal) -> Option<BigDecimal> {
+        if other == BigDecimal::from(BigInt::from(0)) {
+            None
+        } else {
+            Some(BigDecimal(self.0.div(other.0)))
+        }
+    }
+}
+
+impl Display for BigDecimal {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        self.0.fmt(f)
+    }
+}
+
+impl From<BigInt> for BigDecimal {
+    fn from(n: BigInt) -> BigDecimal {
+        BigDecimal::new(n, 0)
+    }
+}
+
+impl FromStr for BigDecimal {
+    type Err = <bigdecimal::BigDecimal as FromStr>::Err;
+
+    fn from_str(s: &str) -> Result<BigDecimal, Self::Err> {
+        bigdecimal::BigDecimal::from_str(s).map(BigDecimal)
+    }
+}
+
+impl Add for BigDecimal {
+    type Output = BigDecimal;
+
+    fn add(self, other: BigDecimal) -> BigDecimal {
+        BigDecimal(self.0.add(other.0))
+    }
+}
+
+impl Sub for BigDecimal {
+    type Output = BigDecimal;
+
+    fn sub(self, other: BigDecimal) -> BigDecimal {
+        BigDecimal(self.0.sub(other.0))
+    }
+}
+
+impl Mul for BigDecimal {
+    type Output = BigDecimal;
+
+    fn mul(self, other: BigDecimal) -> BigDecimal {
+        BigDecimal(self.0.mul(other.0))
+    }
+}
+
+impl Div for BigDecimal {
+    type Output = BigDecimal;
+
+    fn div(self, other: BigDecimal) -> BigDecimal {
+        self.checked_div(other)
+            .expect("Cannot divide by zero-valued `BigDecimal`!")
+    }
+}
+
 /// A byte array that's serialized as a hex string prefixed by `0x`.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Bytes(Box<[u8]>);
@@ -243,3 +364,52 @@ impl<'de> Deserialize<'de> for Bytes {
         Bytes::from_str(&hex_string).map_err(D::Error::custom)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn big_decimal_arithmetic_is_exact() {
+        assert_eq!((decimal("0.1") + decimal("0.2")).to_string(), "0.3");
+        assert_eq!((decimal("0.1") - decimal("0.3")).to_string(), "-0.2");
+        assert_eq!((decimal("0.1") * decimal("0.2")).to_string(), "0.02");
+
+        let wei = BigDecimal::from(BigInt::from(1234500000000000000u64));
+        let ether = BigDecimal::from(BigInt::from(1000000000000000000u64));
+        assert_eq!((wei / ether).to_string(), "1.2345");
+    }
+
+    #[test]
+    fn big_decimal_division_rounds_at_fixed_scale() {
+        let one = decimal("1");
+        let two = decimal("2");
+        let three = decimal("3");
+
+        assert_eq!(
+            (one / three.clone()).round(18).to_string(),
+            "0.333333333333333333"
+        );
+        assert_eq!(
+            (two / three.clone()).round(18).to_string(),
+            "0.666666666666666667"
+        );
+        assert_eq!(
+            (decimal("-2") / three).round(18).to_string(),
+            "-0.666666666666666667"
+        );
+        assert_eq!(decimal("2.5").round(0).to_string(), "3");
+        assert_eq!(decimal("1.5").round(3).to_string(), "1.500");
+    }
+
+    #[test]
+    fn big_decimal_digits_and_exponent() {
+        let n = BigDecimal::new(BigInt::from(12345), -4);
+        assert_eq!(n.to_string(), "1.2345");
+        assert_eq!(n.as_bigint_and_exponent(), (BigInt::from(12345), -4));
+    }
+}
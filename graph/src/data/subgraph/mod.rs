@@ -2,6 +2,7 @@ use ethabi::Contract;
 use failure;
 use failure::{Error, SyncFailure};
 use futures::stream;
+use graphql_parser::schema::ParseError as SchemaParseError;
 use parity_wasm;
 use parity_wasm::elements::Module;
 use serde::de;
@@ -15,6 +16,7 @@ use web3::types::Address;
 
 use components::link_resolver::LinkResolver;
 use components::store::StoreError;
+use data::graphql::validation::SchemaValidationError;
 use data::query::QueryExecutionError;
 use data::schema::Schema;
 
@@ -92,44 +94,73 @@ pub struct SubgraphName(String);
 impl SubgraphName {
     pub fn new(s: impl Into<String>) -> Result<Self, ()> {
         let s = s.into();
+        Self::validate(&s).map_err(|_| ())?;
+        Ok(SubgraphName(s))
+    }
 
+    /// Checks whether `s` is a valid subgraph name. If it is not, the error
+    /// describes the rule that the name violates.
+    pub fn validate(s: &str) -> Result<(), String> {
         // Note: these validation rules must be kept consistent with the validation rules
         // implemented in any other components that rely on subgraph names.
 
         // Enforce length limits
-        if s.is_empty() || s.len() > 255 {
-            return Err(());
+        if s.is_empty() {
+            return Err("name must not be empty".to_owned());
+        }
+        if s.len() > 255 {
+            return Err("name must not be longer than 255 characters".to_owned());
         }
 
         // Check that the name contains only allowed characters.
-        if !s
+        if let Some(c) = s
             .chars()
-            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '/')
+            .find(|&c| !(c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '/'))
         {
-            return Err(());
+            return Err(format!(
+                "name contains {:?}, only a-z, A-Z, 0-9, '-', '_' and '/' are allowed",
+                c
+            ));
+        }
+
+        // Names are split into components at '/' when resolving URL paths
+        if s.starts_with('/') || s.ends_with('/') {
+            return Err("name must not start or end with '/'".to_owned());
         }
 
         // Parse into components and validate each
         for part in s.split("/") {
             // Each part must be non-empty and not too long
-            if part.is_empty() || part.len() > 32 {
-                return Err(());
+            if part.is_empty() {
+                return Err("name must not contain empty components".to_owned());
+            }
+            if part.len() > 32 {
+                return Err(format!(
+                    "component {:?} must not be longer than 32 characters",
+                    part
+                ));
             }
 
             // To keep URLs unambiguous, reserve the token "graphql"
             if part == "graphql" {
-                return Err(());
+                return Err("component \"graphql\" is reserved".to_owned());
             }
 
             // Part should not start or end with a special character or start with a number.
             let first_char = part.chars().next().unwrap();
             let last_char = part.chars().last().unwrap();
-            if !first_char.is_ascii_alphabetic() || !last_char.is_ascii_alphanumeric() {
-                return Err(());
+            if !first_char.is_ascii_alphabetic() {
+                return Err(format!("component {:?} must start with a letter", part));
+            }
+            if !last_char.is_ascii_alphanumeric() {
+                return Err(format!(
+                    "component {:?} must end with a letter or a digit",
+                    part
+                ));
             }
         }
 
-        Ok(SubgraphName(s))
+        Ok(())
     }
 }
 
@@ -154,8 +185,13 @@ impl<'de> de::Deserialize<'de> for SubgraphName {
         D: de::Deserializer<'de>,
     {
         let s: String = de::Deserialize::deserialize(deserializer)?;
-        SubgraphName::new(s.clone())
-            .map_err(|()| de::Error::invalid_value(de::Unexpected::Str(&s), &"valid subgraph name"))
+        SubgraphName::validate(&s).map_err(|reason| {
+            de::Error::invalid_value(
+                de::Unexpected::Str(&s),
+                &format!("valid subgraph name ({})", reason).as_str(),
+            )
+        })?;
+        Ok(SubgraphName(s))
     }
 }
 
@@ -188,6 +224,42 @@ fn test_subgraph_name_validation() {
     assert!(SubgraphName::new("this-component-is-longer-than-the-length-limit").is_err());
 }
 
+#[test]
+fn test_subgraph_name_validation_reasons() {
+    // Uppercase letters are allowed
+    assert_eq!(SubgraphName::validate("Org/SubGraph"), Ok(()));
+
+    // Spaces are not
+    assert_eq!(
+        SubgraphName::validate("my subgraph"),
+        Err("name contains ' ', only a-z, A-Z, 0-9, '-', '_' and '/' are allowed".to_owned())
+    );
+
+    // Slashes may only separate non-empty components
+    assert_eq!(SubgraphName::validate("org/team/subgraph"), Ok(()));
+    assert_eq!(
+        SubgraphName::validate("/org/subgraph"),
+        Err("name must not start or end with '/'".to_owned())
+    );
+    assert_eq!(
+        SubgraphName::validate("org/subgraph/"),
+        Err("name must not start or end with '/'".to_owned())
+    );
+    assert_eq!(
+        SubgraphName::validate("org//subgraph"),
+        Err("name must not contain empty components".to_owned())
+    );
+
+    assert_eq!(
+        SubgraphName::validate("org/1subgraph"),
+        Err("component \"1subgraph\" must start with a letter".to_owned())
+    );
+    assert_eq!(
+        SubgraphName::validate("org/graphql"),
+        Err("component \"graphql\" is reserved".to_owned())
+    );
+}
+
 /// Result of a creating a subgraph in the registar.
 #[derive(Serialize)]
 pub struct CreateSubgraphResult {
@@ -201,8 +273,22 @@ pub enum SubgraphRegistrarError {
     ResolveError(SubgraphManifestResolveError),
     #[fail(display = "subgraph already exists: {}", _0)]
     NameExists(String),
+    #[fail(display = "invalid subgraph name: {}", _0)]
+    InvalidName(String),
     #[fail(display = "subgraph name not found: {}", _0)]
     NameNotFound(String),
+    #[fail(display = "subgraph deployment not found: {}", _0)]
+    DeploymentNotFound(String),
+    #[fail(
+        display = "subgraph deployment {} is still referenced by {} subgraph version(s)",
+        _0, _1
+    )]
+    DeploymentInUse(String, usize),
+    #[fail(display = "start block {} is beyond the chain head block {}", _0, _1)]
+    StartBlockBeyondChainHead(u64, u64),
+    /// Occurs when no GraphQL API can be derived from a subgraph's schema.
+    #[fail(display = "GraphQL schema error: {}", _0)]
+    SchemaValidationError(failure::Error),
     #[fail(display = "subgraph registrar internal query error: {}", _0)]
     QueryExecutionError(QueryExecutionError),
     #[fail(display = "subgraph registrar error with store: {}", _0)]
@@ -268,10 +354,27 @@ pub enum SubgraphManifestResolveError {
     NonUtf8,
     #[fail(display = "subgraph is not valid YAML")]
     InvalidFormat,
+    /// The GraphQL schema is syntactically invalid or not compatible with The Graph.
+    #[fail(display = "schema error: {}", _0)]
+    SchemaError(failure::Error),
     #[fail(display = "resolve error: {}", _0)]
     ResolveError(failure::Error),
 }
 
+impl SubgraphManifestResolveError {
+    /// Tells errors in the schema apart from other errors resolving a manifest,
+    /// e.g. failing to fetch one of its files.
+    fn from_resolve_error(e: failure::Error) -> Self {
+        if e.downcast_ref::<SchemaParseError>().is_some()
+            || e.downcast_ref::<SchemaValidationError>().is_some()
+        {
+            SubgraphManifestResolveError::SchemaError(e)
+        } else {
+            SubgraphManifestResolveError::ResolveError(e)
+        }
+    }
+}
+
 impl From<serde_yaml::Error> for SubgraphManifestResolveError {
     fn from(e: serde_yaml::Error) -> Self {
         SubgraphManifestResolveError::ParseError(e)
@@ -505,7 +608,7 @@ impl SubgraphManifest {
             .and_then(move |unresolved| {
                 unresolved
                     .resolve(&*resolver)
-                    .map_err(SubgraphManifestResolveError::ResolveError)
+                    .map_err(SubgraphManifestResolveError::from_resolve_error)
             })
     }
 }
@@ -167,6 +167,8 @@ impl SubgraphDeploymentEntity {
         }
     }
 
+    /// Creates the deployment. The block pointer it is created with is also recorded as the
+    /// block it starts indexing at, so that resetting the deployment can return to it.
     pub fn create_operations(self, id: &SubgraphDeploymentId) -> Vec<EntityOperation> {
         let mut ops = vec![];
 
@@ -195,6 +197,14 @@ impl SubgraphDeploymentEntity {
             self.latest_ethereum_block_number,
         );
         entity.set("totalEthereumBlocksCount", self.total_ethereum_blocks_count);
+        entity.set(
+            "startEthereumBlockHash",
+            format!("{:x}", self.latest_ethereum_block_hash),
+        );
+        entity.set(
+            "startEthereumBlockNumber",
+            self.latest_ethereum_block_number,
+        );
         ops.push(set_entity_operation(Self::TYPENAME, id.to_string(), entity));
 
         ops
@@ -232,6 +242,33 @@ impl SubgraphDeploymentEntity {
         ops
     }
 
+    /// Moves the deployment's block pointer back to `block_ptr` so that it is indexed again
+    /// from there, and marks it as not synced.
+    pub fn reset_operations(
+        id: &SubgraphDeploymentId,
+        block_ptr: EthereumBlockPointer,
+    ) -> Vec<EntityOperation> {
+        let mut ops = vec![];
+
+        ops.push(EntityOperation::AbortUnless {
+            description: "Subgraph deployment entity must exist to be reset".to_owned(),
+            query: Self::query().filter(EntityFilter::And(vec![EntityFilter::Equal(
+                "id".to_owned(),
+                id.to_string().into(),
+            )])),
+            entity_ids: vec![id.to_string()],
+        });
+
+        let mut entity = Entity::new();
+        entity.set("id", id.to_string());
+        entity.set("synced", false);
+        entity.set("latestEthereumBlockHash", block_ptr.hash_hex());
+        entity.set("latestEthereumBlockNumber", block_ptr.number);
+        ops.push(set_entity_operation(Self::TYPENAME, id.to_string(), entity));
+
+        ops
+    }
+
     pub fn update_ethereum_blocks_count_operations(
         id: &SubgraphDeploymentId,
         total_blocks_count: u64,
@@ -278,6 +315,28 @@ impl SubgraphDeploymentEntity {
         ops
     }
 
+    /// Marks the deployment as failed and records the error that caused the failure.
+    pub fn fail_operations(id: &SubgraphDeploymentId, error: String) -> Vec<EntityOperation> {
+        let mut ops = vec![];
+
+        ops.push(EntityOperation::AbortUnless {
+            description: "Subgraph deployment entity must exist to be updated".to_owned(),
+            query: Self::query().filter(EntityFilter::And(vec![EntityFilter::Equal(
+                "id".to_owned(),
+                id.to_string().into(),
+            )])),
+            entity_ids: vec![id.to_string()],
+        });
+
+        let mut entity = Entity::new();
+        entity.set("id", id.to_string());
+        entity.set("failed", true);
+        entity.set("latestError", error);
+        ops.push(set_entity_operation(Self::TYPENAME, id.to_string(), entity));
+
+        ops
+    }
+
     pub fn update_synced_operations(
         id: &SubgraphDeploymentId,
         synced: bool,
@@ -346,6 +405,16 @@ impl SubgraphManifestEntity {
         format!("{}-manifest", subgraph_id)
     }
 
+    /// Returns the ID of the subgraph deployment a manifest entity with the given ID belongs to.
+    pub fn deployment_id(manifest_id: &str) -> Option<SubgraphDeploymentId> {
+        let suffix = "-manifest";
+        if manifest_id.ends_with(suffix) {
+            SubgraphDeploymentId::new(&manifest_id[..manifest_id.len() - suffix.len()]).ok()
+        } else {
+            None
+        }
+    }
+
     fn write_operations(self, id: &str) -> Vec<EntityOperation> {
         let mut ops = vec![];
 
@@ -3,9 +3,16 @@ use prelude::Query;
 /// A GraphQL subscription made by a client.
 ///
 /// At the moment, this only contains the GraphQL query submitted as the
-/// subscription payload.
+/// subscription payload, the name of the operation to execute and whether
+/// the client wants the current result before any entity changes.
 #[derive(Clone, Debug)]
 pub struct Subscription {
     /// The GraphQL subscription query.
     pub query: Query,
+    /// The name of the operation to execute; required if the query defines
+    /// more than one operation.
+    pub operation_name: Option<String>,
+    /// Whether to send the current result of the subscription before the
+    /// results for entity changes.
+    pub send_initial: bool,
 }
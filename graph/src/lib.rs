@@ -1,4 +1,5 @@
 extern crate backtrace;
+extern crate bigdecimal;
 extern crate diesel;
 pub extern crate ethabi;
 extern crate futures;
@@ -67,9 +68,10 @@ pub mod prelude {
     pub use components::server::query::GraphQLServer;
     pub use components::server::subscription::SubscriptionServer;
     pub use components::store::{
-        ChainStore, EntityChange, EntityChangeOperation, EntityChangeStream, EntityFilter,
-        EntityKey, EntityOperation, EntityOrder, EntityQuery, EntityRange, EventSource, Store,
-        StoreError, SubgraphDeploymentStore, TransactionAbortError,
+        ChainStore, DeploymentStatus, EntityChange, EntityChangeOperation, EntityChangeStream,
+        EntityFilter, EntityKey, EntityNullsOrder, EntityOperation, EntityOrder, EntityQuery,
+        EntityRange, EventSource, Store, StoreError, SubgraphDeploymentStore,
+        TransactionAbortError, ANY_ENTITY_TYPE, UPDATED_AT_BLOCK_ATTRIBUTE,
     };
     pub use components::subgraph::{
         RuntimeHost, RuntimeHostBuilder, SubgraphAssignmentProvider, SubgraphInstance,
@@ -80,7 +82,7 @@ pub mod prelude {
     pub use data::graphql::SerializableValue;
     pub use data::query::{Query, QueryError, QueryExecutionError, QueryResult, QueryVariables};
     pub use data::schema::Schema;
-    pub use data::store::scalar::{BigInt, BigIntSign};
+    pub use data::store::scalar::{BigDecimal, BigInt, BigIntSign};
     pub use data::store::{
         AssignmentEvent, Attribute, Entity, NodeId, SubgraphEntityPair, Value, ValueType,
     };
@@ -1,4 +1,5 @@
 use slog::{debug, trace, Logger};
+use std::cmp;
 use std::fmt::Debug;
 use std::marker::PhantomData;
 use std::sync::Arc;
@@ -16,12 +17,13 @@ use tokio_retry::Retry;
 /// 1. Call this function with an operation name (used for logging) and a `Logger`.
 /// 2. Optional: Chain a call to `.when(...)` to set a custom retry condition.
 /// 3. Optional: call `.log_after(...)` or `.no_logging()`.
-/// 4. Call either `.limit(...)` or `.no_limit()`.
-/// 5. Call one of `.timeout_secs(...)`, `.timeout_millis(...)`, `.timeout(...)`, and
+/// 4. Optional: call `.min_delay(...)` and/or `.max_delay(...)` to tune the backoff.
+/// 5. Call either `.limit(...)` or `.no_limit()`.
+/// 6. Call one of `.timeout_secs(...)`, `.timeout_millis(...)`, `.timeout(...)`, and
 ///    `.no_timeout()`.
-/// 6. Call `.run(...)`.
+/// 7. Call `.run(...)`.
 ///
-/// All steps are required, except Step 2 and Step 3.
+/// All steps are required, except Steps 2, 3 and 4.
 ///
 /// Example usage:
 /// ```
@@ -52,6 +54,8 @@ pub fn retry<I, E>(operation_name: impl ToString, logger: &Logger) -> RetryConfi
         condition: RetryIf::Error,
         log_after: 1,
         limit: RetryConfigProperty::Unknown,
+        min_delay: Duration::from_millis(2),
+        max_delay: Duration::from_secs(30),
         phantom_item: PhantomData,
         phantom_error: PhantomData,
     }
@@ -63,6 +67,8 @@ pub struct RetryConfig<I, E> {
     condition: RetryIf<I, E>,
     log_after: u64,
     limit: RetryConfigProperty<usize>,
+    min_delay: Duration,
+    max_delay: Duration,
     phantom_item: PhantomData<I>,
     phantom_error: PhantomData<E>,
 }
@@ -109,6 +115,20 @@ where
         self
     }
 
+    /// Set the delay before the first retry. Later delays double from there.
+    /// Defaults to 2 milliseconds.
+    pub fn min_delay(mut self, min_delay: Duration) -> Self {
+        self.min_delay = min_delay;
+        self
+    }
+
+    /// Set the upper bound for the delay between two attempts.
+    /// Defaults to 30 seconds.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
     /// Set how long (in seconds) to wait for an attempt to complete before giving up on that
     /// attempt.
     pub fn timeout_secs(self, timeout_secs: u64) -> RetryConfigWithTimeout<I, E> {
@@ -156,6 +176,8 @@ where
         let condition = self.inner.condition;
         let log_after = self.inner.log_after;
         let limit_opt = self.inner.limit.unwrap(&operation_name, "limit");
+        let min_delay = self.inner.min_delay;
+        let max_delay = self.inner.max_delay;
         let timeout = self.timeout;
 
         trace!(logger, "Run with retry: {}", operation_name);
@@ -166,6 +188,8 @@ where
             condition,
             log_after,
             limit_opt,
+            min_delay,
+            max_delay,
             move || try_it().timeout(timeout),
         )
     }
@@ -189,6 +213,8 @@ impl<I, E> RetryConfigNoTimeout<I, E> {
         let condition = self.inner.condition;
         let log_after = self.inner.log_after;
         let limit_opt = self.inner.limit.unwrap(&operation_name, "limit");
+        let min_delay = self.inner.min_delay;
+        let max_delay = self.inner.max_delay;
 
         trace!(logger, "Run with retry: {}", operation_name);
 
@@ -198,6 +224,8 @@ impl<I, E> RetryConfigNoTimeout<I, E> {
             condition,
             log_after,
             limit_opt,
+            min_delay,
+            max_delay,
             move || {
                 try_it().map_err(|e| {
                     // No timeout, so all errors are inner errors
@@ -218,6 +246,8 @@ fn run_retry<I, E, F, R>(
     condition: RetryIf<I, E>,
     log_after: u64,
     limit_opt: Option<usize>,
+    min_delay: Duration,
+    max_delay: Duration,
     try_it_with_timeout: F,
 ) -> impl Future<Item = I, Error = timeout::Error<E>> + Send
 where
@@ -229,7 +259,7 @@ where
     let condition = Arc::new(condition);
 
     let mut attempt_count = 0;
-    Retry::spawn(retry_strategy(limit_opt), move || {
+    Retry::spawn(retry_strategy(limit_opt, min_delay, max_delay), move || {
         let operation_name = operation_name.clone();
         let logger = logger.clone();
         let condition = condition.clone();
@@ -301,11 +331,16 @@ where
     })
 }
 
-fn retry_strategy(limit_opt: Option<usize>) -> Box<Iterator<Item = Duration> + Send> {
-    // Exponential backoff, but with a maximum
-    let max_delay_ms = 30_000;
+fn retry_strategy(
+    limit_opt: Option<usize>,
+    min_delay: Duration,
+    max_delay: Duration,
+) -> Box<Iterator<Item = Duration> + Send> {
+    // Exponential backoff (doubling, starting at `min_delay`), but with a maximum
+    let min_delay_ms = min_delay.as_secs() * 1000 + u64::from(min_delay.subsec_millis());
     let backoff = ExponentialBackoff::from_millis(2)
-        .max_delay(Duration::from_millis(max_delay_ms))
+        .factor(cmp::max(min_delay_ms / 2, 1))
+        .max_delay(max_delay)
         .map(jitter);
 
     // Apply limit (maximum retry count)
@@ -484,4 +519,17 @@ mod tests {
         }));
         assert_eq!(result, Ok(10));
     }
+
+    #[test]
+    fn retry_strategy_respects_delay_bounds() {
+        let delays: Vec<_> =
+            retry_strategy(Some(7), Duration::from_secs(1), Duration::from_secs(10)).collect();
+
+        // Delays are jittered, so only their upper bounds are known
+        let bounds = [1, 2, 4, 8, 10, 10];
+        assert_eq!(delays.len(), bounds.len());
+        for (delay, bound) in delays.iter().zip(bounds.iter()) {
+            assert!(*delay <= Duration::from_secs(*bound));
+        }
+    }
 }
@@ -36,6 +36,8 @@ where
     pub introspecting: bool,
     /// Variable values.
     pub variable_values: Arc<HashMap<q::Name, q::Value>>,
+    /// The maximum number of fields a selection set may expand to.
+    pub max_selection_fields: Option<usize>,
 }
 
 impl<'a, R1, R2> ExecutionContext<'a, R1, R2>
@@ -88,7 +90,7 @@ where
     let mut result_map: BTreeMap<String, q::Value> = BTreeMap::new();
 
     // Group fields with the same response key, so we can execute them together
-    let grouped_field_set = collect_fields(ctx.clone(), object_type, selection_set, None);
+    let grouped_field_set = collect_fields(ctx.clone(), object_type, selection_set, None)?;
 
     // Process all field groups in order
     for (response_key, fields) in grouped_field_set {
@@ -141,7 +143,7 @@ pub fn collect_fields<'a, R1, R2>(
     object_type: &s::ObjectType,
     selection_set: &'a q::SelectionSet,
     visited_fragments: Option<HashSet<&'a q::Name>>,
-) -> IndexMap<&'a String, Vec<&'a q::Field>>
+) -> Result<IndexMap<&'a String, Vec<&'a q::Field>>, QueryExecutionError>
 where
     R1: Resolver,
     R2: Resolver,
@@ -209,6 +211,8 @@ where
                             });
 
                     if let Some(grouped_field_set) = fragment_grouped_field_set {
+                        let grouped_field_set = grouped_field_set?;
+
                         // Add all items from each fragments group to the field group
                         // with the corresponding response key
                         for (response_key, mut fragment_group) in grouped_field_set {
@@ -226,7 +230,19 @@ where
         };
     }
 
-    grouped_fields
+    // Reject selection sets that expand to too many fields; every alias and
+    // every field pulled in through a fragment counts
+    if let Some(max_selection_fields) = ctx.max_selection_fields {
+        let field_count: usize = grouped_fields.values().map(|group| group.len()).sum();
+        if field_count > max_selection_fields {
+            return Err(QueryExecutionError::TooManyFields(
+                selection_set.span.0,
+                max_selection_fields,
+            ));
+        }
+    }
+
+    Ok(grouped_fields)
 }
 
 /// Determines whether a fragment is applicable to the given object type.
@@ -1,7 +1,7 @@
 use graphql_parser::{query as q, schema as s};
 use std::collections::HashMap;
 
-use graph::prelude::{EntityChangeStream, QueryExecutionError};
+use graph::prelude::{EntityChangeStream, QueryExecutionError, QueryResult};
 use prelude::*;
 
 /// A GraphQL resolver that can resolve entities, enum values, scalar types and interfaces/unions.
@@ -112,4 +112,22 @@ pub trait Resolver: Clone + Send + Sync {
             "Resolving field streams is not supported by this resolver",
         )))
     }
+
+    // Resolves a change stream for a given field together with the current result
+    // of the subscription, which `snapshot` produces. The stream is set up before the
+    // snapshot is taken, so that no change made in between is missed; changes made
+    // while the snapshot is taken may show up in both.
+    fn resolve_field_stream_with_snapshot<'a, 'b, F>(
+        &self,
+        schema: &'a s::Document,
+        object_type: &'a s::ObjectType,
+        field: &'b q::Field,
+        snapshot: F,
+    ) -> Result<(QueryResult, EntityChangeStream), QueryExecutionError>
+    where
+        F: FnOnce() -> QueryResult,
+    {
+        let stream = self.resolve_field_stream(schema, object_type, field)?;
+        Ok((snapshot(), stream))
+    }
 }
@@ -36,6 +36,9 @@ pub mod prelude {
     pub use super::query::{execute_query, QueryExecutionOptions};
     pub use super::schema::{api_schema, APISchemaError};
     pub use super::store::{build_query, StoreResolver};
-    pub use super::subscription::{execute_subscription, SubscriptionExecutionOptions};
+    pub use super::subscription::{
+        execute_subscription, execute_subscription_with_shared_resolver,
+        SubscriptionExecutionOptions,
+    };
     pub use super::values::{object_value, MaybeCoercible};
 }
@@ -1,5 +1,5 @@
 use graphql_parser::query::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use graph::prelude::QueryExecutionError;
 
@@ -125,6 +125,52 @@ pub fn get_fragment<'a>(document: &'a Document, name: &Name) -> Option<&'a Fragm
         .find(|fd| &fd.name == name)
 }
 
+/// Returns the first fragment spread in the selection set (or in the fragments
+/// it references) whose fragment is not defined in the document.
+pub fn find_undefined_fragment_spread<'a>(
+    document: &'a Document,
+    selection_set: &'a SelectionSet,
+) -> Option<&'a FragmentSpread> {
+    find_undefined_fragment_spread_in(document, selection_set, &mut HashSet::new())
+}
+
+fn find_undefined_fragment_spread_in<'a>(
+    document: &'a Document,
+    selection_set: &'a SelectionSet,
+    visited_fragments: &mut HashSet<&'a Name>,
+) -> Option<&'a FragmentSpread> {
+    for selection in selection_set.items.iter() {
+        let undefined = match selection {
+            Selection::Field(field) => {
+                find_undefined_fragment_spread_in(document, &field.selection_set, visited_fragments)
+            }
+            Selection::InlineFragment(fragment) => find_undefined_fragment_spread_in(
+                document,
+                &fragment.selection_set,
+                visited_fragments,
+            ),
+            Selection::FragmentSpread(spread) => {
+                match get_fragment(document, &spread.fragment_name) {
+                    None => Some(spread),
+                    // Fragments that were checked already don't need to be checked again;
+                    // this also prevents cycles between fragments from recursing forever
+                    Some(_) if !visited_fragments.insert(&spread.fragment_name) => None,
+                    Some(fragment) => find_undefined_fragment_spread_in(
+                        document,
+                        &fragment.selection_set,
+                        visited_fragments,
+                    ),
+                }
+            }
+        };
+
+        if undefined.is_some() {
+            return undefined;
+        }
+    }
+    None
+}
+
 /// Returns the variable definitions for an operation.
 pub fn get_variable_definitions(
     operation: &OperationDefinition,
@@ -136,3 +182,160 @@ pub fn get_variable_definitions(
         OperationDefinition::SelectionSet(_) => None,
     }
 }
+
+/// Checks that the selection sets of an operation are nested no deeper than `max_depth`.
+///
+/// Fields of the top-level selection set are at depth 1. Fragment spreads and inline
+/// fragments don't add to the depth, but the fields they contain do.
+pub fn check_query_depth(
+    document: &Document,
+    operation: &OperationDefinition,
+    max_depth: usize,
+) -> Result<(), QueryExecutionError> {
+    let selection_set = match operation {
+        OperationDefinition::Query(q) => &q.selection_set,
+        OperationDefinition::Subscription(s) => &s.selection_set,
+        OperationDefinition::Mutation(m) => &m.selection_set,
+        OperationDefinition::SelectionSet(s) => s,
+    };
+    check_selection_set_depth(document, selection_set, 1, max_depth, &mut vec![])
+}
+
+/// Checks that no selection set of an operation expands to more than `max_fields` fields.
+///
+/// Fields pulled in through fragments count towards the selection set that spreads
+/// them, whatever their type condition, so this may reject selection sets that
+/// execution would accept.
+pub fn check_selection_fields(
+    document: &Document,
+    operation: &OperationDefinition,
+    max_fields: usize,
+) -> Result<(), QueryExecutionError> {
+    let selection_set = match operation {
+        OperationDefinition::Query(q) => &q.selection_set,
+        OperationDefinition::Subscription(s) => &s.selection_set,
+        OperationDefinition::Mutation(m) => &m.selection_set,
+        OperationDefinition::SelectionSet(s) => s,
+    };
+    check_selection_set_fields(document, selection_set, max_fields)
+}
+
+fn check_selection_set_fields(
+    document: &Document,
+    selection_set: &SelectionSet,
+    max_fields: usize,
+) -> Result<(), QueryExecutionError> {
+    let mut fields = vec![];
+    expand_selection_set_fields(document, selection_set, &mut vec![], &mut fields);
+
+    if fields.len() > max_fields {
+        return Err(QueryExecutionError::TooManyFields(
+            selection_set.span.0,
+            max_fields,
+        ));
+    }
+
+    for field in fields {
+        if !field.selection_set.items.is_empty() {
+            check_selection_set_fields(document, &field.selection_set, max_fields)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Collects the fields of a selection set, including those of its fragments.
+fn expand_selection_set_fields<'a>(
+    document: &'a Document,
+    selection_set: &'a SelectionSet,
+    visited_fragments: &mut Vec<&'a Name>,
+    fields: &mut Vec<&'a Field>,
+) {
+    for selection in selection_set.items.iter() {
+        match selection {
+            Selection::Field(field) => fields.push(field),
+            Selection::InlineFragment(fragment) => expand_selection_set_fields(
+                document,
+                &fragment.selection_set,
+                visited_fragments,
+                fields,
+            ),
+            Selection::FragmentSpread(spread) => {
+                // Skip unknown fragments and fragments that spread themselves
+                // (directly or indirectly); execution reports those
+                if visited_fragments.contains(&&spread.fragment_name) {
+                    continue;
+                }
+                if let Some(fragment) = get_fragment(document, &spread.fragment_name) {
+                    visited_fragments.push(&fragment.name);
+                    expand_selection_set_fields(
+                        document,
+                        &fragment.selection_set,
+                        visited_fragments,
+                        fields,
+                    );
+                    visited_fragments.pop();
+                }
+            }
+        }
+    }
+}
+
+fn check_selection_set_depth<'a>(
+    document: &'a Document,
+    selection_set: &'a SelectionSet,
+    depth: usize,
+    max_depth: usize,
+    visited_fragments: &mut Vec<&'a Name>,
+) -> Result<(), QueryExecutionError> {
+    if selection_set.items.is_empty() {
+        return Ok(());
+    }
+
+    if depth > max_depth {
+        return Err(QueryExecutionError::QueryTooDeep(
+            selection_set.span.0,
+            max_depth,
+        ));
+    }
+
+    for selection in selection_set.items.iter() {
+        match selection {
+            Selection::Field(field) => check_selection_set_depth(
+                document,
+                &field.selection_set,
+                depth + 1,
+                max_depth,
+                visited_fragments,
+            )?,
+            Selection::InlineFragment(fragment) => check_selection_set_depth(
+                document,
+                &fragment.selection_set,
+                depth,
+                max_depth,
+                visited_fragments,
+            )?,
+            Selection::FragmentSpread(spread) => {
+                // Skip unknown fragments and fragments that spread themselves
+                // (directly or indirectly); execution reports those
+                if visited_fragments.contains(&&spread.fragment_name) {
+                    continue;
+                }
+                if let Some(fragment) = get_fragment(document, &spread.fragment_name) {
+                    visited_fragments.push(&fragment.name);
+                    let result = check_selection_set_depth(
+                        document,
+                        &fragment.selection_set,
+                        depth,
+                        max_depth,
+                        visited_fragments,
+                    );
+                    visited_fragments.pop();
+                    result?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
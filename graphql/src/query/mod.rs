@@ -18,6 +18,10 @@ where
     pub logger: Logger,
     /// The resolver to use.
     pub resolver: R,
+    /// The maximum number of fields a selection set may expand to, if any.
+    pub max_selection_fields: Option<usize>,
+    /// The maximum depth to which selection sets may be nested.
+    pub max_depth: usize,
 }
 
 /// Executes a query and returns a result.
@@ -33,6 +37,11 @@ where
         Err(e) => return QueryResult::from(e),
     };
 
+    // Reject overly nested queries before doing any work for them
+    if let Err(e) = qast::check_query_depth(&query.document, operation, options.max_depth) {
+        return QueryResult::from(e);
+    }
+
     // Parse variable values
     let coerced_variable_values =
         match coerce_variable_values(&query.schema, operation, &query.variables) {
@@ -55,6 +64,7 @@ where
         document: &query.document,
         fields: vec![],
         variable_values: Arc::new(coerced_variable_values),
+        max_selection_fields: options.max_selection_fields,
     };
 
     let result = match *operation {
@@ -7,8 +7,8 @@ use schema::ast;
 
 #[derive(Fail, Debug)]
 pub enum APISchemaError {
-    #[fail(display = "type {} already exists in the input schema", _0)]
-    TypeExists(String),
+    #[fail(display = "type {} already exists in the input schema at {}", _0, _1)]
+    TypeExists(String, Pos),
     #[fail(display = "Type {} not found", _0)]
     TypeNotFound(String),
 }
@@ -28,28 +28,42 @@ pub fn api_schema(input_schema: &Document) -> Result<Document, APISchemaError> {
     add_order_direction_enum(&mut schema);
     add_types_for_object_types(&mut schema, &object_types)?;
     add_types_for_interface_types(&mut schema, &interface_types)?;
+    add_change_block_fields(&mut schema)?;
     add_query_type(&mut schema, &object_types, &interface_types)?;
     add_subscription_type(&mut schema, &object_types, &interface_types)?;
 
     Ok(schema)
 }
 
+/// Returns the error for a type of the input schema that clashes with a type the API
+/// schema adds, pointing at where the type is defined.
+fn type_exists(existing_type: &TypeDefinition) -> APISchemaError {
+    let (name, position) = match existing_type {
+        TypeDefinition::Scalar(t) => (&t.name, t.position),
+        TypeDefinition::Object(t) => (&t.name, t.position),
+        TypeDefinition::Interface(t) => (&t.name, t.position),
+        TypeDefinition::Union(t) => (&t.name, t.position),
+        TypeDefinition::Enum(t) => (&t.name, t.position),
+        TypeDefinition::InputObject(t) => (&t.name, t.position),
+    };
+    APISchemaError::TypeExists(name.to_owned(), position)
+}
+
 /// Adds built-in GraphQL scalar types (`Int`, `String` etc.) to the schema.
 fn add_builtin_scalar_types(schema: &mut Document) -> Result<(), APISchemaError> {
     for name in ["Boolean", "ID", "Int", "Float", "String", "Bytes", "BigInt"].into_iter() {
-        match ast::get_named_type(schema, &name.to_string()) {
-            None => {
-                let typedef = TypeDefinition::Scalar(ScalarType {
-                    position: Pos::default(),
-                    description: None,
-                    name: name.to_string(),
-                    directives: vec![],
-                });
-                let def = Definition::TypeDefinition(typedef);
-                schema.definitions.push(def);
-            }
-            Some(_) => return Err(APISchemaError::TypeExists(name.to_string())),
+        if let Some(existing_type) = ast::get_named_type(schema, &name.to_string()) {
+            return Err(type_exists(existing_type));
         }
+
+        let typedef = TypeDefinition::Scalar(ScalarType {
+            position: Pos::default(),
+            description: None,
+            name: name.to_string(),
+            directives: vec![],
+        });
+        let def = Definition::TypeDefinition(typedef);
+        schema.definitions.push(def);
     }
     Ok(())
 }
@@ -107,29 +121,28 @@ fn add_order_by_type(
 ) -> Result<(), APISchemaError> {
     let type_name = format!("{}_orderBy", type_name).to_string();
 
-    match ast::get_named_type(schema, &type_name) {
-        None => {
-            let typedef = TypeDefinition::Enum(EnumType {
+    if let Some(existing_type) = ast::get_named_type(schema, &type_name) {
+        return Err(type_exists(existing_type));
+    }
+
+    let typedef = TypeDefinition::Enum(EnumType {
+        position: Pos::default(),
+        description: None,
+        name: type_name,
+        directives: vec![],
+        values: fields
+            .iter()
+            .map(|field| &field.name)
+            .map(|name| EnumValue {
                 position: Pos::default(),
                 description: None,
-                name: type_name,
+                name: name.to_owned(),
                 directives: vec![],
-                values: fields
-                    .iter()
-                    .map(|field| &field.name)
-                    .map(|name| EnumValue {
-                        position: Pos::default(),
-                        description: None,
-                        name: name.to_owned(),
-                        directives: vec![],
-                    })
-                    .collect(),
-            });
-            let def = Definition::TypeDefinition(typedef);
-            schema.definitions.push(def);
-        }
-        Some(_) => return Err(APISchemaError::TypeExists(type_name)),
-    }
+            })
+            .collect(),
+    });
+    let def = Definition::TypeDefinition(typedef);
+    schema.definitions.push(def);
     Ok(())
 }
 
@@ -141,21 +154,20 @@ fn add_filter_type(
 ) -> Result<(), APISchemaError> {
     let filter_type_name = format!("{}_filter", type_name).to_string();
 
-    match ast::get_named_type(schema, &filter_type_name) {
-        None => {
-            let typedef = TypeDefinition::InputObject(InputObjectType {
-                position: Pos::default(),
-                description: None,
-                name: filter_type_name,
-                directives: vec![],
-                fields: field_input_values(schema, fields)?,
-            });
-            let def = Definition::TypeDefinition(typedef);
-            schema.definitions.push(def);
-        }
-        Some(_) => return Err(APISchemaError::TypeExists(filter_type_name)),
+    if let Some(existing_type) = ast::get_named_type(schema, &filter_type_name) {
+        return Err(type_exists(existing_type));
     }
 
+    let typedef = TypeDefinition::InputObject(InputObjectType {
+        position: Pos::default(),
+        description: None,
+        name: filter_type_name,
+        directives: vec![],
+        fields: field_input_values(schema, fields)?,
+    });
+    let def = Definition::TypeDefinition(typedef);
+    schema.definitions.push(def);
+
     Ok(())
 }
 
@@ -288,6 +300,60 @@ fn input_value(name: &Name, suffix: &'static str, value_type: Type) -> InputValu
     }
 }
 
+/// Adds a `_Block_` type and a `_changeBlock` field of that type to all object types.
+///
+/// The `_changeBlock` field is computed when queried and returns the number and hash
+/// of the block at which the entity was last changed.
+fn add_change_block_fields(schema: &mut Document) -> Result<(), APISchemaError> {
+    let type_name = String::from("_Block_");
+
+    if let Some(existing_type) = ast::get_named_type(schema, &type_name) {
+        return Err(type_exists(existing_type));
+    }
+
+    for definition in schema.definitions.iter_mut() {
+        if let Definition::TypeDefinition(TypeDefinition::Object(object_type)) = definition {
+            object_type.fields.push(Field {
+                position: Pos::default(),
+                description: None,
+                name: "_changeBlock".to_string(),
+                arguments: vec![],
+                field_type: Type::NamedType(type_name.clone()),
+                directives: vec![],
+            });
+        }
+    }
+
+    let typedef = TypeDefinition::Object(ObjectType {
+        position: Pos::default(),
+        description: None,
+        name: type_name,
+        implements_interfaces: vec![],
+        directives: vec![],
+        fields: vec![
+            Field {
+                position: Pos::default(),
+                description: None,
+                name: "number".to_string(),
+                arguments: vec![],
+                field_type: Type::NonNullType(Box::new(Type::NamedType("BigInt".to_string()))),
+                directives: vec![],
+            },
+            Field {
+                position: Pos::default(),
+                description: None,
+                name: "hash".to_string(),
+                arguments: vec![],
+                field_type: Type::NonNullType(Box::new(Type::NamedType("Bytes".to_string()))),
+                directives: vec![],
+            },
+        ],
+    });
+    let def = Definition::TypeDefinition(typedef);
+    schema.definitions.push(def);
+    Ok(())
+}
+
 /// Adds a root `Query` object type to the schema.
 fn add_query_type(
     schema: &mut Document,
@@ -296,8 +362,8 @@ fn add_query_type(
 ) -> Result<(), APISchemaError> {
     let type_name = String::from("Query");
 
-    if ast::get_named_type(schema, &type_name).is_some() {
-        return Err(APISchemaError::TypeExists(type_name));
+    if let Some(existing_type) = ast::get_named_type(schema, &type_name) {
+        return Err(type_exists(existing_type));
     }
 
     let typedef = TypeDefinition::Object(ObjectType {
@@ -326,8 +392,8 @@ fn add_subscription_type(
 ) -> Result<(), APISchemaError> {
     let type_name = String::from("Subscription");
 
-    if ast::get_named_type(schema, &type_name).is_some() {
-        return Err(APISchemaError::TypeExists(type_name));
+    if let Some(existing_type) = ast::get_named_type(schema, &type_name) {
+        return Err(type_exists(existing_type));
     }
 
     let typedef = TypeDefinition::Object(ObjectType {
@@ -424,6 +490,18 @@ mod tests {
             .expect("String type is missing in API schema");
     }
 
+    #[test]
+    fn api_schema_errors_point_at_clashing_types() {
+        let input_schema = parse_schema("type User { id: ID! }\n\ntype Query { user: User }")
+            .expect("Failed to parse input schema");
+        let error = api_schema(&input_schema).expect_err("Query type should clash");
+
+        assert_eq!(
+            error.to_string(),
+            "type Query already exists in the input schema at 3:1"
+        );
+    }
+
     #[test]
     fn api_schema_contains_order_direction_enum() {
         let input_schema = parse_schema("type User { id: ID!, name: String! }")
@@ -451,6 +529,28 @@ mod tests {
             .expect("Root Query type is missing in API schema");
     }
 
+    #[test]
+    fn api_schema_contains_change_block_field() {
+        let input_schema =
+            parse_schema("type User { id: ID! }").expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        ast::get_named_type(&schema, &"_Block_".to_string())
+            .expect("_Block_ type is missing in API schema");
+
+        let user_type = match ast::get_named_type(&schema, &"User".to_string()) {
+            Some(TypeDefinition::Object(t)) => Some(t),
+            _ => None,
+        }
+        .expect("User type is missing in API schema");
+        let change_block = ast::get_field_type(user_type, &"_changeBlock".to_string())
+            .expect("_changeBlock field is missing on User type");
+        assert_eq!(
+            change_block.field_type,
+            Type::NamedType("_Block_".to_string())
+        );
+    }
+
     #[test]
     fn api_schema_contains_field_order_by_enum() {
         let input_schema = parse_schema("type User { id: ID!, name: String! }")
@@ -248,6 +248,56 @@ pub fn get_type_definition_from_type<'a>(
     }
 }
 
+/// Returns the object type that a field of an object type refers to, provided
+/// the field references a single entity rather than a list of entities.
+pub fn get_referenced_object_type<'a>(
+    schema: &'a Document,
+    object_type_name: &Name,
+    field_name: &Name,
+) -> Option<&'a ObjectType> {
+    let object_type = match get_named_type(schema, object_type_name) {
+        Some(TypeDefinition::Object(t)) => t,
+        _ => return None,
+    };
+    let field = get_field_type(object_type, field_name)?;
+
+    let is_list = match &field.field_type {
+        Type::ListType(_) => true,
+        Type::NonNullType(inner) => match inner.as_ref() {
+            Type::ListType(_) => true,
+            _ => false,
+        },
+        Type::NamedType(_) => false,
+    };
+    if is_list {
+        return None;
+    }
+
+    match get_type_definition_from_field_type(schema, field) {
+        Some(TypeDefinition::Object(t)) => Some(t),
+        _ => None,
+    }
+}
+
+/// Returns the enum type of a field of an object type, if the field's type is an enum
+/// or a list of enum values.
+pub fn get_field_enum_type<'a>(
+    schema: &'a Document,
+    object_type_name: &Name,
+    field_name: &Name,
+) -> Option<&'a EnumType> {
+    let object_type = match get_named_type(schema, object_type_name) {
+        Some(TypeDefinition::Object(t)) => t,
+        _ => return None,
+    };
+    let field = get_field_type(object_type, field_name)?;
+
+    match get_type_definition_from_field_type(schema, field) {
+        Some(TypeDefinition::Enum(t)) => Some(t),
+        _ => None,
+    }
+}
+
 /// Looks up a directive in a object type, if it is provided.
 pub fn get_object_type_directive(object_type: &ObjectType, name: Name) -> Option<&Directive> {
     object_type
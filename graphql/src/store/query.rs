@@ -16,6 +16,8 @@ pub fn build_query(
         filter: build_filter(entity, arguments)?,
         order_by: build_order_by(entity, arguments)?,
         order_direction: build_order_direction(arguments)?,
+        order_nulls: None,
+        as_of_block: None,
     })
 }
 
@@ -66,12 +68,12 @@ fn build_range(
         return Err(QueryExecutionError::RangeArgumentsError(errors));
     }
 
-    Ok(match (first.unwrap(), skip.unwrap()) {
-        (None, None) => None,
-        (Some(first), None) => Some(EntityRange { first, skip: 0 }),
-        (Some(first), Some(skip)) => Some(EntityRange { first, skip }),
-        (None, Some(skip)) => Some(EntityRange { first: 100, skip }),
-    })
+    // Queries without `first` or `skip` still get a range, so that the store can apply
+    // its limit for unbounded queries to them; the store also caps `first` at that limit
+    Ok(Some(EntityRange {
+        first: first.unwrap(),
+        skip: skip.unwrap().unwrap_or(0),
+    }))
 }
 
 /// Parses GraphQL arguments into a EntityFilter, if present.
@@ -521,17 +523,20 @@ mod tests {
     }
 
     #[test]
-    fn build_query_yields_no_range_if_none_is_present() {
+    fn build_query_yields_unbounded_range_if_none_is_present() {
         assert_eq!(
             build_query(&default_object(), &HashMap::new())
                 .unwrap()
                 .range,
-            None,
+            Some(EntityRange {
+                first: None,
+                skip: 0,
+            }),
         );
     }
 
     #[test]
-    fn build_query_yields_default_first_if_only_skip_is_present() {
+    fn build_query_yields_unbounded_range_if_only_skip_is_present() {
         assert_eq!(
             build_query(
                 &default_object(),
@@ -542,7 +547,7 @@ mod tests {
             .unwrap()
             .range,
             Some(EntityRange {
-                first: 100,
+                first: None,
                 skip: 50,
             }),
         );
@@ -559,7 +564,10 @@ mod tests {
             )
             .unwrap()
             .range,
-            Some(EntityRange { first: 70, skip: 0 }),
+            Some(EntityRange {
+                first: Some(70),
+                skip: 0
+            }),
         );
     }
 
@@ -5,6 +5,7 @@ use std::result;
 use std::sync::Arc;
 
 use graph::components::store::*;
+use graph::data::store::scalar::Bytes;
 use graph::data::subgraph::schema::SubgraphDeploymentEntity;
 use graph::prelude::*;
 
@@ -198,10 +199,15 @@ where
         mut entity: Entity,
         object_type: &s::ObjectType,
     ) -> Result<Entity, QueryExecutionError> {
+        let subgraph_id = parse_subgraph_id(object_type)?;
+
+        // Remember where the entity comes from, so that `_changeBlock` can be
+        // looked up if it is queried
+        entity.set("__typename", object_type.name.clone());
+        entity.set("__subgraph", subgraph_id.to_string());
+
         let subgraph_deployment_entity_pair = SubgraphDeploymentEntity::subgraph_entity_pair();
-        if (parse_subgraph_id(object_type)?, object_type.name.clone())
-            == subgraph_deployment_entity_pair
-        {
+        if (subgraph_id, object_type.name.clone()) == subgraph_deployment_entity_pair {
             let id = entity
                 .id()
                 .expect("subgraph deployment entity should have ID");
@@ -216,6 +222,51 @@ where
         }
         Ok(entity)
     }
+
+    /// Resolves the `_changeBlock` field of an entity, i.e. the block at which the
+    /// entity was last changed.
+    fn resolve_change_block(
+        &self,
+        parent: &Option<q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        let parent_object = match parent {
+            Some(q::Value::Object(parent_object)) => parent_object,
+            _ => return Ok(q::Value::Null),
+        };
+        let attribute = |name: &str| match parent_object.get(name) {
+            Some(q::Value::String(s)) => Some(s.to_owned()),
+            _ => None,
+        };
+
+        let (subgraph_id, entity_type, entity_id) = match (
+            attribute("__subgraph"),
+            attribute("__typename"),
+            attribute("id"),
+        ) {
+            (Some(subgraph_id), Some(entity_type), Some(entity_id)) => {
+                (subgraph_id, entity_type, entity_id)
+            }
+            _ => return Ok(q::Value::Null),
+        };
+        let subgraph_id = SubgraphDeploymentId::new(subgraph_id)
+            .map_err(|()| QueryExecutionError::SubgraphDeploymentIdError(entity_type.clone()))?;
+
+        Ok(
+            match self.store.change_block(EntityKey {
+                subgraph_id,
+                entity_type,
+                entity_id,
+            })? {
+                Some(block_ptr) => {
+                    let mut block = Entity::new();
+                    block.set("number", block_ptr.number);
+                    block.set("hash", Value::Bytes(Bytes::from(block_ptr.hash.as_ref())));
+                    block.into()
+                }
+                None => q::Value::Null,
+            },
+        )
+    }
 }
 
 impl<S> Resolver for StoreResolver<S>
@@ -265,6 +316,10 @@ where
         object_type: &s::ObjectType,
         arguments: &HashMap<&q::Name, q::Value>,
     ) -> Result<q::Value, QueryExecutionError> {
+        if object_type.name == "_Block_" {
+            return self.resolve_change_block(parent);
+        }
+
         let id = arguments.get(&"id".to_string()).and_then(|id| match id {
             q::Value::String(s) => Some(s),
             _ => None,
@@ -11,14 +11,18 @@ use query::ast as qast;
 use schema::ast as sast;
 
 /// Options available for subscription execution.
-pub struct SubscriptionExecutionOptions<R>
-where
-    R: Resolver,
-{
+///
+/// The resolver is either owned by the subscription or, as an `Arc<R>`, shared
+/// with other subscriptions.
+pub struct SubscriptionExecutionOptions<R> {
     /// The logger to use during subscription execution.
     pub logger: Logger,
     /// The resolver to use.
     pub resolver: R,
+    /// The maximum number of fields a selection set may expand to, if any.
+    pub max_selection_fields: Option<usize>,
+    /// The maximum depth to which selection sets may be nested.
+    pub max_depth: usize,
 }
 
 pub fn execute_subscription<R>(
@@ -28,10 +32,80 @@ pub fn execute_subscription<R>(
 where
     R: Resolver + 'static,
 {
-    info!(options.logger, "Execute subscription");
+    execute_subscription_with_shared_resolver(
+        subscription,
+        SubscriptionExecutionOptions {
+            logger: options.logger,
+            resolver: Arc::new(options.resolver),
+            max_selection_fields: options.max_selection_fields,
+            max_depth: options.max_depth,
+        },
+    )
+}
+
+/// Executes a subscription with a resolver that the caller may share with other
+/// subscriptions, instead of one that is owned by the subscription.
+///
+/// The subscription is rejected up front if it is nested deeper than `max_depth` or if
+/// any of its selection sets expands to more than `max_selection_fields` fields, since
+/// its selection set is executed again for every entity change it receives.
+///
+/// With `subscription.send_initial`, the first result is the current result of the subscription,
+/// taken after the subscription to entity changes is set up so that no change is missed.
+pub fn execute_subscription_with_shared_resolver<R>(
+    subscription: &Subscription,
+    options: SubscriptionExecutionOptions<Arc<R>>,
+) -> Result<SubscriptionResult, SubscriptionError>
+where
+    R: Resolver + 'static,
+{
+    let SubscriptionExecutionOptions {
+        logger,
+        resolver,
+        max_selection_fields,
+        max_depth,
+    } = options;
 
-    // Obtain the only operation of the subscription (fail if there is none or more than one)
-    let operation = qast::get_operation(&subscription.query.document, None)?;
+    info!(logger, "Execute subscription");
+
+    // Obtain the operation with the requested name, or the only operation of the
+    // subscription if no name is given (fail if there is none or more than one)
+    let operation = qast::get_operation(
+        &subscription.query.document,
+        subscription
+            .operation_name
+            .as_ref()
+            .map(|name| name.as_str()),
+    )?;
+
+    // Only subscriptions can be executed, and only if all the fragments they
+    // reference are defined
+    let subscription_operation = match operation {
+        q::OperationDefinition::Subscription(subscription) => subscription,
+        _ => {
+            return Err(SubscriptionError::from(
+                QueryExecutionError::NotASubscription(qast::get_operation_name(operation).cloned()),
+            ))
+        }
+    };
+    if let Some(spread) = qast::find_undefined_fragment_spread(
+        &subscription.query.document,
+        &subscription_operation.selection_set,
+    ) {
+        return Err(SubscriptionError::from(
+            QueryExecutionError::UndefinedFragment(spread.position, spread.fragment_name.clone()),
+        ));
+    }
+
+    // Reject overly nested or wide subscriptions before establishing a stream for them
+    qast::check_query_depth(&subscription.query.document, operation, max_depth)?;
+    if let Some(max_selection_fields) = max_selection_fields {
+        qast::check_selection_fields(
+            &subscription.query.document,
+            operation,
+            max_selection_fields,
+        )?;
+    }
 
     // Parse variable values
     let coerced_variable_values = match coerce_variable_values(
@@ -45,13 +119,12 @@ where
 
     // Create an introspection type store and resolver
     let introspection_schema = introspection_schema();
-    let introspection_resolver =
-        IntrospectionResolver::new(&options.logger, &subscription.query.schema);
+    let introspection_resolver = IntrospectionResolver::new(&logger, &subscription.query.schema);
 
     // Create a fresh execution context
     let ctx = ExecutionContext {
-        logger: options.logger,
-        resolver: Arc::new(options.resolver),
+        logger,
+        resolver,
         schema: &subscription.query.schema,
         introspection_resolver: Arc::new(introspection_resolver),
         introspection_schema: &introspection_schema,
@@ -59,29 +132,28 @@ where
         document: &subscription.query.document,
         fields: vec![],
         variable_values: Arc::new(coerced_variable_values),
+        max_selection_fields,
     };
 
-    match *operation {
-        // Execute top-level `subscription { ... }` expressions
-        q::OperationDefinition::Subscription(ref subscription) => {
-            let source_stream = create_source_event_stream(&ctx, subscription)?;
-            let response_stream = map_source_to_response_stream(&ctx, subscription, source_stream)?;
-            Ok(response_stream)
-        }
+    // Execute the top-level `subscription { ... }` expression
+    let (initial_result, source_stream) =
+        create_source_event_stream(&ctx, subscription_operation, subscription.send_initial)?;
+    let response_stream =
+        map_source_to_response_stream(&ctx, subscription_operation, source_stream)?;
 
-        // Everything else (queries, mutations) is unsupported
-        _ => Err(SubscriptionError::from(QueryExecutionError::NotSupported(
-            "Only subscriptions are supported".to_string(),
-        ))),
-    }
+    Ok(match initial_result {
+        Some(result) => Box::new(stream::once(Ok(result)).chain(response_stream)),
+        None => response_stream,
+    })
 }
 
 fn create_source_event_stream<'a, R1, R2>(
     ctx: &'a ExecutionContext<'a, R1, R2>,
     operation: &q::Subscription,
-) -> Result<EntityChangeStream, SubscriptionError>
+    send_initial: bool,
+) -> Result<(Option<QueryResult>, EntityChangeStream), SubscriptionError>
 where
-    R1: Resolver,
+    R1: Resolver + 'static,
     R2: Resolver,
 {
     let subscription_type = sast::get_root_subscription_type(&ctx.schema.document)
@@ -92,7 +164,7 @@ where
         &subscription_type,
         &operation.selection_set,
         None,
-    );
+    )?;
 
     if grouped_field_set.is_empty() {
         return Err(SubscriptionError::from(QueryExecutionError::EmptyQuery));
@@ -104,16 +176,23 @@ where
 
     let fields = grouped_field_set.get_index(0).unwrap();
     let field = fields.1[0];
-    let argument_values = coerce_argument_values(ctx.clone(), subscription_type, field)?;
 
-    resolve_field_stream(ctx, subscription_type, field, argument_values)
+    // Reject invalid arguments before subscribing; the resolver reads the
+    // arguments it needs from the field itself
+    coerce_argument_values(ctx.clone(), subscription_type, field)?;
+
+    if send_initial {
+        resolve_field_stream_with_snapshot(ctx, operation, subscription_type, field)
+            .map(|(result, stream)| (Some(result), stream))
+    } else {
+        resolve_field_stream(ctx, subscription_type, field).map(|stream| (None, stream))
+    }
 }
 
 fn resolve_field_stream<'a, R1, R2>(
     ctx: &'a ExecutionContext<'a, R1, R2>,
     object_type: &'a s::ObjectType,
     field: &'a q::Field,
-    _argument_values: HashMap<&q::Name, q::Value>,
 ) -> Result<EntityChangeStream, SubscriptionError>
 where
     R1: Resolver,
@@ -124,6 +203,33 @@ where
         .map_err(SubscriptionError::from)
 }
 
+fn resolve_field_stream_with_snapshot<'a, R1, R2>(
+    ctx: &'a ExecutionContext<'a, R1, R2>,
+    subscription: &q::Subscription,
+    object_type: &'a s::ObjectType,
+    field: &'a q::Field,
+) -> Result<(QueryResult, EntityChangeStream), SubscriptionError>
+where
+    R1: Resolver + 'static,
+    R2: Resolver,
+{
+    let snapshot = || {
+        execute_subscription_selection_set(
+            ctx.logger.clone(),
+            ctx.resolver.clone(),
+            ctx.schema.clone(),
+            ctx.document.clone(),
+            subscription.clone(),
+            ctx.variable_values.clone(),
+            ctx.max_selection_fields,
+        )
+    };
+
+    ctx.resolver
+        .resolve_field_stream_with_snapshot(&ctx.schema.document, object_type, field, snapshot)
+        .map_err(SubscriptionError::from)
+}
+
 fn map_source_to_response_stream<'a, R1, R2>(
     ctx: &ExecutionContext<'a, R1, R2>,
     subscription: &'a q::Subscription,
@@ -139,6 +245,7 @@ where
     let document = ctx.document.clone();
     let subscription = subscription.to_owned();
     let variable_values = ctx.variable_values.clone();
+    let max_selection_fields = ctx.max_selection_fields;
 
     Ok(Box::new(source_stream.map(move |event| {
         execute_subscription_event(
@@ -148,6 +255,7 @@ where
             document.clone(),
             subscription.clone(),
             variable_values.clone(),
+            max_selection_fields,
             event,
         )
     })))
@@ -160,6 +268,7 @@ fn execute_subscription_event<R1>(
     document: q::Document,
     subscription: q::Subscription,
     variable_values: Arc<HashMap<q::Name, q::Value>>,
+    max_selection_fields: Option<usize>,
     event: EntityChange,
 ) -> QueryResult
 where
@@ -167,6 +276,30 @@ where
 {
     debug!(logger, "Execute subscription event"; "event" => format!("{:?}", event));
 
+    execute_subscription_selection_set(
+        logger,
+        resolver,
+        schema,
+        document,
+        subscription,
+        variable_values,
+        max_selection_fields,
+    )
+}
+
+/// Executes the selection set of a subscription against the current state of the store.
+fn execute_subscription_selection_set<R1>(
+    logger: Logger,
+    resolver: Arc<R1>,
+    schema: Schema,
+    document: q::Document,
+    subscription: q::Subscription,
+    variable_values: Arc<HashMap<q::Name, q::Value>>,
+    max_selection_fields: Option<usize>,
+) -> QueryResult
+where
+    R1: Resolver + 'static,
+{
     // Create an introspection type store and resolver
     let introspection_schema = introspection_schema();
     let introspection_resolver = IntrospectionResolver::new(&logger, &schema);
@@ -182,6 +315,7 @@ where
         document: &document,
         fields: vec![],
         variable_values,
+        max_selection_fields,
     };
 
     // We have established that this exists earlier in the subscription execution
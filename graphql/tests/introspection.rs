@@ -489,6 +489,8 @@ fn introspection_query(schema: Schema, query: &str) -> QueryResult {
         QueryExecutionOptions {
             logger: Logger::root(slog::Discard, o!()),
             resolver: MockResolver,
+            max_selection_fields: None,
+            max_depth: 100,
         },
     )
 }
@@ -7,11 +7,14 @@ extern crate graph;
 extern crate graph_core;
 extern crate graph_graphql;
 
+use futures::sync::mpsc::{channel, Sender};
 use graphql_parser::query as q;
 use std::collections::HashMap;
 use std::iter::FromIterator;
+use std::sync::Mutex;
 
 use graph::prelude::*;
+use graph::web3::types::H256;
 use graph_graphql::prelude::*;
 
 fn test_schema() -> Schema {
@@ -49,6 +52,10 @@ fn test_schema() -> Schema {
 #[derive(Clone)]
 struct TestStore {
     entities: Vec<Entity>,
+    /// When set, `find` reports a change to a musician to the subscribers it has at
+    /// that time, as if the change was written while the query was running.
+    change_during_find: bool,
+    subscribers: Arc<Mutex<Vec<Sender<EntityChange>>>>,
 }
 
 impl TestStore {
@@ -124,8 +131,21 @@ impl TestStore {
                     ("writtenBy", Value::from("m3")),
                 ]),
             ],
+            change_during_find: false,
+            subscribers: Arc::new(Mutex::new(vec![])),
         }
     }
+
+    fn musician_change() -> EntityChange {
+        EntityChange::from_key(
+            EntityKey {
+                subgraph_id: SubgraphDeploymentId::new("testschema").unwrap(),
+                entity_type: "Musician".to_owned(),
+                entity_id: "m1".to_owned(),
+            },
+            EntityChangeOperation::Updated,
+        )
+    }
 }
 
 impl Store for TestStore {
@@ -133,6 +153,14 @@ impl Store for TestStore {
         unimplemented!()
     }
 
+    fn deployment_status(&self, _: &SubgraphDeploymentId) -> Result<DeploymentStatus, Error> {
+        unimplemented!()
+    }
+
+    fn check_health(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
     fn set_block_ptr_with_no_changes(
         &self,
         _: SubgraphDeploymentId,
@@ -150,6 +178,22 @@ impl Store for TestStore {
         unimplemented!()
     }
 
+    fn validate_entity_operations(
+        &self,
+        _: Vec<EntityOperation>,
+        _: EventSource,
+    ) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    fn fail_subgraph(&self, _: &SubgraphDeploymentId, _: String) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    fn reset_subgraph(&self, _: &SubgraphDeploymentId) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
     fn transact_block_operations(
         &self,
         _: SubgraphDeploymentId,
@@ -170,13 +214,24 @@ impl Store for TestStore {
     }
 
     fn subscribe(&self, _: Vec<SubgraphEntityPair>) -> EntityChangeStream {
-        unimplemented!()
+        if self.change_during_find {
+            let (sender, receiver) = channel(100);
+            self.subscribers.lock().unwrap().push(sender);
+            return Box::new(receiver);
+        }
+
+        // Report a single change to a musician
+        Box::new(futures::stream::once(Ok(Self::musician_change())))
     }
 
     fn count_entities(&self, _: SubgraphDeploymentId) -> Result<u64, Error> {
         Ok(1)
     }
 
+    fn entity_types(&self, _: SubgraphDeploymentId) -> Result<Vec<String>, Error> {
+        unimplemented!()
+    }
+
     fn get(&self, key: EntityKey) -> Result<Option<Entity>, QueryExecutionError> {
         self.entities
             .iter()
@@ -193,6 +248,13 @@ impl Store for TestStore {
     }
 
     fn find(&self, query: EntityQuery) -> Result<Vec<Entity>, QueryExecutionError> {
+        // Subscribers only hear about the change once; their streams end afterwards
+        if self.change_during_find {
+            for sender in self.subscribers.lock().unwrap().drain(..) {
+                sender.send(Self::musician_change()).wait().unwrap();
+            }
+        }
+
         let entity_name = Value::String(query.entity_type.clone());
 
         let entities = self
@@ -236,6 +298,22 @@ impl Store for TestStore {
     fn find_one(&self, query: EntityQuery) -> Result<Option<Entity>, QueryExecutionError> {
         Ok(self.find(query)?.pop())
     }
+
+    fn count_query(&self, query: EntityQuery) -> Result<u64, QueryExecutionError> {
+        Ok(self.find(query)?.len() as u64)
+    }
+
+    fn change_block(
+        &self,
+        key: EntityKey,
+    ) -> Result<Option<EthereumBlockPointer>, QueryExecutionError> {
+        // Bands were last changed in block 3, all other entities are not known to
+        // have been changed by a block
+        Ok(match key.entity_type.as_str() {
+            "Band" => Some((H256::zero(), 3u64).into()),
+            _ => None,
+        })
+    }
 }
 
 fn execute_query_document(query: q::Document) -> QueryResult {
@@ -259,11 +337,66 @@ fn execute_query_document_with_variables(
     let options = QueryExecutionOptions {
         logger: logger,
         resolver: store_resolver,
+        max_selection_fields: None,
+        max_depth: 100,
     };
 
     execute_query(&query, options)
 }
 
+#[test]
+fn can_query_change_block() {
+    let result = execute_query_document(
+        graphql_parser::parse_query(
+            "
+            query {
+                bands {
+                    name
+                    _changeBlock { number }
+                }
+                musician(id: \"m1\") {
+                    name
+                    _changeBlock { number }
+                }
+            }
+            ",
+        )
+        .expect("Invalid test query"),
+    );
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    let block_3 = object_value(vec![("number", q::Value::String(String::from("3")))]);
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![
+            (
+                "bands",
+                q::Value::List(vec![
+                    object_value(vec![
+                        ("name", q::Value::String(String::from("The Musicians"))),
+                        ("_changeBlock", block_3.clone()),
+                    ]),
+                    object_value(vec![
+                        ("name", q::Value::String(String::from("The Amateurs"))),
+                        ("_changeBlock", block_3),
+                    ]),
+                ]),
+            ),
+            (
+                "musician",
+                object_value(vec![
+                    ("name", q::Value::String(String::from("John"))),
+                    ("_changeBlock", q::Value::Null),
+                ]),
+            ),
+        ])),
+    )
+}
+
 #[test]
 fn can_query_one_to_one_relationship() {
     let result = execute_query_document(
@@ -684,3 +817,501 @@ fn include_directive_works_with_query_variables() {
         )]))
     );
 }
+
+#[test]
+fn rejects_selection_sets_with_too_many_fields() {
+    let query = Query {
+        schema: test_schema(),
+        document: graphql_parser::parse_query(
+            "
+            query {
+                musicians {
+                    a: name
+                    b: name
+                    c: name
+                    d: name
+                }
+            }
+            ",
+        )
+        .expect("Invalid test query"),
+        variables: None,
+    };
+
+    let logger = Logger::root(slog::Discard, o!());
+    let store = Arc::new(TestStore::new());
+    let options = QueryExecutionOptions {
+        logger: logger.clone(),
+        resolver: StoreResolver::new(&logger, store),
+        max_selection_fields: Some(3),
+        max_depth: 100,
+    };
+
+    let result = execute_query(&query, options);
+    match result.errors {
+        Some(errors) => match errors[0] {
+            QueryError::ExecutionError(QueryExecutionError::TooManyFields(_, 3)) => (),
+            ref e => panic!("Unexpected error: {}", e),
+        },
+        None => panic!("Expected query to be rejected"),
+    }
+}
+
+fn execute_query_with_max_depth(query: &str, max_depth: usize) -> QueryResult {
+    let query = Query {
+        schema: test_schema(),
+        document: graphql_parser::parse_query(query).expect("Invalid test query"),
+        variables: None,
+    };
+
+    let logger = Logger::root(slog::Discard, o!());
+    let store = Arc::new(TestStore::new());
+    let options = QueryExecutionOptions {
+        logger: logger.clone(),
+        resolver: StoreResolver::new(&logger, store),
+        max_selection_fields: None,
+        max_depth,
+    };
+
+    execute_query(&query, options)
+}
+
+#[test]
+fn accepts_queries_at_the_maximum_depth() {
+    let result = execute_query_with_max_depth(
+        "
+        query {
+            musicians {
+                mainBand {
+                    name
+                }
+            }
+        }
+        ",
+        3,
+    );
+    assert!(result.errors.is_none(), "{:?}", result.errors);
+    assert!(result.data.is_some());
+}
+
+#[test]
+fn rejects_queries_beyond_the_maximum_depth() {
+    // Fields pulled in through fragments count towards the depth as well
+    let result = execute_query_with_max_depth(
+        "
+        query {
+            musicians {
+                ...BandName
+            }
+        }
+
+        fragment BandName on Musician {
+            mainBand {
+                name
+            }
+        }
+        ",
+        2,
+    );
+    match result.errors {
+        Some(errors) => match errors[0] {
+            QueryError::ExecutionError(QueryExecutionError::QueryTooDeep(_, 2)) => (),
+            ref e => panic!("Unexpected error: {}", e),
+        },
+        None => panic!("Expected query to be rejected"),
+    }
+}
+
+fn execute_subscription_document(
+    query: &str,
+    operation_name: Option<&str>,
+) -> Result<(), SubscriptionError> {
+    let subscription = Subscription {
+        query: Query {
+            schema: test_schema(),
+            document: graphql_parser::parse_query(query).expect("Invalid test query"),
+            variables: None,
+        },
+        operation_name: operation_name.map(String::from),
+        send_initial: false,
+    };
+
+    let logger = Logger::root(slog::Discard, o!());
+    let store = Arc::new(TestStore::new());
+    let options = SubscriptionExecutionOptions {
+        logger: logger.clone(),
+        resolver: StoreResolver::new(&logger, store),
+        max_selection_fields: None,
+        max_depth: 100,
+    };
+
+    execute_subscription(&subscription, options).map(|_| ())
+}
+
+/// Runs a subscription and returns the result for the first entity change it receives.
+fn execute_subscription_document_with_variables(
+    query: &str,
+    variables: QueryVariables,
+) -> QueryResult {
+    let subscription = Subscription {
+        query: Query {
+            schema: test_schema(),
+            document: graphql_parser::parse_query(query).expect("Invalid test query"),
+            variables: Some(variables),
+        },
+        operation_name: None,
+        send_initial: false,
+    };
+
+    let logger = Logger::root(slog::Discard, o!());
+    let store = Arc::new(TestStore::new());
+    let options = SubscriptionExecutionOptions {
+        logger: logger.clone(),
+        resolver: StoreResolver::new(&logger, store),
+        max_selection_fields: None,
+        max_depth: 100,
+    };
+
+    let results = execute_subscription(&subscription, options)
+        .expect("Failed to execute subscription")
+        .take(1)
+        .collect()
+        .wait()
+        .expect("Subscription stream failed");
+    results
+        .into_iter()
+        .next()
+        .expect("Subscription produced no result")
+}
+
+#[test]
+fn subscriptions_honor_skip_and_include_directives() {
+    let query = "
+        subscription musicians($withIds: Boolean!, $withoutNames: Boolean!) {
+          musicians {
+            id @include(if: $withIds)
+            name @skip(if: $withoutNames)
+          }
+        }
+    ";
+    let variables = |with_ids: bool, without_names: bool| {
+        QueryVariables::new(HashMap::from_iter(
+            vec![
+                (String::from("withIds"), q::Value::Boolean(with_ids)),
+                (
+                    String::from("withoutNames"),
+                    q::Value::Boolean(without_names),
+                ),
+            ]
+            .into_iter(),
+        ))
+    };
+    let musicians = |fields: &[&str]| {
+        let musicians = vec![
+            ("m1", "John"),
+            ("m2", "Lisa"),
+            ("m3", "Tom"),
+            ("m4", "Valerie"),
+        ];
+        Some(object_value(vec![(
+            "musicians",
+            q::Value::List(
+                musicians
+                    .into_iter()
+                    .map(|(id, name)| {
+                        object_value(
+                            vec![
+                                ("id", q::Value::String(String::from(id))),
+                                ("name", q::Value::String(String::from(name))),
+                            ]
+                            .into_iter()
+                            .filter(|(field, _)| fields.contains(field))
+                            .collect(),
+                        )
+                    })
+                    .collect(),
+            ),
+        )]))
+    };
+
+    let result = execute_subscription_document_with_variables(query, variables(true, false));
+    assert_eq!(result.data, musicians(&["id", "name"]));
+
+    let result = execute_subscription_document_with_variables(query, variables(false, false));
+    assert_eq!(result.data, musicians(&["name"]));
+
+    let result = execute_subscription_document_with_variables(query, variables(true, true));
+    assert_eq!(result.data, musicians(&["id"]));
+}
+
+#[test]
+fn subscription_results_are_keyed_by_field_aliases() {
+    let result = execute_subscription_document_with_variables(
+        "
+        subscription {
+          artists: musicians {
+            key: id
+          }
+        }
+        ",
+        QueryVariables::new(HashMap::new()),
+    );
+
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "artists",
+            q::Value::List(
+                vec!["m1", "m2", "m3", "m4"]
+                    .into_iter()
+                    .map(|id| object_value(vec![("key", q::Value::String(String::from(id)))]))
+                    .collect(),
+            ),
+        )]))
+    );
+}
+
+#[test]
+fn subscriptions_with_fragment_spreads_require_the_fragments_to_be_defined() {
+    let result = execute_subscription_document(
+        "
+        subscription {
+            musicians {
+                ...MusicianName
+            }
+        }
+
+        fragment MusicianName on Musician {
+            name
+        }
+        ",
+        None,
+    );
+    assert!(result.is_ok(), "{:?}", result.err());
+
+    let result = execute_subscription_document(
+        "
+        subscription {
+            musicians {
+                ...MusicianName
+            }
+        }
+        ",
+        None,
+    );
+    match result {
+        Err(SubscriptionError::GraphQLError(ref errors)) => match errors[0] {
+            QueryExecutionError::UndefinedFragment(_, ref name) => {
+                assert_eq!(name, "MusicianName")
+            }
+            ref e => panic!("Unexpected error: {}", e),
+        },
+        Ok(_) => panic!("Expected subscription to be rejected"),
+    }
+}
+
+#[test]
+fn subscriptions_nested_too_deeply_are_rejected() {
+    let logger = Logger::root(slog::Discard, o!());
+    let resolver = Arc::new(StoreResolver::new(&logger, Arc::new(TestStore::new())));
+    let subscribe = |query: &str| {
+        let subscription = Subscription {
+            query: Query {
+                schema: test_schema(),
+                document: graphql_parser::parse_query(query).expect("Invalid test query"),
+                variables: None,
+            },
+            operation_name: None,
+            send_initial: false,
+        };
+        execute_subscription_with_shared_resolver(
+            &subscription,
+            SubscriptionExecutionOptions {
+                logger: logger.clone(),
+                resolver: resolver.clone(),
+                max_selection_fields: None,
+                max_depth: 2,
+            },
+        )
+    };
+
+    let result = subscribe("subscription { musicians { name } }");
+    assert!(result.is_ok(), "{:?}", result.err());
+
+    let result = subscribe("subscription { musicians { bands { name } } }");
+    match result {
+        Err(SubscriptionError::GraphQLError(ref errors)) => match errors[0] {
+            QueryExecutionError::QueryTooDeep(_, max_depth) => assert_eq!(max_depth, 2),
+            ref e => panic!("Unexpected error: {}", e),
+        },
+        Ok(_) => panic!("Expected subscription to be rejected"),
+    }
+}
+
+#[test]
+fn subscriptions_with_too_many_fields_are_rejected() {
+    let logger = Logger::root(slog::Discard, o!());
+    let resolver = Arc::new(StoreResolver::new(&logger, Arc::new(TestStore::new())));
+    let subscribe = |query: &str| {
+        let subscription = Subscription {
+            query: Query {
+                schema: test_schema(),
+                document: graphql_parser::parse_query(query).expect("Invalid test query"),
+                variables: None,
+            },
+            operation_name: None,
+            send_initial: false,
+        };
+        execute_subscription_with_shared_resolver(
+            &subscription,
+            SubscriptionExecutionOptions {
+                logger: logger.clone(),
+                resolver: resolver.clone(),
+                max_selection_fields: Some(2),
+                max_depth: 100,
+            },
+        )
+    };
+
+    let result = subscribe("subscription { musicians { id name } }");
+    assert!(result.is_ok(), "{:?}", result.err());
+
+    // Nested selection sets are checked before the subscription is established,
+    // including the fields pulled in through fragments
+    let result = subscribe(
+        "
+        subscription {
+            musicians { id ...MusicianName }
+        }
+
+        fragment MusicianName on Musician { name bands { name } }
+        ",
+    );
+    match result {
+        Err(SubscriptionError::GraphQLError(ref errors)) => match errors[0] {
+            QueryExecutionError::TooManyFields(_, max_fields) => assert_eq!(max_fields, 2),
+            ref e => panic!("Unexpected error: {}", e),
+        },
+        Ok(_) => panic!("Expected subscription to be rejected"),
+    }
+}
+
+#[test]
+fn subscriptions_execute_the_named_operation() {
+    let query = "
+        query Musicians {
+            musicians {
+                name
+            }
+        }
+
+        subscription MusicianUpdates {
+            musicians {
+                name
+            }
+        }
+        ";
+
+    let result = execute_subscription_document(query, Some("MusicianUpdates"));
+    assert!(result.is_ok(), "{:?}", result.err());
+
+    match execute_subscription_document(query, Some("Musicians")) {
+        Err(SubscriptionError::GraphQLError(ref errors)) => match errors[0] {
+            QueryExecutionError::NotASubscription(Some(ref name)) => {
+                assert_eq!(name, "Musicians")
+            }
+            ref e => panic!("Unexpected error: {}", e),
+        },
+        Ok(_) => panic!("Expected query operation to be rejected"),
+    }
+
+    match execute_subscription_document(query, None) {
+        Err(SubscriptionError::GraphQLError(ref errors)) => match errors[0] {
+            QueryExecutionError::OperationNameRequired => (),
+            ref e => panic!("Unexpected error: {}", e),
+        },
+        Ok(_) => panic!("Expected subscription without operation name to be rejected"),
+    }
+}
+
+#[test]
+fn subscriptions_send_their_initial_result_without_missing_changes() {
+    let logger = Logger::root(slog::Discard, o!());
+    let store = Arc::new(TestStore {
+        change_during_find: true,
+        ..TestStore::new()
+    });
+    let subscription = Subscription {
+        query: Query {
+            schema: test_schema(),
+            document: graphql_parser::parse_query("subscription { musicians { name } }")
+                .expect("Invalid test query"),
+            variables: None,
+        },
+        operation_name: None,
+        send_initial: true,
+    };
+    let options = SubscriptionExecutionOptions {
+        logger: logger.clone(),
+        resolver: StoreResolver::new(&logger, store),
+        max_selection_fields: None,
+        max_depth: 100,
+    };
+
+    let results = execute_subscription(&subscription, options)
+        .expect("Failed to execute subscription")
+        .collect()
+        .wait()
+        .expect("Subscription stream failed");
+
+    // The initial result is followed by the result for the change made while it was
+    // queried, which the subscription would have missed had it subscribed afterwards
+    let musicians = Some(object_value(vec![(
+        "musicians",
+        q::Value::List(
+            vec!["John", "Lisa", "Tom", "Valerie"]
+                .into_iter()
+                .map(|name| object_value(vec![("name", q::Value::String(String::from(name)))]))
+                .collect(),
+        ),
+    )]));
+    assert_eq!(results.len(), 2);
+    for result in results {
+        assert!(result.errors.is_none(), "{:?}", result.errors);
+        assert_eq!(result.data, musicians);
+    }
+}
+
+#[test]
+fn subscriptions_can_share_a_resolver() {
+    let logger = Logger::root(slog::Discard, o!());
+    let resolver = Arc::new(StoreResolver::new(&logger, Arc::new(TestStore::new())));
+    let subscription = Subscription {
+        query: Query {
+            schema: test_schema(),
+            document: graphql_parser::parse_query("subscription { musicians { name } }")
+                .expect("Invalid test query"),
+            variables: None,
+        },
+        operation_name: None,
+        send_initial: false,
+    };
+
+    let results: Vec<_> = (0..2)
+        .map(|_| {
+            execute_subscription_with_shared_resolver(
+                &subscription,
+                SubscriptionExecutionOptions {
+                    logger: logger.clone(),
+                    resolver: resolver.clone(),
+                    max_selection_fields: None,
+                    max_depth: 100,
+                },
+            )
+        })
+        .collect();
+    assert!(results.iter().all(|result| result.is_ok()));
+
+    // Both subscriptions hold on to the resolver instead of a copy of it
+    assert_eq!(Arc::strong_count(&resolver), 3);
+}
@@ -6,6 +6,7 @@ use std::collections::HashSet;
 use std::sync::Mutex;
 
 use graph::components::store::*;
+use graph::data::subgraph::schema::{SubgraphDeploymentEntity, SUBGRAPHS_ID};
 use graph::prelude::*;
 use graph::web3::types::H256;
 
@@ -43,6 +44,88 @@ impl MockStore {
         }
     }
 
+    /// Applies entity operations to `entities` and returns the resulting entity changes.
+    fn apply_to(
+        &self,
+        entities: &mut HashMap<SubgraphDeploymentId, HashMap<String, HashMap<String, Entity>>>,
+        ops: Vec<EntityOperation>,
+    ) -> Result<Vec<EntityChange>, StoreError> {
+        let mut entity_changes = vec![];
+        for op in ops {
+            match op {
+                EntityOperation::Set { key, data } => {
+                    let entities_of_type = entities
+                        .entry(key.subgraph_id.clone())
+                        .or_default()
+                        .entry(key.entity_type.clone())
+                        .or_default();
+
+                    if entities_of_type.contains_key(&key.entity_id) {
+                        let existing_entity = entities_of_type.get_mut(&key.entity_id).unwrap();
+                        existing_entity.merge(data);
+
+                        entity_changes
+                            .push(EntityChange::from_key(key, EntityChangeOperation::Updated));
+                    } else {
+                        let mut new_entity = data;
+                        new_entity.insert("id".to_owned(), key.entity_id.clone().into());
+                        entities_of_type.insert(key.entity_id.clone(), new_entity);
+
+                        entity_changes
+                            .push(EntityChange::from_key(key, EntityChangeOperation::Added));
+                    }
+                }
+                EntityOperation::Remove { key } => {
+                    if let Some(in_subgraph) = entities.get_mut(&key.subgraph_id) {
+                        if let Some(of_type) = in_subgraph.get_mut(&key.entity_type) {
+                            if of_type.remove(&key.entity_id).is_some() {
+                                entity_changes.push(EntityChange::from_key(
+                                    key,
+                                    EntityChangeOperation::Removed,
+                                ));
+                            }
+                        }
+                    }
+                }
+                EntityOperation::AbortUnless {
+                    description,
+                    query,
+                    entity_ids: mut expected_entity_ids,
+                } => {
+                    if query.range.is_some() && query.order_by.is_none() {
+                        return Err(TransactionAbortError::Other(format!(
+                            "AbortUnless query cannot have a range without order_by: {}",
+                            description
+                        ))
+                        .into());
+                    }
+
+                    let query_results = self.execute_query(entities, query.clone()).unwrap();
+                    let mut actual_entity_ids = query_results
+                        .into_iter()
+                        .map(|entity| entity.id().unwrap())
+                        .collect::<Vec<_>>();
+
+                    if query.order_by.is_none() {
+                        actual_entity_ids.sort();
+                        expected_entity_ids.sort();
+                    }
+
+                    if actual_entity_ids != expected_entity_ids {
+                        return Err(TransactionAbortError::AbortUnless {
+                            expected_entity_ids,
+                            actual_entity_ids,
+                            description,
+                        }
+                        .into());
+                    }
+                }
+            }
+        }
+
+        Ok(entity_changes)
+    }
+
     fn execute_query(
         &self,
         entities: &HashMap<SubgraphDeploymentId, HashMap<String, HashMap<String, Entity>>>,
@@ -76,7 +159,9 @@ impl MockStore {
             filter,
             order_by,
             order_direction,
+            order_nulls,
             range,
+            as_of_block: _,
         } = query;
 
         // List all entities with correct type
@@ -103,6 +188,7 @@ impl MockStore {
             unimplemented!();
         } else {
             assert_eq!(order_direction, None);
+            assert_eq!(order_nulls, None);
 
             // Randomize order to help identify bugs where ordering is assumed to be deterministic.
             let mut sorted_entities = filtered_entities;
@@ -135,13 +221,44 @@ impl Store for MockStore {
     }
 
     fn find_one(&self, query: EntityQuery) -> Result<Option<Entity>, QueryExecutionError> {
-        Ok(self.find(query)?.pop())
+        let entity_type = query.entity_type.clone();
+        let mut entities = self.find(query)?;
+        match entities.len() {
+            0 | 1 => Ok(entities.pop()),
+            _ => Err(QueryExecutionError::TooManyResults(entity_type)),
+        }
+    }
+
+    fn count_query(&self, query: EntityQuery) -> Result<u64, QueryExecutionError> {
+        let query = EntityQuery {
+            order_by: None,
+            order_direction: None,
+            order_nulls: None,
+            range: None,
+            ..query
+        };
+        Ok(self.find(query)?.len() as u64)
+    }
+
+    fn change_block(
+        &self,
+        _: EntityKey,
+    ) -> Result<Option<EthereumBlockPointer>, QueryExecutionError> {
+        unimplemented!();
     }
 
     fn block_ptr(&self, _: SubgraphDeploymentId) -> Result<EthereumBlockPointer, Error> {
         unimplemented!();
     }
 
+    fn deployment_status(&self, _: &SubgraphDeploymentId) -> Result<DeploymentStatus, Error> {
+        unimplemented!();
+    }
+
+    fn check_health(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
     fn set_block_ptr_with_no_changes(
         &self,
         _: SubgraphDeploymentId,
@@ -169,74 +286,7 @@ impl Store for MockStore {
         let mut entities_ref = self.entities.lock().unwrap();
 
         let mut entities: HashMap<_, _> = entities_ref.clone();
-        let mut entity_changes = vec![];
-        for op in ops {
-            match op {
-                EntityOperation::Set { key, data } => {
-                    let entities_of_type = entities
-                        .entry(key.subgraph_id.clone())
-                        .or_default()
-                        .entry(key.entity_type.clone())
-                        .or_default();
-
-                    if entities_of_type.contains_key(&key.entity_id) {
-                        let existing_entity = entities_of_type.get_mut(&key.entity_id).unwrap();
-                        existing_entity.merge(data);
-
-                        entity_changes
-                            .push(EntityChange::from_key(key, EntityChangeOperation::Updated));
-                    } else {
-                        let mut new_entity = data;
-                        new_entity.insert("id".to_owned(), key.entity_id.clone().into());
-                        entities_of_type.insert(key.entity_id.clone(), new_entity);
-
-                        entity_changes
-                            .push(EntityChange::from_key(key, EntityChangeOperation::Added));
-                    }
-                }
-                EntityOperation::Remove { key } => {
-                    if let Some(in_subgraph) = entities.get_mut(&key.subgraph_id) {
-                        if let Some(of_type) = in_subgraph.get_mut(&key.entity_type) {
-                            if of_type.remove(&key.entity_id).is_some() {
-                                entity_changes.push(EntityChange::from_key(
-                                    key,
-                                    EntityChangeOperation::Removed,
-                                ));
-                            }
-                        }
-                    }
-                }
-                EntityOperation::AbortUnless {
-                    description,
-                    query,
-                    entity_ids: mut expected_entity_ids,
-                } => {
-                    if query.range.is_some() && query.order_by.is_none() {
-                        panic!("AbortUnless query cannot have a range without order_by");
-                    }
-
-                    let query_results = self.execute_query(&entities, query.clone()).unwrap();
-                    let mut actual_entity_ids = query_results
-                        .into_iter()
-                        .map(|entity| entity.id().unwrap())
-                        .collect::<Vec<_>>();
-
-                    if query.order_by.is_none() {
-                        actual_entity_ids.sort();
-                        expected_entity_ids.sort();
-                    }
-
-                    if actual_entity_ids != expected_entity_ids {
-                        return Err(TransactionAbortError::AbortUnless {
-                            expected_entity_ids,
-                            actual_entity_ids,
-                            description,
-                        }
-                        .into());
-                    }
-                }
-            }
-        }
+        let entity_changes = self.apply_to(&mut entities, ops)?;
 
         *entities_ref = entities;
         ::std::mem::drop(entities_ref);
@@ -245,10 +295,8 @@ impl Store for MockStore {
         // send entity changes to subscribers.
         let subscriptions = self.subscriptions.lock().unwrap();
         for entity_change in entity_changes {
-            let entity_type = entity_change.subgraph_entity_pair();
-
             for (entity_types_set, sender) in subscriptions.iter() {
-                if entity_types_set.contains(&entity_type) {
+                if entity_change.matches(entity_types_set) {
                     let entity_change = entity_change.clone();
                     let sender = sender.clone();
 
@@ -265,6 +313,26 @@ impl Store for MockStore {
         Ok(())
     }
 
+    fn validate_entity_operations(
+        &self,
+        ops: Vec<EntityOperation>,
+        _: EventSource,
+    ) -> Result<(), StoreError> {
+        let mut entities = self.entities.lock().unwrap().clone();
+        self.apply_to(&mut entities, ops).map(|_| ())
+    }
+
+    fn fail_subgraph(&self, id: &SubgraphDeploymentId, error: String) -> Result<(), StoreError> {
+        self.apply_entity_operations(
+            SubgraphDeploymentEntity::fail_operations(id, error),
+            EventSource::None,
+        )
+    }
+
+    fn reset_subgraph(&self, _: &SubgraphDeploymentId) -> Result<(), StoreError> {
+        unimplemented!();
+    }
+
     fn revert_block_operations(
         &self,
         _: SubgraphDeploymentId,
@@ -288,6 +356,10 @@ impl Store for MockStore {
     fn count_entities(&self, _: SubgraphDeploymentId) -> Result<u64, Error> {
         unimplemented!();
     }
+
+    fn entity_types(&self, _: SubgraphDeploymentId) -> Result<Vec<String>, Error> {
+        unimplemented!();
+    }
 }
 
 impl SubgraphDeploymentStore for MockStore {
@@ -302,9 +374,32 @@ impl SubgraphDeploymentStore for MockStore {
         Ok(self.schemas.keys().any(|id| subgraph_id == id))
     }
 
+    fn deployed_subgraphs(&self) -> Result<Vec<SubgraphDeploymentId>, Error> {
+        let mut ids: Vec<_> = self
+            .schemas
+            .keys()
+            .filter(|id| *id != &*SUBGRAPHS_ID)
+            .cloned()
+            .collect();
+        ids.sort();
+        Ok(ids)
+    }
+
     fn subgraph_schema(&self, subgraph_id: SubgraphDeploymentId) -> Result<Schema, Error> {
         Ok(self.schemas.get(&subgraph_id).unwrap().clone())
     }
+
+    fn subgraph_schemas(
+        &self,
+        ids: &[SubgraphDeploymentId],
+    ) -> Result<HashMap<SubgraphDeploymentId, Schema>, Error> {
+        Ok(ids
+            .iter()
+            .map(|id| (id.clone(), self.schemas.get(id).unwrap().clone()))
+            .collect())
+    }
+
+    fn invalidate_schema_cache(&self, _: &SubgraphDeploymentId) {}
 }
 
 impl ChainStore for MockStore {
@@ -325,6 +420,14 @@ impl ChainStore for MockStore {
         unimplemented!();
     }
 
+    fn insert_new_blocks<'a, B, E>(&self, _: B) -> Box<Future<Item = usize, Error = E> + Send + 'a>
+    where
+        B: Stream<Item = EthereumBlock, Error = E> + Send + 'a,
+        E: From<Error> + Send + 'a,
+    {
+        unimplemented!();
+    }
+
     fn attempt_chain_head_update(&self, _: u64) -> Result<Vec<H256>, Error> {
         unimplemented!();
     }
@@ -341,6 +444,10 @@ impl ChainStore for MockStore {
         unimplemented!();
     }
 
+    fn blocks(&self, _: &[H256]) -> Result<Vec<EthereumBlock>, Error> {
+        unimplemented!();
+    }
+
     fn ancestor_block(
         &self,
         _: EthereumBlockPointer,
@@ -354,7 +461,7 @@ pub struct FakeStore;
 
 impl Store for FakeStore {
     fn get(&self, _: EntityKey) -> Result<Option<Entity>, QueryExecutionError> {
-        unimplemented!();
+        Ok(None)
     }
 
     fn find(&self, _: EntityQuery) -> Result<Vec<Entity>, QueryExecutionError> {
@@ -365,10 +472,29 @@ impl Store for FakeStore {
         unimplemented!();
     }
 
+    fn count_query(&self, _: EntityQuery) -> Result<u64, QueryExecutionError> {
+        unimplemented!();
+    }
+
+    fn change_block(
+        &self,
+        _: EntityKey,
+    ) -> Result<Option<EthereumBlockPointer>, QueryExecutionError> {
+        unimplemented!();
+    }
+
     fn block_ptr(&self, _: SubgraphDeploymentId) -> Result<EthereumBlockPointer, Error> {
         unimplemented!();
     }
 
+    fn deployment_status(&self, _: &SubgraphDeploymentId) -> Result<DeploymentStatus, Error> {
+        unimplemented!();
+    }
+
+    fn check_health(&self) -> Result<(), Error> {
+        unimplemented!();
+    }
+
     fn set_block_ptr_with_no_changes(
         &self,
         _: SubgraphDeploymentId,
@@ -396,6 +522,22 @@ impl Store for FakeStore {
         Ok(())
     }
 
+    fn validate_entity_operations(
+        &self,
+        _: Vec<EntityOperation>,
+        _: EventSource,
+    ) -> Result<(), StoreError> {
+        Ok(())
+    }
+
+    fn fail_subgraph(&self, _: &SubgraphDeploymentId, _: String) -> Result<(), StoreError> {
+        unimplemented!();
+    }
+
+    fn reset_subgraph(&self, _: &SubgraphDeploymentId) -> Result<(), StoreError> {
+        unimplemented!();
+    }
+
     fn revert_block_operations(
         &self,
         _: SubgraphDeploymentId,
@@ -412,6 +554,10 @@ impl Store for FakeStore {
     fn count_entities(&self, _: SubgraphDeploymentId) -> Result<u64, Error> {
         unimplemented!();
     }
+
+    fn entity_types(&self, _: SubgraphDeploymentId) -> Result<Vec<String>, Error> {
+        unimplemented!();
+    }
 }
 
 impl ChainStore for FakeStore {
@@ -429,6 +575,14 @@ impl ChainStore for FakeStore {
         unimplemented!();
     }
 
+    fn insert_new_blocks<'a, B, E>(&self, _: B) -> Box<Future<Item = usize, Error = E> + Send + 'a>
+    where
+        B: Stream<Item = EthereumBlock, Error = E> + Send + 'a,
+        E: From<Error> + Send + 'a,
+    {
+        unimplemented!();
+    }
+
     fn attempt_chain_head_update(&self, _: u64) -> Result<Vec<H256>, Error> {
         unimplemented!();
     }
@@ -445,6 +599,10 @@ impl ChainStore for FakeStore {
         unimplemented!();
     }
 
+    fn blocks(&self, _: &[H256]) -> Result<Vec<EthereumBlock>, Error> {
+        unimplemented!();
+    }
+
     fn ancestor_block(
         &self,
         _: EthereumBlockPointer,
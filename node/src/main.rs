@@ -1,4 +1,5 @@
 extern crate clap;
+extern crate ctrlc;
 extern crate env_logger;
 extern crate futures;
 extern crate itertools;
@@ -23,6 +24,7 @@ use itertools::FoldWhile::{Continue, Done};
 use itertools::Itertools;
 use std::env;
 use std::net::ToSocketAddrs;
+use std::sync::mpsc;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -38,16 +40,48 @@ use graph_core::{
 };
 use graph_datasource_ethereum::{BlockStreamBuilder, Transport};
 use graph_runtime_wasm::RuntimeHostBuilder as WASMRuntimeHostBuilder;
-use graph_server_http::GraphQLServer as GraphQLQueryServer;
+use graph_server_http::{
+    AllowedOrigins, GraphQLServer as GraphQLQueryServer, QueryConcurrencyLimits,
+};
 use graph_server_json_rpc::JsonRpcServer;
 use graph_server_websocket::SubscriptionServer as GraphQLSubscriptionServer;
-use graph_store_postgres::{Store as DieselStore, StoreConfig};
+use graph_store_postgres::{IsolationLevel, Store as DieselStore, StoreConfig};
+
+/// How long in-flight work gets to finish after SIGINT or SIGTERM before the
+/// runtime is torn down, unless overridden with `GRAPH_SHUTDOWN_DRAIN_SECS`.
+const DEFAULT_SHUTDOWN_DRAIN_SECS: u64 = 10;
+
+/// What triggered shutting down the node.
+enum ShutdownReason {
+    Panic,
+    Signal,
+}
 
 fn main() {
-    let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+    let (panic_sender, panic_receiver) = oneshot::channel();
+    let (subscription_shutdown_sender, subscription_shutdown_receiver) = oneshot::channel();
     // Register guarded panic logger which ensures logs flush on shutdown
     let (panic_logger, _panic_guard) = guarded_logger();
-    register_panic_hook(panic_logger, shutdown_sender);
+    register_panic_hook(panic_logger, panic_sender);
+
+    // Shut down after a panic as well as on SIGINT and SIGTERM
+    let (shutdown_sender, shutdown_receiver) = mpsc::channel();
+    let panic_shutdown_sender = shutdown_sender.clone();
+    std::thread::spawn(move || {
+        if panic_receiver.wait().is_ok() {
+            let _ = panic_shutdown_sender.send(ShutdownReason::Panic);
+        }
+    });
+    ctrlc::set_handler(move || {
+        let _ = shutdown_sender.send(ShutdownReason::Signal);
+    })
+    .expect("Failed to register signal handler");
+
+    let drain_period = env::var("GRAPH_SHUTDOWN_DRAIN_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or_else(|| Duration::from_secs(DEFAULT_SHUTDOWN_DRAIN_SECS));
 
     // Create components for tokio context: multi-threaded runtime,
     // executor context on the runtime, and Timer handle.
@@ -58,35 +92,54 @@ fn main() {
     let timer = Timer::default();
     let timer_handle = timer.handle();
 
-    // Shutdown the runtime after a panic
-    std::thread::spawn(|| {
+    // Shutdown the subscription server and the runtime after a panic or a signal
+    std::thread::spawn(move || {
         let shutdown_logger = logger(false);
-        shutdown_receiver
-            .wait()
-            .map(|_| {
-                let _ = runtime
-                    .shutdown_now()
-                    .wait()
-                    .expect("Failed to shutdown Tokio Runtime");
-                info!(
-                    shutdown_logger,
-                    "Runtime cleaned up and shutdown successfully"
-                );
-            })
+        let reason = shutdown_receiver
+            .recv()
             .expect("Runtime shutdown process did not finish");
+
+        let _ = subscription_shutdown_sender.send(());
+
+        // Give in-flight work a chance to finish after a signal; another
+        // signal cuts the drain period short
+        if let ShutdownReason::Signal = reason {
+            info!(
+                shutdown_logger,
+                "Shutting down, waiting up to {}s for in-flight work to finish",
+                drain_period.as_secs()
+            );
+            let _ = shutdown_receiver.recv_timeout(drain_period);
+        }
+
+        let _ = runtime
+            .shutdown_now()
+            .wait()
+            .expect("Failed to shutdown Tokio Runtime");
+        info!(
+            shutdown_logger,
+            "Runtime cleaned up and shutdown successfully"
+        );
+
+        // The panic hook exits the process itself after a panic
+        if let ShutdownReason::Signal = reason {
+            std::process::exit(0);
+        }
     });
 
     // Setup runtime context with defaults and run the main application
     tokio_executor::with_default(&mut executor, &mut enter, |enter| {
         tokio_timer::with_default(&timer_handle, enter, |enter| {
             enter
-                .block_on(future::lazy(|| async_main()))
+                .block_on(future::lazy(|| async_main(subscription_shutdown_receiver)))
                 .expect("Failed to run main function");
         })
     });
 }
 
-fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
+fn async_main(
+    subscription_shutdown: oneshot::Receiver<()>,
+) -> impl Future<Item = (), Error = ()> + Send + 'static {
     env_logger::init();
     // Setup CLI using Clap, provide general info and capture postgres url
     let matches = App::new("graph-node")
@@ -185,6 +238,14 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
                 .long("debug")
                 .help("Enable debug logging"),
         )
+        .arg(
+            Arg::with_name("log-query-timing")
+                .long("log-query-timing")
+                .help(
+                    "Log how long parsing and executing GraphQL queries takes, \
+                     as well as the SQL of entity queries (requires --debug)",
+                ),
+        )
         .arg(
             Arg::with_name("elasticsearch-url")
                 .long("elasticsearch-url")
@@ -215,6 +276,167 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
                 .env("ETHEREUM_POLLING_INTERVAL")
                 .help("How often to poll the Ethereum node for new blocks"),
         )
+        .arg(
+            Arg::with_name("ethereum-ancestor-count")
+                .long("ethereum-ancestor-count")
+                .value_name("COUNT")
+                .default_value("50")
+                .env("ETHEREUM_ANCESTOR_COUNT")
+                .help("How many ancestors of a new chain head block must be stored before it becomes the chain head"),
+        )
+        .arg(
+            Arg::with_name("store-isolation-level")
+                .long("store-isolation-level")
+                .value_name("LEVEL")
+                .default_value("repeatable-read")
+                .possible_values(&["read-committed", "repeatable-read", "serializable"])
+                .env("GRAPH_STORE_ISOLATION_LEVEL")
+                .help("Transaction isolation level used when processing blocks"),
+        )
+        .arg(
+            Arg::with_name("store-schema-cache-capacity")
+                .long("store-schema-cache-capacity")
+                .value_name("CAPACITY")
+                .default_value("100")
+                .env("GRAPH_STORE_SCHEMA_CACHE_CAPACITY")
+                .help("How many subgraph schemas to keep cached in memory"),
+        )
+        .arg(
+            Arg::with_name("store-connection-pool-size")
+                .long("store-connection-pool-size")
+                .value_name("SIZE")
+                .default_value("10")
+                .env("GRAPH_STORE_CONNECTION_POOL_SIZE")
+                .help("How many connections to Postgres the store may open"),
+        )
+        .arg(
+            Arg::with_name("store-read-connection-reserve")
+                .long("store-read-connection-reserve")
+                .value_name("SIZE")
+                .default_value("2")
+                .env("GRAPH_STORE_READ_CONNECTION_RESERVE")
+                .help("How many additional connections to Postgres are reserved for reads"),
+        )
+        .arg(
+            Arg::with_name("store-read-connection-timeout")
+                .long("store-read-connection-timeout")
+                .value_name("MILLISECONDS")
+                .default_value("500")
+                .env("GRAPH_STORE_READ_CONNECTION_TIMEOUT")
+                .help("How long reads wait for a shared and then a reserved connection before failing"),
+        )
+        .arg(
+            Arg::with_name("store-slow-query-threshold")
+                .long("store-slow-query-threshold")
+                .value_name("MILLISECONDS")
+                .env("GRAPH_STORE_SLOW_QUERY_THRESHOLD")
+                .help("Log entity queries and operations that take at least this long as warnings"),
+        )
+        .arg(
+            Arg::with_name("store-unbounded-query-limit")
+                .long("store-unbounded-query-limit")
+                .value_name("ENTITIES")
+                .default_value("1000")
+                .env("GRAPH_STORE_UNBOUNDED_QUERY_LIMIT")
+                .help(
+                    "How many entities queries without `first` may return, and the \
+                     largest `first` queries may ask for; 0 removes the limit",
+                ),
+        )
+        .arg(
+            Arg::with_name("store-max-block-operations")
+                .long("store-max-block-operations")
+                .value_name("OPERATIONS")
+                .default_value("1000000")
+                .env("GRAPH_STORE_MAX_BLOCK_OPERATIONS")
+                .help("How many entity operations a subgraph may apply for a single block"),
+        )
+        .arg(
+            Arg::with_name("store-subscription-cleanup-interval")
+                .long("store-subscription-cleanup-interval")
+                .value_name("MILLISECONDS")
+                .default_value("5000")
+                .env("GRAPH_STORE_SUBSCRIPTION_CLEANUP_INTERVAL")
+                .help("How often subscriptions that were closed by clients are cleaned up"),
+        )
+        .arg(
+            Arg::with_name("store-replay-buffer-size")
+                .long("store-replay-buffer-size")
+                .value_name("SIZE")
+                .default_value("1000")
+                .env("GRAPH_STORE_REPLAY_BUFFER_SIZE")
+                .help("How many recent entity changes are kept for replaying them to resubscribing clients"),
+        )
+        .arg(
+            Arg::with_name("store-notification-namespace")
+                .long("store-notification-namespace")
+                .value_name("NAMESPACE")
+                .env("GRAPH_STORE_NOTIFICATION_NAMESPACE")
+                .help("Namespace for entity change notifications, for nodes whose stores share a database but not their subgraphs"),
+        )
+        .arg(
+            Arg::with_name("query-concurrency-limit")
+                .long("query-concurrency-limit")
+                .value_name("LIMIT")
+                .default_value("100")
+                .env("GRAPH_QUERY_CONCURRENCY_LIMIT")
+                .help("How many queries for a single subgraph may run at the same time"),
+        )
+        .arg(
+            Arg::with_name("subgraph-query-concurrency-limit")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .long("subgraph-query-concurrency-limit")
+                .value_name("DEPLOYMENT_ID:LIMIT")
+                .help("Overrides the query concurrency limit for a specific subgraph deployment"),
+        )
+        .arg(
+            Arg::with_name("http-allowed-origins")
+                .long("http-allowed-origins")
+                .value_name("ORIGINS")
+                .default_value("*")
+                .env("GRAPH_HTTP_ALLOWED_ORIGINS")
+                .help(
+                    "Comma-separated list of origins that browsers may send \
+                     GraphQL queries from; `*` allows any origin",
+                ),
+        )
+        .arg(
+            Arg::with_name("max-selection-fields")
+                .takes_value(true)
+                .long("max-selection-fields")
+                .value_name("COUNT")
+                .env("GRAPH_GRAPHQL_MAX_SELECTION_FIELDS")
+                .help("Maximum number of fields a GraphQL selection set may expand to"),
+        )
+        .arg(
+            Arg::with_name("max-query-depth")
+                .takes_value(true)
+                .long("max-query-depth")
+                .value_name("DEPTH")
+                .default_value("20")
+                .env("GRAPH_GRAPHQL_MAX_QUERY_DEPTH")
+                .help("Maximum depth to which GraphQL selection sets may be nested"),
+        )
+        .arg(
+            Arg::with_name("ethereum-call-retries")
+                .takes_value(true)
+                .long("ethereum-call-retries")
+                .value_name("COUNT")
+                .default_value("5")
+                .env("GRAPH_ETHEREUM_CALL_RETRIES")
+                .help("How often to retry contract calls from mappings after transport errors"),
+        )
+        .arg(
+            Arg::with_name("ipfs-timeout")
+                .takes_value(true)
+                .long("ipfs-timeout")
+                .value_name("SECONDS")
+                .default_value("30")
+                .env("GRAPH_IPFS_TIMEOUT")
+                .help("How long mappings wait for files requested with ipfs.cat"),
+        )
         .get_matches();
 
     // Set up logger
@@ -223,6 +445,81 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
     // Safe to unwrap because a value is required by CLI
     let postgres_url = matches.value_of("postgres-url").unwrap().to_string();
 
+    // Safe to unwrap because a default value is provided and values are validated by the CLI
+    let isolation_level: IsolationLevel = matches
+        .value_of("store-isolation-level")
+        .unwrap()
+        .parse()
+        .unwrap();
+    let schema_cache_capacity = matches
+        .value_of("store-schema-cache-capacity")
+        .unwrap()
+        .parse()
+        .expect("Schema cache capacity must be a nonnegative integer");
+    let conn_pool_size = matches
+        .value_of("store-connection-pool-size")
+        .unwrap()
+        .parse()
+        .expect("Connection pool size must be a positive integer");
+    let read_conn_reserve = matches
+        .value_of("store-read-connection-reserve")
+        .unwrap()
+        .parse()
+        .expect("Read connection reserve must be a nonnegative integer");
+    let read_conn_timeout = Duration::from_millis(
+        matches
+            .value_of("store-read-connection-timeout")
+            .unwrap()
+            .parse()
+            .expect("Read connection timeout must be a nonnegative integer"),
+    );
+
+    let unbounded_query_limit = match matches
+        .value_of("store-unbounded-query-limit")
+        .unwrap()
+        .parse()
+        .expect("Unbounded query limit must be a nonnegative integer")
+    {
+        0 => None,
+        limit => Some(limit),
+    };
+
+    let max_block_operations = matches
+        .value_of("store-max-block-operations")
+        .unwrap()
+        .parse()
+        .expect("Maximum number of block operations must be a nonnegative integer");
+
+    // A zero interval would make the cleanup timer panic
+    let subscription_cleanup_interval = match matches
+        .value_of("store-subscription-cleanup-interval")
+        .unwrap()
+        .parse()
+        .ok()
+    {
+        Some(0) | None => panic!("Subscription cleanup interval must be a positive integer"),
+        Some(millis) => Duration::from_millis(millis),
+    };
+
+    let replay_buffer_size = matches
+        .value_of("store-replay-buffer-size")
+        .unwrap()
+        .parse()
+        .expect("Replay buffer size must be a nonnegative integer");
+
+    let slow_query_threshold = matches.value_of("store-slow-query-threshold").map(|ms| {
+        Duration::from_millis(
+            ms.parse()
+                .expect("Slow query threshold must be a nonnegative integer"),
+        )
+    });
+
+    let notification_namespace = matches
+        .value_of("store-notification-namespace")
+        .map(String::from);
+
+    let log_query_timing = matches.is_present("log-query-timing");
+
     let node_id = NodeId::new(matches.value_of("node-id").unwrap())
         .expect("Node ID must contain only a-z, A-Z, 0-9, and '_'");
 
@@ -242,6 +539,16 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
             .expect("Ethereum polling interval must be a nonnegative integer"),
     );
 
+    // Safe to unwrap because a default value is provided
+    let ancestor_count: u64 = matches
+        .value_of("ethereum-ancestor-count")
+        .unwrap()
+        .parse()
+        .expect("Ethereum ancestor count must be a nonnegative integer");
+    if ancestor_count == 0 {
+        panic!("Ethereum ancestor count must be at least 1");
+    }
+
     // Obtain ports to use for the GraphQL server(s)
     let http_port = matches
         .value_of("http-port")
@@ -254,6 +561,60 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
         .parse()
         .expect("invalid GraphQL WebSocket server port");
 
+    // Obtain per-subgraph query concurrency limits
+    let query_concurrency_limit = matches
+        .value_of("query-concurrency-limit")
+        .unwrap()
+        .parse()
+        .expect("Query concurrency limit must be a nonnegative integer");
+    let query_limits = matches
+        .values_of("subgraph-query-concurrency-limit")
+        .map(|values| values.collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .fold(
+            QueryConcurrencyLimits::new(query_concurrency_limit),
+            |limits, value| {
+                let (id, limit) = parse_subgraph_query_concurrency_limit(value)
+                    .expect("failed to parse subgraph query concurrency limit");
+                limits.with_limit(id, limit)
+            },
+        );
+
+    // Obtain the origins that browsers may send GraphQL queries from
+    // Safe to unwrap because a default value is provided
+    let allowed_origins = AllowedOrigins::parse(matches.value_of("http-allowed-origins").unwrap());
+
+    // Obtain the limit for the number of fields in GraphQL selection sets, if any
+    let max_selection_fields = matches.value_of("max-selection-fields").map(|value| {
+        value
+            .parse()
+            .expect("Maximum number of selection fields must be a nonnegative integer")
+    });
+
+    // Safe to unwrap because a default value is provided
+    let max_query_depth = matches
+        .value_of("max-query-depth")
+        .unwrap()
+        .parse()
+        .expect("Maximum query depth must be a nonnegative integer");
+
+    // Safe to unwrap because a default value is provided
+    let ethereum_call_retries = matches
+        .value_of("ethereum-call-retries")
+        .unwrap()
+        .parse()
+        .expect("Ethereum call retry count must be a nonnegative integer");
+
+    // Safe to unwrap because a default value is provided
+    let ipfs_timeout = Duration::from_secs(
+        matches
+            .value_of("ipfs-timeout")
+            .unwrap()
+            .parse()
+            .expect("IPFS timeout must be a nonnegative number of seconds"),
+    );
+
     // Obtain JSON-RPC server port
     let json_rpc_port = matches
         .value_of("admin-port")
@@ -381,21 +742,47 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
 
     // Set up Store
     info!(logger, "Connecting to Postgres"; "url" => &postgres_url);
-    let store = Arc::new(DieselStore::new(
+    let store = match DieselStore::new(
         StoreConfig {
             postgres_url,
             network_name: ethereum_network_name.to_owned(),
+            isolation_level,
+            schema_cache_capacity,
+            conn_pool_size,
+            read_conn_reserve,
+            read_conn_timeout,
+            log_queries: log_query_timing,
+            slow_query_threshold,
+            unbounded_query_limit,
+            max_block_operations,
+            subscription_cleanup_interval,
+            replay_buffer_size,
+            notification_namespace,
         },
         &logger,
         eth_net_identifiers,
+    ) {
+        Ok(store) => Arc::new(store),
+        Err(e) => {
+            crit!(logger, "Failed to set up store"; "error" => format!("{}", e));
+            std::process::exit(1);
+        }
+    };
+    let graphql_runner = Arc::new(graph_core::GraphQlRunner::new(
+        &logger,
+        store.clone(),
+        max_selection_fields,
+        max_query_depth,
     ));
-    let graphql_runner = Arc::new(graph_core::GraphQlRunner::new(&logger, store.clone()));
     let mut graphql_server = GraphQLQueryServer::new(
         &logger,
         graphql_runner.clone(),
         store.clone(),
+        query_limits,
         node_id.clone(),
-    );
+    )
+    .with_allowed_origins(allowed_origins)
+    .with_query_timing(log_query_timing);
     let mut subscription_server =
         GraphQLSubscriptionServer::new(&logger, graphql_runner.clone(), store.clone());
 
@@ -403,7 +790,7 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
     let block_ingestor = graph_datasource_ethereum::BlockIngestor::new(
         store.clone(),
         transport.clone(),
-        50, // ancestor count, which we could make configuable
+        ancestor_count,
         logger.clone(),
         block_polling_interval,
     )
@@ -432,7 +819,9 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
 
     // Prepare for hosting WASM runtimes and managing subgraph instances
     let runtime_host_builder =
-        WASMRuntimeHostBuilder::new(ethereum.clone(), ipfs_client.clone(), store.clone());
+        WASMRuntimeHostBuilder::new(ethereum.clone(), ipfs_client.clone(), store.clone())
+            .with_ethereum_call_retries(ethereum_call_retries)
+            .with_ipfs_timeout(ipfs_timeout);
     let subgraph_instance_manager = SubgraphInstanceManager::new(
         &logger,
         store.clone(),
@@ -491,14 +880,17 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
         let subgraph_id =
             SubgraphDeploymentId::new(hash).expect("Subgraph hash must be a valid IPFS hash");
 
+        // Restarting with the same `--subgraph` flag keeps the existing subgraph
+        // and its current version instead of deploying it again
         tokio::spawn(
             subgraph_registrar
                 .create_subgraph(name.clone())
-                .then(
-                    |result| Ok(result.expect("Failed to create subgraph from `--subgraph` flag")),
-                )
+                .then(|result| match result {
+                    Ok(_) | Err(SubgraphRegistrarError::NameExists(_)) => Ok(()),
+                    Err(e) => panic!("Failed to create subgraph from `--subgraph` flag: {}", e),
+                })
                 .and_then(move |_| {
-                    subgraph_registrar.create_subgraph_version(name, subgraph_id, node_id)
+                    subgraph_registrar.upsert_subgraph_version(name, subgraph_id, node_id, None)
                 })
                 .then(|result| {
                     Ok(result.expect("Failed to deploy subgraph from `--subgraph` flag"))
@@ -513,10 +905,10 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
             .expect("Failed to start GraphQL query server"),
     );
 
-    // Serve GraphQL subscriptions over WebSockets
+    // Serve GraphQL subscriptions over WebSockets until shutdown
     tokio::spawn(
         subscription_server
-            .serve(ws_port)
+            .serve(ws_port, subscription_shutdown)
             .expect("Failed to start GraphQL subscription server"),
     );
 
@@ -555,3 +947,23 @@ fn parse_ethereum_network_and_node(s: &str) -> Result<(&str, &str), Error> {
 
     Ok((name, loc))
 }
+
+/// Parses a per-subgraph query concurrency limit (format is "DEPLOYMENT_ID:LIMIT").
+fn parse_subgraph_query_concurrency_limit(s: &str) -> Result<(SubgraphDeploymentId, usize), Error> {
+    let split_at = s.rfind(':').ok_or_else(|| {
+        format_err!(
+            "Subgraph query concurrency limits must have the format \
+             DEPLOYMENT_ID:LIMIT, got {:?}",
+            s
+        )
+    })?;
+    let (id, limit_with_delim) = s.split_at(split_at);
+
+    let id = SubgraphDeploymentId::new(id)
+        .map_err(|()| format_err!("Invalid subgraph deployment ID: {:?}", id))?;
+    let limit = limit_with_delim[1..]
+        .parse()
+        .map_err(|e| format_err!("Invalid query concurrency limit in {:?}: {}", s, e))?;
+
+    Ok((id, limit))
+}
@@ -391,6 +391,16 @@ pub(crate) type Bytes = Uint8Array;
 /// cases correctly.
 pub(crate) type AscBigInt = Uint8Array;
 
+/// Big decimals are represented as `digits * 10^exp`, with the digits stored like a
+/// `BigInt`.
+#[repr(C)]
+pub(crate) struct AscBigDecimal {
+    pub digits: AscPtr<AscBigInt>,
+    pub exp: i32,
+}
+
+impl AscType for AscBigDecimal {}
+
 pub(crate) type AscAddress = Uint8Array;
 pub(crate) type AscH160 = Uint8Array;
 pub(crate) type AscH256 = Uint8Array;
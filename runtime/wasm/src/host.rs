@@ -1,7 +1,7 @@
 use futures::sync::mpsc::{channel, Sender};
 use futures::sync::oneshot;
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use graph::components::ethereum::*;
 use graph::components::store::Store;
@@ -17,15 +17,34 @@ use graph::web3::types::{Log, Transaction};
 use super::EventHandlerContext;
 use module::{WasmiModule, WasmiModuleConfig};
 
+/// How often `ethereum.call` is retried after transport or RPC errors by default.
+const DEFAULT_ETHEREUM_CALL_RETRIES: usize = 5;
+
+/// How many seconds `ipfs.cat` waits for a file by default.
+const DEFAULT_IPFS_TIMEOUT_SECS: u64 = 30;
+
+/// How many entity operations may pile up while handling a block by default,
+/// before a warning is logged and before handlers fail, respectively.
+const DEFAULT_ENTITY_OPERATIONS_WARNING_THRESHOLD: usize = 100_000;
+const DEFAULT_MAX_ENTITY_OPERATIONS: usize = 1_000_000;
+
 pub struct RuntimeHostConfig {
     subgraph_id: SubgraphDeploymentId,
     data_source: DataSource,
+    ethereum_call_retries: usize,
+    entity_operations_warning_threshold: usize,
+    max_entity_operations: usize,
+    ipfs_timeout: Duration,
 }
 
 pub struct RuntimeHostBuilder<T, L, S> {
     ethereum_adapter: Arc<T>,
     link_resolver: Arc<L>,
     store: Arc<S>,
+    ethereum_call_retries: usize,
+    entity_operations_warning_threshold: usize,
+    max_entity_operations: usize,
+    ipfs_timeout: Duration,
 }
 
 impl<T, L, S> Clone for RuntimeHostBuilder<T, L, S>
@@ -39,6 +58,10 @@ where
             ethereum_adapter: self.ethereum_adapter.clone(),
             link_resolver: self.link_resolver.clone(),
             store: self.store.clone(),
+            ethereum_call_retries: self.ethereum_call_retries,
+            entity_operations_warning_threshold: self.entity_operations_warning_threshold,
+            max_entity_operations: self.max_entity_operations,
+            ipfs_timeout: self.ipfs_timeout,
         }
     }
 }
@@ -54,8 +77,36 @@ where
             ethereum_adapter,
             link_resolver,
             store,
+            ethereum_call_retries: DEFAULT_ETHEREUM_CALL_RETRIES,
+            entity_operations_warning_threshold: DEFAULT_ENTITY_OPERATIONS_WARNING_THRESHOLD,
+            max_entity_operations: DEFAULT_MAX_ENTITY_OPERATIONS,
+            ipfs_timeout: Duration::from_secs(DEFAULT_IPFS_TIMEOUT_SECS),
         }
     }
+
+    /// Sets how often `ethereum.call` is retried after transport or RPC errors.
+    pub fn with_ethereum_call_retries(mut self, retries: usize) -> Self {
+        self.ethereum_call_retries = retries;
+        self
+    }
+
+    /// Sets how many entity operations may pile up while handling a block before a
+    /// warning is logged, and how many before handlers fail.
+    pub fn with_entity_operations_limits(
+        mut self,
+        warning_threshold: usize,
+        max_entity_operations: usize,
+    ) -> Self {
+        self.entity_operations_warning_threshold = warning_threshold;
+        self.max_entity_operations = max_entity_operations;
+        self
+    }
+
+    /// Sets how long `ipfs.cat` waits for a file before failing the handler.
+    pub fn with_ipfs_timeout(mut self, timeout: Duration) -> Self {
+        self.ipfs_timeout = timeout;
+        self
+    }
 }
 
 impl<T, L, S> RuntimeHostBuilderTrait for RuntimeHostBuilder<T, L, S>
@@ -80,6 +131,10 @@ where
             RuntimeHostConfig {
                 subgraph_id,
                 data_source,
+                ethereum_call_retries: self.ethereum_call_retries,
+                entity_operations_warning_threshold: self.entity_operations_warning_threshold,
+                max_entity_operations: self.max_entity_operations,
+                ipfs_timeout: self.ipfs_timeout,
             },
         )
     }
@@ -107,6 +162,8 @@ pub struct RuntimeHost {
     data_source_event_handlers: Vec<MappingEventHandler>,
     handle_event_sender: Sender<HandleEventRequest>,
     _guard: oneshot::Sender<()>,
+    /// Cancels requests the module is waiting for when the host is dropped.
+    _cancel_guard: CancelGuard,
 }
 
 impl RuntimeHost {
@@ -129,6 +186,8 @@ impl RuntimeHost {
 
         // Create channel for canceling the module
         let (cancel_sender, cancel_receiver) = oneshot::channel();
+        let cancel_guard = CancelGuard::new();
+        let cancel_handle = cancel_guard.handle();
 
         // Create channel for event handling requests
         let (handle_event_sender, handle_event_receiver) = channel(100);
@@ -161,6 +220,9 @@ impl RuntimeHost {
             })?
             .clone();
 
+        // Channel for reporting whether the module could be loaded
+        let (module_sender, module_receiver) = oneshot::channel();
+
         thread::spawn(move || {
             debug!(module_logger, "Start WASM runtime");
 
@@ -171,11 +233,25 @@ impl RuntimeHost {
                 ethereum_adapter: ethereum_adapter.clone(),
                 link_resolver: link_resolver.clone(),
                 store: store.clone(),
+                ethereum_call_retries: config.ethereum_call_retries,
+                entity_operations_warning_threshold: config.entity_operations_warning_threshold,
+                max_entity_operations: config.max_entity_operations,
+                ipfs_timeout: config.ipfs_timeout,
+                cancel_handle,
             };
 
-            // Start the mapping as a WASM module
-            let mut module = WasmiModule::new(&module_logger, wasmi_config, task_sender)
-                .expect("Failed to load module");
+            // Start the mapping as a WASM module; if that fails, report the
+            // error to the caller instead of bringing down the thread
+            let mut module = match WasmiModule::new(&module_logger, wasmi_config, task_sender) {
+                Ok(module) => {
+                    module_sender.send(Ok(())).ok();
+                    module
+                }
+                Err(e) => {
+                    module_sender.send(Err(e)).ok();
+                    return;
+                }
+            };
 
             // Pass incoming events to the WASM module and send entity changes back;
             // stop when cancelled from the outside
@@ -217,6 +293,17 @@ impl RuntimeHost {
                 .ok();
         });
 
+        module_receiver
+            .wait()
+            .map_err(|_| format_err!("WASM runtime thread terminated unexpectedly"))?
+            .map_err(|e| {
+                format_err!(
+                    "Failed to load the mapping of data source \"{}\": {}",
+                    data_source_name,
+                    e
+                )
+            })?;
+
         Ok(RuntimeHost {
             data_source_name,
             data_source_contract,
@@ -224,6 +311,7 @@ impl RuntimeHost {
             data_source_event_handlers,
             handle_event_sender,
             _guard: cancel_sender,
+            _cancel_guard: cancel_guard,
         })
     }
 
@@ -6,8 +6,8 @@ use graph::data::store::scalar;
 use graph::data::subgraph::DataSource;
 use graph::prelude::*;
 use graph::serde_json;
-use graph::web3::types::H160;
-use std::collections::HashMap;
+use graph::web3::types::{H160, H256};
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::mem;
 use std::ops::Deref;
@@ -31,6 +31,15 @@ impl<E: fmt::Display> fmt::Display for HostExportError<E> {
     }
 }
 
+/// Identifies an `ethereum.call` whose result can be reused within a block.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct EthereumCallKey {
+    address: H160,
+    function: String,
+    args: Vec<u8>,
+    pub(crate) block_hash: H256,
+}
+
 pub(crate) struct HostExports<E, L, S, U> {
     subgraph_id: SubgraphDeploymentId,
     data_source: DataSource,
@@ -38,6 +47,13 @@ pub(crate) struct HostExports<E, L, S, U> {
     link_resolver: Arc<L>,
     store: Arc<S>,
     task_sink: U,
+    ethereum_call_retries: usize,
+    entity_operations_warning_threshold: usize,
+    max_entity_operations: usize,
+    ipfs_timeout: Duration,
+    cancel_handle: CancelHandle,
+    /// Results of the calls made while processing the current block.
+    pub(crate) ethereum_call_cache: HashMap<EthereumCallKey, Vec<Token>>,
     pub(crate) ctx: Option<EventHandlerContext>,
 }
 
@@ -55,6 +71,11 @@ where
         link_resolver: Arc<L>,
         store: Arc<S>,
         task_sink: U,
+        ethereum_call_retries: usize,
+        entity_operations_warning_threshold: usize,
+        max_entity_operations: usize,
+        ipfs_timeout: Duration,
+        cancel_handle: CancelHandle,
         ctx: Option<EventHandlerContext>,
     ) -> Self {
         HostExports {
@@ -64,6 +85,12 @@ where
             link_resolver,
             store,
             task_sink,
+            ethereum_call_retries,
+            entity_operations_warning_threshold,
+            max_entity_operations,
+            ipfs_timeout,
+            cancel_handle,
+            ethereum_call_cache: HashMap::new(),
             ctx,
         }
     }
@@ -96,6 +123,14 @@ where
         )))
     }
 
+    /// Lets a mapping stop processing with a message of its own choosing.
+    pub(crate) fn abort_with_message(
+        &self,
+        message: String,
+    ) -> Result<(), HostExportError<String>> {
+        Err(HostExportError(format!("Mapping aborted: {}", message)))
+    }
+
     pub(crate) fn store_set(
         &mut self,
         entity_type: String,
@@ -114,34 +149,54 @@ where
             _ => (),
         }
 
-        self.ctx
-            .as_mut()
-            .map(|ctx| &mut ctx.entity_operations)
-            .expect("processing event without context")
-            .push(EntityOperation::Set {
-                key: EntityKey {
-                    subgraph_id: self.subgraph_id.clone(),
-                    entity_type,
-                    entity_id,
-                },
-                data: Entity::from(data),
-            });
+        let key = EntityKey {
+            subgraph_id: self.subgraph_id.clone(),
+            entity_type,
+            entity_id,
+        };
+        self.push_entity_operation(EntityOperation::Set {
+            key,
+            data: Entity::from(data),
+        })
+    }
 
-        Ok(())
+    pub(crate) fn store_remove(
+        &mut self,
+        entity_type: String,
+        entity_id: String,
+    ) -> Result<(), HostExportError<impl ExportError>> {
+        let key = EntityKey {
+            subgraph_id: self.subgraph_id.clone(),
+            entity_type,
+            entity_id,
+        };
+        self.push_entity_operation(EntityOperation::Remove { key })
     }
 
-    pub(crate) fn store_remove(&mut self, entity_type: String, entity_id: String) {
-        self.ctx
-            .as_mut()
-            .map(|ctx| &mut ctx.entity_operations)
-            .expect("processing event without context")
-            .push(EntityOperation::Remove {
-                key: EntityKey {
-                    subgraph_id: self.subgraph_id.clone(),
-                    entity_type,
-                    entity_id,
-                },
-            });
+    /// Adds an operation to those of the block being processed. Warns when the
+    /// operations pile up and fails once there are too many of them, so that a
+    /// runaway handler doesn't exhaust memory.
+    fn push_entity_operation(
+        &mut self,
+        operation: EntityOperation,
+    ) -> Result<(), HostExportError<String>> {
+        let ctx = self.ctx.as_mut().expect("processing event without context");
+
+        if ctx.entity_operations.len() >= self.max_entity_operations {
+            return Err(HostExportError(format!(
+                "Handler exceeded the limit of {} entity operations per block",
+                self.max_entity_operations
+            )));
+        }
+
+        ctx.entity_operations.push(operation);
+        if ctx.entity_operations.len() == self.entity_operations_warning_threshold {
+            warn!(ctx.logger, "Handler has accumulated many entity operations";
+                  "count" => ctx.entity_operations.len(),
+                  "max_entity_operations" => self.max_entity_operations);
+        }
+
+        Ok(())
     }
 
     pub(crate) fn store_get(
@@ -155,24 +210,22 @@ where
             entity_id,
         };
 
-        // Get all operations for this entity
+        // Get all operations for this entity that were buffered earlier in
+        // the current event
         let matching_operations: Vec<_> = self
             .ctx
             .as_ref()
             .map(|ctx| &ctx.entity_operations)
             .expect("processing event without context")
-            .clone()
             .iter()
-            .cloned()
             .filter(|op| op.matches_entity(&store_key))
+            .cloned()
             .collect();
 
         // Shortcut 1: If the latest operation for this entity was a removal,
         // return 0 (= null) to the runtime
         if matching_operations
-            .iter()
-            .peekable()
-            .peek()
+            .last()
             .map(|op| op.is_remove())
             .unwrap_or(false)
         {
@@ -199,8 +252,80 @@ where
             .map_err(HostExportError)
     }
 
-    pub(crate) fn ethereum_call(
+    /// Loads all entities of type `entity_type` whose `field` is `id`, taking into
+    /// account the entity operations buffered earlier in the current event.
+    pub(crate) fn store_load_related(
         &self,
+        entity_type: String,
+        id: String,
+        field: String,
+    ) -> Result<Vec<Entity>, HostExportError<impl ExportError>> {
+        let value = Value::String(id);
+
+        // Group the operations buffered for entities of this type by entity ID
+        let mut buffered_operations: BTreeMap<String, Vec<EntityOperation>> = BTreeMap::new();
+        for op in self
+            .ctx
+            .as_ref()
+            .map(|ctx| &ctx.entity_operations)
+            .expect("processing event without context")
+        {
+            match op {
+                EntityOperation::Set { key, .. } | EntityOperation::Remove { key }
+                    if key.subgraph_id == self.subgraph_id && key.entity_type == entity_type =>
+                {
+                    buffered_operations
+                        .entry(key.entity_id.clone())
+                        .or_insert_with(Vec::new)
+                        .push(op.clone());
+                }
+                _ => (),
+            }
+        }
+
+        let query = EntityQuery::new(self.subgraph_id.clone(), entity_type.as_str())
+            .filter(EntityFilter::Equal(field.clone(), value.clone()));
+        let stored_entities = self.store.find(query).map_err(HostExportError)?;
+
+        // Apply buffered operations to the entities in the store
+        let mut entities = vec![];
+        for entity in stored_entities {
+            let entity_id = entity
+                .id()
+                .map_err(QueryExecutionError::StoreError)
+                .map_err(HostExportError)?;
+            match buffered_operations.remove(&entity_id) {
+                None => entities.push(entity),
+                Some(ops) => entities.extend(
+                    EntityOperation::apply_all(Some(entity), &ops)
+                        .map_err(QueryExecutionError::StoreError)
+                        .map_err(HostExportError)?,
+                ),
+            }
+        }
+
+        // Entities that were written in the current event may not be related in
+        // the store yet
+        for (entity_id, ops) in buffered_operations {
+            let key = EntityKey {
+                subgraph_id: self.subgraph_id.clone(),
+                entity_type: entity_type.clone(),
+                entity_id,
+            };
+            let entity = self.store.get(key).map_err(HostExportError)?;
+            entities.extend(
+                EntityOperation::apply_all(entity, &ops)
+                    .map_err(QueryExecutionError::StoreError)
+                    .map_err(HostExportError)?,
+            );
+        }
+
+        entities.retain(|entity| entity.get(&field) == Some(&value));
+        Ok(entities)
+    }
+
+    pub(crate) fn ethereum_call(
+        &mut self,
         unresolved_call: UnresolvedContractCall,
     ) -> Result<Vec<Token>, HostExportError<impl ExportError>> {
         let ctx = self.ctx.as_ref().expect("processing event without context");
@@ -243,28 +368,106 @@ where
             args: unresolved_call.function_args.clone(),
         };
 
-        // Run Ethereum call in tokio runtime
+        // Reuse the result of an identical call made earlier in the same block
+        let key = EthereumCallKey {
+            address: call.address,
+            function: call.function.name.clone(),
+            args: ::ethabi::encode(&call.args),
+            block_hash: call.block_ptr.hash,
+        };
+        if let Some(result) = self.ethereum_call_cache.get(&key) {
+            return Ok(result.clone());
+        }
+
+        // Run Ethereum call in tokio runtime. This is the only place that
+        // retries failed calls; retry transport and RPC errors, but not calls
+        // that fail the same way every time, e.g. because the contract reverted.
+        // Back off for seconds rather than milliseconds between attempts so that
+        // calls survive brief node outages and rate limiting
         let eth_adapter = self.ethereum_adapter.clone();
         let logger = ctx.logger.clone();
-        self.block_on(future::lazy(move || {
-            eth_adapter.contract_call(&logger, call).map_err(move |e| {
-                HostExportError(format!(
-                    "Failed to call function \"{}\" of contract \"{}\": {}",
-                    unresolved_call.function_name, unresolved_call.contract_name, e
-                ))
-            })
-        }))
+        let attempts = self.ethereum_call_retries + 1;
+        let contract_name = unresolved_call.contract_name.clone();
+        let result = self.block_on(future::lazy(move || {
+            retry("ethereum.call", &logger)
+                .when(
+                    |result: &Result<_, EthereumContractCallError>| match result {
+                        Err(EthereumContractCallError::CallError(_))
+                        | Err(EthereumContractCallError::Error(_)) => true,
+                        _ => false,
+                    },
+                )
+                .limit(attempts)
+                .min_delay(Duration::from_secs(1))
+                .max_delay(Duration::from_secs(10))
+                .no_timeout()
+                .run(move || eth_adapter.contract_call(&logger, call.clone()))
+                .map_err(move |e| {
+                    HostExportError(format!(
+                        "Failed to call function \"{}\" of contract \"{}\": {}",
+                        unresolved_call.function_name, unresolved_call.contract_name, e
+                    ))
+                })
+        }))?;
+
+        // Make sure the result has the outputs the ABI declares; if it doesn't,
+        // the ABI in the manifest is most likely not the one of the contract
+        if result.len() != function.outputs.len() {
+            return Err(HostExportError(format!(
+                "Call to function \"{}\" of contract \"{}\" returned {} values \
+                 instead of the {} its ABI declares",
+                function.name,
+                contract_name,
+                result.len(),
+                function.outputs.len()
+            )));
+        }
+        for (i, (token, output)) in result.iter().zip(function.outputs.iter()).enumerate() {
+            if !token.type_check(&output.kind) {
+                return Err(HostExportError(format!(
+                    "Call to function \"{}\" of contract \"{}\" returned {:?} as output {}, \
+                     but its ABI declares it to be of type {:?}",
+                    function.name, contract_name, token, i, output.kind
+                )));
+            }
+        }
+
+        self.ethereum_call_cache.insert(key, result.clone());
+        Ok(result)
+    }
+
+    /// Returns the number of the block that contains the event being processed.
+    pub(crate) fn ethereum_block_number(
+        &self,
+    ) -> Result<BigInt, HostExportError<impl ExportError>> {
+        let ctx = self.ctx.as_ref().expect("processing event without context");
+        ctx.block
+            .block
+            .number
+            .map(BigInt::from)
+            .ok_or_else(|| HostExportError("Block of the event being processed has no number"))
     }
 
+    /// Converts UTF-8 bytes to a string, trimming all trailing null characters.
+    ///
+    /// This suits strings that were encoded in a fixed length buffer, such as a
+    /// `bytes32`, and padded with nulls. Null characters that are part of the
+    /// actual data are trimmed as well if they come last; use
+    /// `bytes_to_string_strict` to keep them.
     pub(crate) fn bytes_to_string(
         &self,
         bytes: Vec<u8>,
     ) -> Result<String, HostExportError<impl ExportError>> {
-        let s = String::from_utf8(bytes).map_err(HostExportError)?;
-        // The string may have been encoded in a fixed length
-        // buffer and padded with null characters, so trim
-        // trailing nulls.
-        Ok(s.trim_right_matches('\u{0000}').to_string())
+        self.bytes_to_string_strict(bytes)
+            .map(|s| s.trim_right_matches('\u{0000}').to_string())
+    }
+
+    /// Converts UTF-8 bytes to a string, keeping trailing null characters.
+    pub(crate) fn bytes_to_string_strict(
+        &self,
+        bytes: Vec<u8>,
+    ) -> Result<String, HostExportError<impl ExportError>> {
+        String::from_utf8(bytes).map_err(HostExportError)
     }
 
     /// Converts bytes to a hex string.
@@ -277,6 +480,59 @@ where
         format!("0x{}", ::hex::encode(bytes))
     }
 
+    /// Converts an address to a `0x`-prefixed hex string with the mixed-case
+    /// checksum of EIP-55, e.g. `0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed`, as
+    /// block explorers show it. `bytes_to_hex` gives the all-lowercase form.
+    pub(crate) fn address_to_string(&self, address: H160) -> String {
+        let hex = ::hex::encode(address.0);
+
+        // Uppercase each letter whose nibble in the hash of the lowercase
+        // hex string is 8 or more
+        let hash = ::tiny_keccak::keccak256(hex.as_bytes());
+        let checksummed: String = hex
+            .chars()
+            .enumerate()
+            .map(|(i, c)| {
+                let nibble = if i % 2 == 0 {
+                    hash[i / 2] >> 4
+                } else {
+                    hash[i / 2] & 0x0f
+                };
+                if nibble >= 8 {
+                    c.to_ascii_uppercase()
+                } else {
+                    c
+                }
+            })
+            .collect();
+
+        format!("0x{}", checksummed)
+    }
+
+    /// Converts bytes to a base58 string, as used for IPFS hashes.
+    pub(crate) fn bytes_to_base58(&self, bytes: Vec<u8>) -> String {
+        ::bs58::encode(bytes).into_string()
+    }
+
+    /// Decodes a base58 string into bytes.
+    pub(crate) fn base58_to_bytes(
+        &self,
+        string: String,
+    ) -> Result<Vec<u8>, HostExportError<impl ExportError>> {
+        ::bs58::decode(&string)
+            .into_vec()
+            .map_err(|e| HostExportError(format!("Failed to decode `{}` as base58: {}", string, e)))
+    }
+
+    /// Decodes a hex string, with or without a `0x` prefix, into bytes.
+    pub(crate) fn string_to_bytes(
+        &self,
+        string: String,
+    ) -> Result<Vec<u8>, HostExportError<impl ExportError>> {
+        ::hex::decode(string.trim_left_matches("0x"))
+            .map_err(|e| HostExportError(format!("Failed to decode `{}` as hex: {}", string, e)))
+    }
+
     pub(crate) fn big_int_to_string(&self, n: BigInt) -> String {
         format!("{}", n)
     }
@@ -295,6 +551,29 @@ where
         format!("0x{}", ::hex::encode(bytes).trim_left_matches('0'))
     }
 
+    /// Interprets `bytes` as a big-endian unsigned integer of any length.
+    pub(crate) fn bytes_to_big_int(&self, bytes: Vec<u8>) -> BigInt {
+        BigInt::from_unsigned_bytes_be(&bytes)
+    }
+
+    /// Encodes `n` as a big-endian unsigned integer without leading zero bytes.
+    /// The number zero encodes as no bytes at all.
+    pub(crate) fn big_int_to_bytes(
+        &self,
+        n: BigInt,
+    ) -> Result<Vec<u8>, HostExportError<impl ExportError>> {
+        if n < 0.into() {
+            return Err(HostExportError(format!(
+                "Negative BigInt cannot be converted to unsigned bytes: {}",
+                n
+            )));
+        }
+        if n == 0.into() {
+            return Ok(vec![]);
+        }
+        Ok(n.to_bytes_be().1)
+    }
+
     pub(crate) fn big_int_to_i32(
         &self,
         n: BigInt,
@@ -317,45 +596,68 @@ where
         &self,
         bytes: Vec<u8>,
     ) -> Result<serde_json::Value, HostExportError<impl ExportError>> {
-        serde_json::from_reader(&*bytes).map_err(HostExportError)
+        serde_json::from_reader(&*bytes).map_err(|e| {
+            HostExportError(format!(
+                "Failed to parse JSON at line {}, column {}: {} (near `{}`)",
+                e.line(),
+                e.column(),
+                e,
+                json_error_excerpt(&bytes, e.line(), e.column())
+            ))
+        })
     }
 
     pub(crate) fn ipfs_cat(
         &self,
         link: String,
     ) -> Result<Vec<u8>, HostExportError<impl ExportError>> {
+        let cat = self.link_resolver.cat(&Link { link: link.clone() });
         self.block_on(
-            self.link_resolver
-                .cat(&Link { link })
-                .map_err(HostExportError),
+            tokio::timer::Timeout::new(cat, self.ipfs_timeout).map_err(move |e| {
+                if e.is_elapsed() {
+                    HostExportError(format!("Timed out reading `{}` from IPFS", link))
+                } else if let Some(e) = e.into_inner() {
+                    HostExportError(format!("Failed to read `{}` from IPFS: {}", link, e))
+                } else {
+                    HostExportError(format!("Failed to read `{}` from IPFS: timer error", link))
+                }
+            }),
         )
     }
 
-    /// Expects a decimal string.
+    /// Expects a decimal string, surrounding whitespace is ignored.
     pub(crate) fn json_to_i64(
         &self,
         json: String,
     ) -> Result<i64, HostExportError<impl ExportError>> {
-        i64::from_str(&json)
-            .map_err(|_| HostExportError(format!("JSON `{}` cannot be parsed as i64", json)))
+        parse_json_integer(&json, "i64").map_err(HostExportError)
     }
 
-    /// Expects a decimal string.
+    /// Expects a decimal string, surrounding whitespace is ignored.
     pub(crate) fn json_to_u64(
         &self,
         json: String,
     ) -> Result<u64, HostExportError<impl ExportError>> {
-        u64::from_str(&json)
-            .map_err(|_| HostExportError(format!("JSON `{}` cannot be parsed as u64", json)))
+        parse_json_integer(&json, "u64").map_err(HostExportError)
     }
 
-    /// Expects a decimal string.
+    /// Expects a decimal string, surrounding whitespace is ignored.
     pub(crate) fn json_to_f64(
         &self,
         json: String,
     ) -> Result<f64, HostExportError<impl ExportError>> {
-        f64::from_str(&json)
-            .map_err(|_| HostExportError(format!("JSON `{}` cannot be parsed as f64", json)))
+        let s = json.trim();
+        let n = f64::from_str(s)
+            .map_err(|_| HostExportError(format!("JSON `{}` is not a number", json)))?;
+
+        // Numbers that are too large parse as infinity
+        if n.is_infinite() && !s.to_lowercase().contains("inf") {
+            return Err(HostExportError(format!(
+                "JSON `{}` is out of range for f64",
+                json
+            )));
+        }
+        Ok(n)
     }
 
     /// Expects a decimal string.
@@ -368,10 +670,28 @@ where
         Ok(big_int.to_signed_bytes_le())
     }
 
+    /// Expects a decimal string, which may have a fractional part.
+    pub(crate) fn json_to_big_decimal(
+        &self,
+        json: String,
+    ) -> Result<BigDecimal, HostExportError<String>> {
+        BigDecimal::from_str(&json)
+            .map_err(|_| HostExportError(format!("JSON `{}` is not a decimal string", json)))
+            .and_then(checked_big_decimal)
+    }
+
     pub(crate) fn crypto_keccak_256(&self, input: Vec<u8>) -> [u8; 32] {
         ::tiny_keccak::keccak256(&input)
     }
 
+    pub(crate) fn crypto_sha_256(&self, input: Vec<u8>) -> [u8; 32] {
+        use sha2::{Digest, Sha256};
+
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&Sha256::digest(&input));
+        hash
+    }
+
     pub(crate) fn big_int_plus(&self, x: BigInt, y: BigInt) -> BigInt {
         x + y
     }
@@ -392,10 +712,67 @@ where
         x % y
     }
 
-    pub(crate) fn block_on<I: Send + 'static, ER: Send + 'static>(
+    pub(crate) fn big_int_to_big_decimal(&self, n: BigInt) -> BigDecimal {
+        BigDecimal::from(n)
+    }
+
+    pub(crate) fn big_decimal_plus(
+        &self,
+        x: BigDecimal,
+        y: BigDecimal,
+    ) -> Result<BigDecimal, HostExportError<String>> {
+        let (x, y) = checked_operands(x, y)?;
+        checked_big_decimal(x + y)
+    }
+
+    pub(crate) fn big_decimal_minus(
+        &self,
+        x: BigDecimal,
+        y: BigDecimal,
+    ) -> Result<BigDecimal, HostExportError<String>> {
+        let (x, y) = checked_operands(x, y)?;
+        checked_big_decimal(x - y)
+    }
+
+    pub(crate) fn big_decimal_times(
+        &self,
+        x: BigDecimal,
+        y: BigDecimal,
+    ) -> Result<BigDecimal, HostExportError<String>> {
+        let (x, y) = checked_operands(x, y)?;
+        checked_big_decimal(x * y)
+    }
+
+    pub(crate) fn big_decimal_divided_by(
         &self,
-        future: impl Future<Item = I, Error = ER> + Send + 'static,
-    ) -> Result<I, ER> {
+        x: BigDecimal,
+        y: BigDecimal,
+    ) -> Result<BigDecimal, HostExportError<String>> {
+        let (x, y) = checked_operands(x, y)?;
+        x.checked_div(y)
+            .ok_or_else(|| HostExportError("Cannot divide by a zero-valued BigDecimal".to_owned()))
+            .and_then(checked_big_decimal)
+    }
+
+    pub(crate) fn big_decimal_to_string(
+        &self,
+        n: BigDecimal,
+    ) -> Result<String, HostExportError<String>> {
+        checked_big_decimal(n).map(|n| n.to_string())
+    }
+
+    /// Runs `future` on the runtime and waits for its result. Fails early if the
+    /// subgraph is stopped while waiting, so that the mapping doesn't hold up
+    /// shutting the subgraph down.
+    pub(crate) fn block_on<I: Send + 'static>(
+        &self,
+        future: impl Future<Item = I, Error = HostExportError<String>> + Send + 'static,
+    ) -> Result<I, HostExportError<String>> {
+        let future = future.cancelable(&self.cancel_handle, || {
+            HostExportError(String::from(
+                "Cancelled because the subgraph is being stopped",
+            ))
+        });
         let (return_sender, return_receiver) = oneshot::channel();
         self.task_sink
             .clone()
@@ -425,6 +802,44 @@ where
     }
 }
 
+/// Parses a decimal integer from a JSON string, reporting whether a failure was caused
+/// by the string not being a number or by the number not fitting into `type_name`.
+fn parse_json_integer<T: FromStr>(json: &str, type_name: &str) -> Result<T, String> {
+    let s = json.trim();
+    T::from_str(s).map_err(|_| {
+        let digits = if s.starts_with('+') || s.starts_with('-') {
+            &s[1..]
+        } else {
+            s
+        };
+        if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+            format!("JSON `{}` is out of range for {}", json, type_name)
+        } else {
+            format!("JSON `{}` is not a number", json)
+        }
+    })
+}
+
+/// Returns the bytes around the 1-based `line` and `column` of a JSON parse error.
+fn json_error_excerpt(bytes: &[u8], line: usize, column: usize) -> String {
+    const CONTEXT: usize = 20;
+
+    let line_start = if line > 1 {
+        bytes
+            .iter()
+            .enumerate()
+            .filter(|&(_, &b)| b == b'\n')
+            .nth(line - 2)
+            .map_or(0, |(i, _)| i + 1)
+    } else {
+        0
+    };
+    let offset = (line_start + column.saturating_sub(1)).min(bytes.len());
+    let start = offset.saturating_sub(CONTEXT);
+    let end = (offset + CONTEXT).min(bytes.len());
+    String::from_utf8_lossy(&bytes[start..end]).into_owned()
+}
+
 pub(crate) fn string_to_h160(string: &str) -> Result<H160, HostExportError<impl ExportError>> {
     // `H160::from_str` takes a hex string with no leading `0x`.
     let string = string.trim_left_matches("0x");
@@ -432,6 +847,32 @@ pub(crate) fn string_to_h160(string: &str) -> Result<H160, HostExportError<impl
         .map_err(|e| HostExportError(format!("Failed to convert string to Address/H160: {}", e)))
 }
 
+/// The largest exponent, in either direction, that host functions accept in
+/// and return from `BigDecimal`s. Mappings can pass in any `i32` exponent, but
+/// adding or printing decimals whose exponents are that far apart takes
+/// billions of digits.
+const BIG_DECIMAL_MAX_EXP: i64 = 6144;
+
+/// Fails for decimals whose exponent is outside of `BIG_DECIMAL_MAX_EXP`.
+fn checked_big_decimal(n: BigDecimal) -> Result<BigDecimal, HostExportError<String>> {
+    let (_, exp) = n.as_bigint_and_exponent();
+    if exp.abs() > BIG_DECIMAL_MAX_EXP {
+        return Err(HostExportError(format!(
+            "BigDecimal exponent {} is out of range",
+            exp
+        )));
+    }
+    Ok(n)
+}
+
+/// Checks both operands of a `BigDecimal` operation before it is carried out.
+fn checked_operands(
+    x: BigDecimal,
+    y: BigDecimal,
+) -> Result<(BigDecimal, BigDecimal), HostExportError<String>> {
+    Ok((checked_big_decimal(x)?, checked_big_decimal(y)?))
+}
+
 #[test]
 fn test_string_to_h160_with_0x() {
     assert_eq!(
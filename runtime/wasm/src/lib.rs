@@ -1,8 +1,10 @@
+extern crate bs58;
 extern crate ethabi;
 extern crate futures;
 extern crate graph;
 extern crate hex;
 extern crate pwasm_utils;
+extern crate sha2;
 extern crate tiny_keccak;
 extern crate wasmi;
 
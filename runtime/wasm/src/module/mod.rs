@@ -1,6 +1,7 @@
 use std::fmt;
 use std::ops::Deref;
-use std::time::Instant;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 use wasmi::{
     nan_preserving_float::F64, Error, Externals, FuncInstance, FuncRef, HostError, ImportsBuilder,
@@ -49,6 +50,61 @@ const BIG_INT_TIMES: usize = 21;
 const BIG_INT_DIVIDED_BY: usize = 22;
 const BIG_INT_MOD: usize = 23;
 const GAS_FUNC_INDEX: usize = 24;
+const CRYPTO_SHA_256_INDEX: usize = 25;
+const TYPE_CONVERSION_BYTES_TO_BASE_58_INDEX: usize = 26;
+const TYPE_CONVERSION_BASE_58_TO_BYTES_INDEX: usize = 27;
+const TYPE_CONVERSION_BIG_INT_TO_BIG_DECIMAL_INDEX: usize = 28;
+const BIG_DECIMAL_PLUS: usize = 29;
+const BIG_DECIMAL_MINUS: usize = 30;
+const BIG_DECIMAL_TIMES: usize = 31;
+const BIG_DECIMAL_DIVIDED_BY: usize = 32;
+const BIG_DECIMAL_TO_STRING: usize = 33;
+const ABORT_WITH_MESSAGE_FUNC_INDEX: usize = 34;
+const ETHEREUM_BLOCK_NUMBER_FUNC_INDEX: usize = 35;
+const TYPE_CONVERSION_BYTES_TO_BIG_INT_INDEX: usize = 36;
+const TYPE_CONVERSION_BIG_INT_TO_BYTES_INDEX: usize = 37;
+const TYPE_CONVERSION_BYTES_TO_STRING_STRICT_FUNC_INDEX: usize = 38;
+const STORE_LOAD_RELATED_FUNC_INDEX: usize = 39;
+const JSON_TO_BIG_DECIMAL_FUNC_INDEX: usize = 40;
+const TYPE_CONVERSION_STRING_TO_BYTES_FUNC_INDEX: usize = 41;
+const TYPE_CONVERSION_ADDRESS_TO_STRING_FUNC_INDEX: usize = 42;
+
+/// Version of the host API a mapping is compiled against, e.g. `0.0.2`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ApiVersion(u32, u32, u32);
+
+impl FromStr for ApiVersion {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .split('.')
+            .map(|part| part.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format_err!("Invalid API version `{}`", s))?;
+        match parts.as_slice() {
+            [major, minor, patch] => Ok(ApiVersion(*major, *minor, *patch)),
+            _ => Err(format_err!(
+                "Invalid API version `{}`, expected a version like `0.0.2`",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// From this (not yet released) API version on, `typeConversion.bytesToString` keeps
+/// trailing null characters, like `typeConversion.bytesToStringStrict`.
+const STRICT_BYTES_TO_STRING_API_VERSION: ApiVersion = ApiVersion(0, 2, 0);
+
+/// The API version assumed for mappings whose `apiVersion` can't be parsed; it
+/// resolves host functions the way they were resolved before API versions mattered.
+const FALLBACK_API_VERSION: ApiVersion = ApiVersion(0, 0, 1);
 
 pub struct WasmiModuleConfig<T, L, S> {
     pub subgraph_id: SubgraphDeploymentId,
@@ -56,6 +112,19 @@ pub struct WasmiModuleConfig<T, L, S> {
     pub ethereum_adapter: Arc<T>,
     pub link_resolver: Arc<L>,
     pub store: Arc<S>,
+    /// How often to retry `ethereum.call` after transport or RPC errors.
+    pub ethereum_call_retries: usize,
+    /// How many entity operations may pile up while handling a block before
+    /// a warning is logged.
+    pub entity_operations_warning_threshold: usize,
+    /// How many entity operations may pile up while handling a block before
+    /// `store.set` and `store.remove` fail the handler.
+    pub max_entity_operations: usize,
+    /// How long `ipfs.cat` waits for a file.
+    pub ipfs_timeout: Duration,
+    /// Cancels pending `ethereum.call` and `ipfs.cat` requests when the
+    /// subgraph is being stopped.
+    pub cancel_handle: CancelHandle,
 }
 
 /// A WASM module based on wasmi that powers a subgraph runtime.
@@ -82,6 +151,17 @@ where
     ) -> Result<Self, FailureError> {
         let logger = logger.new(o!("component" => "WasmiModule"));
 
+        // The host functions available to the module depend on its API version
+        let api_version = match config.data_source.mapping.api_version.parse::<ApiVersion>() {
+            Ok(api_version) => api_version,
+            Err(e) => {
+                warn!(logger, "Falling back to the host functions of API version {}", FALLBACK_API_VERSION;
+                      "data_source" => &config.data_source.name,
+                      "error" => e.to_string());
+                FALLBACK_API_VERSION
+            }
+        };
+
         let parsed_module = config.data_source.mapping.runtime.clone();
 
         // Inject metering calls, which are used for checking timeouts.
@@ -117,10 +197,11 @@ where
         })?;
 
         // Build import resolver
+        let module_resolver = ModuleResolver::new(api_version);
         let mut imports = ImportsBuilder::new();
         imports.push_resolver("env", &EnvModuleResolver);
         if let Some(user_module) = user_module {
-            imports.push_resolver(user_module, &ModuleResolver);
+            imports.push_resolver(user_module, &module_resolver);
         }
 
         // Instantiate the runtime module using hosted functions and import resolver
@@ -144,6 +225,11 @@ where
             config.link_resolver.clone(),
             config.store.clone(),
             task_sink,
+            config.ethereum_call_retries,
+            config.entity_operations_warning_threshold,
+            config.max_entity_operations,
+            config.ipfs_timeout,
+            config.cancel_handle,
             None,
         );
 
@@ -169,6 +255,17 @@ where
         log: Arc<Log>,
         params: Vec<LogParam>,
     ) -> Result<Vec<EntityOperation>, FailureError> {
+        // Cached contract call results are only reused within the same block
+        let cached_block_hash = self
+            .host_exports
+            .ethereum_call_cache
+            .keys()
+            .next()
+            .map(|key| key.block_hash);
+        if cached_block_hash.is_some() && cached_block_hash != ctx.block.block.hash {
+            self.host_exports.ethereum_call_cache.clear();
+        }
+
         self.host_exports.ctx = Some(ctx);
         self.start_time = Instant::now();
 
@@ -202,10 +299,17 @@ where
                     .entity_operations
             })
             .map_err(|e| {
+                // Report errors raised by host functions, e.g. the message a
+                // mapping aborted with, as they are rather than as a wasmi trap
+                let message = e
+                    .as_host_error()
+                    .and_then(|e| e.downcast_ref::<host_exports::HostExportError<String>>())
+                    .map(|e| e.to_string())
+                    .unwrap_or_else(|| e.to_string());
                 format_err!(
                     "Failed to handle Ethereum event with handler \"{}\": {}",
                     handler_name,
-                    e
+                    message
                 )
             })
     }
@@ -292,6 +396,19 @@ where
             .into())
     }
 
+    /// function abortWithMessage(message: string): void
+    fn abort_with_message(
+        &mut self,
+        message_ptr: AscPtr<AscString>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let message = self.asc_get(message_ptr);
+        Err(self
+            .host_exports
+            .abort_with_message(message)
+            .unwrap_err()
+            .into())
+    }
+
     /// function store.set(entity: string, id: string, data: Entity): void
     fn store_set(
         &mut self,
@@ -314,7 +431,7 @@ where
     ) -> Result<Option<RuntimeValue>, Trap> {
         let entity = self.asc_get(entity_ptr);
         let id = self.asc_get(id_ptr);
-        self.host_exports.store_remove(entity, id);
+        self.host_exports.store_remove(entity, id)?;
         Ok(None)
     }
 
@@ -334,15 +451,40 @@ where
         }))
     }
 
+    /// function store.loadRelated(entity: string, id: string, field: string): Array<Entity>
+    fn store_load_related(
+        &mut self,
+        entity_ptr: AscPtr<AscString>,
+        id_ptr: AscPtr<AscString>,
+        field_ptr: AscPtr<AscString>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let entities = self.host_exports.store_load_related(
+            self.asc_get(entity_ptr),
+            self.asc_get(id_ptr),
+            self.asc_get(field_ptr),
+        )?;
+        let entities_ptr: AscPtr<Array<AscPtr<AscEntity>>> = self.asc_new(&*entities);
+        Ok(Some(RuntimeValue::from(entities_ptr)))
+    }
+
     /// function ethereum.call(call: SmartContractCall): Array<Token>
     fn ethereum_call(
         &mut self,
         call_ptr: AscPtr<AscUnresolvedContractCall>,
     ) -> Result<Option<RuntimeValue>, Trap> {
-        let result = self.host_exports.ethereum_call(self.asc_get(call_ptr))?;
+        let call = self.asc_get(call_ptr);
+        let result = self.host_exports.ethereum_call(call)?;
         Ok(Some(RuntimeValue::from(self.asc_new(&*result))))
     }
 
+    /// function ethereum.blockNumber(): BigInt
+    fn ethereum_block_number(&mut self) -> Result<Option<RuntimeValue>, Trap> {
+        let result = self.host_exports.ethereum_block_number()?;
+        let result_ptr: AscPtr<AscBigInt> = self.asc_new(&result);
+        Ok(Some(RuntimeValue::from(result_ptr)))
+    }
+
+    /// Converts bytes to a string, trimming trailing null characters.
     /// function typeConversion.bytesToString(bytes: Bytes): string
     fn bytes_to_string(
         &mut self,
@@ -352,6 +494,18 @@ where
         Ok(Some(RuntimeValue::from(self.asc_new(&string))))
     }
 
+    /// Converts bytes to a string without trimming trailing null characters.
+    /// function typeConversion.bytesToStringStrict(bytes: Bytes): string
+    fn bytes_to_string_strict(
+        &mut self,
+        bytes_ptr: AscPtr<Uint8Array>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let string = self
+            .host_exports
+            .bytes_to_string_strict(self.asc_get(bytes_ptr))?;
+        Ok(Some(RuntimeValue::from(self.asc_new(&string))))
+    }
+
     /// Converts bytes to a hex string.
     /// function typeConversion.bytesToHex(bytes: Bytes): string
     fn bytes_to_hex(
@@ -362,6 +516,68 @@ where
         Ok(Some(RuntimeValue::from(self.asc_new(&result))))
     }
 
+    /// function typeConversion.bytesToBase58(bytes: Bytes): string
+    fn bytes_to_base58(
+        &mut self,
+        bytes_ptr: AscPtr<Uint8Array>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let result = self.host_exports.bytes_to_base58(self.asc_get(bytes_ptr));
+        Ok(Some(RuntimeValue::from(self.asc_new(&result))))
+    }
+
+    /// function typeConversion.base58ToBytes(s: string): Bytes
+    fn base58_to_bytes(
+        &mut self,
+        str_ptr: AscPtr<AscString>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let bytes = self.host_exports.base58_to_bytes(self.asc_get(str_ptr))?;
+        let bytes_ptr: AscPtr<Uint8Array> = self.asc_new(&*bytes);
+        Ok(Some(RuntimeValue::from(bytes_ptr)))
+    }
+
+    /// function typeConversion.addressToString(address: Address): string
+    fn address_to_string(
+        &mut self,
+        address_ptr: AscPtr<AscH160>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let result = self
+            .host_exports
+            .address_to_string(self.asc_get(address_ptr));
+        Ok(Some(RuntimeValue::from(self.asc_new(&result))))
+    }
+
+    /// function typeConversion.stringToBytes(s: string): Bytes
+    fn string_to_bytes(
+        &mut self,
+        str_ptr: AscPtr<AscString>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let bytes = self.host_exports.string_to_bytes(self.asc_get(str_ptr))?;
+        let bytes_ptr: AscPtr<Uint8Array> = self.asc_new(&*bytes);
+        Ok(Some(RuntimeValue::from(bytes_ptr)))
+    }
+
+    /// function typeConversion.bytesToBigInt(bytes: Bytes): BigInt
+    fn bytes_to_big_int(
+        &mut self,
+        bytes_ptr: AscPtr<Uint8Array>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let result = self.host_exports.bytes_to_big_int(self.asc_get(bytes_ptr));
+        let result_ptr: AscPtr<AscBigInt> = self.asc_new(&result);
+        Ok(Some(RuntimeValue::from(result_ptr)))
+    }
+
+    /// function typeConversion.bigIntToBytes(n: BigInt): Bytes
+    fn big_int_to_bytes(
+        &mut self,
+        big_int_ptr: AscPtr<AscBigInt>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let bytes = self
+            .host_exports
+            .big_int_to_bytes(self.asc_get(big_int_ptr))?;
+        let bytes_ptr: AscPtr<Uint8Array> = self.asc_new(&*bytes);
+        Ok(Some(RuntimeValue::from(bytes_ptr)))
+    }
+
     /// function typeConversion.bigIntToString(n: Uint8Array): string
     fn big_int_to_string(
         &mut self,
@@ -452,6 +668,18 @@ where
         Ok(Some(RuntimeValue::from(big_int_ptr)))
     }
 
+    /// function json.toBigDecimal(json: String): BigDecimal
+    fn json_to_big_decimal(
+        &mut self,
+        json_ptr: AscPtr<AscString>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let big_decimal = self
+            .host_exports
+            .json_to_big_decimal(self.asc_get(json_ptr))?;
+        let big_decimal_ptr: AscPtr<AscBigDecimal> = self.asc_new(&big_decimal);
+        Ok(Some(RuntimeValue::from(big_decimal_ptr)))
+    }
+
     /// function crypto.keccak256(input: Bytes): Bytes
     fn crypto_keccak_256(
         &mut self,
@@ -462,6 +690,16 @@ where
         Ok(Some(RuntimeValue::from(hash_ptr)))
     }
 
+    /// function crypto.sha256(input: Bytes): Bytes
+    fn crypto_sha_256(
+        &mut self,
+        input_ptr: AscPtr<Uint8Array>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let input = self.host_exports.crypto_sha_256(self.asc_get(input_ptr));
+        let hash_ptr: AscPtr<Uint8Array> = self.asc_new(input.as_ref());
+        Ok(Some(RuntimeValue::from(hash_ptr)))
+    }
+
     /// function bigInt.plus(x: BigInt, y: BigInt): BigInt
     fn big_int_plus(
         &mut self,
@@ -526,6 +764,81 @@ where
         let result_ptr: AscPtr<AscBigInt> = self.asc_new(&result);
         Ok(Some(RuntimeValue::from(result_ptr)))
     }
+
+    /// function typeConversion.bigIntToBigDecimal(n: BigInt): BigDecimal
+    fn big_int_to_big_decimal(
+        &mut self,
+        n_ptr: AscPtr<AscBigInt>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let result = self
+            .host_exports
+            .big_int_to_big_decimal(self.asc_get(n_ptr));
+        let result_ptr: AscPtr<AscBigDecimal> = self.asc_new(&result);
+        Ok(Some(RuntimeValue::from(result_ptr)))
+    }
+
+    /// function bigDecimal.plus(x: BigDecimal, y: BigDecimal): BigDecimal
+    fn big_decimal_plus(
+        &mut self,
+        x_ptr: AscPtr<AscBigDecimal>,
+        y_ptr: AscPtr<AscBigDecimal>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let result = self
+            .host_exports
+            .big_decimal_plus(self.asc_get(x_ptr), self.asc_get(y_ptr))?;
+        let result_ptr: AscPtr<AscBigDecimal> = self.asc_new(&result);
+        Ok(Some(RuntimeValue::from(result_ptr)))
+    }
+
+    /// function bigDecimal.minus(x: BigDecimal, y: BigDecimal): BigDecimal
+    fn big_decimal_minus(
+        &mut self,
+        x_ptr: AscPtr<AscBigDecimal>,
+        y_ptr: AscPtr<AscBigDecimal>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let result = self
+            .host_exports
+            .big_decimal_minus(self.asc_get(x_ptr), self.asc_get(y_ptr))?;
+        let result_ptr: AscPtr<AscBigDecimal> = self.asc_new(&result);
+        Ok(Some(RuntimeValue::from(result_ptr)))
+    }
+
+    /// function bigDecimal.times(x: BigDecimal, y: BigDecimal): BigDecimal
+    fn big_decimal_times(
+        &mut self,
+        x_ptr: AscPtr<AscBigDecimal>,
+        y_ptr: AscPtr<AscBigDecimal>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let result = self
+            .host_exports
+            .big_decimal_times(self.asc_get(x_ptr), self.asc_get(y_ptr))?;
+        let result_ptr: AscPtr<AscBigDecimal> = self.asc_new(&result);
+        Ok(Some(RuntimeValue::from(result_ptr)))
+    }
+
+    /// function bigDecimal.dividedBy(x: BigDecimal, y: BigDecimal): BigDecimal
+    fn big_decimal_divided_by(
+        &mut self,
+        x_ptr: AscPtr<AscBigDecimal>,
+        y_ptr: AscPtr<AscBigDecimal>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let result = self
+            .host_exports
+            .big_decimal_divided_by(self.asc_get(x_ptr), self.asc_get(y_ptr))?;
+        let result_ptr: AscPtr<AscBigDecimal> = self.asc_new(&result);
+        Ok(Some(RuntimeValue::from(result_ptr)))
+    }
+
+    /// function bigDecimal.toString(x: BigDecimal): string
+    fn big_decimal_to_string(
+        &mut self,
+        big_decimal_ptr: AscPtr<AscBigDecimal>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let result = self
+            .host_exports
+            .big_decimal_to_string(self.asc_get(big_decimal_ptr))?;
+        Ok(Some(RuntimeValue::from(self.asc_new(&result))))
+    }
 }
 
 impl<T, L, S, U> Externals for WasmiModule<T, L, S, U>
@@ -547,19 +860,29 @@ where
                 args.nth_checked(2)?,
                 args.nth_checked(3)?,
             ),
+            ABORT_WITH_MESSAGE_FUNC_INDEX => self.abort_with_message(args.nth_checked(0)?),
             STORE_SET_FUNC_INDEX => self.store_set(
                 args.nth_checked(0)?,
                 args.nth_checked(1)?,
                 args.nth_checked(2)?,
             ),
             STORE_GET_FUNC_INDEX => self.store_get(args.nth_checked(0)?, args.nth_checked(1)?),
+            STORE_LOAD_RELATED_FUNC_INDEX => self.store_load_related(
+                args.nth_checked(0)?,
+                args.nth_checked(1)?,
+                args.nth_checked(2)?,
+            ),
             STORE_REMOVE_FUNC_INDEX => {
                 self.store_remove(args.nth_checked(0)?, args.nth_checked(1)?)
             }
             ETHEREUM_CALL_FUNC_INDEX => self.ethereum_call(args.nth_checked(0)?),
+            ETHEREUM_BLOCK_NUMBER_FUNC_INDEX => self.ethereum_block_number(),
             TYPE_CONVERSION_BYTES_TO_STRING_FUNC_INDEX => {
                 self.bytes_to_string(args.nth_checked(0)?)
             }
+            TYPE_CONVERSION_BYTES_TO_STRING_STRICT_FUNC_INDEX => {
+                self.bytes_to_string_strict(args.nth_checked(0)?)
+            }
             TYPE_CONVERSION_BYTES_TO_HEX_FUNC_INDEX => self.bytes_to_hex(args.nth_checked(0)?),
             TYPE_CONVERSION_BIG_INT_TO_STRING_FUNC_INDEX => {
                 self.big_int_to_string(args.nth_checked(0)?)
@@ -568,13 +891,28 @@ where
             TYPE_CONVERSION_STRING_TO_H160_FUNC_INDEX => self.string_to_h160(args.nth_checked(0)?),
             TYPE_CONVERSION_I32_TO_BIG_INT_FUNC_INDEX => self.i32_to_big_int(args.nth_checked(0)?),
             TYPE_CONVERSION_BIG_INT_TO_I32_FUNC_INDEX => self.big_int_to_i32(args.nth_checked(0)?),
+            TYPE_CONVERSION_BYTES_TO_BASE_58_INDEX => self.bytes_to_base58(args.nth_checked(0)?),
+            TYPE_CONVERSION_BASE_58_TO_BYTES_INDEX => self.base58_to_bytes(args.nth_checked(0)?),
+            TYPE_CONVERSION_ADDRESS_TO_STRING_FUNC_INDEX => {
+                self.address_to_string(args.nth_checked(0)?)
+            }
+            TYPE_CONVERSION_STRING_TO_BYTES_FUNC_INDEX => {
+                self.string_to_bytes(args.nth_checked(0)?)
+            }
+            TYPE_CONVERSION_BIG_INT_TO_BIG_DECIMAL_INDEX => {
+                self.big_int_to_big_decimal(args.nth_checked(0)?)
+            }
+            TYPE_CONVERSION_BYTES_TO_BIG_INT_INDEX => self.bytes_to_big_int(args.nth_checked(0)?),
+            TYPE_CONVERSION_BIG_INT_TO_BYTES_INDEX => self.big_int_to_bytes(args.nth_checked(0)?),
             JSON_FROM_BYTES_FUNC_INDEX => self.json_from_bytes(args.nth_checked(0)?),
             JSON_TO_I64_FUNC_INDEX => self.json_to_i64(args.nth_checked(0)?),
             JSON_TO_U64_FUNC_INDEX => self.json_to_u64(args.nth_checked(0)?),
             JSON_TO_F64_FUNC_INDEX => self.json_to_f64(args.nth_checked(0)?),
             JSON_TO_BIG_INT_FUNC_INDEX => self.json_to_big_int(args.nth_checked(0)?),
+            JSON_TO_BIG_DECIMAL_FUNC_INDEX => self.json_to_big_decimal(args.nth_checked(0)?),
             IPFS_CAT_FUNC_INDEX => self.ipfs_cat(args.nth_checked(0)?),
             CRYPTO_KECCAK_256_INDEX => self.crypto_keccak_256(args.nth_checked(0)?),
+            CRYPTO_SHA_256_INDEX => self.crypto_sha_256(args.nth_checked(0)?),
             BIG_INT_PLUS => self.big_int_plus(args.nth_checked(0)?, args.nth_checked(1)?),
             BIG_INT_MINUS => self.big_int_minus(args.nth_checked(0)?, args.nth_checked(1)?),
             BIG_INT_TIMES => self.big_int_times(args.nth_checked(0)?, args.nth_checked(1)?),
@@ -582,6 +920,13 @@ where
                 self.big_int_divided_by(args.nth_checked(0)?, args.nth_checked(1)?)
             }
             BIG_INT_MOD => self.big_int_mod(args.nth_checked(0)?, args.nth_checked(1)?),
+            BIG_DECIMAL_PLUS => self.big_decimal_plus(args.nth_checked(0)?, args.nth_checked(1)?),
+            BIG_DECIMAL_MINUS => self.big_decimal_minus(args.nth_checked(0)?, args.nth_checked(1)?),
+            BIG_DECIMAL_TIMES => self.big_decimal_times(args.nth_checked(0)?, args.nth_checked(1)?),
+            BIG_DECIMAL_DIVIDED_BY => {
+                self.big_decimal_divided_by(args.nth_checked(0)?, args.nth_checked(1)?)
+            }
+            BIG_DECIMAL_TO_STRING => self.big_decimal_to_string(args.nth_checked(0)?),
             GAS_FUNC_INDEX => self.gas(args.nth_checked(0)?),
             _ => panic!("Unimplemented function at {}", index),
         }
@@ -596,6 +941,9 @@ impl ModuleImportResolver for EnvModuleResolver {
         Ok(match field_name {
             "gas" => FuncInstance::alloc_host(signature.clone(), GAS_FUNC_INDEX),
             "abort" => FuncInstance::alloc_host(signature.clone(), ABORT_FUNC_INDEX),
+            "abortWithMessage" => {
+                FuncInstance::alloc_host(signature.clone(), ABORT_WITH_MESSAGE_FUNC_INDEX)
+            }
             _ => {
                 return Err(Error::Instantiation(format!(
                     "Export '{}' not found",
@@ -606,68 +954,92 @@ impl ModuleImportResolver for EnvModuleResolver {
     }
 }
 
-pub struct ModuleResolver;
+/// Resolves the host functions a module imports, depending on the API version the
+/// module is compiled against.
+pub struct ModuleResolver {
+    api_version: ApiVersion,
+}
+
+impl ModuleResolver {
+    pub fn new(api_version: ApiVersion) -> Self {
+        ModuleResolver { api_version }
+    }
 
-impl ModuleImportResolver for ModuleResolver {
-    fn resolve_func(&self, field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
-        let signature = signature.clone();
-        Ok(match field_name {
+    /// Returns the index of the host function that implements an import, if any.
+    fn host_function_index(&self, field_name: &str) -> Option<usize> {
+        Some(match field_name {
             // store
-            "store.set" => FuncInstance::alloc_host(signature, STORE_SET_FUNC_INDEX),
-            "store.remove" => FuncInstance::alloc_host(signature, STORE_REMOVE_FUNC_INDEX),
-            "store.get" => FuncInstance::alloc_host(signature, STORE_GET_FUNC_INDEX),
+            "store.set" => STORE_SET_FUNC_INDEX,
+            "store.remove" => STORE_REMOVE_FUNC_INDEX,
+            "store.get" => STORE_GET_FUNC_INDEX,
+            "store.loadRelated" => STORE_LOAD_RELATED_FUNC_INDEX,
 
             // ethereum
-            "ethereum.call" => FuncInstance::alloc_host(signature, ETHEREUM_CALL_FUNC_INDEX),
+            "ethereum.call" => ETHEREUM_CALL_FUNC_INDEX,
+            "ethereum.blockNumber" => ETHEREUM_BLOCK_NUMBER_FUNC_INDEX,
 
             // typeConversion
             "typeConversion.bytesToString" => {
-                FuncInstance::alloc_host(signature, TYPE_CONVERSION_BYTES_TO_STRING_FUNC_INDEX)
-            }
-            "typeConversion.bytesToHex" => {
-                FuncInstance::alloc_host(signature, TYPE_CONVERSION_BYTES_TO_HEX_FUNC_INDEX)
-            }
-            "typeConversion.bigIntToString" => {
-                FuncInstance::alloc_host(signature, TYPE_CONVERSION_BIG_INT_TO_STRING_FUNC_INDEX)
-            }
-            "typeConversion.bigIntToHex" => {
-                FuncInstance::alloc_host(signature, TYPE_CONVERSION_BIG_INT_TO_HEX_FUNC_INDEX)
-            }
-            "typeConversion.stringToH160" => {
-                FuncInstance::alloc_host(signature, TYPE_CONVERSION_STRING_TO_H160_FUNC_INDEX)
-            }
-            "typeConversion.i32ToBigInt" => {
-                FuncInstance::alloc_host(signature, TYPE_CONVERSION_I32_TO_BIG_INT_FUNC_INDEX)
+                if self.api_version >= STRICT_BYTES_TO_STRING_API_VERSION {
+                    TYPE_CONVERSION_BYTES_TO_STRING_STRICT_FUNC_INDEX
+                } else {
+                    TYPE_CONVERSION_BYTES_TO_STRING_FUNC_INDEX
+                }
             }
-            "typeConversion.bigIntToI32" => {
-                FuncInstance::alloc_host(signature, TYPE_CONVERSION_BIG_INT_TO_I32_FUNC_INDEX)
+            "typeConversion.bytesToStringStrict" => {
+                TYPE_CONVERSION_BYTES_TO_STRING_STRICT_FUNC_INDEX
             }
+            "typeConversion.bytesToHex" => TYPE_CONVERSION_BYTES_TO_HEX_FUNC_INDEX,
+            "typeConversion.bigIntToString" => TYPE_CONVERSION_BIG_INT_TO_STRING_FUNC_INDEX,
+            "typeConversion.bigIntToHex" => TYPE_CONVERSION_BIG_INT_TO_HEX_FUNC_INDEX,
+            "typeConversion.stringToH160" => TYPE_CONVERSION_STRING_TO_H160_FUNC_INDEX,
+            "typeConversion.stringToBytes" => TYPE_CONVERSION_STRING_TO_BYTES_FUNC_INDEX,
+            "typeConversion.addressToString" => TYPE_CONVERSION_ADDRESS_TO_STRING_FUNC_INDEX,
+            "typeConversion.i32ToBigInt" => TYPE_CONVERSION_I32_TO_BIG_INT_FUNC_INDEX,
+            "typeConversion.bigIntToI32" => TYPE_CONVERSION_BIG_INT_TO_I32_FUNC_INDEX,
+            "typeConversion.bytesToBase58" => TYPE_CONVERSION_BYTES_TO_BASE_58_INDEX,
+            "typeConversion.base58ToBytes" => TYPE_CONVERSION_BASE_58_TO_BYTES_INDEX,
+            "typeConversion.bytesToBigInt" => TYPE_CONVERSION_BYTES_TO_BIG_INT_INDEX,
+            "typeConversion.bigIntToBytes" => TYPE_CONVERSION_BIG_INT_TO_BYTES_INDEX,
+            "typeConversion.bigIntToBigDecimal" => TYPE_CONVERSION_BIG_INT_TO_BIG_DECIMAL_INDEX,
 
             // json
-            "json.fromBytes" => FuncInstance::alloc_host(signature, JSON_FROM_BYTES_FUNC_INDEX),
-            "json.toI64" => FuncInstance::alloc_host(signature, JSON_TO_I64_FUNC_INDEX),
-            "json.toU64" => FuncInstance::alloc_host(signature, JSON_TO_U64_FUNC_INDEX),
-            "json.toF64" => FuncInstance::alloc_host(signature, JSON_TO_F64_FUNC_INDEX),
-            "json.toBigInt" => FuncInstance::alloc_host(signature, JSON_TO_BIG_INT_FUNC_INDEX),
+            "json.fromBytes" => JSON_FROM_BYTES_FUNC_INDEX,
+            "json.toI64" => JSON_TO_I64_FUNC_INDEX,
+            "json.toU64" => JSON_TO_U64_FUNC_INDEX,
+            "json.toF64" => JSON_TO_F64_FUNC_INDEX,
+            "json.toBigInt" => JSON_TO_BIG_INT_FUNC_INDEX,
+            "json.toBigDecimal" => JSON_TO_BIG_DECIMAL_FUNC_INDEX,
 
             // ipfs
-            "ipfs.cat" => FuncInstance::alloc_host(signature, IPFS_CAT_FUNC_INDEX),
+            "ipfs.cat" => IPFS_CAT_FUNC_INDEX,
 
             // crypto
-            "crypto.keccak256" => FuncInstance::alloc_host(signature, CRYPTO_KECCAK_256_INDEX),
+            "crypto.keccak256" => CRYPTO_KECCAK_256_INDEX,
+            "crypto.sha256" => CRYPTO_SHA_256_INDEX,
 
             // bigInt
-            "bigInt.plus" => FuncInstance::alloc_host(signature, BIG_INT_PLUS),
-            "bigInt.minus" => FuncInstance::alloc_host(signature, BIG_INT_MINUS),
-            "bigInt.times" => FuncInstance::alloc_host(signature, BIG_INT_TIMES),
-            "bigInt.dividedBy" => FuncInstance::alloc_host(signature, BIG_INT_DIVIDED_BY),
-            "bigInt.mod" => FuncInstance::alloc_host(signature, BIG_INT_MOD),
-            _ => {
-                return Err(Error::Instantiation(format!(
-                    "Export '{}' not found",
-                    field_name
-                )))
-            }
+            "bigInt.plus" => BIG_INT_PLUS,
+            "bigInt.minus" => BIG_INT_MINUS,
+            "bigInt.times" => BIG_INT_TIMES,
+            "bigInt.dividedBy" => BIG_INT_DIVIDED_BY,
+            "bigInt.mod" => BIG_INT_MOD,
+
+            // bigDecimal
+            "bigDecimal.plus" => BIG_DECIMAL_PLUS,
+            "bigDecimal.minus" => BIG_DECIMAL_MINUS,
+            "bigDecimal.times" => BIG_DECIMAL_TIMES,
+            "bigDecimal.dividedBy" => BIG_DECIMAL_DIVIDED_BY,
+            "bigDecimal.toString" => BIG_DECIMAL_TO_STRING,
+            _ => return None,
         })
     }
 }
+
+impl ModuleImportResolver for ModuleResolver {
+    fn resolve_func(&self, field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
+        self.host_function_index(field_name)
+            .map(|index| FuncInstance::alloc_host(signature.clone(), index))
+            .ok_or_else(|| Error::Instantiation(format!("Export '{}' not found", field_name)))
+    }
+}
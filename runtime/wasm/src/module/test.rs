@@ -1,8 +1,8 @@
 extern crate graph_mock;
 extern crate ipfs_api;
 
-use self::graph_mock::FakeStore;
-use ethabi::Token;
+use self::graph_mock::{FakeStore, MockStore};
+use ethabi::{self, Token};
 use failure::Error;
 use futures::sync::mpsc::{channel, Sender};
 use graph::components::ethereum::*;
@@ -11,18 +11,29 @@ use graph::data::store::scalar;
 use graph::data::subgraph::*;
 use graph::web3::types::{Address, H160, H256};
 use hex;
+use std::collections::HashMap;
 use std::io::Cursor;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use wasmi::nan_preserving_float::F32;
 
 use std::env;
 
 use super::*;
+use UnresolvedContractCall;
 
 mod abi;
 
+/// Answers contract calls with `true`, or the configured result, after failing with
+/// the queued errors first.
 #[derive(Default)]
-struct MockEthereumAdapter {}
+struct MockEthereumAdapter {
+    contract_call_errors: Mutex<Vec<EthereumContractCallError>>,
+    contract_call_result: Mutex<Option<Vec<Token>>>,
+    contract_calls: AtomicUsize,
+}
 
 impl EthereumAdapter for MockEthereumAdapter {
     fn net_identifiers(
@@ -71,7 +82,16 @@ impl EthereumAdapter for MockEthereumAdapter {
         _: &Logger,
         _: EthereumContractCall,
     ) -> Box<Future<Item = Vec<Token>, Error = EthereumContractCallError> + Send> {
-        unimplemented!();
+        self.contract_calls.fetch_add(1, Ordering::SeqCst);
+        let mut errors = self.contract_call_errors.lock().unwrap();
+        if errors.is_empty() {
+            let result = self.contract_call_result.lock().unwrap().clone();
+            Box::new(future::ok(
+                result.unwrap_or_else(|| vec![Token::Bool(true)]),
+            ))
+        } else {
+            Box::new(future::err(errors.remove(0)))
+        }
     }
 }
 
@@ -82,9 +102,33 @@ fn test_module(
     ipfs_api::IpfsClient,
     FakeStore,
     Sender<Box<Future<Item = (), Error = ()> + Send>>,
+>) {
+    test_module_with_ethereum_adapter(data_source, Arc::new(MockEthereumAdapter::default()))
+}
+
+fn test_module_with_ethereum_adapter(
+    data_source: DataSource,
+    mock_ethereum_adapter: Arc<MockEthereumAdapter>,
+) -> (WasmiModule<
+    MockEthereumAdapter,
+    ipfs_api::IpfsClient,
+    FakeStore,
+    Sender<Box<Future<Item = (), Error = ()> + Send>>,
+>) {
+    test_module_with_store(data_source, mock_ethereum_adapter, Arc::new(FakeStore))
+}
+
+fn test_module_with_store<S: Store>(
+    data_source: DataSource,
+    mock_ethereum_adapter: Arc<MockEthereumAdapter>,
+    store: Arc<S>,
+) -> (WasmiModule<
+    MockEthereumAdapter,
+    ipfs_api::IpfsClient,
+    S,
+    Sender<Box<Future<Item = (), Error = ()> + Send>>,
 >) {
     let logger = Logger::root(slog::Discard, o!());
-    let mock_ethereum_adapter = Arc::new(MockEthereumAdapter::default());
     let (task_sender, task_receiver) = channel(100);
     let mut runtime = tokio::runtime::Runtime::new().unwrap();
     runtime.spawn(task_receiver.for_each(tokio::spawn));
@@ -96,13 +140,26 @@ fn test_module(
             data_source,
             ethereum_adapter: mock_ethereum_adapter,
             link_resolver: Arc::new(ipfs_api::IpfsClient::default()),
-            store: Arc::new(FakeStore),
+            store,
+            ethereum_call_retries: 3,
+            entity_operations_warning_threshold: 10_000,
+            max_entity_operations: 100_000,
+            ipfs_timeout: Duration::from_secs(30),
+            cancel_handle: never_canceled(),
         },
         task_sender,
     )
     .unwrap()
 }
 
+/// A cancel handle for a guard that is never dropped.
+fn never_canceled() -> CancelHandle {
+    let guard = CancelGuard::new();
+    let handle = guard.handle();
+    ::std::mem::forget(guard);
+    handle
+}
+
 fn mock_data_source(path: &str) -> DataSource {
     let runtime = parity_wasm::deserialize_file(path).expect("Failed to deserialize wasm");
 
@@ -241,6 +298,90 @@ fn json_conversions() {
     );
 }
 
+#[test]
+fn json_parse_errors_report_their_position() {
+    let module = test_module(mock_data_source("wasm_test/string_to_number.wasm"));
+
+    let json = "{\n  \"name\": \"graph\",\n  \"count\": 12x\n}";
+    let error = module
+        .host_exports
+        .json_from_bytes(json.as_bytes().to_vec())
+        .err()
+        .expect("parsing malformed JSON should fail");
+    let message = format!("{}", error);
+    assert!(message.contains("line 3, column"), message);
+    assert!(message.contains("\"count\": 12x"), message);
+}
+
+#[test]
+fn json_number_conversions_tell_invalid_and_out_of_range_numbers_apart() {
+    let module = test_module(mock_data_source("wasm_test/string_to_number.wasm"));
+    let host_exports = &module.host_exports;
+
+    // Surrounding whitespace is ignored
+    assert_eq!(host_exports.json_to_i64(" -42\n".to_owned()).unwrap(), -42);
+    assert_eq!(host_exports.json_to_u64("\t42 ".to_owned()).unwrap(), 42);
+    assert_eq!(host_exports.json_to_f64(" 4.2 ".to_owned()).unwrap(), 4.2);
+
+    fn error<T, E: ::std::fmt::Display>(result: Result<T, E>) -> String {
+        format!("{}", result.err().expect("conversion should fail"))
+    }
+    assert_eq!(
+        error(host_exports.json_to_i64("9223372036854775808".to_owned())),
+        "JSON `9223372036854775808` is out of range for i64"
+    );
+    assert_eq!(
+        error(host_exports.json_to_u64("-1".to_owned())),
+        "JSON `-1` is out of range for u64"
+    );
+    assert_eq!(
+        error(host_exports.json_to_f64("1e400".to_owned())),
+        "JSON `1e400` is out of range for f64"
+    );
+    assert_eq!(
+        error(host_exports.json_to_i64("12abc".to_owned())),
+        "JSON `12abc` is not a number"
+    );
+    assert_eq!(
+        error(host_exports.json_to_u64("".to_owned())),
+        "JSON `` is not a number"
+    );
+    assert_eq!(
+        error(host_exports.json_to_f64("four".to_owned())),
+        "JSON `four` is not a number"
+    );
+}
+
+#[test]
+fn json_to_big_decimal_preserves_all_digits() {
+    let module = test_module(mock_data_source("wasm_test/string_to_number.wasm"));
+    let host_exports = &module.host_exports;
+
+    // `0.1` has no exact f64 representation
+    assert_eq!(
+        host_exports.json_to_big_decimal("0.1".to_owned()).unwrap(),
+        BigDecimal::new(BigInt::from(1), -1)
+    );
+    let precise = "-12345678901234567890.123456789012345678901";
+    assert_eq!(
+        host_exports
+            .json_to_big_decimal(precise.to_owned())
+            .unwrap()
+            .to_string(),
+        precise
+    );
+    assert_eq!(
+        format!(
+            "{}",
+            host_exports
+                .json_to_big_decimal("0.1.2".to_owned())
+                .err()
+                .expect("conversion should fail")
+        ),
+        "JSON `0.1.2` is not a decimal string"
+    );
+}
+
 #[test]
 fn ipfs_cat() {
     let mut module = test_module(mock_data_source("wasm_test/ipfs_cat.wasm"));
@@ -264,6 +405,122 @@ fn ipfs_cat() {
     assert_eq!(data, "42");
 }
 
+/// A link resolver for files that never become available.
+struct UnavailableLinkResolver;
+
+impl LinkResolver for UnavailableLinkResolver {
+    fn cat(&self, _: &Link) -> Box<Future<Item = Vec<u8>, Error = Error> + Send> {
+        Box::new(future::empty())
+    }
+}
+
+#[test]
+fn invalid_modules_fail_to_load() {
+    let logger = Logger::root(slog::Discard, o!());
+    let (task_sender, _task_receiver) = channel(100);
+
+    // A module that doesn't export any memory
+    let mut data_source = mock_data_source("wasm_test/abort.wasm");
+    data_source.mapping.runtime = parity_wasm::builder::module().build();
+
+    let error = WasmiModule::new(
+        &logger,
+        WasmiModuleConfig {
+            subgraph_id: SubgraphDeploymentId::new("testsubgraph").unwrap(),
+            data_source,
+            ethereum_adapter: Arc::new(MockEthereumAdapter::default()),
+            link_resolver: Arc::new(ipfs_api::IpfsClient::default()),
+            store: Arc::new(FakeStore),
+            ethereum_call_retries: 3,
+            entity_operations_warning_threshold: 10_000,
+            max_entity_operations: 100_000,
+            ipfs_timeout: Duration::from_secs(30),
+            cancel_handle: never_canceled(),
+        },
+        task_sender,
+    )
+    .err()
+    .expect("loading a module without memory should fail");
+    assert!(format!("{}", error).contains("memory export"), "{}", error);
+}
+
+#[test]
+fn ipfs_cat_times_out() {
+    let logger = Logger::root(slog::Discard, o!());
+    let (task_sender, task_receiver) = channel(100);
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.spawn(task_receiver.for_each(tokio::spawn));
+    ::std::mem::forget(runtime);
+    let module = WasmiModule::new(
+        &logger,
+        WasmiModuleConfig {
+            subgraph_id: SubgraphDeploymentId::new("testsubgraph").unwrap(),
+            data_source: mock_data_source("wasm_test/ipfs_cat.wasm"),
+            ethereum_adapter: Arc::new(MockEthereumAdapter::default()),
+            link_resolver: Arc::new(UnavailableLinkResolver),
+            store: Arc::new(FakeStore),
+            ethereum_call_retries: 3,
+            entity_operations_warning_threshold: 10_000,
+            max_entity_operations: 100_000,
+            ipfs_timeout: Duration::from_millis(200),
+            cancel_handle: never_canceled(),
+        },
+        task_sender,
+    )
+    .unwrap();
+
+    let start = Instant::now();
+    let error = module
+        .host_exports
+        .ipfs_cat("QmUnavailable".to_owned())
+        .err()
+        .expect("reading an unavailable file should fail");
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert!(format!("{}", error).contains("Timed out reading `QmUnavailable`"));
+}
+
+#[test]
+fn pending_requests_are_cancelled_when_the_subgraph_stops() {
+    let logger = Logger::root(slog::Discard, o!());
+    let (task_sender, task_receiver) = channel(100);
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.spawn(task_receiver.for_each(tokio::spawn));
+    ::std::mem::forget(runtime);
+    let cancel_guard = CancelGuard::new();
+    let module = WasmiModule::new(
+        &logger,
+        WasmiModuleConfig {
+            subgraph_id: SubgraphDeploymentId::new("testsubgraph").unwrap(),
+            data_source: mock_data_source("wasm_test/ipfs_cat.wasm"),
+            ethereum_adapter: Arc::new(MockEthereumAdapter::default()),
+            link_resolver: Arc::new(UnavailableLinkResolver),
+            store: Arc::new(FakeStore),
+            ethereum_call_retries: 3,
+            entity_operations_warning_threshold: 10_000,
+            max_entity_operations: 100_000,
+            ipfs_timeout: Duration::from_secs(300),
+            cancel_handle: cancel_guard.handle(),
+        },
+        task_sender,
+    )
+    .unwrap();
+
+    // Stop the subgraph while the module waits for a file that never arrives
+    ::std::thread::spawn(move || {
+        ::std::thread::sleep(Duration::from_millis(200));
+        cancel_guard.cancel();
+    });
+
+    let start = Instant::now();
+    let error = module
+        .host_exports
+        .ipfs_cat("QmUnavailable".to_owned())
+        .err()
+        .expect("cancelled request should fail");
+    assert!(start.elapsed() < Duration::from_secs(30));
+    assert!(format!("{}", error).contains("Cancelled"), "{}", error);
+}
+
 #[test]
 fn crypto_keccak256() {
     let mut module = test_module(mock_data_source("wasm_test/crypto.wasm"));
@@ -285,6 +542,215 @@ fn crypto_keccak256() {
     );
 }
 
+#[test]
+fn crypto_sha256() {
+    let module = test_module(mock_data_source("wasm_test/crypto.wasm"));
+
+    // SHA-256 of the empty input
+    let hash = module.host_exports.crypto_sha_256(vec![]);
+    assert_eq!(
+        hex::encode(hash),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+}
+
+#[test]
+fn base58_conversions() {
+    let module = test_module(mock_data_source("wasm_test/crypto.wasm"));
+
+    // An IPFS multihash: SHA-256 function code, digest length, digest
+    let hash = "QmT78zSuBmuS4z925WZfrqQ1qHaJ56DQaTfyMUF7F8ff5o";
+    let bytes = module
+        .host_exports
+        .base58_to_bytes(hash.to_owned())
+        .expect("failed to decode base58");
+    assert_eq!(bytes.len(), 34);
+    assert_eq!(&bytes[..2], &[0x12, 0x20]);
+    assert_eq!(module.host_exports.bytes_to_base58(bytes), hash);
+
+    // `0`, `O`, `I` and `l` are not part of the base58 alphabet
+    let error = module
+        .host_exports
+        .base58_to_bytes("Qm0OIl".to_owned())
+        .err()
+        .expect("decoding invalid base58 should fail");
+    assert!(format!("{}", error).contains("Qm0OIl"));
+}
+
+#[test]
+fn address_to_string_uses_the_eip55_checksum() {
+    let module = test_module(mock_data_source("wasm_test/crypto.wasm"));
+
+    // Test vectors from EIP-55
+    for checksummed in &[
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+        "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+        "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+        "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+    ] {
+        let address = H160::from_str(&checksummed[2..].to_lowercase()).unwrap();
+        assert_eq!(
+            module.host_exports.address_to_string(address),
+            checksummed.to_string()
+        );
+    }
+}
+
+#[test]
+fn hex_string_bytes_conversions() {
+    let module = test_module(mock_data_source("wasm_test/crypto.wasm"));
+    let host_exports = &module.host_exports;
+
+    // The `0x` prefix is optional
+    let bytes = host_exports
+        .string_to_bytes("0x00ff10ab".to_owned())
+        .expect("failed to decode hex");
+    assert_eq!(bytes, vec![0x00, 0xff, 0x10, 0xab]);
+    assert_eq!(
+        host_exports.string_to_bytes("00FF10AB".to_owned()).unwrap(),
+        bytes
+    );
+    assert_eq!(host_exports.bytes_to_hex(bytes.clone()), "0x00ff10ab");
+    assert_eq!(
+        host_exports
+            .string_to_bytes(host_exports.bytes_to_hex(bytes.clone()))
+            .unwrap(),
+        bytes
+    );
+    assert_eq!(
+        host_exports.string_to_bytes("0x".to_owned()).unwrap(),
+        vec![]
+    );
+
+    for invalid in &["0xabc", "0xzz"] {
+        let error = host_exports
+            .string_to_bytes(invalid.to_string())
+            .err()
+            .expect("decoding invalid hex should fail");
+        assert!(format!("{}", error).contains(invalid), "{}", error);
+    }
+}
+
+#[test]
+fn big_int_bytes_conversions() {
+    let module = test_module(mock_data_source("wasm_test/crypto.wasm"));
+
+    // 40 bytes don't fit into a U256
+    let mut bytes = vec![0x01];
+    bytes.extend(vec![0; 39]);
+    let n = module.host_exports.bytes_to_big_int(bytes.clone());
+    assert_eq!(
+        n,
+        BigInt::from_str("8343699359066055009355553539724812947666814540455674882605631280555545803830627148527195652096")
+            .unwrap()
+    );
+    assert_eq!(module.host_exports.big_int_to_bytes(n).unwrap(), bytes);
+
+    // Leading zeros are dropped
+    let n = module.host_exports.bytes_to_big_int(vec![0, 0, 0x12, 0x34]);
+    assert_eq!(n, BigInt::from(0x1234));
+    assert_eq!(
+        module.host_exports.big_int_to_bytes(n).unwrap(),
+        vec![0x12, 0x34]
+    );
+
+    assert_eq!(
+        module.host_exports.bytes_to_big_int(vec![]),
+        BigInt::from(0)
+    );
+    assert_eq!(
+        module
+            .host_exports
+            .big_int_to_bytes(BigInt::from(0))
+            .unwrap(),
+        Vec::<u8>::new()
+    );
+    assert!(module
+        .host_exports
+        .big_int_to_bytes(BigInt::from(-1))
+        .is_err());
+}
+
+#[test]
+fn bytes_to_string_trims_trailing_nulls_unless_strict() {
+    let module = test_module(mock_data_source("wasm_test/crypto.wasm"));
+
+    // A bytes32 holding a short, null-padded string
+    let mut padded = b"graph".to_vec();
+    padded.extend(vec![0; 27]);
+    assert_eq!(
+        module.host_exports.bytes_to_string(padded.clone()).unwrap(),
+        "graph"
+    );
+    assert_eq!(
+        module.host_exports.bytes_to_string_strict(padded).unwrap(),
+        format!("graph{}", "\u{0000}".repeat(27))
+    );
+
+    // Nulls that are part of the data are trimmed too, unless strict
+    let data = b"graph\0\0".to_vec();
+    assert_eq!(
+        module.host_exports.bytes_to_string(data.clone()).unwrap(),
+        "graph"
+    );
+    assert_eq!(
+        module.host_exports.bytes_to_string_strict(data).unwrap(),
+        "graph\u{0000}\u{0000}"
+    );
+
+    // Nulls in the middle are always kept
+    assert_eq!(
+        module
+            .host_exports
+            .bytes_to_string(b"gr\0aph".to_vec())
+            .unwrap(),
+        "gr\u{0000}aph"
+    );
+}
+
+#[test]
+fn modules_are_instantiated_for_each_api_version() {
+    // This module imports `typeConversion.bytesToString`, which differs between versions
+    for api_version in &["0.0.1", "0.1.0", "0.2.0"] {
+        let mut data_source = mock_data_source("wasm_test/ipfs_cat.wasm");
+        data_source.mapping.api_version = api_version.to_string();
+        test_module(data_source);
+    }
+}
+
+#[test]
+fn bytes_to_string_import_depends_on_the_api_version() {
+    let index = |api_version: &str| {
+        ModuleResolver::new(api_version.parse().unwrap())
+            .host_function_index("typeConversion.bytesToString")
+    };
+    assert_eq!(
+        index("0.0.1"),
+        Some(TYPE_CONVERSION_BYTES_TO_STRING_FUNC_INDEX)
+    );
+    assert_eq!(
+        index("0.1.0"),
+        Some(TYPE_CONVERSION_BYTES_TO_STRING_FUNC_INDEX)
+    );
+    assert_eq!(
+        index("0.2.0"),
+        Some(TYPE_CONVERSION_BYTES_TO_STRING_STRICT_FUNC_INDEX)
+    );
+}
+
+#[test]
+fn invalid_api_versions_are_rejected() {
+    assert_eq!("0.0.2".parse::<ApiVersion>().unwrap(), ApiVersion(0, 0, 2));
+    assert!("0.0".parse::<ApiVersion>().is_err());
+    assert!("0.0.2.1".parse::<ApiVersion>().is_err());
+    assert!("latest".parse::<ApiVersion>().is_err());
+
+    // Modules with an invalid API version still get the host functions of old versions
+    let mut data_source = mock_data_source("wasm_test/ipfs_cat.wasm");
+    data_source.mapping.api_version = String::from("latest");
+    test_module(data_source);
+}
+
 #[test]
 fn token_numeric_conversion() {
     let mut module = test_module(mock_data_source("wasm_test/token_to_numeric.wasm"));
@@ -532,3 +998,516 @@ fn abort() {
         .unwrap_err();
     assert_eq!(err.to_string(), "Trap: Trap { kind: Host(HostExportError(\"Mapping aborted at abort.ts, line 6, column 2, with message: not true\")) }");
 }
+
+#[test]
+fn big_decimal_arithmetic() {
+    let module = test_module(mock_data_source("wasm_test/crypto.wasm"));
+    let host_exports = &module.host_exports;
+
+    let wei = host_exports.big_int_to_big_decimal(BigInt::from(1234500000000000000u64));
+    let ether = host_exports.big_int_to_big_decimal(BigInt::from(1000000000000000000u64));
+    let amount = host_exports.big_decimal_divided_by(wei, ether).unwrap();
+    assert_eq!(
+        host_exports.big_decimal_to_string(amount.clone()).unwrap(),
+        "1.2345"
+    );
+
+    let fee = BigDecimal::new(BigInt::from(5), -3);
+    let total = host_exports
+        .big_decimal_plus(amount.clone(), fee.clone())
+        .unwrap();
+    assert_eq!(host_exports.big_decimal_to_string(total).unwrap(), "1.2395");
+    let rest = host_exports
+        .big_decimal_minus(amount.clone(), fee.clone())
+        .unwrap();
+    assert_eq!(host_exports.big_decimal_to_string(rest).unwrap(), "1.2295");
+    let product = host_exports
+        .big_decimal_times(amount.clone(), fee.clone())
+        .unwrap();
+    assert_eq!(
+        host_exports.big_decimal_to_string(product).unwrap(),
+        "0.0061725"
+    );
+
+    // Dividing by zero and exponents that are out of range fail the handler
+    // instead of panicking
+    let zero = host_exports.big_int_to_big_decimal(BigInt::from(0));
+    assert_eq!(
+        host_exports
+            .big_decimal_divided_by(amount, zero)
+            .unwrap_err()
+            .to_string(),
+        "Cannot divide by a zero-valued BigDecimal"
+    );
+    let small = BigDecimal::new(BigInt::from(1), -6144);
+    assert_eq!(
+        host_exports
+            .big_decimal_times(small, fee)
+            .unwrap_err()
+            .to_string(),
+        "BigDecimal exponent -6147 is out of range"
+    );
+    assert!(host_exports
+        .json_to_big_decimal("1e-2147483649".to_owned())
+        .is_err());
+}
+
+#[test]
+fn big_decimal_extreme_exponents() {
+    let module = test_module(mock_data_source("wasm_test/crypto.wasm"));
+    let host_exports = &module.host_exports;
+
+    // Operands are checked before the arithmetic, which would otherwise have
+    // to bridge billions of digits
+    let huge = BigDecimal::new(BigInt::from(1), i32::max_value() as i64);
+    let tiny = BigDecimal::new(BigInt::from(1), i32::min_value() as i64 + 1);
+    assert_eq!(
+        host_exports
+            .big_decimal_plus(huge.clone(), tiny.clone())
+            .unwrap_err()
+            .to_string(),
+        "BigDecimal exponent 2147483647 is out of range"
+    );
+    assert_eq!(
+        host_exports
+            .big_decimal_minus(BigDecimal::from(BigInt::from(1)), tiny.clone())
+            .unwrap_err()
+            .to_string(),
+        "BigDecimal exponent -2147483647 is out of range"
+    );
+    assert!(host_exports
+        .big_decimal_times(huge.clone(), tiny.clone())
+        .is_err());
+    assert!(host_exports
+        .big_decimal_divided_by(huge.clone(), tiny)
+        .is_err());
+    assert!(host_exports.big_decimal_to_string(huge).is_err());
+
+    // The largest exponents still work
+    let max = BigDecimal::new(BigInt::from(1), 6144);
+    let min = BigDecimal::new(BigInt::from(1), -6144);
+    let sum = host_exports.big_decimal_plus(max, min).unwrap();
+    assert_eq!(sum.as_bigint_and_exponent().1, -6144);
+}
+
+fn mock_context() -> EventHandlerContext {
+    EventHandlerContext {
+        logger: Logger::root(slog::Discard, o!()),
+        block: Arc::new(EthereumBlock {
+            block: graph::web3::types::Block {
+                hash: Some(H256::default()),
+                parent_hash: H256::default(),
+                uncles_hash: H256::default(),
+                author: H160::default(),
+                state_root: H256::default(),
+                transactions_root: H256::default(),
+                receipts_root: H256::default(),
+                number: Some(graph::web3::types::U128::from(1)),
+                gas_used: U256::from(100),
+                gas_limit: U256::from(1000),
+                extra_data: graph::web3::types::Bytes(vec![]),
+                logs_bloom: graph::web3::types::H2048::default(),
+                timestamp: U256::from(100000),
+                difficulty: U256::from(10),
+                total_difficulty: U256::from(100),
+                seal_fields: vec![],
+                uncles: vec![],
+                transactions: vec![],
+                size: Some(U256::from(10000)),
+            },
+            transaction_receipts: vec![],
+        }),
+        transaction: Arc::new(graph::web3::types::Transaction::default()),
+        entity_operations: vec![],
+    }
+}
+
+fn user_data(name: &str) -> HashMap<String, Value> {
+    let mut data = HashMap::new();
+    data.insert("name".to_owned(), Value::from(name));
+    data
+}
+
+#[test]
+fn store_get_returns_entities_set_earlier_in_the_same_event() {
+    let mut module = test_module(mock_data_source("wasm_test/crypto.wasm"));
+    module.host_exports.ctx = Some(mock_context());
+
+    module
+        .host_exports
+        .store_set("User".to_owned(), "1".to_owned(), user_data("Alice"))
+        .expect("failed to set entity");
+    module
+        .host_exports
+        .store_set("User".to_owned(), "1".to_owned(), user_data("Bob"))
+        .expect("failed to set entity");
+
+    let user = module
+        .host_exports
+        .store_get("User".to_owned(), "1".to_owned())
+        .expect("failed to get entity")
+        .expect("entity set earlier in the event was not found");
+    assert_eq!(user.get("id"), Some(&Value::from("1")));
+    assert_eq!(user.get("name"), Some(&Value::from("Bob")));
+
+    // Operations on other entities are not taken into account
+    let other = module
+        .host_exports
+        .store_get("User".to_owned(), "2".to_owned())
+        .expect("failed to get entity");
+    assert!(other.is_none());
+}
+
+#[test]
+fn store_get_honors_removals_earlier_in_the_same_event() {
+    let mut module = test_module(mock_data_source("wasm_test/crypto.wasm"));
+    module.host_exports.ctx = Some(mock_context());
+
+    // A removal after a set hides the entity
+    module
+        .host_exports
+        .store_set("User".to_owned(), "1".to_owned(), user_data("Alice"))
+        .expect("failed to set entity");
+    module
+        .host_exports
+        .store_remove("User".to_owned(), "1".to_owned())
+        .expect("failed to remove entity");
+    let user = module
+        .host_exports
+        .store_get("User".to_owned(), "1".to_owned())
+        .expect("failed to get entity");
+    assert!(user.is_none());
+
+    // A set after a removal brings the entity back
+    module
+        .host_exports
+        .store_set("User".to_owned(), "1".to_owned(), user_data("Carol"))
+        .expect("failed to set entity");
+    let user = module
+        .host_exports
+        .store_get("User".to_owned(), "1".to_owned())
+        .expect("failed to get entity")
+        .expect("entity set after removal was not found");
+    assert_eq!(user.get("name"), Some(&Value::from("Carol")));
+}
+
+#[test]
+fn store_get_returns_list_attributes_to_mappings() {
+    let mut module = test_module(mock_data_source("wasm_test/crypto.wasm"));
+    module.host_exports.ctx = Some(mock_context());
+
+    let tags = Value::List(vec![Value::from("a"), Value::from("b")]);
+    let scores = Value::List(vec![
+        Value::Int(1),
+        Value::Null,
+        Value::List(vec![Value::Bool(true)]),
+    ]);
+    let mut data = user_data("Alice");
+    data.insert("tags".to_owned(), tags.clone());
+    data.insert("scores".to_owned(), scores.clone());
+    data.insert("empty".to_owned(), Value::List(vec![]));
+    module
+        .host_exports
+        .store_set("User".to_owned(), "1".to_owned(), data)
+        .expect("failed to set entity");
+
+    let user = module
+        .host_exports
+        .store_get("User".to_owned(), "1".to_owned())
+        .expect("failed to get entity")
+        .expect("entity was not found");
+
+    // Pass the entity to the mapping and read it back
+    let user_ptr: AscPtr<AscEntity> = module.asc_new(&user);
+    let from_mapping: HashMap<String, Value> = module.asc_get(user_ptr);
+    assert_eq!(from_mapping.get("tags"), Some(&tags));
+    assert_eq!(from_mapping.get("scores"), Some(&scores));
+    assert_eq!(from_mapping.get("empty"), Some(&Value::List(vec![])));
+    assert_eq!(Entity::from(from_mapping), user);
+}
+
+#[test]
+fn store_load_related_returns_entities_referencing_an_entity() {
+    let subgraph_id = SubgraphDeploymentId::new("testsubgraph").unwrap();
+    let store = Arc::new(MockStore::new(vec![]));
+    let post = |id: &str, author: &str| EntityOperation::Set {
+        key: EntityKey {
+            subgraph_id: subgraph_id.clone(),
+            entity_type: "Post".to_owned(),
+            entity_id: id.to_owned(),
+        },
+        data: Entity::from(vec![
+            ("id", Value::from(id)),
+            ("author", Value::from(author)),
+        ]),
+    };
+    store
+        .apply_entity_operations(
+            vec![post("1", "alice"), post("2", "alice"), post("3", "bob")],
+            EventSource::None,
+        )
+        .unwrap();
+
+    let mut module = test_module_with_store(
+        mock_data_source("wasm_test/crypto.wasm"),
+        Arc::new(MockEthereumAdapter::default()),
+        store,
+    );
+    module.host_exports.ctx = Some(mock_context());
+
+    let related_ids = |module: &WasmiModule<
+        MockEthereumAdapter,
+        ipfs_api::IpfsClient,
+        MockStore,
+        Sender<Box<Future<Item = (), Error = ()> + Send>>,
+    >| {
+        let mut ids: Vec<_> = module
+            .host_exports
+            .store_load_related("Post".to_owned(), "alice".to_owned(), "author".to_owned())
+            .expect("failed to load related entities")
+            .into_iter()
+            .map(|entity| entity.id().unwrap())
+            .collect();
+        ids.sort();
+        ids
+    };
+    assert_eq!(related_ids(&module), vec!["1", "2"]);
+
+    // Entities written earlier in the same event are taken into account
+    let mut data = HashMap::new();
+    data.insert("author".to_owned(), Value::from("alice"));
+    module
+        .host_exports
+        .store_set("Post".to_owned(), "3".to_owned(), data)
+        .expect("failed to set entity");
+    module
+        .host_exports
+        .store_remove("Post".to_owned(), "1".to_owned())
+        .expect("failed to remove entity");
+    assert_eq!(related_ids(&module), vec!["2", "3"]);
+}
+
+#[test]
+fn entity_operations_beyond_the_limit_trap() {
+    let logger = Logger::root(slog::Discard, o!());
+    let (task_sender, task_receiver) = channel(100);
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.spawn(task_receiver.for_each(tokio::spawn));
+    ::std::mem::forget(runtime);
+    let mut module = WasmiModule::new(
+        &logger,
+        WasmiModuleConfig {
+            subgraph_id: SubgraphDeploymentId::new("testsubgraph").unwrap(),
+            data_source: mock_data_source("wasm_test/abort.wasm"),
+            ethereum_adapter: Arc::new(MockEthereumAdapter::default()),
+            link_resolver: Arc::new(ipfs_api::IpfsClient::default()),
+            store: Arc::new(FakeStore),
+            ethereum_call_retries: 3,
+            entity_operations_warning_threshold: 1,
+            max_entity_operations: 2,
+            ipfs_timeout: Duration::from_secs(30),
+            cancel_handle: never_canceled(),
+        },
+        task_sender,
+    )
+    .unwrap();
+    module.host_exports.ctx = Some(mock_context());
+
+    module
+        .host_exports
+        .store_set("User".to_owned(), "1".to_owned(), HashMap::new())
+        .expect("failed to set entity");
+    module
+        .host_exports
+        .store_remove("User".to_owned(), "2".to_owned())
+        .expect("failed to remove entity");
+
+    // The third operation exceeds the limit and traps
+    let entity_ptr = module.asc_new(&"User".to_owned());
+    let id_ptr = module.asc_new(&"3".to_owned());
+    let trap = module
+        .store_remove(entity_ptr, id_ptr)
+        .expect_err("exceeding the limit should trap");
+    assert!(
+        format!("{:?}", trap).contains("limit of 2 entity operations"),
+        "{:?}",
+        trap
+    );
+    assert_eq!(
+        module
+            .host_exports
+            .ctx
+            .as_ref()
+            .unwrap()
+            .entity_operations
+            .len(),
+        2
+    );
+}
+
+/// Adds the ABI of a `Token` contract with a constant `paused()` function.
+fn with_token_abi(mut data_source: DataSource) -> DataSource {
+    let abi = r#"[{
+        "type": "function",
+        "name": "paused",
+        "inputs": [],
+        "outputs": [{ "name": "", "type": "bool" }],
+        "constant": true,
+        "payable": false
+    }]"#;
+    data_source.mapping.abis.push(MappingABI {
+        name: "Token".to_owned(),
+        contract: ethabi::Contract::load(abi.as_bytes()).expect("invalid test ABI"),
+        link: Link {
+            link: "token".to_owned(),
+        },
+    });
+    data_source
+}
+
+fn paused_call() -> UnresolvedContractCall {
+    UnresolvedContractCall {
+        contract_name: "Token".to_owned(),
+        contract_address: Address::from_str("0123123123012312312301231231230123123123").unwrap(),
+        function_name: "paused".to_owned(),
+        function_args: vec![],
+    }
+}
+
+#[test]
+fn ethereum_call_retries_transport_errors() {
+    let adapter = Arc::new(MockEthereumAdapter::default());
+    *adapter.contract_call_errors.lock().unwrap() = vec![
+        EthereumContractCallError::from(format_err!("connection reset")),
+        EthereumContractCallError::from(format_err!("request timed out")),
+    ];
+    let mut module = test_module_with_ethereum_adapter(
+        with_token_abi(mock_data_source("wasm_test/crypto.wasm")),
+        adapter.clone(),
+    );
+    module.host_exports.ctx = Some(mock_context());
+
+    let result = module
+        .host_exports
+        .ethereum_call(paused_call())
+        .expect("call should succeed after retrying");
+    assert_eq!(result, vec![Token::Bool(true)]);
+    assert_eq!(adapter.contract_calls.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn ethereum_call_does_not_retry_failed_calls() {
+    let adapter = Arc::new(MockEthereumAdapter::default());
+    *adapter.contract_call_errors.lock().unwrap() = vec![EthereumContractCallError::TypeError(
+        Token::Bool(true),
+        ethabi::ParamType::Address,
+    )];
+    let mut module = test_module_with_ethereum_adapter(
+        with_token_abi(mock_data_source("wasm_test/crypto.wasm")),
+        adapter.clone(),
+    );
+    module.host_exports.ctx = Some(mock_context());
+
+    module
+        .host_exports
+        .ethereum_call(paused_call())
+        .expect_err("call should fail");
+    assert_eq!(adapter.contract_calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn ethereum_call_does_not_retry_reverted_calls() {
+    let adapter = Arc::new(MockEthereumAdapter::default());
+    *adapter.contract_call_errors.lock().unwrap() = vec![EthereumContractCallError::Revert(
+        "VM execution error.".to_owned(),
+    )];
+    let mut module = test_module_with_ethereum_adapter(
+        with_token_abi(mock_data_source("wasm_test/crypto.wasm")),
+        adapter.clone(),
+    );
+    module.host_exports.ctx = Some(mock_context());
+
+    let error = module
+        .host_exports
+        .ethereum_call(paused_call())
+        .expect_err("call should fail");
+    assert!(format!("{}", error).contains("call reverted"), "{}", error);
+    assert_eq!(adapter.contract_calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn ethereum_call_rejects_results_that_do_not_match_the_abi() {
+    let adapter = Arc::new(MockEthereumAdapter::default());
+    *adapter.contract_call_result.lock().unwrap() =
+        Some(vec![Token::Bool(true), Token::Bool(false)]);
+    let mut module = test_module_with_ethereum_adapter(
+        with_token_abi(mock_data_source("wasm_test/crypto.wasm")),
+        adapter.clone(),
+    );
+    module.host_exports.ctx = Some(mock_context());
+
+    let error = module
+        .host_exports
+        .ethereum_call(paused_call())
+        .expect_err("call with too many outputs should fail");
+    assert_eq!(
+        error.to_string(),
+        "Call to function \"paused\" of contract \"Token\" returned 2 values \
+         instead of the 1 its ABI declares"
+    );
+
+    // A result of the wrong type is rejected as well
+    *adapter.contract_call_result.lock().unwrap() = Some(vec![Token::Uint(1u64.into())]);
+    module
+        .host_exports
+        .ethereum_call(paused_call())
+        .expect_err("call with an output of the wrong type should fail");
+}
+
+#[test]
+fn identical_ethereum_calls_in_a_block_are_cached() {
+    let adapter = Arc::new(MockEthereumAdapter::default());
+    let mut module = test_module_with_ethereum_adapter(
+        with_token_abi(mock_data_source("wasm_test/crypto.wasm")),
+        adapter.clone(),
+    );
+    module.host_exports.ctx = Some(mock_context());
+
+    let first = module
+        .host_exports
+        .ethereum_call(paused_call())
+        .expect("first call failed");
+    let second = module
+        .host_exports
+        .ethereum_call(paused_call())
+        .expect("second call failed");
+
+    assert_eq!(first, second);
+    assert_eq!(adapter.contract_calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn abort_with_message_reports_the_message() {
+    let module = test_module(mock_data_source("wasm_test/crypto.wasm"));
+
+    let error = module
+        .host_exports
+        .abort_with_message("balance must not be negative".to_owned())
+        .expect_err("aborting should fail");
+    assert_eq!(
+        error.to_string(),
+        "Mapping aborted: balance must not be negative"
+    );
+}
+
+#[test]
+fn ethereum_block_number_is_the_number_of_the_current_block() {
+    let mut module = test_module(mock_data_source("wasm_test/crypto.wasm"));
+    module.host_exports.ctx = Some(mock_context());
+
+    let number = module
+        .host_exports
+        .ethereum_block_number()
+        .expect("failed to get block number");
+    assert_eq!(number, BigInt::from(1u64));
+}
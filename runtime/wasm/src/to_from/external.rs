@@ -3,7 +3,7 @@ use std::collections::HashMap;
 
 use graph::components::ethereum::{EthereumBlockData, EthereumEventData, EthereumTransactionData};
 use graph::data::store;
-use graph::prelude::BigInt;
+use graph::prelude::{BigDecimal, BigInt};
 use graph::serde_json;
 use graph::web3::types as web3;
 
@@ -58,6 +58,30 @@ impl FromAscObj<AscBigInt> for BigInt {
     }
 }
 
+impl ToAscObj<AscBigDecimal> for BigDecimal {
+    fn to_asc_obj<H: AscHeap>(&self, heap: &mut H) -> AscBigDecimal {
+        let (digits, exp) = self.as_bigint_and_exponent();
+
+        // Host functions refuse to return decimals with a larger exponent
+        assert!(
+            exp >= i32::min_value() as i64 && exp <= i32::max_value() as i64,
+            "BigDecimal exponent {} is out of range",
+            exp
+        );
+
+        AscBigDecimal {
+            digits: heap.asc_new(&digits),
+            exp: exp as i32,
+        }
+    }
+}
+
+impl FromAscObj<AscBigDecimal> for BigDecimal {
+    fn from_asc_obj<H: AscHeap>(big_decimal: AscBigDecimal, heap: &H) -> Self {
+        BigDecimal::new(heap.asc_get(big_decimal.digits), big_decimal.exp as i64)
+    }
+}
+
 impl ToAscObj<AscEnum<EthereumValueKind>> for ethabi::Token {
     fn to_asc_obj<H: AscHeap>(&self, heap: &mut H) -> AscEnum<EthereumValueKind> {
         use ethabi::Token::*;
@@ -0,0 +1,118 @@
+use http::header::{self, HeaderMap};
+use http::response::Builder;
+
+/// Origins that browsers may send GraphQL queries from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AllowedOrigins {
+    /// Queries are allowed from any origin.
+    Any,
+    /// Queries are only allowed from these exact origins, e.g. `https://example.com`.
+    Only(Vec<String>),
+}
+
+impl Default for AllowedOrigins {
+    fn default() -> Self {
+        AllowedOrigins::Any
+    }
+}
+
+impl AllowedOrigins {
+    /// Parses a comma-separated list of origins; a `*` in the list allows any origin.
+    pub fn parse(origins: &str) -> Self {
+        let origins: Vec<_> = origins
+            .split(',')
+            .map(|origin| origin.trim())
+            .filter(|origin| !origin.is_empty())
+            .collect();
+
+        if origins.contains(&"*") {
+            AllowedOrigins::Any
+        } else {
+            AllowedOrigins::Only(origins.into_iter().map(String::from).collect())
+        }
+    }
+
+    /// Returns true if queries from `origin` are allowed.
+    pub fn allows(&self, origin: &str) -> bool {
+        match self {
+            AllowedOrigins::Any => true,
+            AllowedOrigins::Only(origins) => origins.iter().any(|allowed| allowed == origin),
+        }
+    }
+
+    /// Returns the `Access-Control-Allow-Origin` value to send in response to a
+    /// request from `origin`, or `None` if the origin is not allowed.
+    pub fn allow_origin(&self, origin: Option<&str>) -> Option<String> {
+        match (self, origin) {
+            (AllowedOrigins::Any, _) => Some(String::from("*")),
+            (_, Some(origin)) if self.allows(origin) => Some(origin.to_owned()),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the `Origin` of a request, if it has one.
+pub fn request_origin(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::ORIGIN)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_owned())
+}
+
+/// Returns true if a request carries credentials, i.e. cookies or an
+/// `Authorization` header.
+pub fn is_credentialed(headers: &HeaderMap) -> bool {
+    headers.contains_key(header::COOKIE) || headers.contains_key(header::AUTHORIZATION)
+}
+
+/// Adds the `Access-Control-Allow-Origin` header for `allow_origin` to a response.
+pub fn add_allow_origin_header(builder: &mut Builder, allow_origin: Option<&str>) {
+    match allow_origin {
+        Some("*") => {
+            builder.header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*");
+        }
+        Some(origin) => {
+            // The response depends on the origin of the request
+            builder
+                .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin)
+                .header(header::VARY, "Origin");
+        }
+        None => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AllowedOrigins;
+
+    #[test]
+    fn parses_origin_lists() {
+        assert_eq!(AllowedOrigins::parse("*"), AllowedOrigins::Any);
+        assert_eq!(
+            AllowedOrigins::parse("https://a.example, *"),
+            AllowedOrigins::Any
+        );
+        assert_eq!(
+            AllowedOrigins::parse("https://a.example, https://b.example,"),
+            AllowedOrigins::Only(vec![
+                String::from("https://a.example"),
+                String::from("https://b.example"),
+            ])
+        );
+    }
+
+    #[test]
+    fn only_exact_origins_are_allowed() {
+        let origins = AllowedOrigins::parse("https://a.example");
+        assert_eq!(
+            origins.allow_origin(Some("https://a.example")),
+            Some(String::from("https://a.example"))
+        );
+        assert_eq!(origins.allow_origin(Some("https://a.example:8000")), None);
+        assert_eq!(origins.allow_origin(None), None);
+        assert_eq!(
+            AllowedOrigins::Any.allow_origin(Some("https://b.example")),
+            Some(String::from("*"))
+        );
+    }
+}
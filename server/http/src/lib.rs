@@ -9,11 +9,17 @@ extern crate hyper;
 extern crate itertools;
 extern crate serde;
 
+mod cors;
+mod query_limits;
+mod rate_limit;
 mod request;
 mod response;
 mod server;
 mod service;
 
+pub use self::cors::AllowedOrigins;
+pub use self::query_limits::{QueryConcurrencyLimits, QueryPermit};
+pub use self::rate_limit::{NoRateLimit, RateLimited, RateLimiter, CLIENT_KEY_HEADER};
 pub use self::request::GraphQLRequest;
 pub use self::response::GraphQLResponse;
 pub use self::server::GraphQLServer;
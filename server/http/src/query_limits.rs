@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use graph::prelude::*;
+
+/// Bounds how many queries for a single subgraph may be executed at the same time.
+///
+/// Every subgraph is subject to the default limit unless a different limit has been
+/// configured for its deployment. Limits are tracked separately per subgraph, so a subgraph
+/// that receives a flood of queries does not affect queries for other subgraphs.
+#[derive(Debug)]
+pub struct QueryConcurrencyLimits {
+    default_limit: usize,
+    limits: HashMap<SubgraphDeploymentId, usize>,
+    active: Arc<Mutex<HashMap<SubgraphDeploymentId, usize>>>,
+}
+
+impl QueryConcurrencyLimits {
+    /// Creates concurrency limits that apply `default_limit` to all subgraphs.
+    pub fn new(default_limit: usize) -> Self {
+        QueryConcurrencyLimits {
+            default_limit,
+            limits: HashMap::new(),
+            active: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Overrides the default limit for a specific subgraph deployment.
+    pub fn with_limit(mut self, subgraph_id: SubgraphDeploymentId, limit: usize) -> Self {
+        self.limits.insert(subgraph_id, limit);
+        self
+    }
+
+    /// Returns the number of queries that may run concurrently for a subgraph.
+    pub fn limit(&self, subgraph_id: &SubgraphDeploymentId) -> usize {
+        self.limits
+            .get(subgraph_id)
+            .cloned()
+            .unwrap_or(self.default_limit)
+    }
+
+    /// Returns the number of queries currently running for a subgraph.
+    pub fn active(&self, subgraph_id: &SubgraphDeploymentId) -> usize {
+        self.active
+            .lock()
+            .unwrap()
+            .get(subgraph_id)
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    /// Tries to reserve a query slot for a subgraph.
+    ///
+    /// Returns `None` if the subgraph is already running as many queries as its limit allows.
+    /// Otherwise, returns a permit that releases the slot again when it is dropped.
+    pub fn try_acquire(&self, subgraph_id: &SubgraphDeploymentId) -> Option<QueryPermit> {
+        let limit = self.limit(subgraph_id);
+        let mut active = self.active.lock().unwrap();
+        let count = active.entry(subgraph_id.clone()).or_insert(0);
+
+        if *count >= limit {
+            return None;
+        }
+
+        *count += 1;
+        Some(QueryPermit {
+            active: self.active.clone(),
+            subgraph_id: subgraph_id.clone(),
+        })
+    }
+}
+
+/// A reserved query slot for a subgraph; the slot is released when the permit is dropped.
+#[derive(Debug)]
+pub struct QueryPermit {
+    active: Arc<Mutex<HashMap<SubgraphDeploymentId, usize>>>,
+    subgraph_id: SubgraphDeploymentId,
+}
+
+impl Drop for QueryPermit {
+    fn drop(&mut self) {
+        let mut active = self.active.lock().unwrap();
+        let is_idle = match active.get_mut(&self.subgraph_id) {
+            Some(count) => {
+                *count -= 1;
+                *count == 0
+            }
+            None => false,
+        };
+        if is_idle {
+            active.remove(&self.subgraph_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limit_is_enforced_per_subgraph() {
+        let busy = SubgraphDeploymentId::new("busy").unwrap();
+        let quiet = SubgraphDeploymentId::new("quiet").unwrap();
+        let limits = QueryConcurrencyLimits::new(2);
+
+        // Fire more queries for one subgraph than its limit allows
+        let first = limits.try_acquire(&busy);
+        let second = limits.try_acquire(&busy);
+        let third = limits.try_acquire(&busy);
+        assert!(first.is_some());
+        assert!(second.is_some());
+        assert!(third.is_none());
+        assert_eq!(limits.active(&busy), 2);
+
+        // Another subgraph is unaffected
+        let other = limits.try_acquire(&quiet);
+        assert!(other.is_some());
+        assert_eq!(limits.active(&quiet), 1);
+
+        // Finishing a query frees up a slot
+        drop(first);
+        assert_eq!(limits.active(&busy), 1);
+        assert!(limits.try_acquire(&busy).is_some());
+    }
+
+    #[test]
+    fn limit_can_be_overridden_per_deployment() {
+        let special = SubgraphDeploymentId::new("special").unwrap();
+        let normal = SubgraphDeploymentId::new("normal").unwrap();
+        let limits = QueryConcurrencyLimits::new(1).with_limit(special.clone(), 3);
+
+        assert_eq!(limits.limit(&special), 3);
+        assert_eq!(limits.limit(&normal), 1);
+
+        let permits: Vec<_> = (0..4).map(|_| limits.try_acquire(&special)).collect();
+        assert_eq!(permits.iter().filter(|p| p.is_some()).count(), 3);
+    }
+}
@@ -0,0 +1,149 @@
+use http::HeaderMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+
+use graph::prelude::*;
+
+/// Header through which proxies pass on the address of the client a query is rate
+/// limited for. It is only taken into account for requests from trusted proxies.
+pub const CLIENT_KEY_HEADER: &str = "X-Forwarded-For";
+
+/// Returns the key that identifies the client of a request for rate limiting.
+///
+/// This is the address of the peer the request came from, unless that peer is one of
+/// `trusted_proxies`. In that case, the client is the rightmost address in the
+/// `X-Forwarded-For` header that isn't a trusted proxy itself; anything to the left
+/// of it may have been made up by the client. Requests from an unknown peer share
+/// the empty client key.
+pub fn client_key(
+    headers: &HeaderMap,
+    peer_addr: Option<SocketAddr>,
+    trusted_proxies: &[IpAddr],
+) -> String {
+    let mut client = match peer_addr {
+        Some(addr) => addr.ip(),
+        None => return String::new(),
+    };
+
+    if trusted_proxies.contains(&client) {
+        let forwarded: Vec<&str> = headers
+            .get_all(CLIENT_KEY_HEADER)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect();
+
+        for addr in forwarded.into_iter().rev() {
+            match addr.trim().parse() {
+                Ok(addr) => client = addr,
+                Err(_) => break,
+            }
+            if !trusted_proxies.contains(&client) {
+                break;
+            }
+        }
+    }
+
+    client.to_string()
+}
+
+/// Error returned by a `RateLimiter` when a client has made too many queries.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimited(pub String);
+
+/// Decides whether a client may run another query against a subgraph.
+///
+/// The rate limiter is consulted for every query before it is executed. Any
+/// `Fn(&SubgraphDeploymentId, &str) -> Result<(), RateLimited>` can be used as
+/// a rate limiter.
+pub trait RateLimiter: Send + Sync + 'static {
+    /// Returns an error if the client identified by `client_key` must not run
+    /// another query against the subgraph right now.
+    fn check(
+        &self,
+        subgraph_id: &SubgraphDeploymentId,
+        client_key: &str,
+    ) -> Result<(), RateLimited>;
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RateLimiter")
+    }
+}
+
+impl<F> RateLimiter for F
+where
+    F: Fn(&SubgraphDeploymentId, &str) -> Result<(), RateLimited> + Send + Sync + 'static,
+{
+    fn check(
+        &self,
+        subgraph_id: &SubgraphDeploymentId,
+        client_key: &str,
+    ) -> Result<(), RateLimited> {
+        self(subgraph_id, client_key)
+    }
+}
+
+/// A rate limiter that lets every query through.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoRateLimit;
+
+impl RateLimiter for NoRateLimit {
+    fn check(&self, _: &SubgraphDeploymentId, _: &str) -> Result<(), RateLimited> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::HeaderValue;
+
+    fn forwarded_for(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(CLIENT_KEY_HEADER, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn client_key_ignores_forwarded_for_from_untrusted_peers() {
+        let peer = "192.0.2.1:4000".parse().ok();
+        let proxy = "10.0.0.1".parse().unwrap();
+
+        assert_eq!(
+            client_key(&forwarded_for("198.51.100.7"), peer, &[]),
+            "192.0.2.1"
+        );
+        assert_eq!(
+            client_key(&forwarded_for("198.51.100.7"), peer, &[proxy]),
+            "192.0.2.1"
+        );
+        assert_eq!(client_key(&HeaderMap::new(), None, &[proxy]), "");
+    }
+
+    #[test]
+    fn client_key_uses_the_client_seen_by_trusted_proxies() {
+        let peer = "10.0.0.1:4000".parse().ok();
+        let proxies = vec!["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+
+        // Addresses that the client put into the header itself are ignored
+        assert_eq!(
+            client_key(
+                &forwarded_for("203.0.113.9, 198.51.100.7, 10.0.0.2"),
+                peer,
+                &proxies
+            ),
+            "198.51.100.7"
+        );
+
+        // Without the header, the proxy is the client
+        assert_eq!(client_key(&HeaderMap::new(), peer, &proxies), "10.0.0.1");
+
+        // Garbage in the header is not used as a key
+        assert_eq!(
+            client_key(&forwarded_for("abuser"), peer, &proxies),
+            "10.0.0.1"
+        );
+    }
+}
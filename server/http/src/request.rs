@@ -1,20 +1,71 @@
 use graph::serde_json;
 use graphql_parser;
 use hyper::Chunk;
+use std::str;
 
 use graph::components::server::query::GraphQLServerError;
 use graph::prelude::*;
 
+/// Content type of request bodies that consist of nothing but the query.
+const RAW_QUERY_CONTENT_TYPE: &str = "application/graphql";
+
 /// Future for a query parsed from an HTTP request.
 pub struct GraphQLRequest {
     body: Chunk,
     schema: Schema,
+    raw_query: bool,
 }
 
 impl GraphQLRequest {
     /// Creates a new GraphQLRequest future based on an HTTP request and a result sender.
     pub fn new(body: Chunk, schema: Schema) -> Self {
-        GraphQLRequest { body, schema }
+        GraphQLRequest {
+            body,
+            schema,
+            raw_query: false,
+        }
+    }
+
+    /// Creates a new GraphQLRequest future for a body with the given content type.
+    ///
+    /// A body of type `application/graphql` is taken to be the query itself, without
+    /// any variables. Bodies of all other types are parsed as JSON.
+    pub fn with_content_type(body: Chunk, schema: Schema, content_type: Option<&str>) -> Self {
+        let raw_query = content_type
+            .and_then(|content_type| content_type.split(';').next())
+            .map(|mime_type| {
+                mime_type
+                    .trim()
+                    .eq_ignore_ascii_case(RAW_QUERY_CONTENT_TYPE)
+            })
+            .unwrap_or(false);
+
+        GraphQLRequest {
+            body,
+            schema,
+            raw_query,
+        }
+    }
+
+    /// Parses a body that consists of nothing but the query.
+    fn parse_raw_query(&self) -> Result<Query, GraphQLServerError> {
+        let query_string = str::from_utf8(&self.body)
+            .map_err(|e| GraphQLServerError::ClientError(format!("{}", e)))?;
+
+        if query_string.trim().is_empty() {
+            return Err(GraphQLServerError::ClientError(String::from(
+                "The request body does not contain a query",
+            )));
+        }
+
+        let document = graphql_parser::parse_query(query_string)
+            .map_err(|e| GraphQLServerError::from(QueryError::from(e)))?;
+
+        Ok(Query {
+            document,
+            variables: None,
+            schema: self.schema.clone(),
+        })
     }
 }
 
@@ -23,6 +74,10 @@ impl Future for GraphQLRequest {
     type Error = GraphQLServerError;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.raw_query {
+            return self.parse_raw_query().map(Async::Ready);
+        }
+
         // Fail if no schema is available
         let schema = self.schema.clone();
 
@@ -81,8 +136,11 @@ mod tests {
     use std::iter::FromIterator;
 
     use graph::prelude::*;
+    use http::StatusCode;
 
     use super::GraphQLRequest;
+    use response::GraphQLResponse;
+    use test_utils;
 
     const EXAMPLE_SCHEMA: &'static str = "type Query @entity { users: [User!] }";
 
@@ -122,6 +180,34 @@ mod tests {
         request.wait().expect_err("Should reject broken queries");
     }
 
+    #[test]
+    fn reports_the_location_of_syntax_errors() {
+        let schema =
+            Schema::parse(EXAMPLE_SCHEMA, SubgraphDeploymentId::new("test").unwrap()).unwrap();
+        let request = GraphQLRequest::new(
+            hyper::Chunk::from("{\"query\": \"{ user {\\n name\\n } } }\"}"),
+            schema,
+        );
+        let error = request.wait().expect_err("Should reject syntax errors");
+
+        let response = GraphQLResponse::new(Err(error))
+            .wait()
+            .expect("Should generate a response");
+        let errors = test_utils::assert_error_response(response, StatusCode::BAD_REQUEST);
+        assert_eq!(errors.len(), 1);
+
+        let error = errors[0].as_object().expect("Query error is not an object");
+        assert!(error.get("message").and_then(|m| m.as_str()).is_some());
+
+        let locations = error
+            .get("locations")
+            .and_then(|locations| locations.as_array())
+            .expect("Query error contains no locations");
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0]["line"], 3);
+        assert_eq!(locations[0]["column"], 6);
+    }
+
     #[test]
     fn accepts_valid_queries() {
         let schema =
@@ -137,6 +223,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn accepts_raw_queries() {
+        let schema =
+            Schema::parse(EXAMPLE_SCHEMA, SubgraphDeploymentId::new("test").unwrap()).unwrap();
+        let request = GraphQLRequest::with_content_type(
+            hyper::Chunk::from("{ user { name } }"),
+            schema,
+            Some("application/graphql; charset=utf-8"),
+        );
+        let query = request.wait().expect("Should accept raw queries");
+        assert_eq!(
+            query.document,
+            graphql_parser::parse_query("{ user { name } }").unwrap()
+        );
+        assert_eq!(query.variables, None);
+    }
+
+    #[test]
+    fn rejects_empty_raw_queries() {
+        let schema =
+            Schema::parse(EXAMPLE_SCHEMA, SubgraphDeploymentId::new("test").unwrap()).unwrap();
+        let request = GraphQLRequest::with_content_type(
+            hyper::Chunk::from(""),
+            schema,
+            Some("application/graphql"),
+        );
+        request.wait().expect_err("Should reject empty raw queries");
+    }
+
+    #[test]
+    fn parses_other_content_types_as_json() {
+        let schema =
+            Schema::parse(EXAMPLE_SCHEMA, SubgraphDeploymentId::new("test").unwrap()).unwrap();
+        let request = GraphQLRequest::with_content_type(
+            hyper::Chunk::from("{ user { name } }"),
+            schema,
+            Some("application/json"),
+        );
+        request
+            .wait()
+            .expect_err("Should parse the body of JSON requests as JSON");
+    }
+
     #[test]
     fn accepts_null_variables() {
         let schema =
@@ -7,16 +7,29 @@ use graph::data::query::QueryResult;
 use graph::serde_json;
 use graph::tokio::prelude::*;
 
+use cors::add_allow_origin_header;
+
 /// Future for HTTP responses to GraphQL query requests.
 pub struct GraphQLResponse {
     result: Result<QueryResult, GraphQLServerError>,
+    allow_origin: Option<String>,
 }
 
 impl GraphQLResponse {
     /// Creates a new GraphQLResponse future based on the result generated by
-    /// running a query.
+    /// running a query. The response may be read from any origin.
     pub fn new(result: Result<QueryResult, GraphQLServerError>) -> Self {
-        GraphQLResponse { result }
+        GraphQLResponse {
+            result,
+            allow_origin: Some(String::from("*")),
+        }
+    }
+
+    /// Sets the `Access-Control-Allow-Origin` header of the response; `None`
+    /// omits the header, so browsers will not let the page read the response.
+    pub fn with_allow_origin(mut self, allow_origin: Option<String>) -> Self {
+        self.allow_origin = allow_origin;
+        self
     }
 
     fn status_code_from_result(&self) -> StatusCode {
@@ -31,6 +44,8 @@ impl GraphQLResponse {
             Err(GraphQLServerError::ClientError(_)) | Err(GraphQLServerError::QueryError(_)) => {
                 StatusCode::BAD_REQUEST
             }
+            Err(GraphQLServerError::TooManyRequests(_))
+            | Err(GraphQLServerError::RateLimited(_)) => StatusCode::TOO_MANY_REQUESTS,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -61,13 +76,13 @@ impl Future for GraphQLResponse {
         let status_code = self.status_code_from_result();
         let json =
             serde_json::to_string(self).expect("Failed to serialize GraphQL response to JSON");
-        let response = Response::builder()
+        let mut builder = Response::builder();
+        builder
             .status(status_code)
-            .header("Access-Control-Allow-Origin", "*")
             .header("Access-Control-Allow-Headers", "Content-Type")
-            .header("Access-Control-Allow-Methods", "GET, OPTIONS, POST")
-            .body(Body::from(json))
-            .unwrap();
+            .header("Access-Control-Allow-Methods", "GET, OPTIONS, POST");
+        add_allow_origin_header(&mut builder, self.allow_origin.as_ref().map(|s| s.as_str()));
+        let response = builder.body(Body::from(json)).unwrap();
         Ok(Async::Ready(response))
     }
 }
@@ -108,6 +123,14 @@ mod tests {
         test_utils::assert_error_response(response, StatusCode::BAD_REQUEST);
     }
 
+    #[test]
+    fn generates_429_for_too_many_requests() {
+        let error = GraphQLServerError::TooManyRequests(String::from("foo"));
+        let future = GraphQLResponse::new(Err(error));
+        let response = future.wait().expect("Should generate a response");
+        test_utils::assert_error_response(response, StatusCode::TOO_MANY_REQUESTS);
+    }
+
     #[test]
     fn generates_200_for_query_results() {
         let data = graphql_parser::query::Value::Object(BTreeMap::new());
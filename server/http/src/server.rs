@@ -1,11 +1,16 @@
 use hyper;
+use hyper::server::conn::AddrStream;
+use hyper::service::make_service_fn;
 use hyper::Server;
 
 use std::error::Error;
 use std::fmt;
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::net::{IpAddr, Ipv4Addr, SocketAddrV4};
 
+use cors::AllowedOrigins;
 use graph::prelude::{GraphQLServer as GraphQLServerTrait, *};
+use query_limits::QueryConcurrencyLimits;
+use rate_limit::{NoRateLimit, RateLimiter};
 use service::GraphQLService;
 
 /// Errors that may occur when starting the server.
@@ -43,19 +48,64 @@ pub struct GraphQLServer<Q, S> {
     logger: Logger,
     graphql_runner: Arc<Q>,
     store: Arc<S>,
+    query_limits: Arc<QueryConcurrencyLimits>,
+    rate_limiter: Arc<RateLimiter>,
+    trusted_proxies: Arc<Vec<IpAddr>>,
+    allowed_origins: Arc<AllowedOrigins>,
+    log_query_timing: bool,
     node_id: NodeId,
 }
 
 impl<Q, S> GraphQLServer<Q, S> {
     /// Creates a new GraphQL server.
-    pub fn new(logger: &Logger, graphql_runner: Arc<Q>, store: Arc<S>, node_id: NodeId) -> Self {
+    pub fn new(
+        logger: &Logger,
+        graphql_runner: Arc<Q>,
+        store: Arc<S>,
+        query_limits: QueryConcurrencyLimits,
+        node_id: NodeId,
+    ) -> Self {
         GraphQLServer {
             logger: logger.new(o!("component" => "GraphQLServer")),
             graphql_runner,
             store,
+            query_limits: Arc::new(query_limits),
+            rate_limiter: Arc::new(NoRateLimit),
+            trusted_proxies: Arc::new(vec![]),
+            allowed_origins: Arc::new(AllowedOrigins::Any),
+            log_query_timing: false,
             node_id,
         }
     }
+
+    /// Consults `rate_limiter` before executing any query. By default, queries
+    /// are not rate limited.
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = rate_limiter;
+        self
+    }
+
+    /// Rate limits queries that come through one of `trusted_proxies` by the client
+    /// address in their `X-Forwarded-For` header. All other queries are rate limited
+    /// by the address they come from, which is also the default for all queries.
+    pub fn with_trusted_proxies(mut self, trusted_proxies: Vec<IpAddr>) -> Self {
+        self.trusted_proxies = Arc::new(trusted_proxies);
+        self
+    }
+
+    /// Only lets browsers read query results from `allowed_origins`. By default,
+    /// any origin is allowed.
+    pub fn with_allowed_origins(mut self, allowed_origins: AllowedOrigins) -> Self {
+        self.allowed_origins = Arc::new(allowed_origins);
+        self
+    }
+
+    /// Logs how long parsing and executing each query takes, at debug level. Off
+    /// by default.
+    pub fn with_query_timing(mut self, log_query_timing: bool) -> Self {
+        self.log_query_timing = log_query_timing;
+        self
+    }
 }
 
 impl<Q, S> GraphQLServerTrait for GraphQLServer<Q, S>
@@ -83,16 +133,33 @@ where
         // incoming queries to the query sink.
         let graphql_runner = self.graphql_runner.clone();
         let store = self.store.clone();
+        let query_limits = self.query_limits.clone();
+        let rate_limiter = self.rate_limiter.clone();
+        let trusted_proxies = self.trusted_proxies.clone();
+        let allowed_origins = self.allowed_origins.clone();
+        let query_timing_logger = if self.log_query_timing {
+            Some(self.logger.clone())
+        } else {
+            None
+        };
         let node_id = self.node_id.clone();
-        let new_service = move || {
-            let service = GraphQLService::new(
+        let new_service = make_service_fn(move |conn: &AddrStream| {
+            let mut service = GraphQLService::new(
                 graphql_runner.clone(),
                 store.clone(),
+                query_limits.clone(),
                 ws_port,
                 node_id.clone(),
-            );
+            )
+            .with_rate_limiter(rate_limiter.clone())
+            .with_peer_addr(conn.remote_addr())
+            .with_trusted_proxies(trusted_proxies.clone())
+            .with_allowed_origins(allowed_origins.clone());
+            if let Some(ref logger) = query_timing_logger {
+                service = service.with_query_timing_logger(logger.clone());
+            }
             future::ok::<GraphQLService<Q, S>, hyper::Error>(service)
-        };
+        });
 
         // Create a task to run the server and handle HTTP requests
         let task = Server::try_bind(&addr.into())?
@@ -1,10 +1,16 @@
 use graph::components::server::query::GraphQLServerError;
 use graph::data::subgraph::schema::{SubgraphEntity, SUBGRAPHS_ID};
 use graph::prelude::*;
+use graphql_parser::query as q;
 use http::header;
 use hyper::service::Service;
 use hyper::{Body, Method, Request, Response, StatusCode};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Instant;
 
+use cors::{add_allow_origin_header, is_credentialed, request_origin, AllowedOrigins};
+use query_limits::QueryConcurrencyLimits;
+use rate_limit::{client_key, NoRateLimit, RateLimiter};
 use request::GraphQLRequest;
 use response::GraphQLResponse;
 
@@ -17,6 +23,12 @@ pub type GraphQLServiceResponse =
 pub struct GraphQLService<Q, S> {
     graphql_runner: Arc<Q>,
     store: Arc<S>,
+    query_limits: Arc<QueryConcurrencyLimits>,
+    rate_limiter: Arc<RateLimiter>,
+    peer_addr: Option<SocketAddr>,
+    trusted_proxies: Arc<Vec<IpAddr>>,
+    allowed_origins: Arc<AllowedOrigins>,
+    query_timing_logger: Option<Logger>,
     ws_port: u16,
     node_id: NodeId,
 }
@@ -26,6 +38,12 @@ impl<Q, S> Clone for GraphQLService<Q, S> {
         Self {
             graphql_runner: self.graphql_runner.clone(),
             store: self.store.clone(),
+            query_limits: self.query_limits.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            peer_addr: self.peer_addr,
+            trusted_proxies: self.trusted_proxies.clone(),
+            allowed_origins: self.allowed_origins.clone(),
+            query_timing_logger: self.query_timing_logger.clone(),
             ws_port: self.ws_port,
             node_id: self.node_id.clone(),
         }
@@ -38,15 +56,59 @@ where
     S: SubgraphDeploymentStore + Store,
 {
     /// Creates a new GraphQL service.
-    pub fn new(graphql_runner: Arc<Q>, store: Arc<S>, ws_port: u16, node_id: NodeId) -> Self {
+    pub fn new(
+        graphql_runner: Arc<Q>,
+        store: Arc<S>,
+        query_limits: Arc<QueryConcurrencyLimits>,
+        ws_port: u16,
+        node_id: NodeId,
+    ) -> Self {
         GraphQLService {
             graphql_runner,
             store,
+            query_limits,
+            rate_limiter: Arc::new(NoRateLimit),
+            peer_addr: None,
+            trusted_proxies: Arc::new(vec![]),
+            allowed_origins: Arc::new(AllowedOrigins::Any),
+            query_timing_logger: None,
             ws_port,
             node_id,
         }
     }
 
+    /// Consults `rate_limiter` before executing any query.
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = rate_limiter;
+        self
+    }
+
+    /// Sets the address of the peer that the requests to this service come from; queries
+    /// are rate limited by this address.
+    pub fn with_peer_addr(mut self, peer_addr: SocketAddr) -> Self {
+        self.peer_addr = Some(peer_addr);
+        self
+    }
+
+    /// Rate limits queries from `trusted_proxies` by the client address in their
+    /// `X-Forwarded-For` header instead of by the address of the proxy.
+    pub fn with_trusted_proxies(mut self, trusted_proxies: Arc<Vec<IpAddr>>) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    /// Only lets browsers read query results from `allowed_origins`.
+    pub fn with_allowed_origins(mut self, allowed_origins: Arc<AllowedOrigins>) -> Self {
+        self.allowed_origins = allowed_origins;
+        self
+    }
+
+    /// Logs how long parsing and executing each query takes to `logger`, at debug level.
+    pub fn with_query_timing_logger(mut self, logger: Logger) -> Self {
+        self.query_timing_logger = Some(logger);
+        self
+    }
+
     fn graphiql_html(&self) -> String {
         include_str!("../assets/index.html")
             .replace("__WS_PORT__", format!("{}", self.ws_port).as_str())
@@ -56,7 +118,7 @@ where
         let service = self.clone();
 
         let entity_query = SubgraphEntity::query().range(EntityRange {
-            first: 2, // Ask for two to find out if there is more than one
+            first: Some(2), // Ask for two to find out if there is more than one
             skip: 0,
         });
 
@@ -152,9 +214,7 @@ where
                         "Subgraph name not found".to_owned(),
                     ))
                 })
-                .and_then(move |subgraph_id| {
-                    service.handle_graphql_query(subgraph_id, request.into_body())
-                }),
+                .and_then(move |subgraph_id| service.handle_graphql_query(subgraph_id, request)),
         )
     }
 
@@ -165,16 +225,38 @@ where
     ) -> GraphQLServiceResponse {
         match SubgraphDeploymentId::new(id) {
             Err(()) => self.handle_not_found(),
-            Ok(id) => self.handle_graphql_query(id, request.into_body()),
+            Ok(id) => self.handle_graphql_query(id, request),
         }
     }
 
     fn handle_graphql_query(
         &self,
         id: SubgraphDeploymentId,
-        request_body: Body,
+        request: Request<Body>,
     ) -> GraphQLServiceResponse {
         let service = self.clone();
+        let content_type = request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_owned());
+
+        // Reject credentialed requests from origins that are not allowed; other
+        // requests are answered without CORS headers, which keeps browsers from
+        // reading the response
+        let origin = request_origin(request.headers());
+        let allow_origin = self
+            .allowed_origins
+            .allow_origin(origin.as_ref().map(|s| s.as_str()));
+        if origin.is_some() && allow_origin.is_none() && is_credentialed(request.headers()) {
+            return Box::new(
+                GraphQLResponse::new(Err(GraphQLServerError::ClientError(format!(
+                    "Origin not allowed: {}",
+                    origin.unwrap()
+                ))))
+                .with_allow_origin(None),
+            );
+        }
 
         match self.store.is_deployed(&id) {
             Err(e) => {
@@ -191,6 +273,20 @@ where
             Ok(true) => (),
         }
 
+        // Reject the query if the client has exceeded its rate limit for the subgraph;
+        // this is answered with a response so the client sees a 429
+        let rate_limit = {
+            let client_key = client_key(request.headers(), self.peer_addr, &self.trusted_proxies);
+            self.rate_limiter.check(&id, &client_key)
+        };
+        if let Err(limited) = rate_limit {
+            return Box::new(
+                GraphQLResponse::new(Err(GraphQLServerError::RateLimited(limited.0)))
+                    .with_allow_origin(allow_origin),
+            );
+        }
+
+        let subgraph_id = id.clone();
         let schema = match self.store.subgraph_schema(id) {
             Ok(schema) => schema,
             Err(e) => {
@@ -201,32 +297,88 @@ where
         };
 
         Box::new(
-            request_body
+            request
+                .into_body()
                 .concat2()
                 .map_err(|_| GraphQLServerError::from("Failed to read request body"))
-                .and_then(move |body| GraphQLRequest::new(body, schema))
-                .and_then(move |query| {
+                .and_then(move |body| {
+                    let start_time = Instant::now();
+                    GraphQLRequest::with_content_type(
+                        body,
+                        schema,
+                        content_type.as_ref().map(|s| s.as_str()),
+                    )
+                    .map(move |query| (query, start_time.elapsed()))
+                })
+                .and_then(move |(query, parse_time)| {
+                    // Reject the query if the subgraph is already running as many queries
+                    // as allowed. The slot is only taken once the body has been read and
+                    // parsed, so that slow clients can't hold on to it; this is answered
+                    // with a response so the client sees a 429
+                    let permit = match service.query_limits.try_acquire(&subgraph_id) {
+                        Some(permit) => permit,
+                        None => {
+                            return future::Either::A(future::err(
+                                GraphQLServerError::TooManyRequests(format!(
+                                    "Too many concurrent queries for subgraph {}, limit is {}",
+                                    subgraph_id,
+                                    service.query_limits.limit(&subgraph_id)
+                                )),
+                            ));
+                        }
+                    };
+
+                    let timing_logger = service.query_timing_logger.clone();
+                    let operation_name = operation_name(&query.document);
+                    let start_time = Instant::now();
+
                     // Run the query using the query runner
-                    service
-                        .graphql_runner
-                        .run_query(query)
-                        .map_err(|e| GraphQLServerError::from(e))
+                    future::Either::B(
+                        service
+                            .graphql_runner
+                            .run_query(query)
+                            .map_err(|e| GraphQLServerError::from(e))
+                            .map(move |result| {
+                                if let Some(logger) = timing_logger {
+                                    let execute_time = start_time.elapsed();
+                                    debug!(
+                                        logger, "Query timing";
+                                        "subgraph_id" => subgraph_id.to_string(),
+                                        "operation_name" => operation_name,
+                                        // Replace these when `as_millis` is stable.
+                                        "parse_secs" => parse_time.as_secs(),
+                                        "parse_ms" => parse_time.subsec_millis(),
+                                        "execute_secs" => execute_time.as_secs(),
+                                        "execute_ms" => execute_time.subsec_millis()
+                                    );
+                                }
+                                result
+                            })
+                            .then(move |result| {
+                                // The query is done, release its slot
+                                drop(permit);
+                                result
+                            }),
+                    )
                 })
-                .then(|result| GraphQLResponse::new(result)),
+                .then(move |result| GraphQLResponse::new(result).with_allow_origin(allow_origin)),
         )
     }
 
     // Handles OPTIONS requests
-    fn handle_graphql_options(&self, _request: Request<Body>) -> GraphQLServiceResponse {
-        Box::new(future::ok(
-            Response::builder()
-                .status(200)
-                .header("Access-Control-Allow-Origin", "*")
-                .header("Access-Control-Allow-Headers", "Content-Type")
-                .header("Access-Control-Allow-Methods", "GET, OPTIONS, POST")
-                .body(Body::from(""))
-                .unwrap(),
-        ))
+    fn handle_graphql_options(&self, request: Request<Body>) -> GraphQLServiceResponse {
+        let origin = request_origin(request.headers());
+        let allow_origin = self
+            .allowed_origins
+            .allow_origin(origin.as_ref().map(|s| s.as_str()));
+
+        let mut builder = Response::builder();
+        builder
+            .status(200)
+            .header("Access-Control-Allow-Headers", "Content-Type")
+            .header("Access-Control-Allow-Methods", "GET, OPTIONS, POST");
+        add_allow_origin_header(&mut builder, allow_origin.as_ref().map(|s| s.as_str()));
+        Box::new(future::ok(builder.body(Body::from("")).unwrap()))
     }
 
     /// Handles 302 redirects
@@ -255,6 +407,24 @@ where
     }
 }
 
+/// Returns the name of the first named operation in a query document.
+fn operation_name(document: &q::Document) -> Option<String> {
+    document
+        .definitions
+        .iter()
+        .filter_map(|definition| match definition {
+            q::Definition::Operation(q::OperationDefinition::Query(query)) => query.name.clone(),
+            q::Definition::Operation(q::OperationDefinition::Mutation(mutation)) => {
+                mutation.name.clone()
+            }
+            q::Definition::Operation(q::OperationDefinition::Subscription(subscription)) => {
+                subscription.name.clone()
+            }
+            _ => None,
+        })
+        .next()
+}
+
 impl<Q, S> Service for GraphQLService<Q, S>
 where
     Q: GraphQlRunner,
@@ -331,13 +501,18 @@ mod tests {
     use graphql_parser::query as q;
     use http::status::StatusCode;
     use hyper::service::Service;
-    use hyper::{Body, Method, Request};
+    use hyper::{Body, Method, Request, Response};
     use std::collections::BTreeMap;
     use std::iter::FromIterator;
 
     use graph::prelude::*;
+    use graph::slog::{Drain, Never, OwnedKVList, Record};
+    use std::sync::Mutex;
 
-    use super::GraphQLService;
+    use super::{operation_name, GraphQLService};
+    use cors::AllowedOrigins;
+    use query_limits::QueryConcurrencyLimits;
+    use rate_limit::RateLimited;
     use test_utils;
 
     /// A simple stupid query runner for testing.
@@ -375,7 +550,8 @@ mod tests {
         let graphql_runner = Arc::new(TestGraphQlRunner);
         let store = Arc::new(MockStore::new(vec![(id.clone(), schema)]));
         let node_id = NodeId::new("test").unwrap();
-        let mut service = GraphQLService::new(graphql_runner, store, 8001, node_id);
+        let query_limits = Arc::new(QueryConcurrencyLimits::new(10));
+        let mut service = GraphQLService::new(graphql_runner, store, query_limits, 8001, node_id);
 
         let request = Request::builder()
             .method(Method::POST)
@@ -418,7 +594,9 @@ mod tests {
             .block_on(future::lazy(move || {
                 let res: Result<_, ()> = Ok({
                     let node_id = NodeId::new("test").unwrap();
-                    let mut service = GraphQLService::new(graphql_runner, store, 8001, node_id);
+                    let query_limits = Arc::new(QueryConcurrencyLimits::new(10));
+                    let mut service =
+                        GraphQLService::new(graphql_runner, store, query_limits, 8001, node_id);
 
                     let request = Request::builder()
                         .method(Method::POST)
@@ -445,4 +623,277 @@ mod tests {
             }))
             .unwrap()
     }
+
+    /// A log drain that remembers the messages of all records logged to it.
+    #[derive(Clone, Default)]
+    struct LogMessages(Arc<Mutex<Vec<String>>>);
+
+    impl Drain for LogMessages {
+        type Ok = ();
+        type Err = Never;
+
+        fn log(&self, record: &Record, _: &OwnedKVList) -> Result<(), Never> {
+            self.0.lock().unwrap().push(record.msg().to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn query_timing_is_logged_on_request() {
+        let id = SubgraphDeploymentId::new("testschema").unwrap();
+        let schema = Schema::parse(
+            "\
+             scalar String \
+             type Query @entity { name: String } \
+             ",
+            id.clone(),
+        )
+        .unwrap();
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+        let store = Arc::new(MockStore::new(vec![(id.clone(), schema)]));
+        let messages = LogMessages::default();
+        let logger = Logger::root(messages.clone(), o!());
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime
+            .block_on(future::lazy(move || {
+                let node_id = NodeId::new("test").unwrap();
+                let query_limits = Arc::new(QueryConcurrencyLimits::new(10));
+                let mut service =
+                    GraphQLService::new(graphql_runner, store, query_limits, 8001, node_id)
+                        .with_query_timing_logger(logger);
+
+                let request = Request::builder()
+                    .method(Method::POST)
+                    .uri(format!("http://localhost:8000/subgraphs/id/{}", id))
+                    .body(Body::from("{\"query\": \"query Names { name }\"}"))
+                    .unwrap();
+                service.call(request).map(|response| {
+                    test_utils::assert_successful_response(response);
+                })
+            }))
+            .expect("Should return a response");
+
+        assert!(messages
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|message| message == "Query timing"));
+    }
+
+    #[test]
+    fn operation_name_is_the_name_of_the_first_named_operation() {
+        let document =
+            |query: &str| graphql_parser::parse_query(query).expect("Invalid test query");
+        assert_eq!(operation_name(&document("{ name }")), None);
+        assert_eq!(
+            operation_name(&document("query Names { name }")),
+            Some(String::from("Names"))
+        );
+        assert_eq!(
+            operation_name(&document("{ name } query Names { name }")),
+            Some(String::from("Names"))
+        );
+    }
+
+    #[test]
+    fn queries_beyond_the_concurrency_limit_yield_too_many_requests() {
+        let id = SubgraphDeploymentId::new("testschema").unwrap();
+        let schema = Schema::parse(
+            "\
+             scalar String \
+             type Query @entity { name: String } \
+             ",
+            id.clone(),
+        )
+        .unwrap();
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+        let store = Arc::new(MockStore::new(vec![(id.clone(), schema)]));
+        let node_id = NodeId::new("test").unwrap();
+        let query_limits = Arc::new(QueryConcurrencyLimits::new(1));
+        let mut service =
+            GraphQLService::new(graphql_runner, store, query_limits.clone(), 8001, node_id);
+
+        // Occupy the only query slot of the subgraph
+        let _permit = query_limits
+            .try_acquire(&id)
+            .expect("Should acquire the only permit");
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://localhost:8000/subgraphs/id/{}", id))
+            .body(Body::from("{\"query\": \"{ name }\"}"))
+            .unwrap();
+
+        // The query is answered with a 429 instead of dropping the connection
+        let response = service
+            .call(request)
+            .wait()
+            .expect("Should return a response");
+        test_utils::assert_error_response(response, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[test]
+    fn rate_limited_queries_yield_too_many_requests() {
+        let id = SubgraphDeploymentId::new("testschema").unwrap();
+        let schema = Schema::parse(
+            "\
+             scalar String \
+             type Query @entity { name: String } \
+             ",
+            id.clone(),
+        )
+        .unwrap();
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+        let store = Arc::new(MockStore::new(vec![(id.clone(), schema)]));
+        let node_id = NodeId::new("test").unwrap();
+        let query_limits = Arc::new(QueryConcurrencyLimits::new(10));
+        let rate_limiter = |_: &SubgraphDeploymentId, client_key: &str| {
+            if client_key == "198.51.100.7" {
+                Err(RateLimited(format!("Too many queries from {}", client_key)))
+            } else {
+                Ok(())
+            }
+        };
+        let mut service = GraphQLService::new(graphql_runner, store, query_limits, 8001, node_id)
+            .with_rate_limiter(Arc::new(rate_limiter))
+            .with_peer_addr("10.0.0.1:4000".parse().unwrap())
+            .with_trusted_proxies(Arc::new(vec!["10.0.0.1".parse().unwrap()]));
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://localhost:8000/subgraphs/id/{}", id))
+            .header("X-Forwarded-For", "198.51.100.7")
+            .body(Body::from("{\"query\": \"{ name }\"}"))
+            .unwrap();
+
+        let response = service
+            .call(request)
+            .wait()
+            .expect("Should return a response");
+        let errors = test_utils::assert_error_response(response, StatusCode::TOO_MANY_REQUESTS);
+
+        let message = errors[0]
+            .as_object()
+            .expect("Query error is not an object")
+            .get("message")
+            .expect("Error contains no message")
+            .as_str()
+            .expect("Error message is not a string");
+        assert_eq!(message, "Too many queries from 198.51.100.7");
+    }
+
+    fn cors_test_service(
+        allowed_origins: AllowedOrigins,
+    ) -> (
+        GraphQLService<TestGraphQlRunner, MockStore>,
+        SubgraphDeploymentId,
+    ) {
+        let id = SubgraphDeploymentId::new("testschema").unwrap();
+        let schema = Schema::parse(
+            "\
+             scalar String \
+             type Query @entity { name: String } \
+             ",
+            id.clone(),
+        )
+        .unwrap();
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+        let store = Arc::new(MockStore::new(vec![(id.clone(), schema)]));
+        let node_id = NodeId::new("test").unwrap();
+        let query_limits = Arc::new(QueryConcurrencyLimits::new(10));
+        let service = GraphQLService::new(graphql_runner, store, query_limits, 8001, node_id)
+            .with_allowed_origins(Arc::new(allowed_origins));
+        (service, id)
+    }
+
+    fn call_in_runtime(
+        service: &mut GraphQLService<TestGraphQlRunner, MockStore>,
+        request: Request<Body>,
+    ) -> Response<Body> {
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime
+            .block_on(service.call(request))
+            .expect("Should return a response")
+    }
+
+    fn allow_origin_header(response: &Response<Body>) -> Option<&str> {
+        response
+            .headers()
+            .get("Access-Control-Allow-Origin")
+            .map(|value| value.to_str().unwrap())
+    }
+
+    #[test]
+    fn allowed_origins_can_read_query_results() {
+        let (mut service, id) = cors_test_service(AllowedOrigins::parse("https://allowed.example"));
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://localhost:8000/subgraphs/id/{}", id))
+            .header("Origin", "https://allowed.example")
+            .header("Cookie", "session=1")
+            .body(Body::from("{\"query\": \"{ name }\"}"))
+            .unwrap();
+
+        let response = call_in_runtime(&mut service, request);
+        assert_eq!(
+            allow_origin_header(&response),
+            Some("https://allowed.example")
+        );
+        test_utils::assert_successful_response(response);
+    }
+
+    #[test]
+    fn disallowed_origins_are_rejected_for_credentialed_requests() {
+        let (mut service, id) = cors_test_service(AllowedOrigins::parse("https://allowed.example"));
+
+        // Without credentials, the query runs but browsers can't read the result
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://localhost:8000/subgraphs/id/{}", id))
+            .header("Origin", "https://other.example")
+            .body(Body::from("{\"query\": \"{ name }\"}"))
+            .unwrap();
+        let response = call_in_runtime(&mut service, request);
+        assert_eq!(allow_origin_header(&response), None);
+        test_utils::assert_successful_response(response);
+
+        // With credentials, the query is rejected
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://localhost:8000/subgraphs/id/{}", id))
+            .header("Origin", "https://other.example")
+            .header("Authorization", "Bearer secret")
+            .body(Body::from("{\"query\": \"{ name }\"}"))
+            .unwrap();
+        let response = call_in_runtime(&mut service, request);
+        assert_eq!(allow_origin_header(&response), None);
+        let errors = test_utils::assert_error_response(response, StatusCode::BAD_REQUEST);
+        let message = errors[0]
+            .as_object()
+            .expect("Query error is not an object")
+            .get("message")
+            .expect("Error contains no message")
+            .as_str()
+            .expect("Error message is not a string");
+        assert_eq!(message, "Origin not allowed: https://other.example");
+    }
+
+    #[test]
+    fn wildcard_origins_allow_any_origin() {
+        let (mut service, id) = cors_test_service(AllowedOrigins::parse("*"));
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://localhost:8000/subgraphs/id/{}", id))
+            .header("Origin", "https://any.example")
+            .header("Cookie", "session=1")
+            .body(Body::from("{\"query\": \"{ name }\"}"))
+            .unwrap();
+
+        let response = call_in_runtime(&mut service, request);
+        assert_eq!(allow_origin_header(&response), Some("*"));
+        test_utils::assert_successful_response(response);
+    }
 }
@@ -18,6 +18,7 @@ use graph::prelude::*;
 
 use graph_server_http::test_utils;
 use graph_server_http::GraphQLServer as HyperGraphQLServer;
+use graph_server_http::QueryConcurrencyLimits;
 
 use tokio::timer::Delay;
 
@@ -82,7 +83,9 @@ mod test {
                 let query_runner = Arc::new(TestGraphQlRunner);
                 let store = mock_store(id.clone());
                 let node_id = NodeId::new("test").unwrap();
-                let mut server = HyperGraphQLServer::new(&logger, query_runner, store, node_id);
+                let query_limits = QueryConcurrencyLimits::new(10);
+                let mut server =
+                    HyperGraphQLServer::new(&logger, query_runner, store, query_limits, node_id);
                 let http_server = server
                     .serve(8001, 8002)
                     .expect("Failed to start GraphQL server");
@@ -131,7 +134,9 @@ mod test {
                 let query_runner = Arc::new(TestGraphQlRunner);
                 let store = mock_store(id.clone());
                 let node_id = NodeId::new("test").unwrap();
-                let mut server = HyperGraphQLServer::new(&logger, query_runner, store, node_id);
+                let query_limits = QueryConcurrencyLimits::new(10);
+                let mut server =
+                    HyperGraphQLServer::new(&logger, query_runner, store, query_limits, node_id);
                 let http_server = server
                     .serve(8002, 8003)
                     .expect("Failed to start GraphQL server");
@@ -214,7 +219,9 @@ mod test {
                 let query_runner = Arc::new(TestGraphQlRunner);
                 let store = mock_store(id.clone());
                 let node_id = NodeId::new("test").unwrap();
-                let mut server = HyperGraphQLServer::new(&logger, query_runner, store, node_id);
+                let query_limits = QueryConcurrencyLimits::new(10);
+                let mut server =
+                    HyperGraphQLServer::new(&logger, query_runner, store, query_limits, node_id);
                 let http_server = server
                     .serve(8003, 8004)
                     .expect("Failed to start GraphQL server");
@@ -263,7 +270,9 @@ mod test {
                 let query_runner = Arc::new(TestGraphQlRunner);
                 let store = mock_store(id.clone());
                 let node_id = NodeId::new("test").unwrap();
-                let mut server = HyperGraphQLServer::new(&logger, query_runner, store, node_id);
+                let query_limits = QueryConcurrencyLimits::new(10);
+                let mut server =
+                    HyperGraphQLServer::new(&logger, query_runner, store, query_limits, node_id);
                 let http_server = server
                     .serve(8005, 8006)
                     .expect("Failed to start GraphQL server");
@@ -20,7 +20,8 @@ const JSON_RPC_INTERNAL_ERROR: i64 = 3;
 
 #[derive(Debug, Deserialize)]
 struct SubgraphCreateParams {
-    name: SubgraphName,
+    /// Validated in the handler, to report which naming rule is violated.
+    name: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -28,6 +29,9 @@ struct SubgraphDeployParams {
     name: SubgraphName,
     ipfs_hash: SubgraphDeploymentId,
     node_id: Option<NodeId>,
+    /// Block at which a new deployment starts indexing, e.g.
+    /// `{"hash": "0x...", "number": 7000000}`.
+    start_block: Option<EthereumBlockPointer>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -56,9 +60,17 @@ where
 
         info!(logger, "Received subgraph_create request"; "params" => format!("{:?}", params));
 
+        if let Err(reason) = SubgraphName::validate(&params.name) {
+            return Box::new(future::err(json_rpc_error(
+                JSON_RPC_CREATE_ERROR,
+                SubgraphRegistrarError::InvalidName(reason).to_string(),
+            )));
+        }
+        let name = SubgraphName::new(params.name).unwrap();
+
         Box::new(
             self.registrar
-                .create_subgraph(params.name)
+                .create_subgraph(name)
                 .map_err(move |e| {
                     if let SubgraphRegistrarError::Unknown(e) = e {
                         error!(logger, "subgraph_create failed: {}", e);
@@ -87,7 +99,7 @@ where
 
         Box::new(
             self.registrar
-                .create_subgraph_version(params.name, params.ipfs_hash, node_id)
+                .create_subgraph_version(params.name, params.ipfs_hash, node_id, params.start_block)
                 .map_err(move |e| {
                     if let SubgraphRegistrarError::Unknown(e) = e {
                         error!(logger, "subgraph_deploy failed: {}", e);
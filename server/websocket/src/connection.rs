@@ -1,9 +1,12 @@
-use futures::future::IntoFuture;
+use futures::future::{Either, IntoFuture};
 use futures::stream::SplitStream;
-use futures::sync::mpsc;
+use futures::sync::{mpsc, oneshot};
 use graphql_parser::parse_query;
 use std::collections::HashMap;
 use std::iter::FromIterator;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
 use tokio_tungstenite::tungstenite::{Error as WsError, Message as WsMessage};
 use tokio_tungstenite::WebSocketStream;
 use uuid::Uuid;
@@ -11,15 +14,52 @@ use uuid::Uuid;
 use graph::prelude::*;
 use graph::serde_json;
 
+/// The GraphQL over WebSocket subprotocols supported by the server, in order of preference.
+const SUPPORTED_PROTOCOLS: &[WsProtocol] = &[WsProtocol::GraphqlTransportWs, WsProtocol::GraphqlWs];
+
+/// A GraphQL over WebSocket subprotocol.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum WsProtocol {
+    /// The `graphql-transport-ws` protocol implemented by `graphql-ws`.
+    GraphqlTransportWs,
+    /// The legacy `graphql-ws` protocol implemented by `subscriptions-transport-ws`.
+    GraphqlWs,
+}
+
+impl WsProtocol {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WsProtocol::GraphqlTransportWs => "graphql-transport-ws",
+            WsProtocol::GraphqlWs => "graphql-ws",
+        }
+    }
+
+    /// Picks the most preferred supported protocol from the comma-separated list of protocols
+    /// in a `Sec-WebSocket-Protocol` header. Returns `None` if none of them is supported.
+    pub fn negotiate(offered: &str) -> Option<WsProtocol> {
+        let offered: Vec<_> = offered.split(',').map(|s| s.trim()).collect();
+        SUPPORTED_PROTOCOLS
+            .iter()
+            .find(|protocol| offered.contains(&protocol.as_str()))
+            .cloned()
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct StartPayload {
     query: String,
     variables: Option<serde_json::Value>,
     operation_name: Option<String>,
+    /// Clients opt in to receiving the current result before any changes.
+    #[serde(default)]
+    send_initial: bool,
 }
 
 /// GraphQL/WebSocket message received from a client.
+///
+/// `start`, `stop` and `connection_terminate` are only sent with `graphql-ws`;
+/// `subscribe`, `complete`, `ping` and `pong` only with `graphql-transport-ws`.
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 enum IncomingMessage {
@@ -27,6 +67,10 @@ enum IncomingMessage {
     ConnectionTerminate,
     Start { id: String, payload: StartPayload },
     Stop { id: String },
+    Subscribe { id: String, payload: StartPayload },
+    Complete { id: String },
+    Ping { payload: Option<serde_json::Value> },
+    Pong { payload: Option<serde_json::Value> },
 }
 
 impl IncomingMessage {
@@ -40,26 +84,63 @@ impl IncomingMessage {
     }
 }
 
+#[derive(Debug, Serialize)]
+struct ErrorPayload {
+    message: String,
+}
+
 /// GraphQL/WebSocket message to be sent to the client.
 #[derive(Debug, Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 enum OutgoingMessage {
     ConnectionAck,
-    Error { id: String, payload: String },
-    Data { id: String, payload: QueryResult },
-    Complete { id: String },
+    Error {
+        id: String,
+        payload: String,
+    },
+    #[serde(rename = "error")]
+    Errors {
+        id: String,
+        payload: Vec<ErrorPayload>,
+    },
+    Data {
+        id: String,
+        payload: QueryResult,
+    },
+    Next {
+        id: String,
+        payload: QueryResult,
+    },
+    Complete {
+        id: String,
+    },
+    Pong {
+        payload: Option<serde_json::Value>,
+    },
 }
 
 impl OutgoingMessage {
-    pub fn from_query_result(id: String, result: QueryResult) -> Self {
-        OutgoingMessage::Data {
-            id: id,
-            payload: result,
+    pub fn from_query_result(protocol: WsProtocol, id: String, result: QueryResult) -> Self {
+        match protocol {
+            WsProtocol::GraphqlTransportWs => OutgoingMessage::Next {
+                id: id,
+                payload: result,
+            },
+            WsProtocol::GraphqlWs => OutgoingMessage::Data {
+                id: id,
+                payload: result,
+            },
         }
     }
 
-    pub fn from_error_string(id: String, s: String) -> Self {
-        OutgoingMessage::Error { id, payload: s }
+    pub fn from_error_string(protocol: WsProtocol, id: String, s: String) -> Self {
+        match protocol {
+            WsProtocol::GraphqlTransportWs => OutgoingMessage::Errors {
+                id,
+                payload: vec![ErrorPayload { message: s }],
+            },
+            WsProtocol::GraphqlWs => OutgoingMessage::Error { id, payload: s },
+        }
     }
 }
 
@@ -81,10 +162,11 @@ fn send_message(
 /// Helper function to send error messages.
 fn send_error_string(
     sink: &mpsc::UnboundedSender<WsMessage>,
+    protocol: WsProtocol,
     operation_id: String,
     error: String,
 ) -> Result<(), WsError> {
-    sink.unbounded_send(OutgoingMessage::from_error_string(operation_id, error).into())
+    sink.unbounded_send(OutgoingMessage::from_error_string(protocol, operation_id, error).into())
         .map_err(|_| WsError::Http(500))
 }
 
@@ -93,13 +175,22 @@ fn send_error_string(
 struct Operations {
     operations: HashMap<String, CancelGuard>,
     msg_sink: mpsc::UnboundedSender<WsMessage>,
+    protocol: WsProtocol,
+    /// Number of active subscriptions across all connections of the server.
+    subscription_count: Arc<AtomicUsize>,
 }
 
 impl Operations {
-    fn new(msg_sink: mpsc::UnboundedSender<WsMessage>) -> Self {
+    fn new(
+        msg_sink: mpsc::UnboundedSender<WsMessage>,
+        protocol: WsProtocol,
+        subscription_count: Arc<AtomicUsize>,
+    ) -> Self {
         Self {
             operations: HashMap::new(),
             msg_sink,
+            protocol,
+            subscription_count,
         }
     }
 
@@ -108,7 +199,9 @@ impl Operations {
     }
 
     fn insert(&mut self, id: String, guard: CancelGuard) {
-        self.operations.insert(id, guard);
+        if self.operations.insert(id, guard).is_none() {
+            self.subscription_count.fetch_add(1, Ordering::SeqCst);
+        }
     }
 
     fn stop(&mut self, operation_id: String) -> Result<(), WsError> {
@@ -117,6 +210,7 @@ impl Operations {
             Some(stopper) => {
                 // Cancel the subscription result stream.
                 stopper.cancel();
+                self.subscription_count.fetch_sub(1, Ordering::SeqCst);
 
                 // Send a GQL_COMPLETE to indicate the operation is been completed.
                 send_message(
@@ -128,6 +222,7 @@ impl Operations {
             }
             None => send_error_string(
                 &self.msg_sink,
+                self.protocol,
                 operation_id.clone(),
                 format!("Unknown operation ID: {}", operation_id),
             ),
@@ -152,6 +247,9 @@ pub struct GraphQlConnection<Q, S> {
     graphql_runner: Arc<Q>,
     stream: WebSocketStream<S>,
     schema: Schema,
+    protocol: WsProtocol,
+    shutdown: oneshot::Receiver<()>,
+    subscription_count: Arc<AtomicUsize>,
 }
 
 impl<Q, S> GraphQlConnection<Q, S>
@@ -165,6 +263,9 @@ where
         schema: Schema,
         stream: WebSocketStream<S>,
         graphql_runner: Arc<Q>,
+        protocol: WsProtocol,
+        shutdown: oneshot::Receiver<()>,
+        subscription_count: Arc<AtomicUsize>,
     ) -> Self {
         GraphQlConnection {
             id: Uuid::new_v4().to_string(),
@@ -172,9 +273,17 @@ where
             graphql_runner,
             stream,
             schema,
+            protocol,
+            shutdown,
+            subscription_count,
         }
     }
 
+    /// The unique ID of this connection.
+    pub(crate) fn id(&self) -> &str {
+        &self.id
+    }
+
     fn handle_incoming_messages(
         ws_stream: SplitStream<WebSocketStream<S>>,
         mut msg_sink: mpsc::UnboundedSender<WsMessage>,
@@ -182,8 +291,10 @@ where
         connection_id: String,
         schema: Schema,
         graphql_runner: Arc<Q>,
+        protocol: WsProtocol,
+        subscription_count: Arc<AtomicUsize>,
     ) -> impl Future<Item = (), Error = WsError> {
-        let mut operations = Operations::new(msg_sink.clone());
+        let mut operations = Operations::new(msg_sink.clone(), protocol, subscription_count);
 
         // Process incoming messages as long as the WebSocket is open
         ws_stream.for_each(move |ws_msg| {
@@ -210,18 +321,25 @@ where
                     msg_sink.close().unwrap();
 
                     // Return an error here to terminate the connection
-                    Err(WsError::ConnectionClosed(None))
+                    Err(WsError::ConnectionClosed)
+                }
+
+                // Answer keep-alive pings
+                IncomingMessage::Ping { payload } => {
+                    send_message(&msg_sink, OutgoingMessage::Pong { payload })
                 }
+                IncomingMessage::Pong { payload: _ } => Ok(()),
 
                 // When receiving a stop request
-                Stop { id } => operations.stop(id),
+                Stop { id } | IncomingMessage::Complete { id } => operations.stop(id),
 
                 // When receiving a start request
-                Start { id, payload } => {
+                Start { id, payload } | Subscribe { id, payload } => {
                     // Respond with a GQL_ERROR if we already have an operation with this ID
                     if operations.contains(&id) {
                         return send_error_string(
                             &msg_sink,
+                            protocol,
                             id.clone(),
                             format!("Operation with ID already started: {}", id),
                         );
@@ -234,6 +352,7 @@ where
                         Err(e) => {
                             return send_error_string(
                                 &msg_sink,
+                                protocol,
                                 id.clone(),
                                 format!("Invalid query: {}: {}", payload.query, e),
                             );
@@ -249,6 +368,7 @@ where
                                 Err(e) => {
                                     return send_error_string(
                                         &msg_sink,
+                                        protocol,
                                         id.clone(),
                                         format!("Invalid variables provided: {}", e),
                                     )
@@ -258,6 +378,7 @@ where
                         _ => {
                             return send_error_string(
                                 &msg_sink,
+                                protocol,
                                 id.clone(),
                                 format!("Invalid variables provided (must be an object)"),
                             )
@@ -271,6 +392,8 @@ where
                             document: query,
                             variables,
                         },
+                        operation_name: payload.operation_name,
+                        send_initial: payload.send_initial,
                     };
 
                     debug!(logger, "Start operation";
@@ -290,8 +413,11 @@ where
                             match e {
                                 SubscriptionError::GraphQLError(e) => {
                                     let result = QueryResult::from(e);
-                                    let msg =
-                                        OutgoingMessage::from_query_result(err_id.clone(), result);
+                                    let msg = OutgoingMessage::from_query_result(
+                                        protocol,
+                                        err_id.clone(),
+                                        result,
+                                    );
                                     error_sink.unbounded_send(msg.into()).unwrap();
                                 }
                             };
@@ -300,7 +426,11 @@ where
                             // Send results back to the client as GQL_DATA
                             result_stream
                                 .map(move |result| {
-                                    OutgoingMessage::from_query_result(result_id.clone(), result)
+                                    OutgoingMessage::from_query_result(
+                                        protocol,
+                                        result_id.clone(),
+                                        result,
+                                    )
                                 })
                                 .map(WsMessage::from)
                                 .forward(result_sink.sink_map_err(|_| ()))
@@ -337,13 +467,16 @@ where
     type Error = ();
 
     fn into_future(self) -> Self::Future {
-        debug!(self.logger, "GraphQL over WebSocket connection opened"; "id" => &self.id);
+        debug!(self.logger, "GraphQL over WebSocket connection opened";
+               "id" => &self.id,
+               "protocol" => self.protocol.as_str());
 
         // Obtain sink/stream pair to send and receive WebSocket messages
         let (ws_sink, ws_stream) = self.stream.split();
 
         // Allocate a channel for writing
         let (msg_sink, msg_stream) = mpsc::unbounded();
+        let close_sink = msg_sink.clone();
 
         // Handle incoming messages asynchronously
         let ws_reader = Self::handle_incoming_messages(
@@ -353,6 +486,8 @@ where
             self.id.clone(),
             self.schema.clone(),
             self.graphql_runner.clone(),
+            self.protocol,
+            self.subscription_count.clone(),
         );
 
         // Send outgoing messages asynchronously
@@ -363,15 +498,200 @@ where
         // as a result of this but most will try to reconnect (GraphiQL for sure,
         // Apollo maybe).
         let ws_writer = ws_writer.map(|_| ());
-        let ws_reader = ws_reader.map(|_| ()).map_err(|_| ());
+
+        // Clients that send a message exceeding the size limits are disconnected
+        // with a protocol error; the message is never buffered in full
+        let capacity_logger = self.logger.clone();
+        let capacity_id = self.id.clone();
+        let ws_reader = ws_reader.map(|_| false).or_else(move |e| match e {
+            WsError::Capacity(reason) => {
+                debug!(capacity_logger, "Closing GraphQL over WebSocket connection";
+                       "connection" => &capacity_id,
+                       "reason" => reason.as_ref());
+                let _ = close_sink.unbounded_send(WsMessage::Close(Some(CloseFrame {
+                    code: CloseCode::Protocol,
+                    reason: "Message too big".into(),
+                })));
+                Ok(true)
+            }
+            _ => Err(()),
+        });
+
+        // Stop reading when the server shuts down. Dropping the reader drops the
+        // active operations, which sends a `complete` message for each of them.
+        // The sender is dropped without sending once the connection is closed, in
+        // which case there is nothing to shut down.
+        let shutdown = self
+            .shutdown
+            .or_else(|_| future::empty::<(), ()>())
+            .map(|_| true);
+        let ws_reader = ws_reader
+            .select(shutdown)
+            .map(|(flush_writer, _)| flush_writer)
+            .map_err(|_| ());
 
         // Return a future that is fulfilled when either we or the client close
-        // our/their end of the WebSocket stream
+        // our/their end of the WebSocket stream. On shutdown, and when closing the
+        // connection because of an oversized message, the writer is given the
+        // chance to flush the remaining messages and close the WebSocket first
         let logger = self.logger.clone();
         let id = self.id.clone();
-        Box::new(ws_reader.select(ws_writer).then(move |_| {
-            debug!(logger, "GraphQL over WebSocket connection closed"; "connection" => id);
-            Ok(())
+        Box::new(ws_reader.select2(ws_writer).then(move |result| {
+            let closed: Box<Future<Item = (), Error = ()> + Send> = match result {
+                Ok(Either::A((true, ws_writer))) => {
+                    debug!(logger, "Shutting down GraphQL over WebSocket connection";
+                           "connection" => &id);
+                    Box::new(ws_writer)
+                }
+                _ => Box::new(future::ok(())),
+            };
+            closed.then(move |_| {
+                debug!(logger, "GraphQL over WebSocket connection closed"; "connection" => id);
+                Ok(())
+            })
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::tungstenite::protocol::{Role, WebSocketConfig};
+
+    /// A query runner for connections that never run any queries.
+    struct TestGraphQlRunner;
+
+    impl GraphQlRunner for TestGraphQlRunner {
+        fn run_query(&self, _: Query) -> QueryResultFuture {
+            unimplemented!()
+        }
+
+        fn run_subscription(&self, _: Subscription) -> SubscriptionResultFuture {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn negotiate_prefers_graphql_transport_ws() {
+        assert_eq!(
+            WsProtocol::negotiate("graphql-ws, graphql-transport-ws"),
+            Some(WsProtocol::GraphqlTransportWs)
+        );
+        assert_eq!(
+            WsProtocol::negotiate("graphql-ws"),
+            Some(WsProtocol::GraphqlWs)
+        );
+        assert_eq!(WsProtocol::negotiate("mqtt, wamp"), None);
+    }
+
+    #[test]
+    fn errors_are_framed_per_protocol() {
+        let legacy = serde_json::to_value(OutgoingMessage::from_error_string(
+            WsProtocol::GraphqlWs,
+            "1".to_owned(),
+            "boom".to_owned(),
+        ))
+        .unwrap();
+        assert_eq!(legacy["type"], "error");
+        assert_eq!(legacy["payload"], "boom");
+
+        let modern = serde_json::to_value(OutgoingMessage::from_error_string(
+            WsProtocol::GraphqlTransportWs,
+            "1".to_owned(),
+            "boom".to_owned(),
+        ))
+        .unwrap();
+        assert_eq!(modern["type"], "error");
+        assert_eq!(modern["payload"][0]["message"], "boom");
+    }
+
+    #[test]
+    fn start_payloads_opt_in_to_the_initial_result() {
+        let payload: StartPayload =
+            serde_json::from_str(r#"{"query": "subscription { a }"}"#).unwrap();
+        assert!(!payload.send_initial);
+
+        let payload: StartPayload =
+            serde_json::from_str(r#"{"query": "subscription { a }", "sendInitial": true}"#)
+                .unwrap();
+        assert!(payload.send_initial);
+    }
+
+    #[test]
+    fn operations_track_the_subscription_count() {
+        let (msg_sink, _msg_stream) = mpsc::unbounded();
+        let subscription_count = Arc::new(AtomicUsize::new(0));
+        let mut operations =
+            Operations::new(msg_sink, WsProtocol::GraphqlWs, subscription_count.clone());
+
+        operations.insert("1".to_owned(), CancelGuard::new());
+        operations.insert("2".to_owned(), CancelGuard::new());
+        assert_eq!(subscription_count.load(Ordering::SeqCst), 2);
+
+        // Stopping an unknown operation leaves the count alone
+        operations.stop("3".to_owned()).unwrap();
+        operations.stop("1".to_owned()).unwrap();
+        assert_eq!(subscription_count.load(Ordering::SeqCst), 1);
+
+        // Closing the connection stops all remaining operations
+        drop(operations);
+        assert_eq!(subscription_count.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn oversized_messages_close_the_connection() {
+        let logger = Logger::root(slog::Discard, o!());
+        let id = SubgraphDeploymentId::new("testschema").unwrap();
+        let schema = Schema::parse("type Query { name: String }", id).unwrap();
+
+        let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Serve a single connection that accepts messages of up to 1 KiB
+        let server =
+            listener
+                .incoming()
+                .into_future()
+                .map_err(|_| ())
+                .and_then(move |(stream, _)| {
+                    let config = WebSocketConfig {
+                        max_send_queue: None,
+                        max_message_size: Some(1024),
+                        max_frame_size: Some(1024),
+                    };
+                    let ws_stream = WebSocketStream::from_raw_socket(
+                        stream.expect("no incoming connection"),
+                        Role::Server,
+                        Some(config),
+                    );
+                    let (_shutdown_sender, shutdown_receiver) = oneshot::channel();
+                    GraphQlConnection::new(
+                        &logger,
+                        schema,
+                        ws_stream,
+                        Arc::new(TestGraphQlRunner),
+                        WsProtocol::GraphqlWs,
+                        shutdown_receiver,
+                        Arc::new(AtomicUsize::new(0)),
+                    )
+                });
+
+        // Send a 2 KiB message and wait for the server's response
+        let client = TcpStream::connect(&addr)
+            .map_err(WsError::from)
+            .and_then(|stream| {
+                WebSocketStream::from_raw_socket(stream, Role::Client, None)
+                    .send(WsMessage::Text("x".repeat(2048)))
+            })
+            .and_then(|ws_stream| ws_stream.into_future().map_err(|(e, _)| e))
+            .map(|(msg, _)| msg);
+
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.spawn(server);
+        match runtime.block_on(client).expect("client failed") {
+            Some(WsMessage::Close(Some(frame))) => assert_eq!(frame.code, CloseCode::Protocol),
+            msg => panic!("Expected the connection to be closed, got {:?}", msg),
+        }
+    }
+}
@@ -10,4 +10,6 @@ extern crate uuid;
 mod connection;
 mod server;
 
-pub use self::server::SubscriptionServer;
+pub use self::server::{
+    ConnectionStats, SubscriptionServer, DEFAULT_MAX_FRAME_SIZE, DEFAULT_MAX_MESSAGE_SIZE,
+};
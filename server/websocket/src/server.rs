@@ -1,20 +1,46 @@
 use futures::prelude::*;
+use futures::sync::oneshot;
 use graph::data::subgraph::schema::SUBGRAPHS_ID;
 use graph::prelude::{SubscriptionServer as SubscriptionServerTrait, *};
 use graph::tokio::net::TcpListener;
+use std::collections::HashMap;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Mutex;
-use tokio_tungstenite::accept_hdr_async;
-use tokio_tungstenite::tungstenite::{handshake::server::Request, Error as WsError};
+use tokio_tungstenite::accept_hdr_async_with_config;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
 
-use connection::GraphQlConnection;
+use connection::{GraphQlConnection, WsProtocol};
+
+/// The number of clients connected to a `SubscriptionServer` and the number
+/// of subscriptions they are running.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ConnectionStats {
+    pub connections: usize,
+    pub subscriptions: usize,
+}
+
+/// The default for the largest message a client may send, in bytes.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 << 20;
+
+/// The default for the largest frame a client may send, in bytes.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 1 << 20;
 
 /// A GraphQL subscription server based on Hyper / Websockets.
 pub struct SubscriptionServer<Q, S> {
     logger: Logger,
     graphql_runner: Arc<Q>,
     store: Arc<S>,
+    /// Clients that send larger messages or frames are disconnected.
+    max_message_size: usize,
+    max_frame_size: usize,
+    /// Shutdown signals for all active connections, by connection ID.
+    connections: Arc<Mutex<HashMap<String, oneshot::Sender<()>>>>,
+    connection_count: Arc<AtomicUsize>,
+    subscription_count: Arc<AtomicUsize>,
 }
 
 impl<Q, S> SubscriptionServer<Q, S>
@@ -27,35 +53,83 @@ where
             logger: logger.new(o!("component" => "SubscriptionServer")),
             graphql_runner,
             store,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            connection_count: Arc::new(AtomicUsize::new(0)),
+            subscription_count: Arc::new(AtomicUsize::new(0)),
         }
     }
 
-    fn subgraph_id_from_url_path(store: Arc<S>, path: &Path) -> Result<SubgraphDeploymentId, ()> {
-        let path_segments = {
-            let mut segments = path.iter();
+    /// Disconnects clients that send a message larger than `max_message_size` bytes.
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
 
-            // Remove leading '/'
-            assert_eq!(segments.next().and_then(|s| s.to_str()), Some("/"));
+    /// Disconnects clients that send a frame larger than `max_frame_size` bytes.
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
 
-            segments.map(|s| s.to_str().unwrap()).collect::<Vec<_>>()
-        };
+    /// Returns the number of active connections and subscriptions.
+    pub fn connection_stats(&self) -> ConnectionStats {
+        ConnectionStats {
+            connections: self.connection_count.load(Ordering::SeqCst),
+            subscriptions: self.subscription_count.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// Looks up the schema of the subgraph that a WebSocket request path refers to by ID or
+/// by name. Fails with a 404 if the path does not refer to a known subgraph and with a
+/// 500 if the subgraph name cannot be resolved.
+fn subgraph_schema_from_url_path<S>(
+    logger: &Logger,
+    store: &S,
+    path: &Path,
+) -> Result<Schema, ErrorResponse>
+where
+    S: SubgraphDeploymentStore,
+{
+    let path_segments = {
+        let mut segments = path.iter();
 
-        match path_segments.as_slice() {
-            &["subgraphs"] => Ok(SUBGRAPHS_ID.clone()),
-            &["subgraphs", "id", subgraph_id] => SubgraphDeploymentId::new(subgraph_id),
-            &["subgraphs", "name", _] | &["subgraphs", "name", _, _] => {
-                let subgraph_name = path_segments[2..].join("/");
+        // Remove leading '/'
+        assert_eq!(segments.next().and_then(|s| s.to_str()), Some("/"));
 
-                SubgraphName::new(subgraph_name)
-                    .map(|subgraph_name| {
-                        store
-                            .resolve_subgraph_name_to_id(subgraph_name)
-                            .expect("failed to resolve subgraph name to ID")
+        segments.map(|s| s.to_str().unwrap()).collect::<Vec<_>>()
+    };
+
+    match path_segments.as_slice() {
+        &["subgraphs"] => store
+            .subgraph_schema(SUBGRAPHS_ID.clone())
+            .map_err(|_| ErrorResponse::from(StatusCode::NOT_FOUND)),
+        &["subgraphs", "id", subgraph_id] => SubgraphDeploymentId::new(subgraph_id)
+            .map_err(|()| ErrorResponse::from(StatusCode::NOT_FOUND))
+            .and_then(|subgraph_id| {
+                store
+                    .subgraph_schema(subgraph_id)
+                    .map_err(|_| ErrorResponse::from(StatusCode::NOT_FOUND))
+            }),
+        &["subgraphs", "name", _] | &["subgraphs", "name", _, _] => {
+            let subgraph_name = path_segments[2..].join("/");
+
+            SubgraphName::new(subgraph_name.as_str())
+                .map_err(|()| ErrorResponse::from(StatusCode::NOT_FOUND))
+                .and_then(|name| {
+                    store.subgraph_schema_by_name(name).map_err(|e| {
+                        error!(logger, "Failed to establish WS connection, could not resolve subgraph name";
+                                        "subgraph_name" => &subgraph_name,
+                                        "error" => e.to_string(),
+                        );
+                        ErrorResponse::from(StatusCode::INTERNAL_SERVER_ERROR)
                     })
-                    .and_then(|assignment_opt| assignment_opt.ok_or(()))
-            }
-            _ => return Err(()),
+                })
+                .and_then(|schema| schema.ok_or(ErrorResponse::from(StatusCode::NOT_FOUND)))
         }
+        _ => Err(ErrorResponse::from(StatusCode::NOT_FOUND)),
     }
 }
 
@@ -69,6 +143,7 @@ where
     fn serve(
         &mut self,
         port: u16,
+        shutdown: oneshot::Receiver<()>,
     ) -> Result<Box<Future<Item = (), Error = ()> + Send>, Self::ServeError> {
         let logger = self.logger.clone();
         let error_logger = self.logger.clone();
@@ -81,6 +156,11 @@ where
         let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port);
         let graphql_runner = self.graphql_runner.clone();
         let store = self.store.clone();
+        let connections = self.connections.clone();
+        let connection_count = self.connection_count.clone();
+        let subscription_count = self.subscription_count.clone();
+        let max_message_size = self.max_message_size;
+        let max_frame_size = self.max_frame_size;
 
         let socket = TcpListener::bind(&addr).expect("Failed to bind WebSocket port");
 
@@ -94,63 +174,99 @@ where
                 let logger2 = logger.clone();
                 let graphql_runner = graphql_runner.clone();
                 let store = store.clone();
-                let store2 = store.clone();
+                let connections = connections.clone();
+                let connection_count = connection_count.clone();
+                let subscription_count = subscription_count.clone();
+
+                // Schema of the subgraph that the request is resolved to (if any)
+                let schema = Arc::new(Mutex::new(None));
+                let accept_schema = schema.clone();
 
-                // Subgraph that the request is resolved to (if any)
-                let subgraph_id = Arc::new(Mutex::new(None));
-                let accept_subgraph_id = subgraph_id.clone();
+                // Size limits for the messages received from the client
+                let ws_config = WebSocketConfig {
+                    max_send_queue: None,
+                    max_message_size: Some(max_message_size),
+                    max_frame_size: Some(max_frame_size),
+                };
 
-                accept_hdr_async(stream, move |request: &Request| {
-                    // Try to obtain the subgraph ID or name from the URL path.
-                    // Return a 404 if the URL path contains no name/ID segment.
+                // GraphQL over WebSocket protocol negotiated with the client
+                let protocol = Arc::new(Mutex::new(None));
+                let accept_protocol = protocol.clone();
+
+                accept_hdr_async_with_config(stream, move |request: &Request| {
+                    // Try to obtain the subgraph schema by the ID or name in the URL path
                     let path = &request.path;
-                    let subgraph_id = Self::subgraph_id_from_url_path(store.clone(), path.as_ref())
-                        .map_err(|()| WsError::Http(404))?;
+                    let schema = subgraph_schema_from_url_path(&logger, &*store, path.as_ref())?;
 
                     // Check if the subgraph is deployed
-                    match store.is_deployed(&subgraph_id) {
+                    match store.is_deployed(&schema.id) {
                         Err(_) | Ok(false) => {
                             error!(logger, "Failed to establish WS connection, no data found for subgraph";
-                                            "subgraph_id" => subgraph_id.to_string(),
+                                            "subgraph_id" => schema.id.to_string(),
                             );
-                            return Err(WsError::Http(404));
+                            return Err(ErrorResponse::from(StatusCode::NOT_FOUND));
                         }
                         Ok(true) => (),
                     }
 
-                    *accept_subgraph_id.lock().unwrap() = Some(subgraph_id);
+                    // Pick the most preferred protocol offered by the client. Clients that
+                    // don't ask for a protocol are assumed to speak the legacy `graphql-ws`;
+                    // clients that only offer protocols we don't support get a 400
+                    let negotiated = match request.headers.find_first("Sec-WebSocket-Protocol") {
+                        None => WsProtocol::GraphqlWs,
+                        Some(offered) => {
+                            let offered = String::from_utf8_lossy(offered);
+                            match WsProtocol::negotiate(&offered) {
+                                Some(protocol) => protocol,
+                                None => {
+                                    error!(logger, "Failed to establish WS connection, no supported protocol offered";
+                                                    "offered" => offered.as_ref(),
+                                    );
+                                    return Err(ErrorResponse::from(StatusCode::BAD_REQUEST));
+                                }
+                            }
+                        }
+                    };
+
+                    *accept_schema.lock().unwrap() = Some(schema);
+                    *accept_protocol.lock().unwrap() = Some(negotiated);
 
                     Ok(Some(vec![(
                         String::from("Sec-WebSocket-Protocol"),
-                        String::from("graphql-ws"),
+                        String::from(negotiated.as_str()),
                     )]))
-                })
+                }, Some(ws_config))
                 .then(move |result| {
                     match result {
                         Ok(ws_stream) => {
-                            // Obtain the subgraph ID or name that we resolved the request to
-                            let subgraph_id = subgraph_id.lock().unwrap().clone().unwrap();
-
-                            // Get the subgraph schema
-                            let schema = match store2.subgraph_schema(subgraph_id.clone()) {
-                                Ok(schema) => schema,
-                                Err(e) => {
-                                    error!(logger2, "Failed to establish WS connection, could not find schema";
-                                                    "subgraph" => subgraph_id.to_string(),
-                                                    "error" => e.to_string(),
-                                    );
-                                    return Ok(())
-                                }
-                            };
+                            connection_count.fetch_add(1, Ordering::SeqCst);
+
+                            // Obtain the schema of the subgraph we resolved the request to
+                            let schema = schema.lock().unwrap().take().unwrap();
+                            let protocol = protocol.lock().unwrap().clone().unwrap();
 
-                            // Spawn a GraphQL over WebSocket connection
+                            // Spawn a GraphQL over WebSocket connection and keep track of
+                            // it until it is closed, so that it can be shut down gracefully
+                            let (shutdown_sender, shutdown_receiver) = oneshot::channel();
                             let service = GraphQlConnection::new(
                                 &logger2,
                                 schema,
                                 ws_stream,
                                 graphql_runner.clone(),
+                                protocol,
+                                shutdown_receiver,
+                                subscription_count,
                             );
-                            tokio::spawn(service.into_future());
+                            let connection_id = service.id().to_owned();
+                            connections
+                                .lock()
+                                .unwrap()
+                                .insert(connection_id.clone(), shutdown_sender);
+                            tokio::spawn(service.into_future().then(move |result| {
+                                connections.lock().unwrap().remove(&connection_id);
+                                connection_count.fetch_sub(1, Ordering::SeqCst);
+                                result
+                            }));
                         }
                         Err(e) => {
                             // We gracefully skip over failed connection attempts rather
@@ -162,6 +278,118 @@ where
                 })
             });
 
-        Ok(Box::new(task))
+        // Once shutdown is requested, stop accepting connections and ask all
+        // active connections to complete their subscriptions and close
+        let shutdown =
+            shutdown_connections(self.logger.clone(), self.connections.clone(), shutdown);
+
+        Ok(Box::new(task.select(shutdown).map(|_| ()).map_err(|_| ())))
+    }
+}
+
+/// Resolves once `shutdown` fires, after signalling all `connections` to close.
+/// A dropped `shutdown` sender never triggers a shutdown.
+fn shutdown_connections(
+    logger: Logger,
+    connections: Arc<Mutex<HashMap<String, oneshot::Sender<()>>>>,
+    shutdown: oneshot::Receiver<()>,
+) -> impl Future<Item = (), Error = ()> {
+    shutdown
+        .or_else(|_| future::empty::<(), ()>())
+        .map(move |_| {
+            let mut connections = connections.lock().unwrap();
+            info!(logger, "Shutting down GraphQL WebSocket server";
+                  "connections" => connections.len());
+            for (_, connection) in connections.drain() {
+                let _ = connection.send(());
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A store that fails to resolve any subgraph name.
+    struct UnavailableStore;
+
+    impl SubgraphDeploymentStore for UnavailableStore {
+        fn resolve_subgraph_name_to_id(
+            &self,
+            _: SubgraphName,
+        ) -> Result<Option<SubgraphDeploymentId>, Error> {
+            Err(format_err!("database is unavailable"))
+        }
+
+        fn is_deployed(&self, _: &SubgraphDeploymentId) -> Result<bool, Error> {
+            unimplemented!()
+        }
+
+        fn deployed_subgraphs(&self) -> Result<Vec<SubgraphDeploymentId>, Error> {
+            unimplemented!()
+        }
+
+        fn subgraph_schema(&self, _: SubgraphDeploymentId) -> Result<Schema, Error> {
+            unimplemented!()
+        }
+
+        fn subgraph_schemas(
+            &self,
+            _: &[SubgraphDeploymentId],
+        ) -> Result<HashMap<SubgraphDeploymentId, Schema>, Error> {
+            unimplemented!()
+        }
+
+        fn invalidate_schema_cache(&self, _: &SubgraphDeploymentId) {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn store_errors_reject_connections_by_name() {
+        let logger = Logger::root(slog::Discard, o!());
+        let result = subgraph_schema_from_url_path(
+            &logger,
+            &UnavailableStore,
+            Path::new("/subgraphs/name/a/b"),
+        );
+        match result {
+            Err(ref e) if e.error_code == StatusCode::INTERNAL_SERVER_ERROR => (),
+            Err(e) => panic!("expected a 500 error, got: {:?}", e),
+            Ok(_) => panic!("expected a 500 error, got a schema"),
+        }
+
+        // Paths that don't refer to a subgraph don't touch the store
+        let result = subgraph_schema_from_url_path(&logger, &UnavailableStore, Path::new("/foo"));
+        match result {
+            Err(ref e) if e.error_code == StatusCode::NOT_FOUND => (),
+            Err(e) => panic!("expected a 404 error, got: {:?}", e),
+            Ok(_) => panic!("expected a 404 error, got a schema"),
+        }
+    }
+
+    #[test]
+    fn shutdown_closes_all_connections() {
+        let logger = Logger::root(slog::Discard, o!());
+        let connections = Arc::new(Mutex::new(HashMap::new()));
+        let (first_sender, first_receiver) = oneshot::channel();
+        let (second_sender, second_receiver) = oneshot::channel();
+        connections
+            .lock()
+            .unwrap()
+            .insert(String::from("first"), first_sender);
+        connections
+            .lock()
+            .unwrap()
+            .insert(String::from("second"), second_sender);
+
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+        let shutdown = shutdown_connections(logger, connections.clone(), shutdown_receiver);
+        shutdown_sender.send(()).unwrap();
+        shutdown.wait().expect("shutdown failed");
+
+        assert!(connections.lock().unwrap().is_empty());
+        assert_eq!(first_receiver.wait(), Ok(()));
+        assert_eq!(second_receiver.wait(), Ok(()));
     }
 }
@@ -0,0 +1,3 @@
+DROP TRIGGER entities_clear_updated_at_block ON entities;
+DROP FUNCTION clear_updated_at_block();
+ALTER TABLE entities DROP COLUMN updated_at_block;
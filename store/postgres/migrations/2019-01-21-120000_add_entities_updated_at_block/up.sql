@@ -0,0 +1,35 @@
+-- Record the number of the block that last changed each entity
+ALTER TABLE entities ADD COLUMN updated_at_block BIGINT DEFAULT NULL;
+
+-- Backfill the block number from the block recorded as the event source.
+-- Disable the triggers on entities so that the backfill neither records
+-- history nor sends entity change notifications.
+ALTER TABLE entities DISABLE TRIGGER USER;
+
+UPDATE entities
+SET updated_at_block = ethereum_blocks.number
+FROM ethereum_blocks
+WHERE ethereum_blocks.hash = entities.event_source;
+
+ALTER TABLE entities ENABLE TRIGGER USER;
+
+/**************************************************************
+* CLEAR UPDATED AT BLOCK
+*
+* Reverting a block restores entities without recording a block
+* for them, so the block they were last updated at is unknown.
+**************************************************************/
+CREATE OR REPLACE FUNCTION clear_updated_at_block()
+    RETURNS trigger AS
+$$
+BEGIN
+    IF NEW.event_source = 'REVERSION' THEN
+        NEW.updated_at_block := NULL;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER entities_clear_updated_at_block
+    BEFORE INSERT OR UPDATE ON entities
+    FOR EACH ROW EXECUTE PROCEDURE clear_updated_at_block();
@@ -0,0 +1,86 @@
+/**************************************************************
+* ENTITY CHANGES CHANNEL
+*
+* Returns the notification channel that entity changes are
+* emitted over. Stores that share a database set the
+* "graph.entity_changes_channel" setting on their connections to
+* keep their changes apart; without it, the "entity_changes"
+* channel is used.
+**************************************************************/
+
+CREATE OR REPLACE FUNCTION entity_changes_channel()
+    RETURNS text AS
+$$
+BEGIN
+    RETURN COALESCE(
+        NULLIF(current_setting('graph.entity_changes_channel', true), ''),
+        'entity_changes'
+    );
+END
+$$ LANGUAGE plpgsql;
+
+/**************************************************************
+* NOTIFY ENTITY ADDED
+*
+* Emits an entity added notification over the channel returned
+* by entity_changes_channel().
+**************************************************************/
+
+CREATE OR REPLACE FUNCTION notify_entity_added()
+    RETURNS trigger AS
+$$
+DECLARE
+BEGIN
+    PERFORM pg_notify(entity_changes_channel(), json_build_object(
+      'subgraph_id', NEW.subgraph,
+      'entity_type', NEW.entity,
+      'entity_id', NEW.id,
+      'operation', 'added'
+    )::text);
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+/**************************************************************
+ * NOTIFY ENTITY UPDATED
+ *
+ * Emits an entity updated notification over the channel returned
+ * by entity_changes_channel().
+ **************************************************************/
+
+CREATE OR REPLACE FUNCTION notify_entity_updated()
+    RETURNS trigger AS
+$$
+DECLARE
+BEGIN
+    PERFORM pg_notify(entity_changes_channel(), json_build_object(
+        'subgraph_id', NEW.subgraph,
+        'entity_type', NEW.entity,
+        'entity_id', NEW.id,
+        'operation', 'updated'
+    )::text);
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+/**************************************************************
+ * NOTIFY ENTITY REMOVED
+ *
+ * Emits an entity removed notification over the channel returned
+ * by entity_changes_channel().
+ **************************************************************/
+
+CREATE OR REPLACE FUNCTION notify_entity_removed()
+    RETURNS trigger AS
+$$
+DECLARE
+BEGIN
+    PERFORM pg_notify(entity_changes_channel(), json_build_object(
+        'subgraph_id', OLD.subgraph,
+        'entity_type', OLD.entity,
+        'entity_id', OLD.id,
+        'operation', 'removed'
+    )::text);
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
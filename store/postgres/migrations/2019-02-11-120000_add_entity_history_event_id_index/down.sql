@@ -0,0 +1 @@
+DROP INDEX IF EXISTS entity_history_event_id;
@@ -0,0 +1,4 @@
+-- Look up the history of an event quickly, e.g. to tell whether an event
+-- still has history after removing the history of a subgraph
+CREATE INDEX IF NOT EXISTS entity_history_event_id
+    ON entity_history (event_id);
@@ -5,6 +5,7 @@ table! {
         entity -> Varchar,
         data -> Jsonb,
         event_source -> Varchar,
+        updated_at_block -> Nullable<BigInt>,
     }
 }
 
@@ -2,16 +2,47 @@ use graph::prelude::*;
 use graph::serde_json;
 use notification_listener::{NotificationListener, SafeChannelName};
 
+/// Returns the name of the channel that entity changes are sent over for stores in
+/// `namespace`, or the default `entity_changes` channel if there is no namespace.
+///
+/// The name is used as an identifier in `LISTEN` statements, so namespaces may only
+/// contain lowercase letters, digits and underscores, and may not start with a digit.
+pub fn entity_changes_channel(namespace: Option<&str>) -> Result<String, Error> {
+    match namespace {
+        None => Ok(String::from("entity_changes")),
+        Some(namespace) => {
+            let valid = namespace
+                .chars()
+                .next()
+                .map_or(false, |c| c.is_ascii_lowercase() || c == '_')
+                && namespace
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+            if valid {
+                Ok(format!("{}_entity_changes", namespace))
+            } else {
+                Err(format_err!(
+                    "invalid notification namespace `{}`: namespaces may only contain \
+                     lowercase letters, digits and underscores, and may not start with a digit",
+                    namespace
+                ))
+            }
+        }
+    }
+}
+
 pub struct EntityChangeListener {
     notification_listener: NotificationListener,
 }
 
 impl EntityChangeListener {
-    pub fn new(postgres_url: String) -> Self {
+    /// Listens to the changes sent over `channel_name`, which must come from
+    /// `entity_changes_channel`.
+    pub fn new(postgres_url: String, channel_name: String) -> Self {
         EntityChangeListener {
             notification_listener: NotificationListener::new(
                 postgres_url,
-                SafeChannelName::i_promise_this_is_safe("entity_changes"),
+                SafeChannelName::i_promise_this_is_safe(channel_name),
             ),
         }
     }
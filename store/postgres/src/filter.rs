@@ -4,10 +4,12 @@ use diesel::pg::Pg;
 use diesel::prelude::*;
 use diesel::query_builder::BoxedSelectStatement;
 use diesel::serialize::ToSql;
-use diesel::sql_types::{Array, Bool, Float, HasSqlType, Integer, Jsonb, Numeric, Text};
+use diesel::sql_types::{
+    Array, BigInt as BigIntSql, Bool, Float, HasSqlType, Integer, Jsonb, Numeric, Text,
+};
 use std::str::FromStr;
 
-use graph::components::store::EntityFilter;
+use graph::components::store::{EntityFilter, UPDATED_AT_BLOCK_ATTRIBUTE};
 use graph::data::store::*;
 use graph::prelude::BigInt;
 use graph::serde_json;
@@ -120,6 +122,19 @@ impl IntoArrayFilter<SqlValue> for Vec<SqlValue> {
     }
 }
 
+/// Returns the attribute and search query of the first fulltext filter in `filter`
+/// that every matching entity has to satisfy, if there is one. Results for such
+/// filters are ranked by relevance.
+pub(crate) fn fulltext_search(filter: &EntityFilter) -> Option<(Attribute, String)> {
+    match filter {
+        EntityFilter::Fulltext(attribute, Value::String(query)) => {
+            Some((attribute.clone(), query.clone()))
+        }
+        EntityFilter::And(filters) => filters.iter().filter_map(fulltext_search).next(),
+        _ => None,
+    }
+}
+
 /// Adds `filter` to a `SELECT data FROM entities` statement.
 pub(crate) fn store_filter(
     query: BoxedSelectStatement<Jsonb, entities::table, Pg>,
@@ -128,12 +143,46 @@ pub(crate) fn store_filter(
     Ok(query.filter(build_filter(filter)?))
 }
 
+/// Leaves entities that were last written in a block after `block_number` out of a
+/// `SELECT data FROM entities` statement. Entities whose last write isn't associated
+/// with a block known to the store are kept.
+pub(crate) fn store_as_of_block(
+    query: BoxedSelectStatement<Jsonb, entities::table, Pg>,
+    block_number: u64,
+) -> BoxedSelectStatement<Jsonb, entities::table, Pg> {
+    query.filter(
+        sql::<Bool>(
+            "NOT EXISTS (SELECT 1 FROM ethereum_blocks \
+             WHERE ethereum_blocks.hash = entities.event_source \
+             AND ethereum_blocks.number > ",
+        )
+        .bind::<BigIntSql, _>(block_number as i64)
+        .sql(")"),
+    )
+}
+
 fn build_filter(filter: EntityFilter) -> Result<FilterExpression, UnsupportedFilter> {
     use self::EntityFilter::*;
 
     let false_expr = Box::new(false.into_sql::<Bool>()) as FilterExpression;
     let true_expr = Box::new(true.into_sql::<Bool>()) as FilterExpression;
 
+    // Comparisons with the block at which entities were last updated use
+    // the column that records it rather than the entity data
+    if let Some((op, value)) = updated_at_block_comparison(&filter) {
+        return match value {
+            Value::Int(n) => Ok(Box::new(
+                sql("updated_at_block")
+                    .sql(op)
+                    .bind::<BigIntSql, _>(*n as i64),
+            ) as FilterExpression),
+            _ => Err(UnsupportedFilter {
+                filter: op.trim().to_owned(),
+                value: value.clone(),
+            }),
+        };
+    }
+
     match filter {
         And(filters) => filters.into_iter().try_fold(true_expr, |p, filter| {
             build_filter(filter).map(|filter_expr| Box::new(p.and(filter_expr)) as FilterExpression)
@@ -335,5 +384,73 @@ fn build_filter(filter: EntityFilter) -> Result<FilterExpression, UnsupportedFil
                 }
             }
         }
+
+        // TODO: Fulltext searches scan all entities of a type for now; making them
+        // fast requires creating GIN indexes on the searched attributes
+        Fulltext(attribute, value) => match value {
+            Value::String(query) => Ok(Box::new(
+                sql("to_tsvector(data -> ")
+                    .bind::<Text, _>(attribute)
+                    .sql("->> 'data') @@ plainto_tsquery(")
+                    .bind::<Text, _>(query)
+                    .sql(")"),
+            ) as FilterExpression),
+            Value::Bool(_)
+            | Value::BigInt(_)
+            | Value::Bytes(_)
+            | Value::Float(_)
+            | Value::Int(_)
+            | Value::List(_)
+            | Value::Null => {
+                return Err(UnsupportedFilter {
+                    filter: "fulltext".to_owned(),
+                    value,
+                })
+            }
+        },
+
+        // List elements are stored with their type, like all other values, so an
+        // element only matches if both its type and its data are equal to the value
+        ArrayContains(attribute, value) => match value {
+            Value::List(_) | Value::Null => {
+                return Err(UnsupportedFilter {
+                    filter: "array_contains".to_owned(),
+                    value,
+                })
+            }
+            value => {
+                let element =
+                    serde_json::to_string(&vec![value]).expect("failed to serialize list element");
+                Ok(Box::new(
+                    sql("data -> ")
+                        .bind::<Text, _>(attribute)
+                        .sql(" -> 'data' @> ")
+                        .bind::<Text, _>(element)
+                        .sql("::jsonb"),
+                ) as FilterExpression)
+            }
+        },
+    }
+}
+
+/// Returns the operator and value of `filter` if it compares the block at which
+/// entities were last updated.
+fn updated_at_block_comparison(filter: &EntityFilter) -> Option<(&'static str, &Value)> {
+    use self::EntityFilter::*;
+
+    let (attribute, op, value) = match filter {
+        Equal(attribute, value) => (attribute, " = ", value),
+        Not(attribute, value) => (attribute, " != ", value),
+        GreaterThan(attribute, value) => (attribute, " > ", value),
+        LessThan(attribute, value) => (attribute, " < ", value),
+        GreaterOrEqual(attribute, value) => (attribute, " >= ", value),
+        LessOrEqual(attribute, value) => (attribute, " <= ", value),
+        _ => return None,
+    };
+
+    if attribute == UPDATED_AT_BLOCK_ATTRIBUTE {
+        Some((op, value))
+    } else {
+        None
     }
 }
@@ -13,6 +13,7 @@ extern crate graph;
 extern crate graph_graphql;
 extern crate lru_time_cache;
 extern crate postgres;
+extern crate rand;
 extern crate serde;
 extern crate uuid;
 
@@ -26,4 +27,4 @@ mod notification_listener;
 pub mod store;
 
 pub use self::chain_head_listener::ChainHeadUpdateListener;
-pub use self::store::{Store, StoreConfig};
+pub use self::store::{IsolationLevel, Store, StoreConfig};
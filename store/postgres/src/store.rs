@@ -3,13 +3,18 @@ use diesel::dsl::sql;
 use diesel::pg::Pg;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
-use diesel::r2d2::{self, ConnectionManager, Pool};
-use diesel::sql_types::Text;
-use diesel::{delete, insert_into, select, update};
-use filter::store_filter;
+use diesel::query_builder::BoxedSelectStatement;
+use diesel::r2d2::{self, ConnectionManager, Pool, PooledConnection};
+use diesel::sql_types::{Bool, Jsonb, Nullable, Text};
+use diesel::{delete, insert_into, select, sql_query, update};
+use filter::{fulltext_search, store_as_of_block, store_filter};
 use futures::sync::mpsc::{channel, Sender};
 use lru_time_cache::LruCache;
-use std::collections::HashMap;
+use rand::{thread_rng, Rng};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::slice;
+use std::str::FromStr;
 use std::sync::{Mutex, RwLock};
 use std::time::{Duration, Instant};
 use uuid::Uuid;
@@ -21,9 +26,10 @@ use graph::serde_json;
 use graph::web3::types::H256;
 use graph::{tokio, tokio::timer::Interval};
 use graph_graphql::prelude::api_schema;
+use graph_graphql::schema::ast::{get_field_enum_type, get_referenced_object_type};
 
 use chain_head_listener::ChainHeadUpdateListener;
-use entity_changes::EntityChangeListener;
+use entity_changes::{entity_changes_channel, EntityChangeListener};
 use functions::{attempt_chain_head_update, lookup_ancestor_block, revert_block, set_config};
 
 embed_migrations!("./migrations");
@@ -31,9 +37,45 @@ embed_migrations!("./migrations");
 /// Internal representation of a Store subscription.
 struct Subscription {
     pub entities: Vec<SubgraphEntityPair>,
+    /// The operations whose changes the subscription receives; all if `None`.
+    pub operations: Option<Vec<EntityChangeOperation>>,
     pub sender: Sender<EntityChange>,
 }
 
+impl Subscription {
+    /// Returns true if `change` should be sent to the subscription.
+    fn wants(&self, change: &EntityChange) -> bool {
+        change.matches(&self.entities)
+            && self
+                .operations
+                .as_ref()
+                .map_or(true, |operations| operations.contains(&change.operation))
+    }
+}
+
+/// How often `block_transaction` retries a transaction that failed because
+/// Postgres could not serialize it with concurrent transactions.
+const MAX_SERIALIZATION_RETRIES: usize = 10;
+
+/// Returns true if `e` was caused by a serialization failure (SQLSTATE 40001).
+///
+/// Diesel does not expose the SQLSTATE of database errors, so we go by the
+/// message Postgres uses for these failures.
+fn is_serialization_failure(e: &StoreError) -> bool {
+    match e {
+        StoreError::Unknown(e) => e.to_string().contains("could not serialize access"),
+        _ => false,
+    }
+}
+
+/// Returns true if `e` is the error used to deliberately roll back a transaction.
+fn is_rollback(e: &Error) -> bool {
+    match e.downcast_ref::<diesel::result::Error>() {
+        Some(diesel::result::Error::RollbackTransaction) => true,
+        _ => false,
+    }
+}
+
 /// Run all initial schema migrations.
 ///
 /// Creates the "entities" table if it doesn't already exist.
@@ -58,22 +100,131 @@ fn initiate_schema(logger: &Logger, conn: &PgConnection) {
     }
 }
 
+/// Transaction isolation levels that block processing transactions can run with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IsolationLevel {
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl Default for IsolationLevel {
+    /// Repeatable read, so that the reads `AbortUnless` operations do see the
+    /// same snapshot as the writes that follow them.
+    fn default() -> Self {
+        IsolationLevel::RepeatableRead
+    }
+}
+
+impl fmt::Display for IsolationLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IsolationLevel::ReadCommitted => f.write_str("READ COMMITTED"),
+            IsolationLevel::RepeatableRead => f.write_str("REPEATABLE READ"),
+            IsolationLevel::Serializable => f.write_str("SERIALIZABLE"),
+        }
+    }
+}
+
+impl FromStr for IsolationLevel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read-committed" => Ok(IsolationLevel::ReadCommitted),
+            "repeatable-read" => Ok(IsolationLevel::RepeatableRead),
+            "serializable" => Ok(IsolationLevel::Serializable),
+            _ => Err(format_err!(
+                "Invalid transaction isolation level {:?}, expected one of \
+                 read-committed, repeatable-read or serializable",
+                s
+            )),
+        }
+    }
+}
+
 /// Configuration for the Diesel/Postgres store.
 pub struct StoreConfig {
     pub postgres_url: String,
     pub network_name: String,
+
+    /// Isolation level of the transactions that apply entity operations.
+    pub isolation_level: IsolationLevel,
+
+    /// How many parsed subgraph schemas to keep in memory.
+    pub schema_cache_capacity: usize,
+
+    /// Size of the connection pool shared by all store operations.
+    pub conn_pool_size: u32,
+
+    /// How many additional connections are reserved for reads, for when all connections of the
+    /// shared pool are in use. Zero disables the reserve.
+    pub read_conn_reserve: u32,
+
+    /// How long reads wait for a connection of the shared pool, and then for a reserved
+    /// connection, before failing with `QueryExecutionError::StoreBusy`.
+    pub read_conn_timeout: Duration,
+
+    /// Whether to log the SQL of entity queries and how long they take, at debug level.
+    pub log_queries: bool,
+
+    /// Entity queries and operations that take at least this long are logged as warnings,
+    /// regardless of `log_queries`. `None` disables these warnings.
+    pub slow_query_threshold: Option<Duration>,
+
+    /// The most entities a query with a range may return; this is also the default for a
+    /// range without `first`. `None` lets such queries return every matching entity.
+    pub unbounded_query_limit: Option<usize>,
+
+    /// The most entity operations `transact_block_operations` applies for a single block.
+    /// Blocks with more operations fail with `StoreError::TooManyOperations`.
+    pub max_block_operations: usize,
+
+    /// How often subscriptions whose receiving end has gone are removed. Each store adds a
+    /// random jitter of up to 10% so that cleanups of different stores don't align.
+    pub subscription_cleanup_interval: Duration,
+
+    /// How many of the most recent entity changes are kept around for replaying them to
+    /// subscriptions made with `subscribe_since`. Zero disables replaying.
+    pub replay_buffer_size: usize,
+
+    /// Namespace of the channel that entity changes are sent over, for keeping the changes
+    /// of several stores that share a database apart. Stores without a namespace use the
+    /// `entity_changes` channel.
+    pub notification_namespace: Option<String>,
+}
+
+/// Tells the database triggers which channel to send entity changes over, by
+/// setting `graph.entity_changes_channel` on every connection of the pool.
+#[derive(Debug)]
+struct EntityChangesChannel(String);
+
+impl r2d2::CustomizeConnection<PgConnection, r2d2::Error> for EntityChangesChannel {
+    fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), r2d2::Error> {
+        conn.batch_execute(&format!("SET graph.entity_changes_channel = '{}'", self.0))
+            .map_err(r2d2::Error::QueryError)
+    }
 }
 
 /// A Store based on Diesel and Postgres.
 pub struct Store {
     logger: Logger,
     subscriptions: Arc<RwLock<HashMap<String, Subscription>>>,
+    recent_changes: Arc<Mutex<VecDeque<EntityChange>>>,
+    replay_buffer_size: usize,
     change_listener: EntityChangeListener,
     postgres_url: String,
     network_name: String,
+    isolation_level: IsolationLevel,
     genesis_block_ptr: EthereumBlockPointer,
     conn: Pool<ConnectionManager<PgConnection>>,
-    schema_cache: Mutex<LruCache<SubgraphDeploymentId, Schema>>,
+    read_conn_reserve: Option<Pool<ConnectionManager<PgConnection>>>,
+    read_conn_timeout: Duration,
+    schema_cache: Mutex<LruCache<SubgraphDeploymentId, Arc<Schema>>>,
+    log_queries: bool,
+    slow_query_threshold: Option<Duration>,
+    unbounded_query_limit: Option<usize>,
+    max_block_operations: usize,
 }
 
 impl Store {
@@ -81,7 +232,7 @@ impl Store {
         config: StoreConfig,
         logger: &Logger,
         net_identifiers: EthereumNetworkIdentifier,
-    ) -> Self {
+    ) -> Result<Self, StoreError> {
         // Create a store-specific logger
         let logger = logger.new(o!("component" => "Store"));
 
@@ -94,19 +245,51 @@ impl Store {
         }
         let error_handler = Box::new(ErrorHandler(logger.clone()));
 
+        let channel_name =
+            entity_changes_channel(config.notification_namespace.as_ref().map(String::as_str))?;
+
         // Connect to Postgres
         let conn_manager = ConnectionManager::new(config.postgres_url.as_str());
         let pool = Pool::builder()
             .error_handler(error_handler)
+            .connection_customizer(Box::new(EntityChangesChannel(channel_name.clone())))
+            .max_size(config.conn_pool_size)
             .build(conn_manager)
             .unwrap();
-        info!(logger, "Connected to Postgres"; "url" => &config.postgres_url);
+
+        // Set up the connections reserved for reads; these are only opened when needed
+        let read_conn_reserve = if config.read_conn_reserve > 0 {
+            let conn_manager = ConnectionManager::new(config.postgres_url.as_str());
+            Some(
+                Pool::builder()
+                    .error_handler(Box::new(ErrorHandler(logger.clone())))
+                    .max_size(config.read_conn_reserve)
+                    .min_idle(Some(0))
+                    .connection_timeout(config.read_conn_timeout)
+                    .build(conn_manager)
+                    .unwrap(),
+            )
+        } else {
+            None
+        };
+        info!(logger, "Connected to Postgres";
+              "url" => &config.postgres_url,
+              "isolation_level" => config.isolation_level.to_string(),
+              "schema_cache_capacity" => config.schema_cache_capacity,
+              "conn_pool_size" => config.conn_pool_size,
+              "read_conn_reserve" => config.read_conn_reserve,
+              "slow_query_threshold" => format!("{:?}", config.slow_query_threshold),
+              "unbounded_query_limit" => format!("{:?}", config.unbounded_query_limit),
+              "max_block_operations" => config.max_block_operations,
+              "replay_buffer_size" => config.replay_buffer_size,
+              "entity_changes_channel" => &channel_name);
 
         // Create the entities table (if necessary)
         initiate_schema(&logger, &pool.get().unwrap());
 
         // Listen to entity changes in Postgres
-        let mut change_listener = EntityChangeListener::new(config.postgres_url.clone());
+        let mut change_listener =
+            EntityChangeListener::new(config.postgres_url.clone(), channel_name);
         let entity_changes = change_listener
             .take_event_stream()
             .expect("Failed to listen to entity change events in Postgres");
@@ -115,32 +298,43 @@ impl Store {
         let mut store = Store {
             logger: logger.clone(),
             subscriptions: Arc::new(RwLock::new(HashMap::new())),
+            recent_changes: Arc::new(Mutex::new(VecDeque::with_capacity(
+                config.replay_buffer_size,
+            ))),
+            replay_buffer_size: config.replay_buffer_size,
             change_listener,
             postgres_url: config.postgres_url.clone(),
             network_name: config.network_name.clone(),
+            isolation_level: config.isolation_level,
             genesis_block_ptr: (net_identifiers.genesis_block_hash, 0u64).into(),
             conn: pool,
-            schema_cache: Mutex::new(LruCache::with_capacity(100)),
+            read_conn_reserve,
+            read_conn_timeout: config.read_conn_timeout,
+            schema_cache: Mutex::new(LruCache::with_capacity(config.schema_cache_capacity)),
+            log_queries: config.log_queries,
+            slow_query_threshold: config.slow_query_threshold,
+            unbounded_query_limit: config.unbounded_query_limit,
+            max_block_operations: config.max_block_operations,
         };
 
         // Add network to store and check network identifiers
-        store.add_network_if_missing(net_identifiers).unwrap();
+        store.add_network_if_missing(net_identifiers)?;
 
         // Deal with store subscriptions
         store.handle_entity_changes(entity_changes);
-        store.periodically_clean_up_stale_subscriptions();
+        store.periodically_clean_up_stale_subscriptions(config.subscription_cleanup_interval);
 
         // We're ready for processing entity changes
         store.change_listener.start();
 
         // Return the store
-        store
+        Ok(store)
     }
 
     fn add_network_if_missing(
         &self,
         new_net_identifiers: EthereumNetworkIdentifier,
-    ) -> Result<(), Error> {
+    ) -> Result<(), StoreError> {
         use db_schema::ethereum_networks::dsl::*;
 
         let new_genesis_block_hash = new_net_identifiers.genesis_block_hash;
@@ -149,7 +343,7 @@ impl Store {
         let network_identifiers_opt = ethereum_networks
             .select((net_version, genesis_block_hash))
             .filter(name.eq(&self.network_name))
-            .first::<(Option<String>, Option<String>)>(&*self.conn.get()?)
+            .first::<(Option<String>, Option<String>)>(&*self.conn.get().map_err(Error::from)?)
             .optional()?;
 
         match network_identifiers_opt {
@@ -166,27 +360,25 @@ impl Store {
                     ))
                     .on_conflict(name)
                     .do_nothing()
-                    .execute(&*self.conn.get()?)?;
+                    .execute(&*self.conn.get().map_err(Error::from)?)?;
             }
 
             // Network is in database and has identifiers
             Some((Some(last_net_version), Some(last_genesis_block_hash))) => {
                 if last_net_version != new_net_version {
-                    panic!(
-                        "Ethereum node provided net_version {}, \
-                         but we expected {}. Did you change networks \
-                         without changing the network name?",
-                        new_net_version, last_net_version
-                    );
+                    return Err(StoreError::NetworkMismatch {
+                        identifier: "net_version".to_owned(),
+                        provided: new_net_version,
+                        expected: last_net_version,
+                    });
                 }
 
                 if last_genesis_block_hash.parse().ok() != Some(new_genesis_block_hash) {
-                    panic!(
-                        "Ethereum node provided genesis block hash {}, \
-                         but we expected {}. Did you change networks \
-                         without changing the network name?",
-                        new_genesis_block_hash, last_genesis_block_hash
-                    );
+                    return Err(StoreError::NetworkMismatch {
+                        identifier: "genesis block hash".to_owned(),
+                        provided: format!("{}", new_genesis_block_hash),
+                        expected: last_genesis_block_hash,
+                    });
                 }
             }
 
@@ -199,7 +391,7 @@ impl Store {
                             .eq::<Option<String>>(Some(format!("{:x}", new_genesis_block_hash))),
                     ))
                     .filter(name.eq(&self.network_name))
-                    .execute(&*self.conn.get()?)?;
+                    .execute(&*self.conn.get().map_err(Error::from)?)?;
             }
         }
 
@@ -213,25 +405,40 @@ impl Store {
     ) {
         let logger = self.logger.clone();
         let subscriptions = self.subscriptions.clone();
+        let recent_changes = self.recent_changes.clone();
+        let replay_buffer_size = self.replay_buffer_size;
+        let mut sequence = 0u64;
+
+        tokio::spawn(entity_changes.for_each(move |mut change| {
+            sequence += 1;
+            change.sequence = sequence;
 
-        tokio::spawn(entity_changes.for_each(move |change| {
             trace!(logger, "Received entity change event";
                            "subgraph_id" => change.subgraph_id.to_string(),
                            "entity_type" => &change.entity_type,
-                           "entity_id" => &change.entity_id);
+                           "entity_id" => &change.entity_id,
+                           "sequence" => change.sequence);
+
+            // Remember the change for replaying it and obtain IDs and senders of
+            // subscriptions matching it; `subscribe_since` holds the same lock, so every
+            // change is either replayed to a new subscription or sent to it, never both
+            let matches = {
+                let mut recent_changes = recent_changes.lock().unwrap();
+                if replay_buffer_size > 0 {
+                    if recent_changes.len() == replay_buffer_size {
+                        recent_changes.pop_front();
+                    }
+                    recent_changes.push_back(change.clone());
+                }
 
-            // Obtain IDs and senders of subscriptions matching the entity change
-            let matches = subscriptions
-                .read()
-                .unwrap()
-                .iter()
-                .filter(|(_, subscription)| {
-                    subscription
-                        .entities
-                        .contains(&(change.subgraph_id.clone(), change.entity_type.clone()))
-                })
-                .map(|(id, subscription)| (id.clone(), subscription.sender.clone()))
-                .collect::<Vec<_>>();
+                subscriptions
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .filter(|(_, subscription)| subscription.wants(&change))
+                    .map(|(id, subscription)| (id.clone(), subscription.sender.clone()))
+                    .collect::<Vec<_>>()
+            };
 
             let subscriptions = subscriptions.clone();
             let logger = logger.clone();
@@ -256,13 +463,59 @@ impl Store {
         }));
     }
 
-    fn periodically_clean_up_stale_subscriptions(&self) {
+    /// Adds a subscription for each group of entities and the operations to include,
+    /// while holding the lock only once.
+    fn add_subscriptions(
+        &self,
+        groups: Vec<(Vec<SubgraphEntityPair>, Option<Vec<EntityChangeOperation>>)>,
+    ) -> Vec<EntityChangeStream> {
+        let mut subscriptions = self.subscriptions.write().unwrap();
+
+        groups
+            .into_iter()
+            .map(|(entities, operations)| {
+                // Generate a new (unique) UUID; we're looping just to be sure we avoid
+                // collisions
+                let mut id = Uuid::new_v4().to_string();
+                while subscriptions.contains_key(&id) {
+                    id = Uuid::new_v4().to_string();
+                }
+
+                debug!(self.logger, "Subscribe";
+                       "id" => &id,
+                       "entities" => format!("{:?}", entities),
+                       "operations" => format!("{:?}", operations));
+
+                // Prepare the new subscription by creating a channel and a subscription object
+                let (sender, receiver) = channel(100);
+                subscriptions.insert(
+                    id,
+                    Subscription {
+                        entities,
+                        operations,
+                        sender,
+                    },
+                );
+
+                // Return the entity change stream
+                Box::new(receiver) as EntityChangeStream
+            })
+            .collect()
+    }
+
+    fn periodically_clean_up_stale_subscriptions(&self, interval: Duration) {
         let logger = self.logger.clone();
         let subscriptions = self.subscriptions.clone();
 
-        // Clean up stale subscriptions every 5s
+        // Add up to 10% of jitter to the interval
+        let max_jitter = interval / 10;
+        let max_jitter_ms = max_jitter.as_secs() * 1000 + u64::from(max_jitter.subsec_millis());
+        let interval =
+            interval + Duration::from_millis(thread_rng().gen_range(0, max_jitter_ms + 1));
+
+        // Clean up stale subscriptions periodically
         tokio::spawn(
-            Interval::new(Instant::now(), Duration::from_secs(5))
+            Interval::new(Instant::now() + interval, interval)
                 .for_each(move |_| {
                     let mut subscriptions = subscriptions.write().unwrap();
 
@@ -297,12 +550,24 @@ impl Store {
         op_entity: &String,
         op_id: &String,
     ) -> Result<Option<Entity>, QueryExecutionError> {
+        self.get_entity_and_block(conn, op_subgraph, op_entity, op_id)
+            .map(|entity| entity.map(|(entity, _)| entity))
+    }
+
+    /// Like `get_entity`, but also returns the block the entity was last updated at.
+    fn get_entity_and_block(
+        &self,
+        conn: &PgConnection,
+        op_subgraph: &SubgraphDeploymentId,
+        op_entity: &String,
+        op_id: &String,
+    ) -> Result<Option<(Entity, Option<i64>)>, QueryExecutionError> {
         use db_schema::entities::dsl::*;
 
         match entities
             .find((op_id, op_subgraph.to_string(), op_entity))
-            .select(data)
-            .first::<serde_json::Value>(conn)
+            .select((data, updated_at_block))
+            .first::<(serde_json::Value, Option<i64>)>(conn)
             .optional()
             .map_err(|e| {
                 QueryExecutionError::ResolveEntityError(
@@ -312,8 +577,8 @@ impl Store {
                     format!("{}", e),
                 )
             })? {
-            Some(json) => serde_json::from_value::<Entity>(json)
-                .map(Some)
+            Some((json, block)) => serde_json::from_value::<Entity>(json)
+                .map(|entity| Some((entity, block)))
                 .map_err(|e| {
                     QueryExecutionError::ResolveEntityError(
                         op_subgraph.clone(),
@@ -326,37 +591,82 @@ impl Store {
         }
     }
 
-    fn execute_query(
+    /// Builds the statement that `find` runs for `query`.
+    ///
+    /// With `across_subgraphs`, the statement ignores the subgraph of the query, and
+    /// selects `[subgraph, data]` arrays instead of only the entity data. The schema
+    /// needed to order by an attribute of a referenced entity is loaded with `conn`.
+    fn build_query(
         &self,
         conn: &PgConnection,
-        query: EntityQuery,
-    ) -> Result<Vec<Entity>, QueryExecutionError> {
+        query: &EntityQuery,
+        across_subgraphs: bool,
+    ) -> Result<
+        BoxedSelectStatement<'static, Jsonb, db_schema::entities::table, Pg>,
+        QueryExecutionError,
+    > {
         use db_schema::entities::dsl::*;
 
         // Create base boxed query; this will be added to based on the
         // query parameters provided
-        let mut diesel_query = entities
-            .filter(entity.eq(query.entity_type))
-            .filter(subgraph.eq(query.subgraph_id.to_string()))
-            .select(data)
-            .into_boxed::<Pg>();
+        let mut diesel_query = if across_subgraphs {
+            entities
+                .filter(entity.eq(query.entity_type.clone()))
+                .select(sql::<Jsonb>("jsonb_build_array(subgraph, data)"))
+                .into_boxed::<Pg>()
+        } else {
+            entities
+                .filter(entity.eq(query.entity_type.clone()))
+                .filter(subgraph.eq(query.subgraph_id.to_string()))
+                .select(data)
+                .into_boxed::<Pg>()
+        };
+
+        // Remember any fulltext search to rank the results by
+        let search = query.filter.as_ref().and_then(fulltext_search);
 
         // Add specified filter to query
-        if let Some(filter) = query.filter {
+        if let Some(filter) = query.filter.clone() {
             diesel_query = store_filter(diesel_query, filter).map_err(|e| {
                 QueryExecutionError::FilterNotSupportedError(format!("{}", e.value), e.filter)
             })?;
         }
 
+        // Leave out entities written after the requested block
+        if let Some(block_number) = query.as_of_block {
+            diesel_query = store_as_of_block(diesel_query, block_number);
+        }
+
+        // Unless an explicit order is requested, return the most relevant
+        // fulltext search results first
+        if let (None, Some((search_attribute, search_query))) = (&query.order_by, search) {
+            diesel_query = diesel_query.order(
+                sql::<Text>("ts_rank(to_tsvector(data -> ")
+                    .bind::<Text, _>(search_attribute)
+                    .sql("->> 'data'), plainto_tsquery(")
+                    .bind::<Text, _>(search_query)
+                    .sql(")) DESC"),
+            );
+        }
+
         // Add order by filters to query
-        if let Some((order_attribute, value_type)) = query.order_by {
+        if let Some((order_attribute, value_type)) = query.order_by.clone() {
             let direction = query
                 .order_direction
+                .as_ref()
                 .map(|direction| match direction {
                     EntityOrder::Ascending => "ASC",
                     EntityOrder::Descending => "DESC",
                 })
                 .unwrap_or("ASC");
+            let nulls = query
+                .order_nulls
+                .as_ref()
+                .map(|nulls| match nulls {
+                    EntityNullsOrder::NullsFirst => "NULLS FIRST",
+                    EntityNullsOrder::NullsLast => "NULLS LAST",
+                })
+                .unwrap_or("NULLS LAST");
             let cast_type = match value_type {
                 ValueType::BigInt => "::numeric",
                 ValueType::Boolean => "::boolean",
@@ -366,36 +676,112 @@ impl Store {
                 ValueType::Int => "::bigint",
                 ValueType::String => "",
             };
-            diesel_query = diesel_query.order(
-                sql::<Text>("(data ->")
-                    .bind::<Text, _>(order_attribute)
-                    .sql("->> 'data')")
-                    .sql(cast_type)
-                    .sql(" ")
-                    .sql(direction)
-                    .sql(" NULLS LAST"),
-            );
+
+            let order_suffix = format!("{} {} {}", cast_type, direction, nulls);
+            let entity_type = &query.entity_type;
+
+            // Either order by an attribute of the entity itself, or by an
+            // attribute of an entity it references, e.g. `parent.name`
+            let path: Vec<&str> = order_attribute.split('.').collect();
+            diesel_query = match path.as_slice() {
+                [attribute] if *attribute == UPDATED_AT_BLOCK_ATTRIBUTE => {
+                    diesel_query.order(sql::<Text>("updated_at_block").sql(&order_suffix))
+                }
+                [attribute] => diesel_query.order(
+                    sql::<Text>("(data ->")
+                        .bind::<Text, _>(attribute.to_string())
+                        .sql("->> 'data')")
+                        .sql(&order_suffix),
+                ),
+                [reference, attribute] if !across_subgraphs => {
+                    let schema = self
+                        .subgraph_schema_with_conn(conn, &query.subgraph_id)
+                        .map_err(|e| QueryExecutionError::ResolveEntitiesError(e.to_string()))?;
+                    let referenced_type = get_referenced_object_type(
+                        &schema.document,
+                        entity_type,
+                        &reference.to_string(),
+                    )
+                    .ok_or_else(|| {
+                        QueryExecutionError::OrderByNotSupportedError(
+                            entity_type.clone(),
+                            order_attribute.clone(),
+                        )
+                    })?;
+
+                    diesel_query.order(
+                        sql::<Text>("(SELECT parent.data ->")
+                            .bind::<Text, _>(attribute.to_string())
+                            .sql("->> 'data' FROM entities parent WHERE parent.subgraph = ")
+                            .bind::<Text, _>(query.subgraph_id.to_string())
+                            .sql(" AND parent.entity = ")
+                            .bind::<Text, _>(referenced_type.name.clone())
+                            .sql(" AND parent.id = (entities.data ->")
+                            .bind::<Text, _>(reference.to_string())
+                            .sql("->> 'data'))")
+                            .sql(&order_suffix),
+                    )
+                }
+                _ => {
+                    return Err(QueryExecutionError::OrderByNotSupportedError(
+                        entity_type.clone(),
+                        order_attribute.clone(),
+                    ))
+                }
+            };
         }
 
-        // Add range filter to query
-        if let Some(range) = query.range {
-            diesel_query = diesel_query
-                .limit(range.first as i64)
-                .offset(range.skip as i64);
+        // Add range filter to query; `unbounded_query_limit` is both the default
+        // and the maximum for `first`
+        if let Some(ref range) = query.range {
+            let first = match (range.first, self.unbounded_query_limit) {
+                (Some(first), Some(limit)) => Some(first.min(limit)),
+                (first, limit) => first.or(limit),
+            };
+            if let Some(first) = first {
+                diesel_query = diesel_query.limit(first as i64);
+            }
+            diesel_query = diesel_query.offset(range.skip as i64);
         }
 
+        Ok(diesel_query)
+    }
+
+    /// Returns the SQL, including the values bound to it, that `find` runs for `query`.
+    ///
+    /// This is meant for diagnosing slow queries, e.g. by running the SQL with
+    /// `EXPLAIN ANALYZE` in `psql`.
+    pub fn explain_query(&self, query: EntityQuery) -> Result<String, QueryExecutionError> {
+        let conn = self.get_read_conn()?;
+        let diesel_query = self.build_query(&*conn, &query, false)?;
+        Ok(debug_query(&diesel_query).to_string())
+    }
+
+    /// Finds the entities matching `query` in all subgraphs, ignoring the subgraph of
+    /// the query, and returns them together with the ID of their subgraph.
+    ///
+    /// This is meant for administrative tools. It is deliberately not part of the
+    /// `Store` trait, so that GraphQL queries can never reach it. Ordering by an
+    /// attribute of a referenced entity is not supported.
+    pub fn find_across_subgraphs(
+        &self,
+        query: EntityQuery,
+    ) -> Result<Vec<(SubgraphDeploymentId, Entity)>, QueryExecutionError> {
+        let conn = self.get_read_conn()?;
+        let diesel_query = self.build_query(&*conn, &query, true)?;
+
         // Record debug info in case of error
         let diesel_query_debug_info = debug_query(&diesel_query).to_string();
 
-        // Process results; deserialize JSON data
         diesel_query
-            .load::<serde_json::Value>(conn)
+            .load::<serde_json::Value>(&*conn)
             .map(|values| {
                 values
                     .into_iter()
                     .map(|value| {
                         let parse_error_msg = format!("Error parsing entity JSON: {:?}", value);
-                        serde_json::from_value::<Entity>(value).expect(&parse_error_msg)
+                        serde_json::from_value::<(SubgraphDeploymentId, Entity)>(value)
+                            .expect(&parse_error_msg)
                     })
                     .collect()
             })
@@ -407,27 +793,175 @@ impl Store {
             })
     }
 
+    fn execute_query(
+        &self,
+        conn: &PgConnection,
+        query: EntityQuery,
+    ) -> Result<Vec<Entity>, QueryExecutionError> {
+        let diesel_query = self.build_query(conn, &query, false)?;
+
+        // Record debug info in case of error
+        let diesel_query_debug_info = debug_query(&diesel_query).to_string();
+
+        // Process results; deserialize JSON data
+        let start_time = Instant::now();
+        let result = diesel_query.load::<serde_json::Value>(conn);
+        self.log_query(&query.subgraph_id, &diesel_query_debug_info, start_time);
+        let mut entities: Vec<Entity> = result
+            .map(|values| {
+                values
+                    .into_iter()
+                    .map(|value| {
+                        let parse_error_msg = format!("Error parsing entity JSON: {:?}", value);
+                        serde_json::from_value::<Entity>(value).expect(&parse_error_msg)
+                    })
+                    .collect()
+            })
+            .map_err(|e| {
+                QueryExecutionError::ResolveEntitiesError(format!(
+                    "{}, query = {:?}",
+                    e, diesel_query_debug_info
+                ))
+            })?;
+
+        self.fill_in_defaults(conn, &query.subgraph_id, &query.entity_type, &mut entities);
+        Ok(entities)
+    }
+
+    /// Completes entities of type `entity_type` with the defaults from the subgraph schema.
+    ///
+    /// Both `get` and `find` go through this, so that entities look the same no
+    /// matter how they are loaded. Entities are returned as they are stored if the
+    /// schema can't be loaded, e.g. because the subgraph was removed meanwhile.
+    fn fill_in_defaults(
+        &self,
+        conn: &PgConnection,
+        subgraph_id: &SubgraphDeploymentId,
+        entity_type: &str,
+        entities: &mut [Entity],
+    ) {
+        if entities.is_empty() {
+            return;
+        }
+
+        let schema = match self.subgraph_schema_with_conn(conn, subgraph_id) {
+            Ok(schema) => schema,
+            Err(e) => {
+                debug!(
+                    self.logger, "Not filling in defaults, failed to load schema";
+                    "subgraph_id" => subgraph_id.to_string(),
+                    "error" => e.to_string(),
+                );
+                return;
+            }
+        };
+        if !schema.has_defaults(entity_type) {
+            return;
+        }
+        for entity in entities.iter_mut() {
+            schema.fill_in_defaults(entity_type, entity);
+        }
+    }
+
+    /// Logs the SQL of an entity query that started at `start_time`, if query
+    /// logging is enabled or the query was slow.
+    fn log_query(&self, subgraph_id: &SubgraphDeploymentId, sql: &str, start_time: Instant) {
+        let elapsed = start_time.elapsed();
+        if self.log_queries {
+            debug!(
+                self.logger, "Entity query";
+                "subgraph_id" => subgraph_id.to_string(),
+                "sql" => sql,
+                // Replace this when `as_millis` is stable.
+                "secs" => elapsed.as_secs(),
+                "ms" => elapsed.subsec_millis()
+            );
+        }
+        if self.is_slow(elapsed) {
+            warn!(
+                self.logger, "Slow entity query";
+                "subgraph_id" => subgraph_id.to_string(),
+                "sql" => sql,
+                "secs" => elapsed.as_secs(),
+                "ms" => elapsed.subsec_millis()
+            );
+        }
+    }
+
+    /// Returns true if a query or operation that took `elapsed` should be logged as slow.
+    fn is_slow(&self, elapsed: Duration) -> bool {
+        self.slow_query_threshold
+            .map_or(false, |threshold| elapsed >= threshold)
+    }
+
+    /// Counts the entities matching the filter of `query`, ignoring its range and order.
+    fn execute_count_query(
+        &self,
+        conn: &PgConnection,
+        query: EntityQuery,
+    ) -> Result<u64, QueryExecutionError> {
+        use db_schema::entities::dsl::*;
+
+        let mut diesel_query = entities
+            .filter(entity.eq(query.entity_type))
+            .filter(subgraph.eq(query.subgraph_id.to_string()))
+            .select(data)
+            .into_boxed::<Pg>();
+
+        // Use the same filter as `execute_query` so that counts and pages agree
+        if let Some(filter) = query.filter {
+            diesel_query = store_filter(diesel_query, filter).map_err(|e| {
+                QueryExecutionError::FilterNotSupportedError(format!("{}", e.value), e.filter)
+            })?;
+        }
+        if let Some(block_number) = query.as_of_block {
+            diesel_query = store_as_of_block(diesel_query, block_number);
+        }
+
+        // Record debug info in case of error
+        let diesel_query_debug_info = debug_query(&diesel_query).to_string();
+
+        let start_time = Instant::now();
+        let result = diesel_query.count().get_result::<i64>(conn);
+        self.log_query(&query.subgraph_id, &diesel_query_debug_info, start_time);
+        result.map(|count| count as u64).map_err(|e| {
+            QueryExecutionError::ResolveEntitiesError(format!(
+                "{}, query = {:?}",
+                e, diesel_query_debug_info
+            ))
+        })
+    }
+
     /// Applies a set operation in Postgres.
     fn apply_set_operation(
         &self,
         conn: &PgConnection,
-        key: EntityKey,
-        data: Entity,
+        key: &EntityKey,
+        data: &Entity,
         event_source: EventSource,
     ) -> Result<(), StoreError> {
         use db_schema::entities;
 
-        // Load the entity if exists
-        let existing_entity = self
-            .get_entity(conn, &key.subgraph_id, &key.entity_type, &key.entity_id)
-            .map_err(Error::from)?;
+        // Refuse to write values that are not members of the enum of their field
+        self.check_enum_values(conn, key, data)?;
+
+        // Load the entity if exists, together with the block it was last updated at
+        let (existing_entity, previous_block) = match self
+            .get_entity_and_block(conn, &key.subgraph_id, &key.entity_type, &key.entity_id)
+            .map_err(Error::from)?
+        {
+            Some((entity, block)) => (Some(entity), block),
+            None => (None, None),
+        };
 
         // Apply the operation
-        let operation = EntityOperation::Set {
-            key: key.clone(),
-            data,
+        let updated_entity = match existing_entity {
+            Some(mut entity) => {
+                entity.merge(data.clone());
+                entity
+            }
+            None => data.clone(),
         };
-        let updated_entity = operation.apply(existing_entity)?;
         let updated_json: serde_json::Value =
             serde_json::to_value(&updated_entity).map_err(|e| {
                 format_err!(
@@ -439,6 +973,29 @@ impl Store {
                 )
             })?;
 
+        // Remember the block the entity was changed in, if any
+        let updated_at_block = match event_source {
+            EventSource::EthereumBlock(block_ptr) => Some(block_ptr.number as i64),
+            EventSource::None => None,
+        };
+
+        // Overwriting an entity that was written in a later block may indicate a
+        // logic error, e.g. in reprocessing blocks after a reorg
+        if let (Some(block), Some(previous_block)) = (updated_at_block, previous_block) {
+            if block < previous_block {
+                debug!(
+                    self.logger,
+                    "Entity written in a later block is overwritten by an earlier block";
+                    "subgraph_id" => key.subgraph_id.to_string(),
+                    "entity_type" => &key.entity_type,
+                    "entity_id" => &key.entity_id,
+                    "previous_block" => previous_block,
+                    "block" => block,
+                    "event_source" => event_source.to_string()
+                );
+            }
+        }
+
         // Either add or update the entity in Postgres
         insert_into(entities::table)
             .values((
@@ -447,6 +1004,7 @@ impl Store {
                 entities::subgraph.eq(key.subgraph_id.to_string()),
                 entities::data.eq(&updated_json),
                 entities::event_source.eq(event_source.to_string()),
+                entities::updated_at_block.eq(updated_at_block),
             ))
             .on_conflict((entities::id, entities::entity, entities::subgraph))
             .do_update()
@@ -456,6 +1014,7 @@ impl Store {
                 entities::subgraph.eq(key.subgraph_id.to_string()),
                 entities::data.eq(&updated_json),
                 entities::event_source.eq(event_source.to_string()),
+                entities::updated_at_block.eq(updated_at_block),
             ))
             .execute(conn)
             .map(|_| ())
@@ -471,13 +1030,67 @@ impl Store {
             })
     }
 
+    /// Checks that the values an entity has for enum fields, including lists of enum values,
+    /// are members of the enum.
+    fn check_enum_values(
+        &self,
+        conn: &PgConnection,
+        key: &EntityKey,
+        data: &Entity,
+    ) -> Result<(), StoreError> {
+        // The subgraph of subgraphs has no enums
+        if key.subgraph_id == *SUBGRAPHS_ID {
+            return Ok(());
+        }
+
+        // Use the connection of the transaction rather than checking out another one
+        let schema = self.subgraph_schema_with_conn(conn, &key.subgraph_id)?;
+        for (attribute, value) in data.iter() {
+            let enum_type = match get_field_enum_type(&schema.document, &key.entity_type, attribute)
+            {
+                Some(enum_type) => enum_type,
+                None => continue,
+            };
+
+            let values = match value {
+                Value::List(values) => values.iter().collect(),
+                value => vec![value],
+            };
+            for value in values {
+                let is_member = match value {
+                    Value::Null => true,
+                    Value::String(s) => enum_type.values.iter().any(|v| &v.name == s),
+                    _ => false,
+                };
+                if !is_member {
+                    return Err(format_err!(
+                        "Failed to set entity ({}, {}, {}): value {} of field `{}` is not a \
+                         member of enum `{}`",
+                        key.subgraph_id,
+                        key.entity_type,
+                        key.entity_id,
+                        value,
+                        attribute,
+                        enum_type.name
+                    )
+                    .into());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Applies a remove operation by deleting the entity from Postgres.
+    ///
+    /// Returns whether the entity existed. Entity changes are only emitted for
+    /// entities that were actually removed.
     fn apply_remove_operation(
         &self,
         conn: &PgConnection,
-        key: EntityKey,
+        key: &EntityKey,
         event_source: EventSource,
-    ) -> Result<(), StoreError> {
+    ) -> Result<bool, StoreError> {
         use db_schema::entities;
 
         select(set_config(
@@ -496,7 +1109,7 @@ impl Store {
                 .filter(entities::id.eq(&key.entity_id)),
         )
         .execute(conn)
-        .map(|_| ())
+        .map(|count| count > 0)
         .map_err(|e| {
             format_err!(
                 "Failed to remove entity ({}, {}, {}): {}",
@@ -512,15 +1125,19 @@ impl Store {
     fn apply_abort_unless_operation(
         &self,
         conn: &PgConnection,
-        description: String,
-        query: EntityQuery,
-        mut expected_entity_ids: Vec<String>,
+        description: &str,
+        query: &EntityQuery,
+        expected_entity_ids: &[String],
         _event_source: EventSource,
     ) -> Result<(), StoreError> {
         if query.range.is_some() && query.order_by.is_none() {
             // Queries with a range but no sort key can vary non-deterministically in what they
             // return, and so are not suitable for use with AbortUnless.
-            panic!("Cannot use range in an AbortUnless query without order_by");
+            return Err(TransactionAbortError::Other(format!(
+                "Cannot use range in an AbortUnless query without order_by: {}",
+                description
+            ))
+            .into());
         }
 
         // Execute query
@@ -542,6 +1159,7 @@ impl Store {
         // Sort entity IDs lexicographically if and only if no sort order is specified.
         // When no sort order is specified, the entity ordering is arbitrary and should not be a
         // factor in deciding whether or not to abort.
+        let mut expected_entity_ids = expected_entity_ids.to_vec();
         if query.order_by.is_none() {
             expected_entity_ids.sort();
             actual_entity_ids.sort();
@@ -552,7 +1170,7 @@ impl Store {
             return Err(TransactionAbortError::AbortUnless {
                 expected_entity_ids,
                 actual_entity_ids,
-                description,
+                description: description.to_owned(),
             }
             .into());
         }
@@ -565,14 +1183,23 @@ impl Store {
     fn apply_entity_operation(
         &self,
         conn: &PgConnection,
-        operation: EntityOperation,
+        operation: &EntityOperation,
         event_source: EventSource,
     ) -> Result<(), StoreError> {
         match operation {
             EntityOperation::Set { key, data } => {
                 self.apply_set_operation(conn, key, data, event_source)
             }
-            EntityOperation::Remove { key } => self.apply_remove_operation(conn, key, event_source),
+            EntityOperation::Remove { key } => self
+                .apply_remove_operation(conn, key, event_source)
+                .map(|removed| {
+                    if !removed {
+                        debug!(self.logger, "Entity to remove does not exist";
+                               "subgraph_id" => key.subgraph_id.to_string(),
+                               "entity_type" => &key.entity_type,
+                               "entity_id" => &key.entity_id);
+                    }
+                }),
             EntityOperation::AbortUnless {
                 description,
                 query,
@@ -587,15 +1214,180 @@ impl Store {
         }
     }
 
+    /// Obtains a connection for reading from the store.
+    ///
+    /// Waits up to `read_conn_timeout` for a connection of the shared pool, then falls back to
+    /// the connections reserved for reads. Fails with `QueryExecutionError::StoreBusy` if none
+    /// of those becomes available in time, rather than blocking until a writer returns its
+    /// connection.
+    fn get_read_conn(
+        &self,
+    ) -> Result<PooledConnection<ConnectionManager<PgConnection>>, QueryExecutionError> {
+        if let Ok(conn) = self.conn.get_timeout(self.read_conn_timeout) {
+            return Ok(conn);
+        }
+
+        match self.read_conn_reserve {
+            Some(ref reserve) => reserve.get().map_err(|e| {
+                debug!(self.logger, "No connection available for reading";
+                       "error" => e.to_string());
+                QueryExecutionError::StoreBusy
+            }),
+            None => Err(QueryExecutionError::StoreBusy),
+        }
+    }
+
+    /// Looks up an entity using the given connection.
+    ///
+    /// Use this instead of `get` to read an entity inside a transaction, e.g. the one
+    /// started by `block_transaction`, so that writes made in it are visible.
+    pub fn get_with_conn(
+        &self,
+        conn: &PgConnection,
+        key: &EntityKey,
+    ) -> Result<Option<Entity>, QueryExecutionError> {
+        let entity = self.get_entity(conn, &key.subgraph_id, &key.entity_type, &key.entity_id)?;
+
+        match entity {
+            Some(mut entity) => {
+                self.fill_in_defaults(
+                    conn,
+                    &key.subgraph_id,
+                    &key.entity_type,
+                    slice::from_mut(&mut entity),
+                );
+                Ok(Some(entity))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Queries entities using the given connection.
+    ///
+    /// Use this instead of `find` to query entities inside a transaction, e.g. the one
+    /// started by `block_transaction`, so that writes made in it are visible.
+    pub fn find_with_conn(
+        &self,
+        conn: &PgConnection,
+        query: EntityQuery,
+    ) -> Result<Vec<Entity>, QueryExecutionError> {
+        self.execute_query(conn, query)
+    }
+
+    /// Looks up the schema of a subgraph using the given connection.
+    ///
+    /// Use this instead of `subgraph_schema` while holding a connection, so that
+    /// loading a schema never waits for a second connection from the pool.
+    fn subgraph_schema_with_conn(
+        &self,
+        conn: &PgConnection,
+        subgraph_id: &SubgraphDeploymentId,
+    ) -> Result<Arc<Schema>, Error> {
+        if let Some(schema) = self.schema_cache.lock().unwrap().get(subgraph_id) {
+            trace!(self.logger, "schema cache hit"; "id" => subgraph_id.to_string());
+            return Ok(schema.clone());
+        }
+        trace!(self.logger, "schema cache miss"; "id" => subgraph_id.to_string());
+
+        let schema = if *subgraph_id == *SUBGRAPHS_ID {
+            // The subgraph of subgraphs schema is built-in.
+            parse_api_schema(subgraph_id, include_str!("subgraphs.graphql"))?
+        } else {
+            let manifest_entity = self
+                .get_entity(
+                    conn,
+                    &*SUBGRAPHS_ID,
+                    &SubgraphManifestEntity::TYPENAME.to_owned(),
+                    &SubgraphManifestEntity::id(subgraph_id),
+                )?
+                .ok_or_else(|| format_err!("Subgraph entity not found {}", subgraph_id))?;
+            manifest_schema(subgraph_id, &manifest_entity)?
+        };
+        let schema = Arc::new(schema);
+
+        if !self.schema_cache.lock().unwrap().contains_key(subgraph_id) {
+            self.schema_cache
+                .lock()
+                .unwrap()
+                .insert(subgraph_id.clone(), schema.clone());
+        }
+
+        Ok(schema)
+    }
+
+    /// Runs `f` in a transaction with the configured isolation level.
+    ///
+    /// This is the transaction that entity operations for blocks are applied in.
+    /// If Postgres aborts the transaction with a serialization failure because
+    /// of a conflicting concurrent write, the whole transaction, including `f`,
+    /// is run again, up to `MAX_SERIALIZATION_RETRIES` times.
+    pub fn block_transaction<T, F>(&self, f: F) -> Result<T, StoreError>
+    where
+        F: Fn(&PgConnection) -> Result<T, StoreError>,
+    {
+        let conn = self.conn.get().map_err(Error::from)?;
+        let mut retries = 0;
+        loop {
+            let result = conn.transaction(|| {
+                // This has to be the first statement in the transaction
+                sql_query(format!(
+                    "SET TRANSACTION ISOLATION LEVEL {}",
+                    self.isolation_level
+                ))
+                .execute(&*conn)
+                .map_err(|e| format_err!("Failed to set transaction isolation level: {}", e))?;
+
+                f(&conn)
+            });
+
+            match result {
+                Err(ref e)
+                    if is_serialization_failure(e) && retries < MAX_SERIALIZATION_RETRIES =>
+                {
+                    retries += 1;
+                    debug!(self.logger, "Retrying block transaction after serialization failure";
+                           "retries" => retries,
+                           "error" => e.to_string());
+                }
+                result => return result,
+            }
+        }
+    }
+
     /// Apply a series of entity operations in Postgres.
+    ///
+    /// The operations are borrowed so that a retried `block_transaction` can apply
+    /// them again without copying them.
     fn apply_entity_operations_with_conn(
         &self,
         conn: &PgConnection,
-        operations: Vec<EntityOperation>,
+        operations: &[EntityOperation],
         event_source: EventSource,
     ) -> Result<(), StoreError> {
-        for operation in operations.into_iter() {
+        for operation in operations {
+            // Only hold on to the key if we may have to log the operation as slow;
+            // the queries of `AbortUnless` operations are logged by `log_query`
+            let key = match operation {
+                EntityOperation::AbortUnless { .. } => None,
+                _ => self
+                    .slow_query_threshold
+                    .map(|_| operation.entity_key().clone()),
+            };
+
+            let start_time = Instant::now();
             self.apply_entity_operation(conn, operation, event_source)?;
+
+            let elapsed = start_time.elapsed();
+            if let Some(key) = key.filter(|_| self.is_slow(elapsed)) {
+                warn!(
+                    self.logger, "Slow entity operation";
+                    "subgraph_id" => key.subgraph_id.to_string(),
+                    "entity_type" => &key.entity_type,
+                    "entity_id" => &key.entity_id,
+                    "secs" => elapsed.as_secs(),
+                    "ms" => elapsed.subsec_millis()
+                );
+            }
         }
         Ok(())
     }
@@ -603,8 +1395,22 @@ impl Store {
 
 impl StoreTrait for Store {
     fn block_ptr(&self, subgraph_id: SubgraphDeploymentId) -> Result<EthereumBlockPointer, Error> {
-        let subgraph_entity = self
-            .get(SubgraphDeploymentEntity::key(subgraph_id.clone()))
+        use db_schema::entities::dsl::*;
+
+        // Only read the pointer fields of the deployment entity
+        let conn = self.get_read_conn()?;
+        let (hash, number) = entities
+            .find((
+                subgraph_id.to_string(),
+                SUBGRAPHS_ID.to_string(),
+                SubgraphDeploymentEntity::TYPENAME,
+            ))
+            .select((
+                sql::<Nullable<Text>>("data->'latestEthereumBlockHash'->>'data'"),
+                sql::<Nullable<Text>>("data->'latestEthereumBlockNumber'->>'data'"),
+            ))
+            .first::<(Option<String>, Option<String>)>(&*conn)
+            .optional()
             .map_err(|e| format_err!("error reading subgraph entity: {}", e))?
             .ok_or_else(|| {
                 format_err!(
@@ -613,6 +1419,29 @@ impl StoreTrait for Store {
                 )
             })?;
 
+        let hash = hash
+            .ok_or_else(|| format_err!("SubgraphDeployment is missing latestEthereumBlockHash"))?
+            .parse::<H256>()
+            .map_err(|e| format_err!("latestEthereumBlockHash: {}", e))?;
+        let number = number
+            .ok_or_else(|| format_err!("SubgraphDeployment is missing latestEthereumBlockNumber"))?
+            .parse::<u64>()
+            .map_err(|e| format_err!("latestEthereumBlockNumber: {}", e))?;
+
+        Ok(EthereumBlockPointer { hash, number })
+    }
+
+    fn deployment_status(&self, id: &SubgraphDeploymentId) -> Result<DeploymentStatus, Error> {
+        let subgraph_entity = self
+            .get(SubgraphDeploymentEntity::key(id.clone()))
+            .map_err(|e| format_err!("error reading subgraph entity: {}", e))?
+            .ok_or_else(|| {
+                format_err!(
+                    "could not read status of non-existent subgraph deployment {}",
+                    id
+                )
+            })?;
+
         let hash = subgraph_entity
             .get("latestEthereumBlockHash")
             .ok_or_else(|| format_err!("SubgraphDeployment is missing latestEthereumBlockHash"))?
@@ -634,49 +1463,112 @@ impl StoreTrait for Store {
             })?
             .to_u64();
 
-        Ok(EthereumBlockPointer { hash, number })
+        let flag = |name: &str| -> Result<bool, Error> {
+            subgraph_entity
+                .get(name)
+                .ok_or_else(|| format_err!("SubgraphDeployment is missing {}", name))?
+                .to_owned()
+                .as_bool()
+                .ok_or_else(|| format_err!("SubgraphDeployment has wrong type in {}", name))
+        };
+
+        Ok(DeploymentStatus {
+            latest_block: EthereumBlockPointer { hash, number },
+            chain_head_block: self.chain_head_ptr()?,
+            synced: flag("synced")?,
+            failed: flag("failed")?,
+        })
     }
 
-    fn get(&self, key: EntityKey) -> Result<Option<Entity>, QueryExecutionError> {
+    fn check_health(&self) -> Result<(), Error> {
+        // Don't wait for a connection for long if the pool is exhausted
         let conn = self
             .conn
-            .get()
-            .map_err(|e| QueryExecutionError::StoreError(e.into()))?;
-        self.get_entity(&*conn, &key.subgraph_id, &key.entity_type, &key.entity_id)
+            .get_timeout(Duration::from_secs(1))
+            .map_err(|e| format_err!("failed to get a Postgres connection: {}", e))?;
+        sql_query("SELECT 1")
+            .execute(&*conn)
+            .map_err(|e| format_err!("failed to query Postgres: {}", e))?;
+        Ok(())
+    }
+
+    fn get(&self, key: EntityKey) -> Result<Option<Entity>, QueryExecutionError> {
+        let conn = self.get_read_conn()?;
+        self.get_with_conn(&*conn, &key)
     }
 
     fn find(&self, query: EntityQuery) -> Result<Vec<Entity>, QueryExecutionError> {
-        let conn = self
-            .conn
-            .get()
-            .map_err(|e| QueryExecutionError::StoreError(e.into()))?;
-        self.execute_query(&conn, query)
+        let conn = self.get_read_conn()?;
+        self.find_with_conn(&*conn, query)
     }
 
     fn find_one(&self, mut query: EntityQuery) -> Result<Option<Entity>, QueryExecutionError> {
         if let Some(mut range) = query.range.clone() {
-            if range.first == 0 {
+            if range.first == Some(0) {
                 return Ok(None);
             }
 
-            range.first = 1;
+            // Ask for two entities so that we can tell if there is more than one
+            range.first = Some(2);
             query.range = Some(range);
         } else {
-            query.range = Some(EntityRange { first: 1, skip: 0 })
+            query.range = Some(EntityRange {
+                first: Some(2),
+                skip: 0,
+            })
         }
 
-        let conn = self
-            .conn
-            .get()
-            .map_err(|e| QueryExecutionError::StoreError(e.into()))?;
+        let conn = self.get_read_conn()?;
 
+        let entity_type = query.entity_type.clone();
         let mut results = self.execute_query(&conn, query)?;
         match results.len() {
             0 | 1 => Ok(results.pop()),
-            n => panic!("find_one query found {} results", n),
+            _ => Err(QueryExecutionError::TooManyResults(entity_type)),
         }
     }
 
+    fn count_query(&self, query: EntityQuery) -> Result<u64, QueryExecutionError> {
+        let conn = self.get_read_conn()?;
+        self.execute_count_query(&conn, query)
+    }
+
+    fn change_block(
+        &self,
+        key: EntityKey,
+    ) -> Result<Option<EthereumBlockPointer>, QueryExecutionError> {
+        use db_schema::ethereum_blocks;
+
+        let conn = self.get_read_conn()?;
+
+        // The event source of an entity is the hash of the block that last changed it;
+        // look the block up in the same query as the entity
+        let block = ethereum_blocks::table
+            .select((ethereum_blocks::hash, ethereum_blocks::number))
+            .filter(
+                sql::<Bool>("hash = (SELECT event_source FROM entities WHERE id = ")
+                    .bind::<Text, _>(&key.entity_id)
+                    .sql(" AND subgraph = ")
+                    .bind::<Text, _>(key.subgraph_id.to_string())
+                    .sql(" AND entity = ")
+                    .bind::<Text, _>(&key.entity_type)
+                    .sql(")"),
+            )
+            .first::<(String, i64)>(&*conn)
+            .optional()
+            .map_err(|e| {
+                QueryExecutionError::ResolveEntityError(
+                    key.subgraph_id.clone(),
+                    key.entity_type.clone(),
+                    key.entity_id.clone(),
+                    format!("{}", e),
+                )
+            })?;
+
+        Ok(block
+            .and_then(|(hash, number)| hash.parse::<H256>().ok().map(|hash| (hash, number).into())))
+    }
+
     fn set_block_ptr_with_no_changes(
         &self,
         subgraph_id: SubgraphDeploymentId,
@@ -710,6 +1602,15 @@ impl StoreTrait for Store {
             }
         }
 
+        // Refuse to apply an unreasonable number of operations in one transaction
+        if operations.len() > self.max_block_operations {
+            return Err(StoreError::TooManyOperations {
+                block_number: block_ptr_to.number,
+                count: operations.len(),
+                max: self.max_block_operations,
+            });
+        }
+
         // Update subgraph block pointer in same transaction
         operations.append(
             &mut SubgraphDeploymentEntity::update_ethereum_block_pointer_operations(
@@ -728,8 +1629,131 @@ impl StoreTrait for Store {
         operations: Vec<EntityOperation>,
         event_source: EventSource,
     ) -> Result<(), StoreError> {
-        let conn = self.conn.get().map_err(Error::from)?;
-        conn.transaction(|| self.apply_entity_operations_with_conn(&conn, operations, event_source))
+        // Nothing to do; don't bother the database with an empty transaction
+        if operations.is_empty() {
+            return Ok(());
+        }
+
+        // Cached schemas of deployments whose manifest is written become stale
+        let changed_manifests = operations
+            .iter()
+            .map(|op| op.entity_key())
+            .filter(|key| {
+                key.subgraph_id == *SUBGRAPHS_ID
+                    && key.entity_type == SubgraphManifestEntity::TYPENAME
+            })
+            .filter_map(|key| SubgraphManifestEntity::deployment_id(&key.entity_id))
+            .collect::<Vec<_>>();
+
+        self.block_transaction(|conn| {
+            self.apply_entity_operations_with_conn(conn, &operations, event_source)
+        })?;
+
+        for subgraph_id in changed_manifests {
+            self.invalidate_schema_cache(&subgraph_id);
+        }
+        Ok(())
+    }
+
+    fn validate_entity_operations(
+        &self,
+        operations: Vec<EntityOperation>,
+        event_source: EventSource,
+    ) -> Result<(), StoreError> {
+        let result = self.block_transaction(|conn| {
+            self.apply_entity_operations_with_conn(conn, &operations, event_source)?;
+
+            // Discard all writes
+            Err(diesel::result::Error::RollbackTransaction.into())
+        });
+
+        match result {
+            Err(StoreError::Unknown(ref e)) if is_rollback(e) => Ok(()),
+            result => result,
+        }
+    }
+
+    fn fail_subgraph(&self, id: &SubgraphDeploymentId, error: String) -> Result<(), StoreError> {
+        self.apply_entity_operations(
+            SubgraphDeploymentEntity::fail_operations(id, error),
+            EventSource::None,
+        )
+    }
+
+    fn reset_subgraph(&self, id: &SubgraphDeploymentId) -> Result<(), StoreError> {
+        use db_schema::entities;
+        use db_schema::entities::dsl;
+
+        // The subgraph of subgraphs holds the deployments themselves
+        if *id == *SUBGRAPHS_ID {
+            return Err(format_err!("the subgraph of subgraphs cannot be reset").into());
+        }
+
+        self.block_transaction(|conn| {
+            select(set_config(
+                "vars.current_event_source",
+                EventSource::None.to_string(),
+                true,
+            ))
+            .execute(conn)
+            .map_err(|e| {
+                format_err!("Failed to save event source for resetting subgraph: {}", e)
+            })?;
+
+            delete(entities::table.filter(entities::subgraph.eq(id.to_string())))
+                .execute(conn)
+                .map_err(|e| format_err!("Failed to remove entities of subgraph {}: {}", id, e))?;
+
+            // Forget the history of the subgraph as well; otherwise reverting a block
+            // after re-indexing would also undo the changes made before the reset.
+            // Events are only removed if no other subgraph has history for them,
+            // since removing an event also removes all of its history. The
+            // statements in a `WITH` all see the history from before the delete,
+            // so the history of this subgraph is excluded explicitly
+            sql_query(
+                "WITH deleted AS \
+                   (DELETE FROM entity_history WHERE subgraph = $1 RETURNING event_id) \
+                 DELETE FROM event_meta_data \
+                 WHERE id IN (SELECT event_id FROM deleted) \
+                   AND NOT EXISTS \
+                     (SELECT 1 FROM entity_history \
+                      WHERE entity_history.event_id = event_meta_data.id \
+                        AND entity_history.subgraph != $1)",
+            )
+            .bind::<Text, _>(id.to_string())
+            .execute(conn)
+            .map_err(|e| format_err!("Failed to remove history of subgraph {}: {}", id, e))?;
+
+            // Go back to the block the deployment started at; deployments created
+            // before the start block was recorded started at the genesis block
+            let start_block = dsl::entities
+                .find((
+                    id.to_string(),
+                    SUBGRAPHS_ID.to_string(),
+                    SubgraphDeploymentEntity::TYPENAME,
+                ))
+                .select((
+                    sql::<Nullable<Text>>("data->'startEthereumBlockHash'->>'data'"),
+                    sql::<Nullable<Text>>("data->'startEthereumBlockNumber'->>'data'"),
+                ))
+                .first::<(Option<String>, Option<String>)>(conn)
+                .optional()
+                .map_err(|e| format_err!("Failed to read start block of subgraph {}: {}", id, e))?;
+            let start_block_ptr = match start_block {
+                Some((Some(hash), Some(number))) => EthereumBlockPointer {
+                    hash: hash
+                        .parse::<H256>()
+                        .map_err(|e| format_err!("startEthereumBlockHash: {}", e))?,
+                    number: number
+                        .parse::<u64>()
+                        .map_err(|e| format_err!("startEthereumBlockNumber: {}", e))?,
+                },
+                _ => self.genesis_block_ptr,
+            };
+
+            let ops = SubgraphDeploymentEntity::reset_operations(id, start_block_ptr);
+            self.apply_entity_operations_with_conn(conn, &ops, EventSource::None)
+        })
     }
 
     fn revert_block_operations(
@@ -743,50 +1767,68 @@ impl StoreTrait for Store {
             panic!("revert_block_operations must revert a single block only");
         }
 
-        let conn = self.conn.get().map_err(Error::from)?;
-        conn.transaction(|| {
+        self.block_transaction(|conn| {
             let ops = SubgraphDeploymentEntity::update_ethereum_block_pointer_operations(
                 &subgraph_id,
                 block_ptr_from,
                 block_ptr_to,
             );
-            self.apply_entity_operations_with_conn(&conn, ops, EventSource::None)?;
+            self.apply_entity_operations_with_conn(conn, &ops, EventSource::None)?;
 
+            // Reverting writes to the entities table directly, which fires the same
+            // entity change notifications as any other write
             select(revert_block(
                 &block_ptr_from.hash_hex(),
                 block_ptr_from.number as i64,
                 &block_ptr_to.hash_hex(),
                 subgraph_id.to_string(),
             ))
-            .execute(&*conn)
+            .execute(conn)
             .map(|_| ())
             .map_err(|e| format_err!("Error reverting block: {}", e).into())
         })
     }
 
     fn subscribe(&self, entities: Vec<SubgraphEntityPair>) -> EntityChangeStream {
-        let subscriptions = self.subscriptions.clone();
+        self.subscribe_many(vec![entities]).pop().unwrap()
+    }
 
-        // Generate a new (unique) UUID; we're looping just to be sure we avoid collisions
-        let mut id = Uuid::new_v4().to_string();
-        while subscriptions.read().unwrap().contains_key(&id) {
-            id = Uuid::new_v4().to_string();
-        }
+    fn subscribe_many(&self, groups: Vec<Vec<SubgraphEntityPair>>) -> Vec<EntityChangeStream> {
+        self.add_subscriptions(
+            groups
+                .into_iter()
+                .map(|entities| (entities, None))
+                .collect(),
+        )
+    }
+
+    fn subscribe_to_operations(
+        &self,
+        entities: Vec<SubgraphEntityPair>,
+        operations: Option<Vec<EntityChangeOperation>>,
+    ) -> EntityChangeStream {
+        self.add_subscriptions(vec![(entities, operations)])
+            .pop()
+            .unwrap()
+    }
 
-        debug!(self.logger, "Subscribe";
-               "id" => &id,
-               "entities" => format!("{:?}", entities));
+    fn subscribe_since(&self, entities: Vec<SubgraphEntityPair>, since: u64) -> EntityChangeStream {
+        // Hold on to the recent changes until the subscription is registered, so that no
+        // change is missed or delivered twice
+        let recent_changes = self.recent_changes.lock().unwrap();
 
-        // Prepare the new subscription by creating a channel and a subscription object
-        let (sender, receiver) = channel(100);
-        let subscription = Subscription { entities, sender };
+        let missed_changes = recent_changes
+            .iter()
+            .filter(|change| change.sequence > since && change.matches(&entities))
+            .cloned()
+            .collect::<Vec<_>>();
 
-        // Add the new subscription
-        let mut subscriptions = subscriptions.write().unwrap();
-        subscriptions.insert(id, subscription);
+        debug!(self.logger, "Replay missed entity changes";
+               "since" => since,
+               "changes" => missed_changes.len());
 
-        // Return the subscription ID and entity change stream
-        Box::new(receiver)
+        let live_changes = self.subscribe(entities);
+        Box::new(stream::iter_ok(missed_changes).chain(live_changes))
     }
 
     fn count_entities(&self, subgraph_id: SubgraphDeploymentId) -> Result<u64, Error> {
@@ -798,6 +1840,38 @@ impl StoreTrait for Store {
             .get_result(&*self.conn.get()?)?;
         Ok(count as u64)
     }
+
+    fn entity_types(&self, subgraph_id: SubgraphDeploymentId) -> Result<Vec<String>, Error> {
+        use db_schema::entities::dsl::*;
+
+        entities
+            .select(entity)
+            .filter(subgraph.eq(subgraph_id.to_string()))
+            .distinct()
+            .load::<String>(&*self.conn.get()?)
+            .map_err(Error::from)
+    }
+}
+
+/// Parses a subgraph schema and turns it into the API schema used for queries.
+fn parse_api_schema(subgraph_id: &SubgraphDeploymentId, raw_schema: &str) -> Result<Schema, Error> {
+    let mut schema = Schema::parse(raw_schema, subgraph_id.clone())?;
+    schema.document = api_schema(&schema.document)?;
+    Ok(schema)
+}
+
+/// Extracts the API schema of a subgraph deployment from its manifest entity.
+fn manifest_schema(
+    subgraph_id: &SubgraphDeploymentId,
+    manifest_entity: &Entity,
+) -> Result<Schema, Error> {
+    match manifest_entity.get("schema") {
+        Some(Value::String(raw)) => parse_api_schema(subgraph_id, raw),
+        _ => Err(format_err!(
+            "Schema not present or has wrong type, subgraph: {}",
+            subgraph_id
+        )),
+    }
 }
 
 impl SubgraphDeploymentStore for Store {
@@ -873,46 +1947,115 @@ impl SubgraphDeploymentStore for Store {
             .map(|entity_opt| entity_opt.is_some())
     }
 
+    fn deployed_subgraphs(&self) -> Result<Vec<SubgraphDeploymentId>, Error> {
+        // The subgraph of subgraphs has no deployment entity, so it is not listed.
+        let query = SubgraphDeploymentEntity::query()
+            .order_by(("id".to_owned(), ValueType::String), EntityOrder::Ascending);
+        self.find(query)
+            .map_err(|e| format_err!("Failed to query SubgraphDeployment entities: {}", e))?
+            .into_iter()
+            .map(|entity| {
+                let id = entity.id()?;
+                SubgraphDeploymentId::new(id.clone())
+                    .map_err(|()| format_err!("Invalid subgraph deployment ID: {}", id))
+            })
+            .collect()
+    }
+
     fn subgraph_schema(&self, subgraph_id: SubgraphDeploymentId) -> Result<Schema, Error> {
+        // Only check out a connection if the schema is not cached yet
         if let Some(schema) = self.schema_cache.lock().unwrap().get(&subgraph_id) {
             trace!(self.logger, "schema cache hit"; "id" => subgraph_id.to_string());
-            return Ok(schema.clone());
+            return Ok(schema.as_ref().clone());
         }
-        trace!(self.logger, "schema cache miss"; "id" => subgraph_id.to_string());
 
-        let raw_schema = if subgraph_id == *SUBGRAPHS_ID {
-            // The subgraph of subgraphs schema is built-in.
-            include_str!("subgraphs.graphql").to_owned()
-        } else {
-            let manifest_entity = self
-                .get(EntityKey {
-                    subgraph_id: SUBGRAPHS_ID.clone(),
-                    entity_type: SubgraphManifestEntity::TYPENAME.to_owned(),
-                    entity_id: SubgraphManifestEntity::id(&subgraph_id),
-                })?
-                .ok_or_else(|| format_err!("Subgraph entity not found {}", subgraph_id))?;
+        let conn = self.get_read_conn()?;
+        self.subgraph_schema_with_conn(&*conn, &subgraph_id)
+            .map(|schema| schema.as_ref().clone())
+    }
 
-            match manifest_entity.get("schema") {
-                Some(Value::String(raw)) => raw.clone(),
-                _ => {
-                    return Err(format_err!(
-                        "Schema not present or has wrong type, subgraph: {}",
-                        subgraph_id
-                    ))
+    fn subgraph_schemas(
+        &self,
+        ids: &[SubgraphDeploymentId],
+    ) -> Result<HashMap<SubgraphDeploymentId, Schema>, Error> {
+        let mut schemas = HashMap::new();
+        let mut misses = vec![];
+
+        {
+            let mut cache = self.schema_cache.lock().unwrap();
+            for id in ids {
+                match cache.get(id) {
+                    Some(schema) => {
+                        schemas.insert(id.clone(), schema.as_ref().clone());
+                    }
+                    None => misses.push(id.clone()),
                 }
             }
-        };
-        let mut schema = Schema::parse(&raw_schema, subgraph_id.clone())?;
-        schema.document = api_schema(&schema.document)?;
+        }
+        trace!(self.logger, "schema cache lookup";
+               "hits" => schemas.len(),
+               "misses" => misses.len());
 
-        if !self.schema_cache.lock().unwrap().contains_key(&subgraph_id) {
-            self.schema_cache
-                .lock()
-                .unwrap()
-                .insert(subgraph_id, schema.clone());
+        if misses.is_empty() {
+            return Ok(schemas);
         }
 
-        Ok(schema)
+        let mut loaded = vec![];
+
+        // The subgraph of subgraphs schema is built-in.
+        let (builtin, misses): (Vec<_>, Vec<_>) =
+            misses.into_iter().partition(|id| *id == *SUBGRAPHS_ID);
+        if !builtin.is_empty() {
+            loaded.push(parse_api_schema(
+                &*SUBGRAPHS_ID,
+                include_str!("subgraphs.graphql"),
+            )?);
+        }
+
+        // Load the manifests of all other deployments with a single query
+        if !misses.is_empty() {
+            let manifest_ids = misses
+                .iter()
+                .map(|id| Value::from(SubgraphManifestEntity::id(id)))
+                .collect();
+            let manifest_entities = self.find(
+                SubgraphManifestEntity::query()
+                    .filter(EntityFilter::In("id".to_owned(), manifest_ids)),
+            )?;
+
+            for manifest_entity in manifest_entities {
+                let subgraph_id = SubgraphManifestEntity::deployment_id(&manifest_entity.id()?)
+                    .ok_or_else(|| format_err!("Invalid subgraph manifest entity ID"))?;
+                loaded.push(manifest_schema(&subgraph_id, &manifest_entity)?);
+            }
+
+            if let Some(missing) = misses
+                .iter()
+                .find(|id| !loaded.iter().any(|schema| schema.id == **id))
+            {
+                return Err(format_err!("Subgraph entity not found {}", missing));
+            }
+        }
+
+        let mut cache = self.schema_cache.lock().unwrap();
+        for schema in loaded {
+            cache.insert(schema.id.clone(), Arc::new(schema.clone()));
+            schemas.insert(schema.id.clone(), schema);
+        }
+
+        Ok(schemas)
+    }
+
+    fn invalidate_schema_cache(&self, subgraph_id: &SubgraphDeploymentId) {
+        if self
+            .schema_cache
+            .lock()
+            .unwrap()
+            .remove(subgraph_id)
+            .is_some()
+        {
+            trace!(self.logger, "schema cache invalidated"; "id" => subgraph_id.to_string());
+        }
     }
 }
 
@@ -959,13 +2102,67 @@ impl ChainStore for Store {
         }))
     }
 
+    fn insert_new_blocks<'a, B, E>(
+        &self,
+        blocks: B,
+    ) -> Box<Future<Item = usize, Error = E> + Send + 'a>
+    where
+        B: Stream<Item = EthereumBlock, Error = E> + Send + 'a,
+        E: From<Error> + Send + 'a,
+    {
+        use db_schema::ethereum_blocks::dsl::*;
+
+        let conn = self.conn.clone();
+        let net_name = self.network_name.clone();
+        Box::new(blocks.fold(0, move |inserted, block| {
+            let json_blob = serde_json::to_value(&block).expect("Failed to serialize block");
+            let values = (
+                hash.eq(format!("{:x}", block.block.hash.unwrap())),
+                number.eq(block.block.number.unwrap().as_u64() as i64),
+                parent_hash.eq(format!("{:x}", block.block.parent_hash)),
+                network_name.eq(&net_name),
+                data.eq(json_blob),
+            );
+
+            // Blocks are immutable, so there is no need to write blocks we already have
+            insert_into(ethereum_blocks)
+                .values(values)
+                .on_conflict(hash)
+                .do_nothing()
+                .execute(&*conn.get().map_err(Error::from)?)
+                .map_err(Error::from)
+                .map_err(E::from)
+                .map(|rows| inserted + rows)
+        }))
+    }
+
     fn attempt_chain_head_update(&self, ancestor_count: u64) -> Result<Vec<H256>, Error> {
+        use db_schema::ethereum_blocks::dsl::*;
+
+        if ancestor_count == 0 {
+            return Err(format_err!(
+                "Cannot update the chain head without checking at least one ancestor"
+            ));
+        }
+
+        let conn = self.conn.get()?;
+
+        // The candidate head block cannot have more ancestors than its block number
+        let candidate_number = ethereum_blocks
+            .filter(network_name.eq(&self.network_name))
+            .select(diesel::dsl::max(number))
+            .first::<Option<i64>>(&*conn)?;
+        let ancestor_count = match candidate_number {
+            Some(n) if (n as u64) < ancestor_count => n as u64,
+            _ => ancestor_count,
+        };
+
         // Call attempt_head_update SQL function
-        select(attempt_chain_head_update(
+        let missing_blocks = select(attempt_chain_head_update(
             &self.network_name,
             ancestor_count as i64,
         ))
-        .load(&*self.conn.get()?)
+        .load(&*conn)
         .map_err(Error::from)
         // We got a single return value, but it's returned generically as a set of rows
         .map(|mut rows: Vec<_>| {
@@ -979,7 +2176,15 @@ impl ChainStore for Store {
                 .map(|h| h.parse())
                 .collect::<Result<Vec<H256>, _>>()
         })
-        .and_then(|r| r.map_err(Error::from))
+        .and_then(|r| r.map_err(Error::from))?;
+
+        if !missing_blocks.is_empty() {
+            debug!(self.logger, "Chain head update is waiting for missing blocks";
+                   "ancestor_count" => ancestor_count,
+                   "missing_blocks" => format!("{:?}", missing_blocks));
+        }
+
+        Ok(missing_blocks)
     }
 
     fn chain_head_updates(&self) -> Self::ChainHeadUpdateListener {
@@ -1024,6 +2229,32 @@ impl ChainStore for Store {
             .map_err(Error::from)
     }
 
+    fn blocks(&self, block_hashes: &[H256]) -> Result<Vec<EthereumBlock>, Error> {
+        use db_schema::ethereum_blocks::dsl::*;
+
+        let hashes: Vec<String> = block_hashes
+            .iter()
+            .map(|block_hash| format!("{:x}", block_hash))
+            .collect();
+
+        let mut json_blocks: HashMap<String, serde_json::Value> = ethereum_blocks
+            .select((hash, data))
+            .filter(network_name.eq(&self.network_name))
+            .filter(hash.eq_any(&hashes))
+            .load::<(String, serde_json::Value)>(&*self.conn.get()?)?
+            .into_iter()
+            .collect();
+
+        // Return the blocks in the order in which they were asked for
+        hashes
+            .iter()
+            .filter_map(|block_hash| json_blocks.remove(block_hash))
+            .map(|json_block| {
+                serde_json::from_value::<EthereumBlock>(json_block).map_err(Error::from)
+            })
+            .collect()
+    }
+
     fn ancestor_block(
         &self,
         block_ptr: EthereumBlockPointer,
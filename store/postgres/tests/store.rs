@@ -6,18 +6,27 @@ extern crate graph;
 extern crate graph_store_postgres;
 extern crate hex;
 
+use diesel::dsl::sql;
 use diesel::pg::PgConnection;
+use diesel::sql_types::{Integer, Text};
 use diesel::*;
 use std::fmt::Debug;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-use graph::components::store::{EntityFilter, EntityKey, EntityOrder, EntityQuery, EntityRange};
+use graph::components::store::{
+    EntityFilter, EntityKey, EntityNullsOrder, EntityOrder, EntityQuery, EntityRange,
+};
 use graph::data::store::scalar;
-use graph::data::subgraph::schema::SubgraphDeploymentEntity;
+use graph::data::subgraph::schema::{
+    SubgraphDeploymentEntity, SubgraphManifestEntity, TypedEntity, SUBGRAPHS_ID,
+};
 use graph::prelude::*;
+use graph::slog::{Drain, Never, OwnedKVList, Record};
 use graph::web3::types::H256;
-use graph_store_postgres::{db_schema, Store as DieselStore, StoreConfig};
+use graph_store_postgres::{db_schema, IsolationLevel, Store as DieselStore, StoreConfig};
 
 /// Helper function to ensure and obtain the Postgres URL to use for testing.
 fn postgres_test_url() -> String {
@@ -27,6 +36,26 @@ fn postgres_test_url() -> String {
         .unwrap()
 }
 
+/// Returns the store configuration that tests use unless they need something else.
+fn test_store_config() -> StoreConfig {
+    StoreConfig {
+        postgres_url: postgres_test_url(),
+        network_name: "fake_network".to_owned(),
+        isolation_level: IsolationLevel::default(),
+        schema_cache_capacity: 100,
+        conn_pool_size: 10,
+        read_conn_reserve: 2,
+        read_conn_timeout: Duration::from_millis(500),
+        log_queries: false,
+        slow_query_threshold: None,
+        unbounded_query_limit: None,
+        max_block_operations: 1_000_000,
+        subscription_cleanup_interval: Duration::from_secs(5),
+        replay_buffer_size: 100,
+        notification_namespace: None,
+    }
+}
+
 lazy_static! {
     static ref TEST_MUTEX: Mutex<()> = Mutex::new(());
     static ref TEST_SUBGRAPH_ID: SubgraphDeploymentId =
@@ -75,6 +104,18 @@ lazy_static! {
 
 /// Test harness for running database integration tests.
 fn run_test<R, F>(test: F)
+where
+    F: FnOnce(Arc<DieselStore>) -> R + Send + 'static,
+    R: IntoFuture + Send + 'static,
+    R::Item: Send,
+    R::Error: Send + Debug,
+    R::Future: Send,
+{
+    run_test_with_config(test_store_config(), test)
+}
+
+/// Like `run_test`, but with a store created from `config`.
+fn run_test_with_config<R, F>(config: StoreConfig, test: F)
 where
     F: FnOnce(Arc<DieselStore>) -> R + Send + 'static,
     R: IntoFuture + Send + 'static,
@@ -93,20 +134,13 @@ where
         .block_on(future::lazy(move || {
             // Set up Store
             let logger = Logger::root(slog::Discard, o!());
-            let postgres_url = postgres_test_url();
             let net_identifiers = EthereumNetworkIdentifier {
                 net_version: "graph test suite".to_owned(),
                 genesis_block_hash: TEST_BLOCK_0_PTR.hash,
             };
-            let network_name = "fake_network".to_owned();
-            let store = Arc::new(DieselStore::new(
-                StoreConfig {
-                    postgres_url,
-                    network_name,
-                },
-                &logger,
-                net_identifiers,
-            ));
+            let store = Arc::new(
+                DieselStore::new(config, &logger, net_identifiers).expect("Failed to create store"),
+            );
 
             // Reset state before starting
             remove_test_data();
@@ -322,6 +356,38 @@ fn get_entity() {
     })
 }
 
+#[test]
+fn get_entity_returns_fields_in_a_stable_order() {
+    run_test(|store| -> Result<(), ()> {
+        let key = EntityKey {
+            subgraph_id: TEST_SUBGRAPH_ID.clone(),
+            entity_type: "user".to_owned(),
+            entity_id: "1".to_owned(),
+        };
+        let fields = |entity: Entity| -> Vec<String> { entity.keys().cloned().collect() };
+
+        let first = fields(store.get(key.clone()).unwrap().unwrap());
+        let second = fields(store.get(key).unwrap().unwrap());
+
+        assert_eq!(first, second);
+        assert_eq!(
+            first,
+            vec![
+                "age",
+                "bin_name",
+                "coffee",
+                "email",
+                "favorite_color",
+                "id",
+                "name",
+                "weight",
+            ]
+        );
+
+        Ok(())
+    })
+}
+
 #[test]
 fn insert_entity() {
     run_test(|store| -> Result<(), ()> {
@@ -480,6 +546,260 @@ fn test_find(expected_entity_ids: Vec<&str>, query: EntityQuery) {
     })
 }
 
+#[test]
+fn find_one_fails_if_more_than_one_entity_matches() {
+    run_test(|store| -> Result<(), ()> {
+        let user_query = |filter| EntityQuery {
+            subgraph_id: TEST_SUBGRAPH_ID.clone(),
+            entity_type: "user".to_owned(),
+            filter: Some(filter),
+            order_by: None,
+            order_direction: None,
+            order_nulls: None,
+            range: None,
+            as_of_block: None,
+        };
+
+        // Only user 2 drinks coffee, users 1 and 3 don't
+        let entity = store
+            .find_one(user_query(EntityFilter::Equal(
+                "coffee".into(),
+                true.into(),
+            )))
+            .expect("find_one failed for a single match");
+        assert_eq!(entity.unwrap().id().unwrap(), "2");
+
+        match store.find_one(user_query(EntityFilter::Equal(
+            "coffee".into(),
+            false.into(),
+        ))) {
+            Err(QueryExecutionError::TooManyResults(ref entity_type)) => {
+                assert_eq!(entity_type, "user")
+            }
+            result => panic!("expected a TooManyResults error, got {:?}", result),
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn explain_query_returns_the_sql_find_runs() {
+    run_test(|store| -> Result<(), ()> {
+        let sql = store
+            .explain_query(
+                EntityQuery::new(TEST_SUBGRAPH_ID.clone(), "user")
+                    .filter(EntityFilter::Equal("name".into(), "Johnton".into()))
+                    .order_by(("age".to_owned(), ValueType::Int), EntityOrder::Descending)
+                    .range(EntityRange {
+                        first: Some(5),
+                        skip: 0,
+                    }),
+            )
+            .expect("failed to explain query");
+
+        assert!(sql.contains("->> 'data' = $"), sql);
+        assert!(sql.contains("::bigint DESC NULLS LAST"), sql);
+        assert!(sql.contains("LIMIT"), sql);
+        assert!(sql.contains("\"Johnton\""), sql);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn find_across_subgraphs_returns_entities_of_all_subgraphs() {
+    run_test(|store| -> Result<(), ()> {
+        let other_subgraph_id = SubgraphDeploymentId::new("AcrossSubgraphsTestSubgraph").unwrap();
+        let manifest = SubgraphManifest {
+            id: other_subgraph_id.clone(),
+            location: "/ipfs/test".to_owned(),
+            spec_version: "1".to_owned(),
+            description: None,
+            repository: None,
+            schema: Schema::parse("scalar Foo", other_subgraph_id.clone()).unwrap(),
+            data_sources: vec![],
+        };
+
+        // Create SubgraphDeploymentEntity
+        store
+            .apply_entity_operations(
+                SubgraphDeploymentEntity::new(&manifest, false, false, *TEST_BLOCK_0_PTR, 1)
+                    .create_operations(&other_subgraph_id),
+                EventSource::None,
+            )
+            .unwrap();
+
+        // Add a user to the other subgraph
+        store
+            .apply_entity_operations(
+                vec![EntityOperation::Set {
+                    key: EntityKey {
+                        subgraph_id: other_subgraph_id.clone(),
+                        entity_type: "user".to_owned(),
+                        entity_id: "4".to_owned(),
+                    },
+                    data: Entity::from(vec![("id", Value::from("4"))]),
+                }],
+                EventSource::None,
+            )
+            .unwrap();
+
+        let query = EntityQuery::new(TEST_SUBGRAPH_ID.clone(), "user");
+
+        // `find` only returns users of the subgraph of the query
+        let ids: Vec<String> = store
+            .find(query.clone())
+            .expect("failed to find users")
+            .iter()
+            .map(|user| user.id().unwrap())
+            .collect();
+        assert_eq!(ids.len(), 3);
+        assert!(!ids.contains(&"4".to_owned()));
+
+        // `find_across_subgraphs` returns the users of both subgraphs
+        let mut users: Vec<(SubgraphDeploymentId, String)> = store
+            .find_across_subgraphs(query)
+            .expect("failed to find users across subgraphs")
+            .into_iter()
+            .map(|(subgraph_id, user)| (subgraph_id, user.id().unwrap()))
+            .collect();
+        users.sort();
+        let mut expected = vec![
+            (TEST_SUBGRAPH_ID.clone(), "1".to_owned()),
+            (TEST_SUBGRAPH_ID.clone(), "2".to_owned()),
+            (TEST_SUBGRAPH_ID.clone(), "3".to_owned()),
+            (other_subgraph_id, "4".to_owned()),
+        ];
+        expected.sort();
+        assert_eq!(users, expected);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn check_health_succeeds_when_postgres_is_reachable() {
+    run_test(|store| -> Result<(), ()> {
+        store.check_health().expect("store is not healthy");
+        Ok(())
+    })
+}
+
+#[test]
+fn find_is_limited_by_the_store() {
+    let config = StoreConfig {
+        unbounded_query_limit: Some(2),
+        ..test_store_config()
+    };
+    run_test_with_config(config, |store| -> Result<(), ()> {
+        let user_ids = |range| {
+            store
+                .find(
+                    EntityQuery::new(TEST_SUBGRAPH_ID.clone(), "user")
+                        .order_by(("id".to_owned(), ValueType::String), EntityOrder::Ascending)
+                        .range(range),
+                )
+                .unwrap()
+                .into_iter()
+                .map(|entity| entity.id().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // The test store returns at most two entities for queries without `first`
+        assert_eq!(
+            user_ids(EntityRange {
+                first: None,
+                skip: 0
+            }),
+            vec!["1", "2"]
+        );
+        assert_eq!(
+            user_ids(EntityRange {
+                first: None,
+                skip: 1
+            }),
+            vec!["2", "3"]
+        );
+
+        // An explicit `first` is clamped to the limit
+        assert_eq!(
+            user_ids(EntityRange {
+                first: Some(3),
+                skip: 0
+            }),
+            vec!["1", "2"]
+        );
+        assert_eq!(
+            user_ids(EntityRange {
+                first: Some(1),
+                skip: 1
+            }),
+            vec!["2"]
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn find_by_block_entities_were_updated_at() {
+    run_test(|store| -> Result<(), ()> {
+        // Update user 2 in block 4; users 1 and 3 were last updated in blocks 1 and 3
+        let mut entity = Entity::new();
+        entity.set("id", "2");
+        entity.set("name", "Cindini");
+        store
+            .transact_block_operations(
+                TEST_SUBGRAPH_ID.clone(),
+                *TEST_BLOCK_3_PTR,
+                *TEST_BLOCK_4_PTR,
+                vec![EntityOperation::Set {
+                    key: EntityKey {
+                        subgraph_id: TEST_SUBGRAPH_ID.clone(),
+                        entity_type: "user".to_owned(),
+                        entity_id: "2".to_owned(),
+                    },
+                    data: entity,
+                }],
+            )
+            .unwrap();
+
+        let find_ids = |query: EntityQuery| -> Vec<String> {
+            store
+                .find(query)
+                .expect("store.find failed to execute query")
+                .into_iter()
+                .map(|entity| entity.id().unwrap())
+                .collect()
+        };
+        let user_query = || {
+            EntityQuery::new(TEST_SUBGRAPH_ID.clone(), "user").order_by(
+                (UPDATED_AT_BLOCK_ATTRIBUTE.to_owned(), ValueType::Int),
+                EntityOrder::Descending,
+            )
+        };
+
+        assert_eq!(find_ids(user_query()), vec!["2", "3", "1"]);
+        assert_eq!(
+            find_ids(user_query().filter(EntityFilter::GreaterOrEqual(
+                UPDATED_AT_BLOCK_ATTRIBUTE.to_owned(),
+                Value::Int(3),
+            ))),
+            vec!["2", "3"]
+        );
+        assert_eq!(
+            find_ids(user_query().filter(EntityFilter::Equal(
+                UPDATED_AT_BLOCK_ATTRIBUTE.to_owned(),
+                Value::Int(1),
+            ))),
+            vec!["1"]
+        );
+
+        Ok(())
+    })
+}
+
 #[test]
 fn find_string_contains() {
     test_find(
@@ -493,11 +813,69 @@ fn find_string_contains() {
             )])),
             order_by: None,
             order_direction: None,
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
 
+#[test]
+fn find_array_contains() {
+    run_test(|store| -> Result<(), ()> {
+        let tagged = |id: &str, tags: Vec<&str>| {
+            let mut entity = Entity::new();
+            entity.set("id", id);
+            entity.set(
+                "tags",
+                Value::List(tags.into_iter().map(Value::from).collect()),
+            );
+            EntityOperation::Set {
+                key: EntityKey {
+                    subgraph_id: TEST_SUBGRAPH_ID.clone(),
+                    entity_type: "Tagged".to_owned(),
+                    entity_id: id.to_owned(),
+                },
+                data: entity,
+            }
+        };
+        store
+            .apply_entity_operations(
+                vec![
+                    tagged("1", vec!["red", "green"]),
+                    tagged("2", vec!["green"]),
+                    tagged("3", vec![]),
+                ],
+                EventSource::None,
+            )
+            .unwrap();
+
+        let find_tagged = |tag: Value| -> Vec<String> {
+            let query = EntityQuery::new(TEST_SUBGRAPH_ID.clone(), "Tagged")
+                .filter(EntityFilter::ArrayContains("tags".to_owned(), tag))
+                .order_by(("id".to_owned(), ValueType::String), EntityOrder::Ascending);
+            store
+                .find(query)
+                .expect("store.find failed to execute query")
+                .into_iter()
+                .map(|entity| entity.id().unwrap())
+                .collect()
+        };
+
+        assert_eq!(find_tagged(Value::from("red")), vec!["1".to_owned()]);
+        assert_eq!(
+            find_tagged(Value::from("green")),
+            vec!["1".to_owned(), "2".to_owned()]
+        );
+        assert!(find_tagged(Value::from("blue")).is_empty());
+
+        // Elements only match values of the same type
+        assert!(find_tagged(Value::from(true)).is_empty());
+
+        Ok(())
+    })
+}
+
 #[test]
 fn find_string_equal() {
     test_find(
@@ -511,11 +889,154 @@ fn find_string_equal() {
             )])),
             order_by: None,
             order_direction: None,
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
 
+#[test]
+fn find_order_by_referenced_entity_field() {
+    run_test(|store| -> Result<(), ()> {
+        // Declare that users reference a team
+        let mut manifest = Entity::new();
+        manifest.set(
+            "schema",
+            "type Team @entity { id: ID!, name: String! } \
+             type user @entity { id: ID!, name: String!, team: Team }",
+        );
+        let mut ops = vec![EntityOperation::Set {
+            key: EntityKey {
+                subgraph_id: SUBGRAPHS_ID.clone(),
+                entity_type: SubgraphManifestEntity::TYPENAME.to_owned(),
+                entity_id: SubgraphManifestEntity::id(&TEST_SUBGRAPH_ID),
+            },
+            data: manifest,
+        }];
+
+        for (user_id, team_id, team_name) in vec![
+            ("1", "t1", "Bees"),
+            ("2", "t2", "Zebras"),
+            ("3", "t3", "Antelopes"),
+        ] {
+            let mut team = Entity::new();
+            team.set("id", team_id);
+            team.set("name", team_name);
+            ops.push(EntityOperation::Set {
+                key: EntityKey {
+                    subgraph_id: TEST_SUBGRAPH_ID.clone(),
+                    entity_type: "Team".to_owned(),
+                    entity_id: team_id.to_owned(),
+                },
+                data: team,
+            });
+
+            let mut user = Entity::new();
+            user.set("team", team_id);
+            ops.push(EntityOperation::Set {
+                key: EntityKey {
+                    subgraph_id: TEST_SUBGRAPH_ID.clone(),
+                    entity_type: "user".to_owned(),
+                    entity_id: user_id.to_owned(),
+                },
+                data: user,
+            });
+        }
+        store
+            .apply_entity_operations(ops, EventSource::None)
+            .unwrap();
+
+        let query = |attribute: &str, direction| EntityQuery {
+            subgraph_id: TEST_SUBGRAPH_ID.clone(),
+            entity_type: "user".to_owned(),
+            filter: None,
+            order_by: Some((attribute.to_owned(), ValueType::String)),
+            order_direction: Some(direction),
+            order_nulls: None,
+            range: None,
+            as_of_block: None,
+        };
+        let ids = |entities: Vec<Entity>| -> Vec<String> {
+            entities.iter().map(|entity| entity.id().unwrap()).collect()
+        };
+
+        let users = store
+            .find(query("team.name", EntityOrder::Ascending))
+            .unwrap();
+        assert_eq!(ids(users), vec!["3", "1", "2"]);
+
+        let users = store
+            .find(query("team.name", EntityOrder::Descending))
+            .unwrap();
+        assert_eq!(ids(users), vec!["2", "1", "3"]);
+
+        // Only a single level of references is supported
+        match store.find(query("team.captain.name", EntityOrder::Ascending)) {
+            Err(QueryExecutionError::OrderByNotSupportedError(entity, field)) => {
+                assert_eq!(entity, "user");
+                assert_eq!(field, "team.captain.name");
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn find_fulltext_ranks_by_relevance() {
+    run_test(|store| -> Result<(), ()> {
+        let ops = vec![
+            ("p1", "A pasta recipe with tomatoes"),
+            ("p2", "Tomatoes, tomatoes and more tomatoes"),
+            ("p3", "How to grow cucumbers"),
+            (
+                "p4",
+                "Growing tomatoes on a balcony, and why tomatoes need sun",
+            ),
+        ]
+        .into_iter()
+        .map(|(id, text)| {
+            let mut post = Entity::new();
+            post.set("id", id);
+            post.set("text", text);
+            EntityOperation::Set {
+                key: EntityKey {
+                    subgraph_id: TEST_SUBGRAPH_ID.clone(),
+                    entity_type: "Post".to_owned(),
+                    entity_id: id.to_owned(),
+                },
+                data: post,
+            }
+        })
+        .collect();
+        store
+            .apply_entity_operations(ops, EventSource::None)
+            .unwrap();
+
+        let posts = store
+            .find(EntityQuery {
+                subgraph_id: TEST_SUBGRAPH_ID.clone(),
+                entity_type: "Post".to_owned(),
+                filter: Some(EntityFilter::Fulltext("text".to_owned(), "tomato".into())),
+                order_by: None,
+                order_direction: None,
+                order_nulls: None,
+                range: None,
+                as_of_block: None,
+            })
+            .unwrap();
+        let ids: Vec<String> = posts.iter().map(|post| post.id().unwrap()).collect();
+
+        // Posts mentioning tomatoes more often rank higher; the post about
+        // cucumbers doesn't match at all
+        assert_eq!(ids, vec!["p2", "p4", "p1"]);
+
+        Ok(())
+    })
+}
+
 #[test]
 fn find_string_not_equal() {
     test_find(
@@ -529,11 +1050,55 @@ fn find_string_not_equal() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Ascending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
 
+fn favorite_color_query(direction: EntityOrder, nulls: Option<EntityNullsOrder>) -> EntityQuery {
+    EntityQuery {
+        subgraph_id: TEST_SUBGRAPH_ID.clone(),
+        entity_type: "user".to_owned(),
+        filter: None,
+        order_by: Some(("favorite_color".to_owned(), ValueType::String)),
+        order_direction: Some(direction),
+        order_nulls: nulls,
+        range: None,
+        as_of_block: None,
+    }
+}
+
+#[test]
+fn find_order_by_places_nulls_last_by_default() {
+    // User 1 has no favorite color
+    test_find(
+        vec!["3", "2", "1"],
+        favorite_color_query(EntityOrder::Ascending, None),
+    );
+    test_find(
+        vec!["2", "3", "1"],
+        favorite_color_query(EntityOrder::Descending, None),
+    );
+}
+
+#[test]
+fn find_order_by_places_nulls_where_requested() {
+    test_find(
+        vec!["1", "3", "2"],
+        favorite_color_query(EntityOrder::Ascending, Some(EntityNullsOrder::NullsFirst)),
+    );
+    test_find(
+        vec!["1", "2", "3"],
+        favorite_color_query(EntityOrder::Descending, Some(EntityNullsOrder::NullsFirst)),
+    );
+    test_find(
+        vec!["2", "3", "1"],
+        favorite_color_query(EntityOrder::Descending, Some(EntityNullsOrder::NullsLast)),
+    );
+}
+
 #[test]
 fn find_string_greater_than() {
     test_find(
@@ -547,7 +1112,9 @@ fn find_string_greater_than() {
             )])),
             order_by: None,
             order_direction: None,
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -565,7 +1132,9 @@ fn find_string_less_than_order_by_asc() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Ascending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -583,7 +1152,9 @@ fn find_string_less_than_order_by_desc() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -601,7 +1172,12 @@ fn find_string_less_than_range() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
-            range: Some(EntityRange { first: 1, skip: 1 }),
+            order_nulls: None,
+            range: Some(EntityRange {
+                first: Some(1),
+                skip: 1,
+            }),
+            as_of_block: None,
         },
     )
 }
@@ -619,7 +1195,9 @@ fn find_string_multiple_and() {
             ])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -637,7 +1215,9 @@ fn find_string_ends_with() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -655,7 +1235,9 @@ fn find_string_not_ends_with() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -673,7 +1255,9 @@ fn find_string_in() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -691,7 +1275,9 @@ fn find_string_not_in() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -709,7 +1295,9 @@ fn find_float_equal() {
             )])),
             order_by: None,
             order_direction: None,
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -727,7 +1315,9 @@ fn find_float_not_equal() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -745,7 +1335,9 @@ fn find_float_greater_than() {
             )])),
             order_by: None,
             order_direction: None,
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -763,7 +1355,9 @@ fn find_float_less_than() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Ascending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -781,7 +1375,9 @@ fn find_float_less_than_order_by_desc() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -799,7 +1395,12 @@ fn find_float_less_than_range() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
-            range: Some(EntityRange { first: 1, skip: 1 }),
+            order_nulls: None,
+            range: Some(EntityRange {
+                first: Some(1),
+                skip: 1,
+            }),
+            as_of_block: None,
         },
     )
 }
@@ -817,7 +1418,12 @@ fn find_float_in() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
-            range: Some(EntityRange { first: 5, skip: 0 }),
+            order_nulls: None,
+            range: Some(EntityRange {
+                first: Some(5),
+                skip: 0,
+            }),
+            as_of_block: None,
         },
     )
 }
@@ -835,7 +1441,12 @@ fn find_float_not_in() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
-            range: Some(EntityRange { first: 5, skip: 0 }),
+            order_nulls: None,
+            range: Some(EntityRange {
+                first: Some(5),
+                skip: 0,
+            }),
+            as_of_block: None,
         },
     )
 }
@@ -853,7 +1464,9 @@ fn find_int_equal() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -871,7 +1484,9 @@ fn find_int_not_equal() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -889,7 +1504,9 @@ fn find_int_greater_than() {
             )])),
             order_by: None,
             order_direction: None,
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -907,7 +1524,9 @@ fn find_int_greater_or_equal() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Ascending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -925,7 +1544,9 @@ fn find_int_less_than() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Ascending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -943,7 +1564,9 @@ fn find_int_less_or_equal() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Ascending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -961,7 +1584,9 @@ fn find_int_less_than_order_by_desc() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -979,7 +1604,12 @@ fn find_int_less_than_range() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
-            range: Some(EntityRange { first: 1, skip: 1 }),
+            order_nulls: None,
+            range: Some(EntityRange {
+                first: Some(1),
+                skip: 1,
+            }),
+            as_of_block: None,
         },
     )
 }
@@ -997,7 +1627,12 @@ fn find_int_in() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
-            range: Some(EntityRange { first: 5, skip: 0 }),
+            order_nulls: None,
+            range: Some(EntityRange {
+                first: Some(5),
+                skip: 0,
+            }),
+            as_of_block: None,
         },
     )
 }
@@ -1015,7 +1650,12 @@ fn find_int_not_in() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
-            range: Some(EntityRange { first: 5, skip: 0 }),
+            order_nulls: None,
+            range: Some(EntityRange {
+                first: Some(5),
+                skip: 0,
+            }),
+            as_of_block: None,
         },
     )
 }
@@ -1033,7 +1673,9 @@ fn find_bool_equal() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -1051,7 +1693,9 @@ fn find_bool_not_equal() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Ascending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -1069,7 +1713,12 @@ fn find_bool_in() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
-            range: Some(EntityRange { first: 5, skip: 0 }),
+            order_nulls: None,
+            range: Some(EntityRange {
+                first: Some(5),
+                skip: 0,
+            }),
+            as_of_block: None,
         },
     )
 }
@@ -1087,7 +1736,12 @@ fn find_bool_not_in() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
-            range: Some(EntityRange { first: 5, skip: 0 }),
+            order_nulls: None,
+            range: Some(EntityRange {
+                first: Some(5),
+                skip: 0,
+            }),
+            as_of_block: None,
         },
     )
 }
@@ -1105,7 +1759,9 @@ fn find_bytes_equal() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -1123,7 +1779,9 @@ fn find_null_equal() {
             )),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -1138,7 +1796,9 @@ fn find_null_not_equal() {
             filter: Some(EntityFilter::Not("favorite_color".to_owned(), Value::Null)),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -1156,7 +1816,9 @@ fn find_null_not_in() {
             )),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -1171,7 +1833,9 @@ fn find_order_by_float() {
             filter: None,
             order_by: Some(("weight".to_owned(), ValueType::Float)),
             order_direction: Some(EntityOrder::Ascending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     );
     test_find(
@@ -1182,7 +1846,9 @@ fn find_order_by_float() {
             filter: None,
             order_by: Some(("weight".to_owned(), ValueType::Float)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     );
 }
@@ -1197,7 +1863,9 @@ fn find_order_by_id() {
             filter: None,
             order_by: Some(("id".to_owned(), ValueType::ID)),
             order_direction: Some(EntityOrder::Ascending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     );
     test_find(
@@ -1208,7 +1876,9 @@ fn find_order_by_id() {
             filter: None,
             order_by: Some(("id".to_owned(), ValueType::ID)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     );
 }
@@ -1223,7 +1893,9 @@ fn find_order_by_int() {
             filter: None,
             order_by: Some(("age".to_owned(), ValueType::Int)),
             order_direction: Some(EntityOrder::Ascending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     );
     test_find(
@@ -1234,7 +1906,9 @@ fn find_order_by_int() {
             filter: None,
             order_by: Some(("age".to_owned(), ValueType::Int)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     );
 }
@@ -1249,7 +1923,9 @@ fn find_order_by_string() {
             filter: None,
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Ascending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     );
     test_find(
@@ -1260,7 +1936,9 @@ fn find_order_by_string() {
             filter: None,
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     );
 }
@@ -1278,7 +1956,9 @@ fn find_where_nested_and_or() {
             ])])),
             order_by: Some(("id".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Ascending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         },
     )
 }
@@ -1295,7 +1975,9 @@ fn revert_block() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         };
 
         // Revert block 3
@@ -1336,7 +2018,9 @@ fn revert_block_with_delete() {
             )])),
             order_by: Some(("name".to_owned(), ValueType::String)),
             order_direction: Some(EntityOrder::Descending),
+            order_nulls: None,
             range: None,
+            as_of_block: None,
         };
 
         // Delete entity with id=2
@@ -1438,6 +2122,39 @@ fn revert_block_with_partial_update() {
     })
 }
 
+#[test]
+fn deployed_subgraphs_lists_all_deployments() {
+    run_test(|store| -> Result<(), ()> {
+        let subgraph_id = SubgraphDeploymentId::new("DeployedSubgraphsTestSubgraph").unwrap();
+        let manifest = SubgraphManifest {
+            id: subgraph_id.clone(),
+            location: "/ipfs/test".to_owned(),
+            spec_version: "1".to_owned(),
+            description: None,
+            repository: None,
+            schema: Schema::parse("scalar Foo", subgraph_id.clone()).unwrap(),
+            data_sources: vec![],
+        };
+
+        // Create a SubgraphDeploymentEntity in addition to the one of the test subgraph
+        store
+            .apply_entity_operations(
+                SubgraphDeploymentEntity::new(&manifest, false, false, *TEST_BLOCK_0_PTR, 1)
+                    .create_operations(&subgraph_id),
+                EventSource::None,
+            )
+            .unwrap();
+
+        let mut deployed = store.deployed_subgraphs().unwrap();
+        deployed.sort();
+        let mut expected = vec![subgraph_id, TEST_SUBGRAPH_ID.clone()];
+        expected.sort();
+        assert_eq!(deployed, expected);
+
+        Ok(())
+    })
+}
+
 #[test]
 fn entity_changes_are_fired_and_forwarded_to_subscriptions() {
     run_test(|store| {
@@ -1551,24 +2268,28 @@ fn entity_changes_are_fired_and_forwarded_to_subscriptions() {
                             entity_type: "User".to_owned(),
                             entity_id: added_entities[0].clone().0,
                             operation: EntityChangeOperation::Added,
+                            sequence: 0,
                         },
                         EntityChange {
                             subgraph_id: subgraph_id.clone(),
                             entity_type: "User".to_owned(),
                             entity_id: added_entities[1].clone().0,
                             operation: EntityChangeOperation::Added,
+                            sequence: 0,
                         },
                         EntityChange {
                             subgraph_id: subgraph_id.clone(),
                             entity_type: "User".to_owned(),
                             entity_id: "1".to_owned(),
                             operation: EntityChangeOperation::Updated,
+                            sequence: 0,
                         },
                         EntityChange {
                             subgraph_id: subgraph_id.clone(),
                             entity_type: "User".to_owned(),
                             entity_id: added_entities[1].clone().0,
                             operation: EntityChangeOperation::Removed,
+                            sequence: 0,
                         },
                     ]
                 );
@@ -1578,3 +2299,1850 @@ fn entity_changes_are_fired_and_forwarded_to_subscriptions() {
             .and_then(|_| Ok(()))
     })
 }
+
+#[test]
+fn reverted_entity_changes_are_forwarded_to_subscriptions() {
+    run_test(|store| {
+        let subgraph_id = SubgraphDeploymentId::new("RevertChangeTestSubgraph").unwrap();
+        let manifest = SubgraphManifest {
+            id: subgraph_id.clone(),
+            location: "/ipfs/test".to_owned(),
+            spec_version: "1".to_owned(),
+            description: None,
+            repository: None,
+            schema: Schema::parse("scalar Foo", subgraph_id.clone()).unwrap(),
+            data_sources: vec![],
+        };
+
+        // Create SubgraphDeploymentEntity
+        store
+            .apply_entity_operations(
+                SubgraphDeploymentEntity::new(&manifest, false, false, *TEST_BLOCK_0_PTR, 1)
+                    .create_operations(&subgraph_id),
+                EventSource::None,
+            )
+            .unwrap();
+
+        let subscription = store.subscribe(vec![(subgraph_id.clone(), "User".to_owned())]);
+
+        // Add an entity in one block, then revert that block
+        let key = EntityKey {
+            subgraph_id: subgraph_id.clone(),
+            entity_type: "User".to_owned(),
+            entity_id: "1".to_owned(),
+        };
+        store
+            .transact_block_operations(
+                subgraph_id.clone(),
+                *TEST_BLOCK_0_PTR,
+                *TEST_BLOCK_1_PTR,
+                vec![EntityOperation::Set {
+                    key: key.clone(),
+                    data: Entity::from(vec![("id", Value::from("1"))]),
+                }],
+            )
+            .unwrap();
+        store
+            .revert_block_operations(subgraph_id.clone(), *TEST_BLOCK_1_PTR, *TEST_BLOCK_0_PTR)
+            .unwrap();
+        assert!(store.get(key.clone()).unwrap().is_none());
+
+        subscription
+            .take(2)
+            .collect()
+            .and_then(move |changes| {
+                // Keep the store around until we're done reading from it
+                let _store = store;
+
+                assert_eq!(
+                    changes,
+                    vec![
+                        EntityChange::from_key(key.clone(), EntityChangeOperation::Added),
+                        EntityChange::from_key(key, EntityChangeOperation::Removed),
+                    ]
+                );
+
+                Ok(())
+            })
+            .and_then(|_| Ok(()))
+    })
+}
+
+#[test]
+fn removing_a_missing_entity_emits_no_entity_change() {
+    run_test(|store| {
+        let subgraph_id = SubgraphDeploymentId::new("MissingRemoveTestSubgraph").unwrap();
+        let manifest = SubgraphManifest {
+            id: subgraph_id.clone(),
+            location: "/ipfs/test".to_owned(),
+            spec_version: "1".to_owned(),
+            description: None,
+            repository: None,
+            schema: Schema::parse("scalar Foo", subgraph_id.clone()).unwrap(),
+            data_sources: vec![],
+        };
+
+        // Create SubgraphDeploymentEntity
+        store
+            .apply_entity_operations(
+                SubgraphDeploymentEntity::new(&manifest, false, false, *TEST_BLOCK_0_PTR, 1)
+                    .create_operations(&subgraph_id),
+                EventSource::None,
+            )
+            .unwrap();
+
+        let subscription = store.subscribe(vec![(subgraph_id.clone(), "User".to_owned())]);
+
+        let key = |id: &str| EntityKey {
+            subgraph_id: subgraph_id.clone(),
+            entity_type: "User".to_owned(),
+            entity_id: id.to_owned(),
+        };
+
+        // Remove an entity that doesn't exist, then add one
+        store
+            .apply_entity_operations(
+                vec![EntityOperation::Remove {
+                    key: key("missing"),
+                }],
+                EventSource::None,
+            )
+            .unwrap();
+        store
+            .apply_entity_operations(
+                vec![EntityOperation::Set {
+                    key: key("1"),
+                    data: Entity::from(vec![("id", Value::from("1"))]),
+                }],
+                EventSource::None,
+            )
+            .unwrap();
+
+        // The first change is the addition, not the removal
+        let added = EntityChange::from_key(key("1"), EntityChangeOperation::Added);
+        subscription
+            .take(1)
+            .collect()
+            .and_then(move |changes| {
+                // Keep the store around until we're done reading from it
+                let _store = store;
+
+                assert_eq!(changes, vec![added]);
+
+                Ok(())
+            })
+            .and_then(|_| Ok(()))
+    })
+}
+
+#[test]
+fn subscriptions_only_receive_changes_of_the_requested_operations() {
+    run_test(|store| {
+        let subgraph_id = SubgraphDeploymentId::new("OperationsTestSubgraph").unwrap();
+        let manifest = SubgraphManifest {
+            id: subgraph_id.clone(),
+            location: "/ipfs/test".to_owned(),
+            spec_version: "1".to_owned(),
+            description: None,
+            repository: None,
+            schema: Schema::parse("scalar Foo", subgraph_id.clone()).unwrap(),
+            data_sources: vec![],
+        };
+
+        // Create SubgraphDeploymentEntity
+        store
+            .apply_entity_operations(
+                SubgraphDeploymentEntity::new(&manifest, false, false, *TEST_BLOCK_0_PTR, 1)
+                    .create_operations(&subgraph_id),
+                EventSource::None,
+            )
+            .unwrap();
+
+        let subscription = store.subscribe_to_operations(
+            vec![(subgraph_id.clone(), "User".to_owned())],
+            Some(vec![EntityChangeOperation::Added]),
+        );
+
+        let key = |id: &str| EntityKey {
+            subgraph_id: subgraph_id.clone(),
+            entity_type: "User".to_owned(),
+            entity_id: id.to_owned(),
+        };
+        let user = |id: &str, name: &str| {
+            Entity::from(vec![("id", Value::from(id)), ("name", Value::from(name))])
+        };
+
+        // Add a user, update it, then add another user
+        for (id, name) in vec![("1", "Alice"), ("1", "Alicia"), ("2", "Bob")] {
+            store
+                .apply_entity_operations(
+                    vec![EntityOperation::Set {
+                        key: key(id),
+                        data: user(id, name),
+                    }],
+                    EventSource::None,
+                )
+                .unwrap();
+        }
+
+        // The update is not delivered
+        let expected = vec![
+            EntityChange::from_key(key("1"), EntityChangeOperation::Added),
+            EntityChange::from_key(key("2"), EntityChangeOperation::Added),
+        ];
+        subscription
+            .take(2)
+            .collect()
+            .and_then(move |changes| {
+                // Keep the store around until we're done reading from it
+                let _store = store;
+
+                assert_eq!(changes, expected);
+
+                Ok(())
+            })
+            .and_then(|_| Ok(()))
+    })
+}
+
+#[test]
+fn stores_only_receive_changes_from_their_notification_namespace() {
+    run_test(|store| {
+        let logger = Logger::root(slog::Discard, o!());
+        let config = |notification_namespace: Option<&str>| StoreConfig {
+            conn_pool_size: 2,
+            read_conn_reserve: 0,
+            notification_namespace: notification_namespace.map(String::from),
+            ..test_store_config()
+        };
+        let net_identifiers = || EthereumNetworkIdentifier {
+            net_version: "graph test suite".to_owned(),
+            genesis_block_hash: TEST_BLOCK_0_PTR.hash,
+        };
+
+        // Namespaces end up in `LISTEN` statements and must be plain identifiers
+        assert!(DieselStore::new(config(Some("other; DROP")), &logger, net_identifiers()).is_err());
+
+        let other_store = Arc::new(
+            DieselStore::new(config(Some("other_instance")), &logger, net_identifiers())
+                .expect("Failed to create store"),
+        );
+
+        let subscription = store.subscribe(vec![(TEST_SUBGRAPH_ID.clone(), "user".to_owned())]);
+        let other_subscription =
+            other_store.subscribe(vec![(TEST_SUBGRAPH_ID.clone(), "user".to_owned())]);
+
+        let key = |id: &str| EntityKey {
+            subgraph_id: TEST_SUBGRAPH_ID.clone(),
+            entity_type: "user".to_owned(),
+            entity_id: id.to_owned(),
+        };
+        let set_user = |store: &DieselStore, id: &str| {
+            store
+                .apply_entity_operations(
+                    vec![EntityOperation::Set {
+                        key: key(id),
+                        data: Entity::from(vec![("id", Value::from(id))]),
+                    }],
+                    EventSource::None,
+                )
+                .unwrap();
+        };
+
+        // Each store only sees the change written through it
+        set_user(&other_store, "10");
+        set_user(&store, "11");
+
+        let expected = vec![EntityChange::from_key(
+            key("11"),
+            EntityChangeOperation::Added,
+        )];
+        let other_expected = vec![EntityChange::from_key(
+            key("10"),
+            EntityChangeOperation::Added,
+        )];
+        subscription
+            .take(1)
+            .collect()
+            .join(other_subscription.take(1).collect())
+            .and_then(move |(changes, other_changes)| {
+                // Keep the stores around until we're done reading from them
+                let _stores = (store, other_store);
+
+                assert_eq!(changes, expected);
+                assert_eq!(other_changes, other_expected);
+
+                Ok(())
+            })
+            .and_then(|_| Ok(()))
+    })
+}
+
+#[test]
+fn subscribe_many_returns_a_stream_per_group() {
+    run_test(|store| {
+        let subgraph_id = SubgraphDeploymentId::new("SubscribeManyTestSubgraph").unwrap();
+        let manifest = SubgraphManifest {
+            id: subgraph_id.clone(),
+            location: "/ipfs/test".to_owned(),
+            spec_version: "1".to_owned(),
+            description: None,
+            repository: None,
+            schema: Schema::parse("scalar Foo", subgraph_id.clone()).unwrap(),
+            data_sources: vec![],
+        };
+
+        // Create SubgraphDeploymentEntity
+        store
+            .apply_entity_operations(
+                SubgraphDeploymentEntity::new(&manifest, false, false, *TEST_BLOCK_0_PTR, 1)
+                    .create_operations(&subgraph_id),
+                EventSource::None,
+            )
+            .unwrap();
+
+        let entity_types = vec!["User", "Post", "Comment"];
+        let mut streams = store.subscribe_many(
+            entity_types
+                .iter()
+                .map(|entity_type| vec![(subgraph_id.clone(), entity_type.to_string())])
+                .collect(),
+        );
+        assert_eq!(streams.len(), entity_types.len());
+
+        // Add one entity of each type, in reverse order
+        let key = |entity_type: &str| EntityKey {
+            subgraph_id: subgraph_id.clone(),
+            entity_type: entity_type.to_owned(),
+            entity_id: "1".to_owned(),
+        };
+        for entity_type in entity_types.iter().rev() {
+            store
+                .apply_entity_operations(
+                    vec![EntityOperation::Set {
+                        key: key(entity_type),
+                        data: Entity::from(vec![("id", Value::from("1"))]),
+                    }],
+                    EventSource::None,
+                )
+                .unwrap();
+        }
+
+        // Each stream receives the change for the entity type of its group
+        let expected: Vec<_> = entity_types
+            .iter()
+            .map(|entity_type| {
+                EntityChange::from_key(key(entity_type), EntityChangeOperation::Added)
+            })
+            .collect();
+        let comments = streams.pop().unwrap();
+        let posts = streams.pop().unwrap();
+        let users = streams.pop().unwrap();
+        users
+            .take(1)
+            .chain(posts.take(1))
+            .chain(comments.take(1))
+            .collect()
+            .and_then(move |changes| {
+                // Keep the store around until we're done reading from it
+                let _store = store;
+
+                assert_eq!(changes, expected);
+
+                Ok(())
+            })
+            .and_then(|_| Ok(()))
+    })
+}
+
+#[test]
+fn subscribe_since_replays_missed_changes_first() {
+    run_test(|store| {
+        let subgraph_id = SubgraphDeploymentId::new("ReplayTestSubgraph").unwrap();
+        let manifest = SubgraphManifest {
+            id: subgraph_id.clone(),
+            location: "/ipfs/test".to_owned(),
+            spec_version: "1".to_owned(),
+            description: None,
+            repository: None,
+            schema: Schema::parse("scalar Foo", subgraph_id.clone()).unwrap(),
+            data_sources: vec![],
+        };
+
+        // Create SubgraphDeploymentEntity
+        store
+            .apply_entity_operations(
+                SubgraphDeploymentEntity::new(&manifest, false, false, *TEST_BLOCK_0_PTR, 1)
+                    .create_operations(&subgraph_id),
+                EventSource::None,
+            )
+            .unwrap();
+
+        let entities = vec![(subgraph_id.clone(), "User".to_owned())];
+        let subscription = store.subscribe(entities.clone());
+
+        let key = move |id: &str| EntityKey {
+            subgraph_id: subgraph_id.clone(),
+            entity_type: "User".to_owned(),
+            entity_id: id.to_owned(),
+        };
+        let expected = vec![key("2"), key("3"), key("4")]
+            .into_iter()
+            .map(|key| EntityChange::from_key(key, EntityChangeOperation::Added))
+            .collect::<Vec<_>>();
+        let add = move |store: &Arc<DieselStore>, id: &str| {
+            store
+                .apply_entity_operations(
+                    vec![EntityOperation::Set {
+                        key: key(id),
+                        data: Entity::from(vec![("id", Value::from(id))]),
+                    }],
+                    EventSource::None,
+                )
+                .unwrap();
+        };
+
+        add(&store, "1");
+        add(&store, "2");
+        add(&store, "3");
+
+        subscription
+            .take(3)
+            .collect()
+            .and_then(move |changes| {
+                // Resubscribe as if only the first change had been seen
+                let resumed = store.subscribe_since(entities, changes[0].sequence);
+                add(&store, "4");
+
+                resumed.take(3).collect().map(move |resumed_changes| {
+                    // Keep the store around until we're done reading from it
+                    let _store = store;
+
+                    // The missed changes are replayed with their original sequence
+                    // numbers, followed by the live change
+                    assert_eq!(resumed_changes[0].sequence, changes[1].sequence);
+                    assert_eq!(resumed_changes[1].sequence, changes[2].sequence);
+                    assert!(resumed_changes[2].sequence > changes[2].sequence);
+                    assert_eq!(resumed_changes, expected);
+                })
+            })
+            .and_then(|_| Ok(()))
+    })
+}
+
+#[test]
+fn enum_fields_only_accept_members_of_the_enum() {
+    run_test(|store| -> Result<(), ()> {
+        let subgraph_id = SubgraphDeploymentId::new("EnumTestSubgraph").unwrap();
+        let manifest = SubgraphManifest {
+            id: subgraph_id.clone(),
+            location: "/ipfs/test".to_owned(),
+            spec_version: "1".to_owned(),
+            description: None,
+            repository: None,
+            schema: Schema::parse(
+                "
+                enum Color { RED, GREEN }
+
+                type Paint @entity {
+                  id: ID!
+                  color: Color
+                  mixedFrom: [Color!]
+                }
+                ",
+                subgraph_id.clone(),
+            )
+            .unwrap(),
+            data_sources: vec![],
+        };
+
+        // Create SubgraphDeploymentEntity
+        store
+            .apply_entity_operations(
+                SubgraphDeploymentEntity::new(&manifest, false, false, *TEST_BLOCK_0_PTR, 1)
+                    .create_operations(&subgraph_id),
+                EventSource::None,
+            )
+            .unwrap();
+
+        let set_paint = |color: &str, mixed_from: Vec<&str>| {
+            store.apply_entity_operations(
+                vec![EntityOperation::Set {
+                    key: EntityKey {
+                        subgraph_id: subgraph_id.clone(),
+                        entity_type: "Paint".to_owned(),
+                        entity_id: "1".to_owned(),
+                    },
+                    data: Entity::from(vec![
+                        ("id", Value::from("1")),
+                        ("color", Value::from(color)),
+                        (
+                            "mixedFrom",
+                            Value::List(mixed_from.into_iter().map(Value::from).collect()),
+                        ),
+                    ]),
+                }],
+                EventSource::None,
+            )
+        };
+
+        assert!(set_paint("RED", vec!["RED", "GREEN"]).is_ok());
+        assert!(set_paint("PURPLE", vec!["RED"]).is_err());
+        assert!(set_paint("GREEN", vec!["RED", "BLUE"]).is_err());
+
+        // Only the valid write was applied
+        let paint = store
+            .get(EntityKey {
+                subgraph_id: subgraph_id.clone(),
+                entity_type: "Paint".to_owned(),
+                entity_id: "1".to_owned(),
+            })
+            .unwrap()
+            .unwrap();
+        assert_eq!(paint.get("color"), Some(&Value::from("RED")));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn get_and_find_fill_in_defaults() {
+    run_test(|store| -> Result<(), ()> {
+        let subgraph_id = SubgraphDeploymentId::new("DefaultsTestSubgraph").unwrap();
+        let manifest = SubgraphManifest {
+            id: subgraph_id.clone(),
+            location: "/ipfs/test".to_owned(),
+            spec_version: "1".to_owned(),
+            description: None,
+            repository: None,
+            schema: Schema::parse(
+                "
+                type Account @entity {
+                  id: ID!
+                  status: String! @default(value: \"active\")
+                }
+                ",
+                subgraph_id.clone(),
+            )
+            .unwrap(),
+            data_sources: vec![],
+        };
+
+        // Create SubgraphDeploymentEntity
+        store
+            .apply_entity_operations(
+                SubgraphDeploymentEntity::new(&manifest, false, false, *TEST_BLOCK_0_PTR, 1)
+                    .create_operations(&subgraph_id),
+                EventSource::None,
+            )
+            .unwrap();
+
+        // Write an account without a status
+        let key = EntityKey {
+            subgraph_id: subgraph_id.clone(),
+            entity_type: "Account".to_owned(),
+            entity_id: "1".to_owned(),
+        };
+        store
+            .apply_entity_operations(
+                vec![EntityOperation::Set {
+                    key: key.clone(),
+                    data: Entity::from(vec![("id", Value::from("1"))]),
+                }],
+                EventSource::None,
+            )
+            .unwrap();
+
+        let account = store.get(key).unwrap().unwrap();
+        assert_eq!(account.get("status"), Some(&Value::from("active")));
+
+        let accounts = store
+            .find(EntityQuery {
+                subgraph_id: subgraph_id.clone(),
+                entity_type: "Account".to_owned(),
+                filter: None,
+                order_by: None,
+                order_direction: None,
+                order_nulls: None,
+                range: None,
+                as_of_block: None,
+            })
+            .unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].get("status"), Some(&Value::from("active")));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn wildcard_subscriptions_receive_changes_for_all_entity_types() {
+    run_test(|store| {
+        let subgraph_id = SubgraphDeploymentId::new("WildcardSubscriptionTestSubgraph").unwrap();
+        let manifest = SubgraphManifest {
+            id: subgraph_id.clone(),
+            location: "/ipfs/test".to_owned(),
+            spec_version: "1".to_owned(),
+            description: None,
+            repository: None,
+            schema: Schema::parse("scalar Foo", subgraph_id.clone()).unwrap(),
+            data_sources: vec![],
+        };
+
+        // Create SubgraphDeploymentEntity
+        store
+            .apply_entity_operations(
+                SubgraphDeploymentEntity::new(&manifest, false, false, *TEST_BLOCK_0_PTR, 1)
+                    .create_operations(&subgraph_id),
+                EventSource::None,
+            )
+            .unwrap();
+
+        // Subscribe to all entity types of the subgraph
+        let subscription = store.subscribe_all(subgraph_id.clone());
+
+        // Add entities of two different types
+        let ops = vec![("User", "1"), ("Cat", "2")]
+            .into_iter()
+            .map(|(entity_type, id)| EntityOperation::Set {
+                key: EntityKey {
+                    subgraph_id: subgraph_id.clone(),
+                    entity_type: entity_type.to_owned(),
+                    entity_id: id.to_owned(),
+                },
+                data: Entity::from(vec![("id", Value::from(id))]),
+            })
+            .collect();
+        store
+            .transact_block_operations(
+                subgraph_id.clone(),
+                *TEST_BLOCK_0_PTR,
+                *TEST_BLOCK_1_PTR,
+                ops,
+            )
+            .unwrap();
+
+        subscription
+            .take(2)
+            .collect()
+            .and_then(move |changes| {
+                // Keep the store around until we're done reading from it
+                let _store = store;
+
+                assert_eq!(
+                    changes,
+                    vec![
+                        EntityChange {
+                            subgraph_id: subgraph_id.clone(),
+                            entity_type: "User".to_owned(),
+                            entity_id: "1".to_owned(),
+                            operation: EntityChangeOperation::Added,
+                            sequence: 0,
+                        },
+                        EntityChange {
+                            subgraph_id: subgraph_id.clone(),
+                            entity_type: "Cat".to_owned(),
+                            entity_id: "2".to_owned(),
+                            operation: EntityChangeOperation::Added,
+                            sequence: 0,
+                        },
+                    ]
+                );
+
+                Ok(())
+            })
+            .and_then(|_| Ok(()))
+    })
+}
+
+#[test]
+fn entity_types_lists_types_with_data() {
+    run_test(|store| -> Result<(), ()> {
+        let cat_op = EntityOperation::Set {
+            key: EntityKey {
+                subgraph_id: TEST_SUBGRAPH_ID.clone(),
+                entity_type: "cat".to_owned(),
+                entity_id: "c1".to_owned(),
+            },
+            data: Entity::from(vec![
+                ("id", Value::from("c1")),
+                ("name", Value::from("Tom")),
+            ]),
+        };
+        store
+            .transact_block_operations(
+                TEST_SUBGRAPH_ID.clone(),
+                *TEST_BLOCK_3_PTR,
+                *TEST_BLOCK_4_PTR,
+                vec![cat_op],
+            )
+            .unwrap();
+
+        // Each type is listed once, no matter how many entities it has
+        let mut entity_types = store.entity_types(TEST_SUBGRAPH_ID.clone()).unwrap();
+        entity_types.sort();
+        assert_eq!(entity_types, vec!["cat".to_owned(), "user".to_owned()]);
+
+        // A deployment without any entities has no entity types
+        let empty_id = SubgraphDeploymentId::new("emptysubgraph").unwrap();
+        assert_eq!(store.entity_types(empty_id).unwrap(), Vec::<String>::new());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn count_query_applies_filter() {
+    run_test(|store| -> Result<(), ()> {
+        let query = EntityQuery::new(TEST_SUBGRAPH_ID.clone(), "user")
+            .filter(EntityFilter::And(vec![EntityFilter::Equal(
+                "coffee".to_owned(),
+                Value::Bool(false),
+            )]))
+            .order_by(
+                ("name".to_owned(), ValueType::String),
+                EntityOrder::Ascending,
+            )
+            .range(EntityRange {
+                first: Some(1),
+                skip: 0,
+            });
+
+        // The page is limited by the range, the count is not
+        assert_eq!(store.find(query.clone()).unwrap().len(), 1);
+        assert_eq!(store.count_query(query).unwrap(), 2);
+
+        // Without a filter, all users are counted
+        let all_users = EntityQuery::new(TEST_SUBGRAPH_ID.clone(), "user");
+        assert_eq!(store.count_query(all_users).unwrap(), 3);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn block_transactions_use_configured_isolation_level() {
+    run_test(|_| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let store = DieselStore::new(
+            StoreConfig {
+                isolation_level: IsolationLevel::RepeatableRead,
+                ..test_store_config()
+            },
+            &logger,
+            EthereumNetworkIdentifier {
+                net_version: "graph test suite".to_owned(),
+                genesis_block_hash: TEST_BLOCK_0_PTR.hash,
+            },
+        )
+        .expect("Failed to create store");
+
+        let isolation_level = store
+            .block_transaction(|conn| {
+                select(sql::<Text>("current_setting('transaction_isolation')"))
+                    .get_result::<String>(conn)
+                    .map_err(StoreError::from)
+            })
+            .unwrap();
+        assert_eq!(isolation_level, "repeatable read");
+
+        Ok(())
+    })
+}
+
+#[test]
+fn blocks_with_too_many_operations_are_rejected() {
+    run_test(|_| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let store = DieselStore::new(
+            StoreConfig {
+                max_block_operations: 1,
+                ..test_store_config()
+            },
+            &logger,
+            EthereumNetworkIdentifier {
+                net_version: "graph test suite".to_owned(),
+                genesis_block_hash: TEST_BLOCK_0_PTR.hash,
+            },
+        )
+        .expect("Failed to create store");
+
+        let operations = vec![
+            create_test_entity(
+                "4",
+                "user",
+                "Tom",
+                "tom@email.com",
+                35 as i32,
+                170.2 as f32,
+                true,
+                None,
+            ),
+            create_test_entity(
+                "5",
+                "user",
+                "Pat",
+                "pat@email.com",
+                52 as i32,
+                160.5 as f32,
+                false,
+                None,
+            ),
+        ];
+        match store.transact_block_operations(
+            TEST_SUBGRAPH_ID.clone(),
+            *TEST_BLOCK_3_PTR,
+            *TEST_BLOCK_4_PTR,
+            operations,
+        ) {
+            Err(StoreError::TooManyOperations {
+                block_number,
+                count,
+                max,
+            }) => {
+                assert_eq!(block_number, TEST_BLOCK_4_PTR.number);
+                assert_eq!(count, 2);
+                assert_eq!(max, 1);
+            }
+            result => panic!("expected a TooManyOperations error, got {:?}", result),
+        }
+
+        // Nothing was written and the subgraph did not advance
+        let key = EntityKey {
+            subgraph_id: TEST_SUBGRAPH_ID.clone(),
+            entity_type: "user".to_owned(),
+            entity_id: "4".to_owned(),
+        };
+        assert!(store.get(key).unwrap().is_none());
+        assert_eq!(
+            store.block_ptr(TEST_SUBGRAPH_ID.clone()).unwrap(),
+            *TEST_BLOCK_3_PTR
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn block_transactions_are_retried_after_serialization_failures() {
+    run_test(|store| -> Result<(), ()> {
+        let other_conn = PgConnection::establish(postgres_test_url().as_str())
+            .expect("Failed to connect to Postgres");
+        other_conn
+            .execute(
+                "create table if not exists serialization_retry_test \
+                 (id integer primary key, value integer not null); \
+                 delete from serialization_retry_test; \
+                 insert into serialization_retry_test values (1, 0)",
+            )
+            .expect("Failed to create test table");
+
+        let attempts = AtomicUsize::new(0);
+        store
+            .block_transaction(|conn| {
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+
+                // Take the transaction snapshot
+                conn.execute("select value from serialization_retry_test")?;
+
+                // Simulate a conflicting write by another transaction, but only
+                // during the first attempt
+                if attempt == 0 {
+                    other_conn
+                        .execute("update serialization_retry_test set value = value + 1")
+                        .expect("Failed to write concurrently");
+                }
+
+                conn.execute("update serialization_retry_test set value = value + 10")?;
+                Ok(())
+            })
+            .expect("Failed to retry block transaction");
+        assert_eq!(2, attempts.load(Ordering::SeqCst));
+
+        let value = select(sql::<Integer>(
+            "(select value from serialization_retry_test where id = 1)",
+        ))
+        .get_result::<i32>(&other_conn)
+        .unwrap();
+        assert_eq!(11, value);
+
+        other_conn
+            .execute("drop table serialization_retry_test")
+            .unwrap();
+        Ok(())
+    })
+}
+
+#[test]
+fn reads_with_conn_see_writes_of_the_same_transaction() {
+    run_test(|store| -> Result<(), ()> {
+        use db_schema::entities;
+
+        let key = EntityKey {
+            subgraph_id: TEST_SUBGRAPH_ID.clone(),
+            entity_type: "user".to_owned(),
+            entity_id: "1".to_owned(),
+        };
+        let query = EntityQuery {
+            subgraph_id: TEST_SUBGRAPH_ID.clone(),
+            entity_type: "user".to_owned(),
+            filter: None,
+            order_by: None,
+            order_direction: None,
+            order_nulls: None,
+            range: None,
+            as_of_block: None,
+        };
+
+        let result = store.block_transaction(|conn| {
+            // Delete a user inside the transaction
+            delete(
+                entities::table
+                    .filter(entities::id.eq("1"))
+                    .filter(entities::entity.eq("user")),
+            )
+            .execute(conn)?;
+
+            // The transaction no longer sees the user, other connections still do
+            assert_eq!(store.get_with_conn(conn, &key).unwrap(), None);
+            assert_eq!(store.find_with_conn(conn, query.clone()).unwrap().len(), 2);
+            assert!(store.get(key.clone()).unwrap().is_some());
+            assert_eq!(store.find(query.clone()).unwrap().len(), 3);
+
+            // Roll back
+            Err::<(), _>(format_err!("rolling back").into())
+        });
+        assert!(result.is_err());
+        assert!(store.get(key.clone()).unwrap().is_some());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn find_page_tells_whether_there_is_a_next_page() {
+    run_test(|store| -> Result<(), ()> {
+        let page = |first| {
+            let (entities, has_next_page) = store
+                .find_page(
+                    EntityQuery::new(TEST_SUBGRAPH_ID.clone(), "user")
+                        .order_by(
+                            ("name".to_owned(), ValueType::String),
+                            EntityOrder::Ascending,
+                        )
+                        .range(EntityRange {
+                            first: Some(first),
+                            skip: 0,
+                        }),
+                )
+                .unwrap();
+            (entities.len(), has_next_page)
+        };
+
+        // There are three users
+        assert_eq!(page(2), (2, true));
+        assert_eq!(page(3), (3, false));
+        assert_eq!(page(4), (3, false));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn find_as_of_block_leaves_out_entities_written_later() {
+    run_test(|store| -> Result<(), ()> {
+        use db_schema::ethereum_blocks;
+
+        // Make the blocks in which users were written known to the store
+        let conn = PgConnection::establish(postgres_test_url().as_str())
+            .expect("Failed to connect to Postgres");
+        let blocks = vec![*TEST_BLOCK_1_PTR, *TEST_BLOCK_2_PTR, *TEST_BLOCK_3_PTR];
+        for block in blocks.iter() {
+            insert_into(ethereum_blocks::table)
+                .values((
+                    ethereum_blocks::hash.eq(block.hash_hex()),
+                    ethereum_blocks::number.eq(block.number as i64),
+                    ethereum_blocks::network_name.eq("fake_network"),
+                    ethereum_blocks::data.eq(graph::serde_json::Value::Object(Default::default())),
+                ))
+                .on_conflict_do_nothing()
+                .execute(&conn)
+                .expect("Failed to insert test block");
+        }
+
+        let ids_as_of = |block_number| -> Vec<String> {
+            let mut ids: Vec<String> = store
+                .find(EntityQuery::new(TEST_SUBGRAPH_ID.clone(), "user").as_of_block(block_number))
+                .unwrap()
+                .iter()
+                .map(|entity| entity.id().unwrap())
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        // User 1 was written in block 1, user 2 in block 2 and user 3 last in block 3
+        assert_eq!(ids_as_of(1), vec!["1"]);
+        assert_eq!(ids_as_of(2), vec!["1", "2"]);
+        assert_eq!(ids_as_of(3), vec!["1", "2", "3"]);
+
+        // Counts agree with the entities that are found
+        let count = store
+            .count_query(EntityQuery::new(TEST_SUBGRAPH_ID.clone(), "user").as_of_block(2))
+            .unwrap();
+        assert_eq!(count, 2);
+
+        for block in blocks.iter() {
+            delete(ethereum_blocks::table.filter(ethereum_blocks::hash.eq(block.hash_hex())))
+                .execute(&conn)
+                .expect("Failed to remove test block");
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn change_block_is_the_block_that_last_changed_the_entity() {
+    run_test(|store| -> Result<(), ()> {
+        use db_schema::ethereum_blocks;
+
+        // Make block 2 known to the store, but not block 3
+        let conn = PgConnection::establish(postgres_test_url().as_str())
+            .expect("Failed to connect to Postgres");
+        insert_into(ethereum_blocks::table)
+            .values((
+                ethereum_blocks::hash.eq(TEST_BLOCK_2_PTR.hash_hex()),
+                ethereum_blocks::number.eq(TEST_BLOCK_2_PTR.number as i64),
+                ethereum_blocks::network_name.eq("fake_network"),
+                ethereum_blocks::data.eq(graph::serde_json::Value::Object(Default::default())),
+            ))
+            .on_conflict_do_nothing()
+            .execute(&conn)
+            .expect("Failed to insert test block");
+
+        let user_key = |id: &str| EntityKey {
+            subgraph_id: TEST_SUBGRAPH_ID.clone(),
+            entity_type: "user".to_owned(),
+            entity_id: id.to_owned(),
+        };
+
+        // User 2 was written in block 2
+        let change_block = store.change_block(user_key("2")).unwrap();
+        assert_eq!(change_block, Some(*TEST_BLOCK_2_PTR));
+        assert_eq!(change_block.unwrap().number, 2);
+
+        // User 3 was last changed in block 3, which is unknown to the store
+        assert_eq!(store.change_block(user_key("3")).unwrap(), None);
+
+        // Entities that don't exist have no change block
+        assert_eq!(store.change_block(user_key("4")).unwrap(), None);
+
+        delete(
+            ethereum_blocks::table.filter(ethereum_blocks::hash.eq(TEST_BLOCK_2_PTR.hash_hex())),
+        )
+        .execute(&conn)
+        .expect("Failed to remove test block");
+
+        Ok(())
+    })
+}
+
+#[test]
+fn updating_manifest_invalidates_cached_schema() {
+    run_test(|store| -> Result<(), ()> {
+        let has_token_type = |schema: &Schema| schema.document.to_string().contains("type Token");
+
+        // Load the schema, which caches it
+        let schema = store.subgraph_schema(TEST_SUBGRAPH_ID.clone()).unwrap();
+        assert!(!has_token_type(&schema));
+
+        // Write a new schema to the deployment's manifest
+        let mut manifest = Entity::new();
+        manifest.set("schema", "type Token @entity { id: ID! }");
+        store
+            .apply_entity_operations(
+                vec![EntityOperation::Set {
+                    key: EntityKey {
+                        subgraph_id: SUBGRAPHS_ID.clone(),
+                        entity_type: SubgraphManifestEntity::TYPENAME.to_owned(),
+                        entity_id: SubgraphManifestEntity::id(&TEST_SUBGRAPH_ID),
+                    },
+                    data: manifest,
+                }],
+                EventSource::None,
+            )
+            .unwrap();
+
+        // The next lookup reflects the new schema
+        let schema = store.subgraph_schema(TEST_SUBGRAPH_ID.clone()).unwrap();
+        assert!(has_token_type(&schema));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn reads_wait_for_a_connection_only_as_long_as_the_read_timeout() {
+    run_test(|_| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let create_store = |read_conn_reserve| {
+            DieselStore::new(
+                StoreConfig {
+                    conn_pool_size: 1,
+                    read_conn_reserve,
+                    read_conn_timeout: Duration::from_millis(100),
+                    ..test_store_config()
+                },
+                &logger,
+                EthereumNetworkIdentifier {
+                    net_version: "graph test suite".to_owned(),
+                    genesis_block_hash: TEST_BLOCK_0_PTR.hash,
+                },
+            )
+            .expect("Failed to create store")
+        };
+        let key = EntityKey {
+            subgraph_id: TEST_SUBGRAPH_ID.clone(),
+            entity_type: "user".to_owned(),
+            entity_id: "1".to_owned(),
+        };
+
+        // Without a reserve, reads fail after the timeout while a writer holds the only
+        // connection
+        let store = create_store(0);
+        store
+            .block_transaction(|_| {
+                let start = Instant::now();
+                match store.get(key.clone()) {
+                    Err(QueryExecutionError::StoreBusy) => (),
+                    result => panic!("Expected the store to be busy, got: {:?}", result),
+                }
+                assert!(start.elapsed() >= Duration::from_millis(100));
+                assert!(start.elapsed() < Duration::from_secs(5));
+                Ok(())
+            })
+            .unwrap();
+
+        // Once the writer is done, reads succeed again
+        assert!(store.get(key.clone()).unwrap().is_some());
+
+        // With a reserve, reads succeed while a writer holds the only shared connection
+        let store = create_store(1);
+        store
+            .block_transaction(|_| {
+                assert!(store.get(key.clone()).unwrap().is_some());
+                Ok(())
+            })
+            .unwrap();
+
+        Ok(())
+    })
+}
+
+#[test]
+fn applying_no_entity_operations_does_not_use_a_connection() {
+    run_test(|_| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let store = DieselStore::new(
+            StoreConfig {
+                conn_pool_size: 1,
+                read_conn_reserve: 0,
+                read_conn_timeout: Duration::from_millis(100),
+                ..test_store_config()
+            },
+            &logger,
+            EthereumNetworkIdentifier {
+                net_version: "graph test suite".to_owned(),
+                genesis_block_hash: TEST_BLOCK_0_PTR.hash,
+            },
+        )
+        .expect("Failed to create store");
+
+        // Holding the only connection would make any transaction wait for it
+        store
+            .block_transaction(|_| {
+                let start = Instant::now();
+                store.apply_entity_operations(vec![], EventSource::None)?;
+                assert!(start.elapsed() < Duration::from_secs(5));
+                Ok(())
+            })
+            .unwrap();
+
+        Ok(())
+    })
+}
+
+/// A log drain that remembers the messages of all records logged to it.
+#[derive(Clone, Default)]
+struct LogMessages(Arc<Mutex<Vec<String>>>);
+
+impl LogMessages {
+    fn contains(&self, message: &str) -> bool {
+        self.0.lock().unwrap().iter().any(|m| m == message)
+    }
+}
+
+impl Drain for LogMessages {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &Record, _: &OwnedKVList) -> Result<(), Never> {
+        self.0.lock().unwrap().push(record.msg().to_string());
+        Ok(())
+    }
+}
+
+#[test]
+fn overwriting_an_entity_from_a_later_block_is_logged() {
+    run_test(|_| -> Result<(), ()> {
+        let messages = LogMessages::default();
+        let logger = Logger::root(messages.clone(), o!());
+        let store = DieselStore::new(
+            StoreConfig {
+                ..test_store_config()
+            },
+            &logger,
+            EthereumNetworkIdentifier {
+                net_version: "graph test suite".to_owned(),
+                genesis_block_hash: TEST_BLOCK_0_PTR.hash,
+            },
+        )
+        .expect("Failed to create store");
+        let warning = "Entity written in a later block is overwritten by an earlier block";
+
+        // User 1 was last written in block 1, so writing it in block 3 is in order
+        let user_1 = create_test_entity(
+            "1",
+            "user",
+            "Johnton",
+            "tonofjohn@email.com",
+            67 as i32,
+            184.4 as f32,
+            false,
+            None,
+        );
+        store
+            .apply_entity_operations(vec![user_1], EventSource::EthereumBlock(*TEST_BLOCK_3_PTR))
+            .unwrap();
+        assert!(!messages.contains(warning));
+
+        // User 3 was last written in block 3, so writing it in block 1 is out of order
+        let user_3 = create_test_entity(
+            "3",
+            "user",
+            "Shaqueeena",
+            "teeko@email.com",
+            28 as i32,
+            111.7 as f32,
+            false,
+            None,
+        );
+        store
+            .apply_entity_operations(vec![user_3], EventSource::EthereumBlock(*TEST_BLOCK_1_PTR))
+            .unwrap();
+        assert!(messages.contains(warning));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn queries_and_operations_beyond_the_slow_query_threshold_are_logged() {
+    run_test(|_| -> Result<(), ()> {
+        let messages = LogMessages::default();
+        let logger = Logger::root(messages.clone(), o!());
+        let store = DieselStore::new(
+            StoreConfig {
+                slow_query_threshold: Some(Duration::from_secs(0)),
+                ..test_store_config()
+            },
+            &logger,
+            EthereumNetworkIdentifier {
+                net_version: "graph test suite".to_owned(),
+                genesis_block_hash: TEST_BLOCK_0_PTR.hash,
+            },
+        )
+        .expect("Failed to create store");
+
+        // With a threshold of zero, every query and operation is slow
+        store
+            .find(EntityQuery::new(TEST_SUBGRAPH_ID.clone(), "user"))
+            .unwrap();
+        assert!(messages.contains("Slow entity query"));
+
+        store
+            .apply_entity_operations(
+                vec![EntityOperation::Remove {
+                    key: EntityKey {
+                        subgraph_id: TEST_SUBGRAPH_ID.clone(),
+                        entity_type: "user".to_owned(),
+                        entity_id: "1".to_owned(),
+                    },
+                }],
+                EventSource::None,
+            )
+            .unwrap();
+        assert!(messages.contains("Slow entity operation"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn entity_queries_are_logged_on_request() {
+    run_test(|_| -> Result<(), ()> {
+        let store_logging = |log_queries| {
+            let messages = LogMessages::default();
+            let logger = Logger::root(messages.clone(), o!());
+            let store = DieselStore::new(
+                StoreConfig {
+                    log_queries,
+                    ..test_store_config()
+                },
+                &logger,
+                EthereumNetworkIdentifier {
+                    net_version: "graph test suite".to_owned(),
+                    genesis_block_hash: TEST_BLOCK_0_PTR.hash,
+                },
+            )
+            .expect("Failed to create store");
+            (store, messages)
+        };
+
+        // Queries are only logged if logging them is enabled
+        let (store, messages) = store_logging(false);
+        store
+            .find(EntityQuery::new(TEST_SUBGRAPH_ID.clone(), "user"))
+            .unwrap();
+        assert!(!messages.contains("Entity query"));
+
+        let (store, messages) = store_logging(true);
+        store
+            .find(EntityQuery::new(TEST_SUBGRAPH_ID.clone(), "user"))
+            .unwrap();
+        assert!(messages.contains("Entity query"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn reset_subgraph_removes_entities_and_rewinds_the_block_pointer() {
+    run_test(|store| -> Result<(), ()> {
+        assert_eq!(store.count_entities(TEST_SUBGRAPH_ID.clone()).unwrap(), 3);
+        assert_eq!(
+            store.block_ptr(TEST_SUBGRAPH_ID.clone()).unwrap(),
+            *TEST_BLOCK_3_PTR
+        );
+
+        store.reset_subgraph(&TEST_SUBGRAPH_ID).unwrap();
+
+        assert_eq!(store.count_entities(TEST_SUBGRAPH_ID.clone()).unwrap(), 0);
+        assert_eq!(
+            store.block_ptr(TEST_SUBGRAPH_ID.clone()).unwrap(),
+            *TEST_BLOCK_0_PTR
+        );
+
+        // The subgraph of subgraphs can't be reset
+        assert!(store.reset_subgraph(&SUBGRAPHS_ID).is_err());
+        assert!(store.is_deployed(&TEST_SUBGRAPH_ID).unwrap());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn reverting_after_reset_subgraph_ignores_changes_from_before_the_reset() {
+    run_test(|store| -> Result<(), ()> {
+        store.reset_subgraph(&TEST_SUBGRAPH_ID).unwrap();
+
+        // Re-index blocks 1 to 3 without user 3, which block 3 updated before the reset
+        let user_1 = create_test_entity(
+            "1",
+            "user",
+            "Johnton",
+            "tonofjohn@email.com",
+            67 as i32,
+            184.4 as f32,
+            false,
+            None,
+        );
+        store
+            .transact_block_operations(
+                TEST_SUBGRAPH_ID.clone(),
+                *TEST_BLOCK_0_PTR,
+                *TEST_BLOCK_1_PTR,
+                vec![user_1],
+            )
+            .unwrap();
+        store
+            .transact_block_operations(
+                TEST_SUBGRAPH_ID.clone(),
+                *TEST_BLOCK_1_PTR,
+                *TEST_BLOCK_2_PTR,
+                vec![],
+            )
+            .unwrap();
+        let user_1 = create_test_entity(
+            "1",
+            "user",
+            "Johnton",
+            "johnton@email.com",
+            67 as i32,
+            184.4 as f32,
+            false,
+            None,
+        );
+        store
+            .transact_block_operations(
+                TEST_SUBGRAPH_ID.clone(),
+                *TEST_BLOCK_2_PTR,
+                *TEST_BLOCK_3_PTR,
+                vec![user_1],
+            )
+            .unwrap();
+
+        // Reverting block 3 only undoes what the re-indexed block 3 did
+        store
+            .revert_block_operations(
+                TEST_SUBGRAPH_ID.clone(),
+                *TEST_BLOCK_3_PTR,
+                *TEST_BLOCK_2_PTR,
+            )
+            .unwrap();
+
+        let user_1 = store
+            .get(EntityKey {
+                subgraph_id: TEST_SUBGRAPH_ID.clone(),
+                entity_type: "user".to_owned(),
+                entity_id: "1".to_owned(),
+            })
+            .unwrap()
+            .expect("user 1 is missing after the revert");
+        assert_eq!(
+            user_1.get("email"),
+            Some(&Value::String("tonofjohn@email.com".to_owned()))
+        );
+        let user_3 = store
+            .get(EntityKey {
+                subgraph_id: TEST_SUBGRAPH_ID.clone(),
+                entity_type: "user".to_owned(),
+                entity_id: "3".to_owned(),
+            })
+            .unwrap();
+        assert_eq!(user_3, None);
+        assert_eq!(store.count_entities(TEST_SUBGRAPH_ID.clone()).unwrap(), 1);
+        assert_eq!(
+            store.block_ptr(TEST_SUBGRAPH_ID.clone()).unwrap(),
+            *TEST_BLOCK_2_PTR
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn reset_subgraph_rewinds_to_the_start_block() {
+    run_test(|store| -> Result<(), ()> {
+        let subgraph_id = SubgraphDeploymentId::new("ResetStartBlockTestSubgraph").unwrap();
+        let manifest = SubgraphManifest {
+            id: subgraph_id.clone(),
+            location: "/ipfs/test".to_owned(),
+            spec_version: "1".to_owned(),
+            description: None,
+            repository: None,
+            schema: Schema::parse("scalar Foo", subgraph_id.clone()).unwrap(),
+            data_sources: vec![],
+        };
+
+        // Deploy the subgraph starting at block 1 and index block 2
+        store
+            .apply_entity_operations(
+                SubgraphDeploymentEntity::new(&manifest, false, false, *TEST_BLOCK_1_PTR, 1)
+                    .create_operations(&subgraph_id),
+                EventSource::None,
+            )
+            .unwrap();
+        store
+            .transact_block_operations(
+                subgraph_id.clone(),
+                *TEST_BLOCK_1_PTR,
+                *TEST_BLOCK_2_PTR,
+                vec![],
+            )
+            .unwrap();
+
+        store.reset_subgraph(&subgraph_id).unwrap();
+
+        assert_eq!(store.block_ptr(subgraph_id).unwrap(), *TEST_BLOCK_1_PTR);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn deployment_status_reflects_the_deployment_entity() {
+    run_test(|store| -> Result<(), ()> {
+        let status = store.deployment_status(&TEST_SUBGRAPH_ID).unwrap();
+        assert_eq!(status.latest_block, *TEST_BLOCK_3_PTR);
+        assert_eq!(status.chain_head_block, store.chain_head_ptr().unwrap());
+        assert!(!status.synced);
+        assert!(!status.failed);
+        assert_eq!(
+            store.block_ptr(TEST_SUBGRAPH_ID.clone()).unwrap(),
+            status.latest_block
+        );
+
+        let unknown = SubgraphDeploymentId::new("unknownDeployment").unwrap();
+        assert!(store.deployment_status(&unknown).is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn fail_subgraph_records_the_error() {
+    run_test(|store| -> Result<(), ()> {
+        store
+            .fail_subgraph(&TEST_SUBGRAPH_ID, "mapping aborted".to_owned())
+            .unwrap();
+
+        let deployment = store
+            .get(SubgraphDeploymentEntity::key(TEST_SUBGRAPH_ID.clone()))
+            .unwrap()
+            .expect("deployment entity is missing");
+        assert_eq!(deployment.get("failed"), Some(&Value::Bool(true)));
+        assert_eq!(
+            deployment.get("latestError"),
+            Some(&Value::String("mapping aborted".to_owned()))
+        );
+        assert!(store.deployment_status(&TEST_SUBGRAPH_ID).unwrap().failed);
+
+        // Failing a deployment that does not exist is an error
+        let unknown = SubgraphDeploymentId::new("unknownDeployment").unwrap();
+        assert!(store.fail_subgraph(&unknown, "error".to_owned()).is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn abort_unless_with_range_but_no_order_is_an_error() {
+    run_test(|store| -> Result<(), ()> {
+        let key = EntityKey {
+            subgraph_id: TEST_SUBGRAPH_ID.clone(),
+            entity_type: "user".to_owned(),
+            entity_id: "7".to_owned(),
+        };
+        let mut data = Entity::new();
+        data.set("id", "7");
+        data.set("name", "Tessa");
+
+        let result = store.apply_entity_operations(
+            vec![
+                EntityOperation::Set {
+                    key: key.clone(),
+                    data,
+                },
+                EntityOperation::AbortUnless {
+                    description: "range without order".to_owned(),
+                    query: EntityQuery::new(TEST_SUBGRAPH_ID.clone(), "user").range(EntityRange {
+                        first: Some(1),
+                        skip: 0,
+                    }),
+                    entity_ids: vec!["1".to_owned()],
+                },
+            ],
+            EventSource::None,
+        );
+
+        match result {
+            Err(e) => assert!(e.to_string().contains("without order_by")),
+            Ok(()) => panic!("AbortUnless with a range but no order should fail"),
+        }
+
+        // The transaction was rolled back
+        assert!(store.get(key).unwrap().is_none());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn validate_entity_operations_discards_writes() {
+    run_test(|store| -> Result<(), ()> {
+        let key = EntityKey {
+            subgraph_id: TEST_SUBGRAPH_ID.clone(),
+            entity_type: "user".to_owned(),
+            entity_id: "7".to_owned(),
+        };
+        let mut data = Entity::new();
+        data.set("id", "7");
+        data.set("name", "Tessa");
+        let set_op = EntityOperation::Set {
+            key: key.clone(),
+            data,
+        };
+        let abort_unless = |entity_ids: Vec<&str>| EntityOperation::AbortUnless {
+            description: "users named Tessa".to_owned(),
+            query: EntityQuery::new(TEST_SUBGRAPH_ID.clone(), "user")
+                .filter(EntityFilter::Equal("name".to_owned(), "Tessa".into())),
+            entity_ids: entity_ids.into_iter().map(|id| id.to_owned()).collect(),
+        };
+
+        // AbortUnless sees the writes of earlier operations
+        store
+            .validate_entity_operations(
+                vec![set_op.clone(), abort_unless(vec!["7"])],
+                EventSource::None,
+            )
+            .expect("operations should be valid");
+        assert!(store.get(key.clone()).unwrap().is_none());
+
+        // A failing AbortUnless is reported
+        match store
+            .validate_entity_operations(vec![set_op, abort_unless(vec![])], EventSource::None)
+        {
+            Err(StoreError::Aborted(TransactionAbortError::AbortUnless {
+                description, ..
+            })) => {
+                assert_eq!(description, "users named Tessa")
+            }
+            result => panic!("unexpected validation result: {:?}", result),
+        }
+        assert!(store.get(key).unwrap().is_none());
+
+        Ok(())
+    })
+}
+
+/// Creates a block with the given number and parent, whose hash is derived from its number.
+fn test_block(number: u64) -> EthereumBlock {
+    use graph::web3::types::{Block, Bytes, H160, H2048, U128, U256};
+
+    EthereumBlock {
+        block: Block {
+            hash: Some(H256::from(0xdef0 + number)),
+            parent_hash: H256::from(0xdef0 + number - 1),
+            uncles_hash: H256::default(),
+            author: H160::default(),
+            state_root: H256::default(),
+            transactions_root: H256::default(),
+            receipts_root: H256::default(),
+            number: Some(U128::from(number)),
+            gas_used: U256::from(100),
+            gas_limit: U256::from(1000),
+            extra_data: Bytes(vec![]),
+            logs_bloom: H2048::default(),
+            timestamp: U256::from(100000),
+            difficulty: U256::from(10),
+            total_difficulty: U256::from(100),
+            seal_fields: vec![],
+            uncles: vec![],
+            transactions: vec![],
+            size: Some(U256::from(10000)),
+        },
+        transaction_receipts: vec![],
+    }
+}
+
+#[test]
+fn insert_new_blocks_skips_existing_blocks() {
+    run_test(|store| {
+        use db_schema::ethereum_blocks;
+
+        let conn = PgConnection::establish(postgres_test_url().as_str())
+            .expect("Failed to connect to Postgres");
+        delete(ethereum_blocks::table.filter(ethereum_blocks::network_name.eq("fake_network")))
+            .execute(&conn)
+            .unwrap();
+
+        let first_store = store.clone();
+        store
+            .insert_new_blocks(stream::iter_ok::<_, Error>(vec![
+                test_block(1),
+                test_block(2),
+            ]))
+            .and_then(move |inserted| {
+                assert_eq!(inserted, 2);
+
+                // Only the block that is not in the store yet is inserted
+                first_store.insert_new_blocks(stream::iter_ok(vec![test_block(2), test_block(3)]))
+            })
+            .and_then(move |inserted| {
+                assert_eq!(inserted, 1);
+
+                // Upserting still rewrites existing blocks
+                store.upsert_blocks(stream::iter_ok(vec![test_block(3)]))
+            })
+    })
+}
+
+#[test]
+fn blocks_are_fetched_by_hash_in_the_requested_order() {
+    run_test(|store| {
+        use db_schema::ethereum_blocks;
+
+        let conn = PgConnection::establish(postgres_test_url().as_str())
+            .expect("Failed to connect to Postgres");
+        delete(ethereum_blocks::table.filter(ethereum_blocks::network_name.eq("fake_network")))
+            .execute(&conn)
+            .unwrap();
+
+        let blocks_store = store.clone();
+        store
+            .insert_new_blocks(stream::iter_ok::<_, Error>(vec![
+                test_block(1),
+                test_block(2),
+                test_block(3),
+            ]))
+            .and_then(move |inserted| {
+                assert_eq!(inserted, 3);
+
+                let hash = |number: u64| test_block(number).block.hash.unwrap();
+                let missing_hash = H256::from(0xdef0 + 4);
+                let blocks = blocks_store.blocks(&[hash(3), missing_hash, hash(1), hash(2)])?;
+
+                let numbers: Vec<_> = blocks
+                    .iter()
+                    .map(|block| block.block.number.unwrap().as_u64())
+                    .collect();
+                assert_eq!(numbers, vec![3, 1, 2]);
+                assert_eq!(blocks[0].block.hash, Some(hash(3)));
+
+                Ok(())
+            })
+    })
+}
+
+#[test]
+fn blocks_are_on_the_main_chain_if_the_chain_head_descends_from_them() {
+    run_test(|store| {
+        use db_schema::{ethereum_blocks, ethereum_networks};
+
+        let conn = PgConnection::establish(postgres_test_url().as_str())
+            .expect("Failed to connect to Postgres");
+        delete(ethereum_blocks::table.filter(ethereum_blocks::network_name.eq("fake_network")))
+            .execute(&conn)
+            .unwrap();
+
+        // Blocks 0 to 3 form the main chain, the orphaned block 2 was replaced in a reorg
+        let mut orphaned_block = test_block(2);
+        orphaned_block.block.hash = Some(H256::from(0xfed2));
+        let orphaned_ptr = EthereumBlockPointer::from(&orphaned_block);
+
+        let chain_store = store.clone();
+        store
+            .insert_new_blocks(stream::iter_ok::<_, Error>(vec![
+                test_block(0),
+                test_block(1),
+                orphaned_block,
+                test_block(2),
+                test_block(3),
+            ]))
+            .and_then(move |_| {
+                let ptr = |number: u64| EthereumBlockPointer::from(&test_block(number));
+
+                update(ethereum_networks::table.filter(ethereum_networks::name.eq("fake_network")))
+                    .set((
+                        ethereum_networks::head_block_hash.eq(ptr(3).hash_hex()),
+                        ethereum_networks::head_block_number.eq(3i64),
+                    ))
+                    .execute(&conn)
+                    .unwrap();
+
+                assert!(chain_store.is_on_main_chain(ptr(0))?);
+                assert!(chain_store.is_on_main_chain(ptr(2))?);
+                assert!(chain_store.is_on_main_chain(ptr(3))?);
+                assert!(!chain_store.is_on_main_chain(orphaned_ptr)?);
+                assert!(!chain_store.is_on_main_chain(ptr(4))?);
+
+                Ok(())
+            })
+    })
+}
+
+#[test]
+fn attempt_chain_head_update_validates_ancestor_count() {
+    run_test(|store| -> Result<(), ()> {
+        use db_schema::{ethereum_blocks, ethereum_networks};
+
+        // Start from a chain without any blocks
+        let conn = PgConnection::establish(postgres_test_url().as_str())
+            .expect("Failed to connect to Postgres");
+        delete(ethereum_blocks::table.filter(ethereum_blocks::network_name.eq("fake_network")))
+            .execute(&conn)
+            .unwrap();
+        update(ethereum_networks::table.filter(ethereum_networks::name.eq("fake_network")))
+            .set((
+                ethereum_networks::head_block_hash.eq(None::<String>),
+                ethereum_networks::head_block_number.eq(None::<i64>),
+            ))
+            .execute(&conn)
+            .unwrap();
+
+        // Store blocks 1 and 2, but not their parent block 0
+        let block_hash = |n: u64| format!("{:064x}", 0xabc0 + n);
+        for n in 1..3 {
+            insert_into(ethereum_blocks::table)
+                .values((
+                    ethereum_blocks::hash.eq(block_hash(n)),
+                    ethereum_blocks::number.eq(n as i64),
+                    ethereum_blocks::parent_hash.eq(block_hash(n - 1)),
+                    ethereum_blocks::network_name.eq("fake_network"),
+                    ethereum_blocks::data.eq(graph::serde_json::Value::Object(Default::default())),
+                ))
+                .execute(&conn)
+                .expect("Failed to insert test block");
+        }
+
+        // Checking no ancestors at all is an error
+        assert!(store.attempt_chain_head_update(0).is_err());
+
+        // An oversized count checks all ancestors the new head can have
+        let missing_blocks = store.attempt_chain_head_update(u64::max_value()).unwrap();
+        assert_eq!(missing_blocks, vec![block_hash(0).parse::<H256>().unwrap()]);
+        assert_eq!(store.chain_head_ptr().unwrap(), None);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn subgraph_schemas_are_loaded_in_batches() {
+    run_test(|store| -> Result<(), ()> {
+        let ids = vec![TEST_SUBGRAPH_ID.clone(), SUBGRAPHS_ID.clone()];
+        let schemas = store.subgraph_schemas(&ids).unwrap();
+        assert_eq!(schemas.len(), 2);
+
+        // Loaded schemas are cached and match the ones loaded individually
+        let cached = store.subgraph_schema(TEST_SUBGRAPH_ID.clone()).unwrap();
+        assert_eq!(
+            schemas[&*TEST_SUBGRAPH_ID].document.to_string(),
+            cached.document.to_string()
+        );
+        assert_eq!(store.subgraph_schemas(&ids).unwrap().len(), 2);
+        assert!(schemas[&*SUBGRAPHS_ID]
+            .document
+            .to_string()
+            .contains("type SubgraphDeployment"));
+
+        // A deployment without a manifest is an error
+        let unknown = SubgraphDeploymentId::new("unknownDeployment").unwrap();
+        assert!(store
+            .subgraph_schemas(&[TEST_SUBGRAPH_ID.clone(), unknown])
+            .is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn subgraph_schema_by_unknown_name_is_none() {
+    run_test(|store| -> Result<(), ()> {
+        let name = SubgraphName::new("unknown/subgraph").unwrap();
+        assert!(store.subgraph_schema_by_name(name).unwrap().is_none());
+        Ok(())
+    })
+}